@@ -1,6 +1,306 @@
+use std::path::Path;
+use std::process::Command;
+
 fn main() {
     if std::env::var_os("CARGO_FEATURE_STD").is_none() {
-        println!("cargo:rustc-link-arg=/ENTRY:_start");
-        println!("cargo:rustc-link-arg=/SUBSYSTEM:windows");
+        println!("cargo:rustc-link-arg-bins=/ENTRY:_start");
+        println!("cargo:rustc-link-arg-bins=/SUBSYSTEM:windows");
     }
+
+    // Metadata that identifies a build, see `src/version.rs`:
+    println!("cargo:rerun-if-changed=build.rs");
+    for path in [".git/HEAD", ".git/refs/heads"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_owned())
+        .filter(|hash| !hash.is_empty())
+        .unwrap_or_else(|| "unknown".to_owned());
+    println!("cargo:rustc-env=CLICK_ONCE_GIT_HASH={git_hash}");
+    println!(
+        "cargo:rustc-env=CLICK_ONCE_TARGET={}",
+        std::env::var("TARGET").unwrap_or_default()
+    );
+
+    let mut features = std::env::vars()
+        .filter_map(|(key, _)| {
+            let feature = key.strip_prefix("CARGO_FEATURE_")?;
+            Some(feature.to_ascii_lowercase().replace('_', "-"))
+        })
+        .collect::<Vec<_>>();
+    features.sort();
+    if features.is_empty() {
+        features.push("none".to_owned());
+    }
+    println!("cargo:rustc-env=CLICK_ONCE_FEATURES={}", features.join(","));
+
+    embed_resources(&git_hash);
+}
+
+/// Resource types from `winuser.h`.
+const RT_VERSION: u16 = 16;
+const RT_MANIFEST: u16 = 24;
+/// English (United States).
+const LANGUAGE: u16 = 0x0409;
+/// UTF-16.
+const CODE_PAGE: u16 = 1200;
+
+/// Read a build option from an environment variable.
+fn option(name: &str, default: &str) -> String {
+    println!("cargo:rerun-if-env-changed={name}");
+    std::env::var(name).unwrap_or_else(|_| default.to_owned())
+}
+
+/// Embed a version resource and an application manifest in the executable.
+/// They are written as a compiled resource file which the MSVC linker accepts
+/// as input, so no resource compiler is needed. See `src/version.rs` for where
+/// they are read back at runtime.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/menurc/resourceheader>
+/// - <https://learn.microsoft.com/en-us/windows/win32/menurc/vs-versioninfo>
+/// - <https://learn.microsoft.com/en-us/windows/win32/sbscs/application-manifests>
+fn embed_resources(git_hash: &str) {
+    if !std::env::var("TARGET")
+        .unwrap_or_default()
+        .contains("windows-msvc")
+    {
+        return;
+    }
+
+    let dpi_awareness = option("CLICK_ONCE_DPI_AWARENESS", "system");
+    let dpi_aware = match dpi_awareness.as_str() {
+        "unaware" => "false",
+        "system" => "true",
+        "PerMonitorV2" => "true/pm",
+        other => panic!(
+            "CLICK_ONCE_DPI_AWARENESS must be \"unaware\", \"system\" or \"PerMonitorV2\" but was \"{other}\""
+        ),
+    };
+    let long_path_aware = option("CLICK_ONCE_LONG_PATH_AWARE", "true");
+    assert!(
+        matches!(long_path_aware.as_str(), "true" | "false"),
+        "CLICK_ONCE_LONG_PATH_AWARE must be \"true\" or \"false\" but was \"{long_path_aware}\""
+    );
+    let execution_level = option("CLICK_ONCE_EXECUTION_LEVEL", "asInvoker");
+    assert!(
+        matches!(
+            execution_level.as_str(),
+            "asInvoker" | "highestAvailable" | "requireAdministrator"
+        ),
+        "CLICK_ONCE_EXECUTION_LEVEL must be \"asInvoker\", \"highestAvailable\" or \
+        \"requireAdministrator\" but was \"{execution_level}\""
+    );
+
+    let version = ["MAJOR", "MINOR", "PATCH"].map(|part| {
+        std::env::var(format!("CARGO_PKG_VERSION_{part}"))
+            .ok()
+            .and_then(|value| value.parse::<u16>().ok())
+            .unwrap_or(0)
+    });
+    let version_text = format!("{}.{}.{}.0", version[0], version[1], version[2]);
+
+    let manifest = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<assembly xmlns="urn:schemas-microsoft-com:asm.v1" manifestVersion="1.0">
+  <assemblyIdentity type="win32" name="click-once" version="{version_text}"/>
+  <trustInfo xmlns="urn:schemas-microsoft-com:asm.v3">
+    <security>
+      <requestedPrivileges>
+        <requestedExecutionLevel level="{execution_level}" uiAccess="false"/>
+      </requestedPrivileges>
+    </security>
+  </trustInfo>
+  <compatibility xmlns="urn:schemas-microsoft-com:compatibility.v1">
+    <application>
+      <supportedOS Id="{{8e0f7a12-bfb3-4fe8-b9a5-48fd50a15a9a}}"/>
+    </application>
+  </compatibility>
+  <application xmlns="urn:schemas-microsoft-com:asm.v3">
+    <windowsSettings>
+      <dpiAware xmlns="http://schemas.microsoft.com/SMI/2005/WindowsSettings">{dpi_aware}</dpiAware>
+      <dpiAwareness xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">{dpi_awareness}</dpiAwareness>
+      <longPathAware xmlns="http://schemas.microsoft.com/SMI/2016/WindowsSettings">{long_path_aware}</longPathAware>
+    </windowsSettings>
+  </application>
+</assembly>
+"#
+    );
+
+    let strings = [
+        ("CompanyName", option("CLICK_ONCE_COMPANY_NAME", "")),
+        (
+            "FileDescription",
+            option(
+                "CLICK_ONCE_FILE_DESCRIPTION",
+                "click-once: mouse button debouncer",
+            ),
+        ),
+        ("FileVersion", version_text.clone()),
+        ("InternalName", "click-once".to_owned()),
+        (
+            "LegalCopyright",
+            option(
+                "CLICK_ONCE_LEGAL_COPYRIGHT",
+                "Licensed under the Apache License 2.0",
+            ),
+        ),
+        ("OriginalFilename", "click-once.exe".to_owned()),
+        ("ProductName", "click-once".to_owned()),
+        (
+            "ProductVersion",
+            format!(
+                "{} ({git_hash})",
+                std::env::var("CARGO_PKG_VERSION").unwrap_or_default()
+            ),
+        ),
+    ];
+
+    let mut resources = Vec::new();
+    // A resource file starts with an empty entry:
+    push_resource(&mut resources, 0, 0, 0, &[]);
+    push_resource(
+        &mut resources,
+        RT_VERSION,
+        1,
+        LANGUAGE,
+        &version_info(version, &strings),
+    );
+    push_resource(
+        &mut resources,
+        RT_MANIFEST,
+        1,
+        LANGUAGE,
+        manifest.as_bytes(),
+    );
+
+    let path =
+        Path::new(&std::env::var("OUT_DIR").expect("OUT_DIR should be set")).join("click-once.res");
+    std::fs::write(&path, resources).expect("failed to write resource file");
+    println!("cargo:rustc-link-arg-bins={}", path.display());
+    // Otherwise the linker would write a default manifest next to the
+    // executable:
+    println!("cargo:rustc-link-arg-bins=/MANIFEST:NO");
+}
+
+fn pad_to_u32(data: &mut Vec<u8>) {
+    while !data.len().is_multiple_of(4) {
+        data.push(0);
+    }
+}
+
+/// Nul terminated UTF-16 text.
+fn wide(text: &str) -> Vec<u8> {
+    text.encode_utf16()
+        .chain([0])
+        .flat_map(u16::to_le_bytes)
+        .collect()
+}
+
+/// Append a resource with a numeric type and name to a resource file.
+fn push_resource(resources: &mut Vec<u8>, kind: u16, name: u16, language: u16, data: &[u8]) {
+    /// Size of a header with numeric type and name.
+    const HEADER_SIZE: u32 = 32;
+    /// `MOVEABLE | PURE`, which is what the resource compiler uses.
+    const MEMORY_FLAGS: u16 = 0x0030;
+
+    resources.extend((data.len() as u32).to_le_bytes());
+    resources.extend(HEADER_SIZE.to_le_bytes());
+    for word in [0xFFFF, kind, 0xFFFF, name] {
+        resources.extend(word.to_le_bytes());
+    }
+    resources.extend(0_u32.to_le_bytes()); // DataVersion
+    resources.extend(if kind == 0 { 0 } else { MEMORY_FLAGS }.to_le_bytes());
+    resources.extend(language.to_le_bytes());
+    resources.extend(0_u32.to_le_bytes()); // Version
+    resources.extend(0_u32.to_le_bytes()); // Characteristics
+    resources.extend(data);
+    pad_to_u32(resources);
+}
+
+/// A node in the tree that makes up a version resource. `value_length` is in
+/// bytes for binary values and in characters for text.
+fn version_node(
+    key: &str,
+    is_text: bool,
+    value_length: u16,
+    value: &[u8],
+    children: &[Vec<u8>],
+) -> Vec<u8> {
+    let mut node = vec![0; 2];
+    node.extend(value_length.to_le_bytes());
+    node.extend(u16::from(is_text).to_le_bytes());
+    node.extend(wide(key));
+    pad_to_u32(&mut node);
+    node.extend(value);
+    for child in children {
+        pad_to_u32(&mut node);
+        node.extend(child);
+    }
+    let length = node.len() as u16;
+    node[..2].copy_from_slice(&length.to_le_bytes());
+    node
+}
+
+/// Build a `VS_VERSIONINFO` structure.
+fn version_info(version: [u16; 3], strings: &[(&str, String)]) -> Vec<u8> {
+    let version_ms = u32::from(version[0]) << 16 | u32::from(version[1]);
+    let version_ls = u32::from(version[2]) << 16;
+    let fixed_info = [
+        0xFEEF_04BD, // dwSignature
+        0x0001_0000, // dwStrucVersion
+        version_ms,  // dwFileVersionMS
+        version_ls,  // dwFileVersionLS
+        version_ms,  // dwProductVersionMS
+        version_ls,  // dwProductVersionLS
+        0x3F,        // dwFileFlagsMask
+        0,           // dwFileFlags
+        0x0004_0004, // dwFileOS: VOS_NT_WINDOWS32
+        1,           // dwFileType: VFT_APP
+        0,           // dwFileSubtype
+        0,           // dwFileDateMS
+        0,           // dwFileDateLS
+    ]
+    .into_iter()
+    .flat_map(u32::to_le_bytes)
+    .collect::<Vec<u8>>();
+
+    let strings = strings
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(key, value)| {
+            let length = value.encode_utf16().count() as u16 + 1;
+            version_node(key, true, length, &wide(value), &[])
+        })
+        .collect::<Vec<_>>();
+    let string_table = version_node(
+        &format!("{LANGUAGE:04x}{CODE_PAGE:04x}"),
+        true,
+        0,
+        &[],
+        &strings,
+    );
+    let string_file_info = version_node("StringFileInfo", true, 0, &[], &[string_table]);
+    let translation = [LANGUAGE, CODE_PAGE]
+        .into_iter()
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<u8>>();
+    let var = version_node("Translation", false, 4, &translation, &[]);
+    let var_file_info = version_node("VarFileInfo", true, 0, &[], &[var]);
+
+    version_node(
+        "VS_VERSION_INFO",
+        false,
+        fixed_info.len() as u16,
+        &fixed_info,
+        &[string_file_info, var_file_info],
+    )
 }
@@ -0,0 +1,72 @@
+//! Start the program when the user logs in using a value under the registry's
+//! `Run` key. The value can be added by the installer or from the tray menu.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/setupapi/run-and-runonce-registry-keys>
+
+use crate::to_utf16;
+use std::path::Path;
+use windows_sys::Win32::Foundation::ERROR_SUCCESS;
+use windows_sys::Win32::System::Registry::{
+    RegDeleteKeyValueW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ, RRF_RT_REG_SZ,
+};
+
+const RUN_KEY: windows_sys::core::PCWSTR =
+    windows_sys::w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run");
+const VALUE_NAME: windows_sys::core::PCWSTR = windows_sys::w!("click-once");
+
+/// Quote an argument for a Windows command line.
+pub fn quote(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+        return arg.to_owned();
+    }
+    format!("\"{}\"", arg.replace('"', "\\\""))
+}
+
+/// Start `exe` with `args` when the current user logs in. Settings that aren't
+/// given as arguments are read from the config file.
+pub fn enable(exe: &Path, args: &[String]) -> Result<(), String> {
+    let command = core::iter::once(format!("\"{}\"", exe.display()))
+        .chain(args.iter().map(|arg| quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let command = to_utf16(&command);
+    let result = unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            RUN_KEY,
+            VALUE_NAME,
+            REG_SZ,
+            command.as_ptr().cast(),
+            (command.len() * 2) as u32,
+        )
+    };
+    if result == ERROR_SUCCESS {
+        Ok(())
+    } else {
+        Err(format!("Failed to add autostart entry (error {result})"))
+    }
+}
+
+/// `true` if the program starts when the current user logs in.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn is_enabled() -> bool {
+    let result = unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            RUN_KEY,
+            VALUE_NAME,
+            RRF_RT_REG_SZ,
+            core::ptr::null_mut(),
+            core::ptr::null_mut(),
+            core::ptr::null_mut(),
+        )
+    };
+    result == ERROR_SUCCESS
+}
+
+/// Remove the autostart entry, returns `false` if there was none.
+pub fn disable() -> bool {
+    unsafe { RegDeleteKeyValueW(HKEY_CURRENT_USER, RUN_KEY, VALUE_NAME) == ERROR_SUCCESS }
+}
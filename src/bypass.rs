@@ -0,0 +1,47 @@
+//! Lets rapid clicks through while a key is held down, for example Ctrl, so
+//! that fast clicks can be made on purpose without pausing the program.
+//!
+//! The key is checked with `GetAsyncKeyState` when a click happens, which is
+//! cheap enough for the mouse hook.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getasynckeystate>
+
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    GetAsyncKeyState, VK_CONTROL, VK_MENU, VK_SHIFT,
+};
+
+/// Virtual key code of the bypass key, `0` if there is none.
+static BYPASS_KEY: AtomicU32 = AtomicU32::new(0);
+
+/// Parse a bypass key: `Ctrl`, `Alt`, `Shift` or a key such as `F13` or a
+/// virtual key code such as `0x14`.
+pub fn parse(text: &str) -> Option<u32> {
+    let text = text.trim();
+    let modifier = [("ctrl", VK_CONTROL), ("alt", VK_MENU), ("shift", VK_SHIFT)]
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(text));
+    match modifier {
+        Some((_, key)) => Some(u32::from(key)),
+        None => crate::keyboard::parse_key(text).map(u32::from),
+    }
+}
+
+pub fn set_key(key: u32) {
+    BYPASS_KEY.store(key, Relaxed);
+}
+
+/// `true` if the bypass key is held down.
+#[inline(always)]
+pub fn is_held() -> bool {
+    match BYPASS_KEY.load(Relaxed) {
+        0 => false,
+        key => {
+            let state = unsafe { GetAsyncKeyState(key as i32) };
+            // The most significant bit is set while the key is down:
+            state < 0
+        }
+    }
+}
@@ -0,0 +1,26 @@
+//! Correlation ids that group the events of a single click, so that tools
+//! reading structured logs can reconstruct complete gestures. Every accepted
+//! down event starts a new click and its up event as well as any bounces that
+//! were blocked before the next accepted press get the same id.
+
+use crate::debounce::{MouseButton, MouseDirection};
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+
+/// The id of the most recent click of any button.
+static LAST_ID: AtomicU32 = AtomicU32::new(0);
+
+/// The id of the current click of each button, `0` before the first press.
+static CURRENT: [AtomicU32; MouseButton::COUNT] = [const { AtomicU32::new(0) }; MouseButton::COUNT];
+
+/// Get the id of the click that an event belongs to.
+#[inline(always)]
+pub fn assign(button: MouseButton, direction: MouseDirection, dropped: bool) -> u32 {
+    let current = &CURRENT[button as usize];
+    if direction == MouseDirection::Down && !dropped {
+        let id = LAST_ID.fetch_add(1, Relaxed).wrapping_add(1);
+        current.store(id, Relaxed);
+        id
+    } else {
+        current.load(Relaxed)
+    }
+}
@@ -0,0 +1,540 @@
+//! Opt-in COM automation server that lets scripts control the running program
+//! without a custom client, for example from PowerShell:
+//!
+//! ```powershell
+//! $clickOnce = New-Object -ComObject ClickOnce.Application
+//! $clickOnce.SetThreshold("left", 50)
+//! $clickOnce.Pause($true)
+//! $clickOnce.GetStats()
+//! ```
+//!
+//! Only late bound `IDispatch` calls are supported (there is no type library)
+//! which is all that PowerShell and VBScript need. The object is a singleton
+//! that lives as long as the program so reference counting is a no-op.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/combaseapi/nf-combaseapi-coregisterclassobject>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/oaidl/nn-oaidl-idispatch>
+//! - <https://learn.microsoft.com/en-us/windows/win32/com/localserver32>
+
+use crate::debounce::MouseButton;
+use crate::{log, log_error, to_utf16};
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use windows_sys::core::{GUID, HRESULT, PCWSTR};
+use windows_sys::Win32::Foundation::{
+    SysAllocStringLen, SysStringLen, CLASS_E_NOAGGREGATION, DISP_E_BADPARAMCOUNT,
+    DISP_E_MEMBERNOTFOUND, DISP_E_NONAMEDARGS, DISP_E_UNKNOWNNAME, ERROR_SUCCESS, E_INVALIDARG,
+    E_NOINTERFACE, E_NOTIMPL, E_OUTOFMEMORY, E_POINTER, RPC_E_CHANGED_MODE, S_OK, VARIANT_FALSE,
+    VARIANT_TRUE,
+};
+use windows_sys::Win32::System::Com::{
+    CoInitializeEx, CoRegisterClassObject, CLSCTX_LOCAL_SERVER, COINIT_APARTMENTTHREADED,
+    DISPATCH_METHOD, DISPATCH_PROPERTYGET, DISPPARAMS, EXCEPINFO, REGCLS_MULTIPLEUSE,
+};
+use windows_sys::Win32::System::Ole::DISPID_UNKNOWN;
+use windows_sys::Win32::System::Registry::{
+    RegDeleteTreeW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_SZ,
+};
+use windows_sys::Win32::System::Variant::{
+    VariantChangeType, VariantClear, VARENUM, VARIANT, VT_BOOL, VT_BSTR, VT_I4,
+};
+
+const PROG_ID: &str = "ClickOnce.Application";
+const CLSID: GUID = GUID::from_u128(0xe3f36f40_2bb8_46d1_b2bd_38e1361660ae);
+const CLSID_TEXT: &str = "{E3F36F40-2BB8-46D1-B2BD-38E1361660AE}";
+
+const IID_IUNKNOWN: GUID = GUID::from_u128(0x00000000_0000_0000_c000_000000000046);
+const IID_ICLASSFACTORY: GUID = GUID::from_u128(0x00000001_0000_0000_c000_000000000046);
+const IID_IDISPATCH: GUID = GUID::from_u128(0x00020400_0000_0000_c000_000000000046);
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Relaxed);
+}
+
+/// The methods that scripts can call, the discriminant is the `DISPID`.
+#[derive(Clone, Copy)]
+enum Method {
+    GetStats = 1,
+    GetThreshold,
+    SetThreshold,
+    Pause,
+    IsPaused,
+}
+impl Method {
+    const ALL: [Self; 5] = [
+        Self::GetStats,
+        Self::GetThreshold,
+        Self::SetThreshold,
+        Self::Pause,
+        Self::IsPaused,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Self::GetStats => "GetStats",
+            Self::GetThreshold => "GetThreshold",
+            Self::SetThreshold => "SetThreshold",
+            Self::Pause => "Pause",
+            Self::IsPaused => "IsPaused",
+        }
+    }
+
+    const fn arg_count(self) -> usize {
+        match self {
+            Self::GetStats | Self::IsPaused => 0,
+            Self::GetThreshold | Self::Pause => 1,
+            Self::SetThreshold => 2,
+        }
+    }
+
+    /// Call the method with its arguments in order. On errors the index of the
+    /// invalid argument is returned if there is one.
+    fn call(self, args: &[&VARIANT]) -> Result<VARIANT, (HRESULT, Option<usize>)> {
+        let arg_error = |index| move |error| (error, Some(index));
+        match self {
+            Self::GetStats => {
+                let mut text = String::new();
+                crate::logging::log_program_config()
+                    .iter()
+                    .for_each(|value| value.write_to_string(&mut text));
+                crate::logging::stats::log_current_stats(&mut |v| v.write_to_string(&mut text));
+                string_variant(text.trim()).map_err(|error| (error, None))
+            }
+            Self::GetThreshold => {
+                let button = button_arg(args[0]).map_err(arg_error(0))?;
+                let threshold = crate::threshold(button).load(Relaxed);
+                Ok(i32_variant(threshold.min(i32::MAX as u32) as i32))
+            }
+            Self::SetThreshold => {
+                let button = button_arg(args[0]).map_err(arg_error(0))?;
+                let threshold = i32_arg(args[1]).map_err(arg_error(1))?;
+                let threshold = u32::try_from(threshold).map_err(|_| (E_INVALIDARG, Some(1)))?;
+                crate::threshold(button).store(threshold, Relaxed);
+                log![
+                    b"\r\nA script set the threshold for the ",
+                    button.name().as_bytes(),
+                    b" button to ",
+                    threshold,
+                    b" ms\r\n",
+                ];
+                Ok(unsafe { core::mem::zeroed() })
+            }
+            Self::Pause => {
+                crate::pause::set_paused(bool_arg(args[0]).map_err(arg_error(0))?);
+                Ok(unsafe { core::mem::zeroed() })
+            }
+            Self::IsPaused => Ok(bool_variant(crate::pause::is_paused())),
+        }
+    }
+}
+
+/// Convert an argument to another type, the result must be cleared if it owns
+/// memory.
+fn convert(arg: &VARIANT, vt: VARENUM) -> Result<VARIANT, HRESULT> {
+    // All zeroes is VT_EMPTY:
+    let mut converted: VARIANT = unsafe { core::mem::zeroed() };
+    let result = unsafe { VariantChangeType(&mut converted, arg, 0, vt) };
+    if result < 0 {
+        Err(result)
+    } else {
+        Ok(converted)
+    }
+}
+
+fn i32_arg(arg: &VARIANT) -> Result<i32, HRESULT> {
+    convert(arg, VT_I4).map(|value| unsafe { value.Anonymous.Anonymous.Anonymous.lVal })
+}
+
+fn bool_arg(arg: &VARIANT) -> Result<bool, HRESULT> {
+    convert(arg, VT_BOOL)
+        .map(|value| unsafe { value.Anonymous.Anonymous.Anonymous.boolVal } != VARIANT_FALSE)
+}
+
+fn button_arg(arg: &VARIANT) -> Result<MouseButton, HRESULT> {
+    let mut value = convert(arg, VT_BSTR)?;
+    let name = unsafe {
+        let bstr = value.Anonymous.Anonymous.Anonymous.bstrVal;
+        let name = if bstr.is_null() {
+            String::new()
+        } else {
+            String::from_utf16_lossy(core::slice::from_raw_parts(
+                bstr,
+                SysStringLen(bstr) as usize,
+            ))
+        };
+        VariantClear(&mut value);
+        name
+    };
+    MouseButton::all()
+        .iter()
+        .copied()
+        .find(|&button| button.name().eq_ignore_ascii_case(name.trim()))
+        .ok_or(E_INVALIDARG)
+}
+
+fn i32_variant(value: i32) -> VARIANT {
+    let mut variant: VARIANT = unsafe { core::mem::zeroed() };
+    variant.Anonymous.Anonymous.vt = VT_I4;
+    variant.Anonymous.Anonymous.Anonymous.lVal = value;
+    variant
+}
+
+fn bool_variant(value: bool) -> VARIANT {
+    let mut variant: VARIANT = unsafe { core::mem::zeroed() };
+    variant.Anonymous.Anonymous.vt = VT_BOOL;
+    variant.Anonymous.Anonymous.Anonymous.boolVal =
+        if value { VARIANT_TRUE } else { VARIANT_FALSE };
+    variant
+}
+
+fn string_variant(text: &str) -> Result<VARIANT, HRESULT> {
+    let wide = text.encode_utf16().collect::<Vec<_>>();
+    let bstr = unsafe { SysAllocStringLen(wide.as_ptr(), wide.len() as u32) };
+    if bstr.is_null() {
+        return Err(E_OUTOFMEMORY);
+    }
+    let mut variant: VARIANT = unsafe { core::mem::zeroed() };
+    variant.Anonymous.Anonymous.vt = VT_BSTR;
+    variant.Anonymous.Anonymous.Anonymous.bstrVal = bstr;
+    Ok(variant)
+}
+
+#[repr(C)]
+struct IUnknownVtbl {
+    query_interface:
+        unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT,
+    add_ref: unsafe extern "system" fn(*mut c_void) -> u32,
+    release: unsafe extern "system" fn(*mut c_void) -> u32,
+}
+
+#[repr(C)]
+struct IClassFactoryVtbl {
+    unknown: IUnknownVtbl,
+    create_instance: unsafe extern "system" fn(
+        *mut c_void,
+        *mut c_void,
+        *const GUID,
+        *mut *mut c_void,
+    ) -> HRESULT,
+    lock_server: unsafe extern "system" fn(*mut c_void, i32) -> HRESULT,
+}
+
+#[repr(C)]
+struct IDispatchVtbl {
+    unknown: IUnknownVtbl,
+    get_type_info_count: unsafe extern "system" fn(*mut c_void, *mut u32) -> HRESULT,
+    get_type_info: unsafe extern "system" fn(*mut c_void, u32, u32, *mut *mut c_void) -> HRESULT,
+    get_ids_of_names: unsafe extern "system" fn(
+        *mut c_void,
+        *const GUID,
+        *const PCWSTR,
+        u32,
+        u32,
+        *mut i32,
+    ) -> HRESULT,
+    invoke: unsafe extern "system" fn(
+        *mut c_void,
+        i32,
+        *const GUID,
+        u32,
+        u16,
+        *const DISPPARAMS,
+        *mut VARIANT,
+        *mut EXCEPINFO,
+        *mut u32,
+    ) -> HRESULT,
+}
+
+/// A COM object is a pointer to a pointer to its vtable.
+#[repr(C)]
+struct ComObject<V: 'static>(&'static V);
+impl<V> ComObject<V> {
+    fn as_ptr(&'static self) -> *mut c_void {
+        (self as *const Self).cast_mut().cast()
+    }
+}
+
+static FACTORY: ComObject<IClassFactoryVtbl> = ComObject(&IClassFactoryVtbl {
+    unknown: IUnknownVtbl {
+        query_interface: factory_query_interface,
+        add_ref,
+        release,
+    },
+    create_instance,
+    lock_server,
+});
+
+static AUTOMATION: ComObject<IDispatchVtbl> = ComObject(&IDispatchVtbl {
+    unknown: IUnknownVtbl {
+        query_interface: automation_query_interface,
+        add_ref,
+        release,
+    },
+    get_type_info_count,
+    get_type_info,
+    get_ids_of_names,
+    invoke,
+});
+
+fn guid_eq(a: &GUID, b: &GUID) -> bool {
+    (a.data1, a.data2, a.data3, a.data4) == (b.data1, b.data2, b.data3, b.data4)
+}
+
+unsafe fn query_interface(
+    object: *mut c_void,
+    supported: &GUID,
+    iid: *const GUID,
+    out: *mut *mut c_void,
+) -> HRESULT {
+    if out.is_null() || iid.is_null() {
+        return E_POINTER;
+    }
+    if guid_eq(&*iid, &IID_IUNKNOWN) || guid_eq(&*iid, supported) {
+        *out = object;
+        S_OK
+    } else {
+        *out = core::ptr::null_mut();
+        E_NOINTERFACE
+    }
+}
+
+unsafe extern "system" fn factory_query_interface(
+    this: *mut c_void,
+    iid: *const GUID,
+    out: *mut *mut c_void,
+) -> HRESULT {
+    query_interface(this, &IID_ICLASSFACTORY, iid, out)
+}
+
+unsafe extern "system" fn automation_query_interface(
+    this: *mut c_void,
+    iid: *const GUID,
+    out: *mut *mut c_void,
+) -> HRESULT {
+    query_interface(this, &IID_IDISPATCH, iid, out)
+}
+
+unsafe extern "system" fn add_ref(_this: *mut c_void) -> u32 {
+    1
+}
+
+unsafe extern "system" fn release(_this: *mut c_void) -> u32 {
+    1
+}
+
+unsafe extern "system" fn create_instance(
+    _this: *mut c_void,
+    outer: *mut c_void,
+    iid: *const GUID,
+    out: *mut *mut c_void,
+) -> HRESULT {
+    if !outer.is_null() {
+        if !out.is_null() {
+            *out = core::ptr::null_mut();
+        }
+        return CLASS_E_NOAGGREGATION;
+    }
+    query_interface(AUTOMATION.as_ptr(), &IID_IDISPATCH, iid, out)
+}
+
+unsafe extern "system" fn lock_server(_this: *mut c_void, _lock: i32) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn get_type_info_count(_this: *mut c_void, count: *mut u32) -> HRESULT {
+    if count.is_null() {
+        return E_POINTER;
+    }
+    *count = 0;
+    S_OK
+}
+
+unsafe extern "system" fn get_type_info(
+    _this: *mut c_void,
+    _index: u32,
+    _lcid: u32,
+    _info: *mut *mut c_void,
+) -> HRESULT {
+    E_NOTIMPL
+}
+
+unsafe extern "system" fn get_ids_of_names(
+    _this: *mut c_void,
+    _iid: *const GUID,
+    names: *const PCWSTR,
+    count: u32,
+    _lcid: u32,
+    ids: *mut i32,
+) -> HRESULT {
+    if names.is_null() || ids.is_null() {
+        return E_POINTER;
+    }
+    let mut result = S_OK;
+    for index in 0..count as usize {
+        let name = *names.add(index);
+        let len = (0..).take_while(|&i| *name.add(i) != 0).count();
+        let name = String::from_utf16_lossy(core::slice::from_raw_parts(name, len));
+        // Only the first name is a method, the rest are named arguments which
+        // aren't supported:
+        let id = Method::ALL
+            .into_iter()
+            .find(|method| index == 0 && method.name().eq_ignore_ascii_case(&name))
+            .map_or(DISPID_UNKNOWN, |method| method as i32);
+        if id == DISPID_UNKNOWN {
+            result = DISP_E_UNKNOWNNAME;
+        }
+        *ids.add(index) = id;
+    }
+    result
+}
+
+#[allow(
+    clippy::too_many_arguments,
+    reason = "signature is defined by IDispatch"
+)]
+unsafe extern "system" fn invoke(
+    _this: *mut c_void,
+    id: i32,
+    _iid: *const GUID,
+    _lcid: u32,
+    flags: u16,
+    params: *const DISPPARAMS,
+    result: *mut VARIANT,
+    _exception: *mut EXCEPINFO,
+    arg_error: *mut u32,
+) -> HRESULT {
+    let Some(method) = Method::ALL.into_iter().find(|&method| method as i32 == id) else {
+        return DISP_E_MEMBERNOTFOUND;
+    };
+    if flags & (DISPATCH_METHOD | DISPATCH_PROPERTYGET) == 0 {
+        return DISP_E_MEMBERNOTFOUND;
+    }
+    let Some(params) = params.as_ref() else {
+        return E_POINTER;
+    };
+    if params.cNamedArgs != 0 {
+        return DISP_E_NONAMEDARGS;
+    }
+    if params.cArgs as usize != method.arg_count() {
+        return DISP_E_BADPARAMCOUNT;
+    }
+    // Arguments are stored in reverse order:
+    let mut args = [core::ptr::null::<VARIANT>(); 2];
+    for (index, arg) in args[..method.arg_count()].iter_mut().enumerate() {
+        *arg = params.rgvarg.add(method.arg_count() - 1 - index);
+    }
+    let args = args[..method.arg_count()]
+        .iter()
+        .map(|&arg| &*arg)
+        .collect::<Vec<_>>();
+
+    match method.call(&args) {
+        Ok(mut value) => {
+            match result.as_mut() {
+                Some(result) => *result = value,
+                None => _ = VariantClear(&mut value),
+            }
+            S_OK
+        }
+        Err((error, index)) => {
+            if let (Some(index), false) = (index, arg_error.is_null()) {
+                *arg_error = (method.arg_count() - 1 - index) as u32;
+            }
+            error
+        }
+    }
+}
+
+/// Set a default value below `HKEY_CURRENT_USER\Software\Classes`, creating the
+/// key if needed.
+fn set_class_value(key: &str, value: &str) -> bool {
+    let key = to_utf16(&format!("Software\\Classes\\{key}"));
+    let value = to_utf16(value);
+    let result = unsafe {
+        RegSetKeyValueW(
+            HKEY_CURRENT_USER,
+            key.as_ptr(),
+            core::ptr::null(),
+            REG_SZ,
+            value.as_ptr().cast(),
+            (value.len() * 2) as u32,
+        )
+    };
+    result == ERROR_SUCCESS
+}
+
+/// Register the ProgID so that scripts can find the object. COM also starts
+/// the program (with the `-Embedding` argument) if it isn't already running.
+fn register() -> Result<(), String> {
+    let exe_path = std::env::current_exe()
+        .map_err(|e| format!("Failed to get the path of the current executable: {e}"))?;
+    let registered = [
+        (PROG_ID.to_owned(), "click-once automation".to_owned()),
+        (format!("{PROG_ID}\\CLSID"), CLSID_TEXT.to_owned()),
+        (
+            format!("CLSID\\{CLSID_TEXT}"),
+            "click-once automation".to_owned(),
+        ),
+        (format!("CLSID\\{CLSID_TEXT}\\ProgID"), PROG_ID.to_owned()),
+        (
+            format!("CLSID\\{CLSID_TEXT}\\LocalServer32"),
+            format!("\"{}\"", exe_path.display()),
+        ),
+    ]
+    .iter()
+    .all(|(key, value)| set_class_value(key, value));
+    if registered {
+        Ok(())
+    } else {
+        Err("Failed to register the COM automation object".to_owned())
+    }
+}
+
+/// Remove the registration that [`register`] created. Returns the number of
+/// removed registry keys.
+pub fn unregister() -> usize {
+    [PROG_ID.to_owned(), format!("CLSID\\{CLSID_TEXT}")]
+        .iter()
+        .filter(|key| {
+            let key = to_utf16(&format!("Software\\Classes\\{key}"));
+            unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, key.as_ptr()) == ERROR_SUCCESS }
+        })
+        .count()
+}
+
+/// Make the automation object available to scripts if that is enabled. Must be
+/// called on the thread that runs the message loop since calls are delivered
+/// as window messages.
+pub fn start() {
+    if !ENABLED.load(Relaxed) {
+        return;
+    }
+    let result = unsafe { CoInitializeEx(core::ptr::null(), COINIT_APARTMENTTHREADED as u32) };
+    // Calls arrive on other threads if COM was already initialized as
+    // multi-threaded which is fine since all state is atomic:
+    if result < 0 && result != RPC_E_CHANGED_MODE {
+        log_error("Failed to initialize COM");
+        return;
+    }
+    if let Err(e) = register() {
+        log_error(e);
+        return;
+    }
+    let mut cookie = 0;
+    let result = unsafe {
+        CoRegisterClassObject(
+            &CLSID,
+            FACTORY.as_ptr(),
+            CLSCTX_LOCAL_SERVER,
+            REGCLS_MULTIPLEUSE as u32,
+            &mut cookie,
+        )
+    };
+    if result < 0 {
+        log_error("Failed to make the COM automation object available");
+    }
+}
@@ -0,0 +1,991 @@
+//! Loads settings from an optional config file at
+//! `%APPDATA%\click-once\config.toml`.
+//!
+//! The file uses a small subset of TOML: `key = value` pairs where the value is
+//! an integer, a boolean or a quoted string, `[profile.<name>]` and
+//! `[app.<executable>]` tables and `#` comments.
+//! The supported keys are `left`, `right`, `middle`, `x1`, `x2`, `left-down`,
+//! `left-up` (and the same for the other buttons, to use different thresholds
+//! for down and up events), `wheel`, `hwheel`, `keyboard`, `pen-tip`,
+//! `pen-barrel` and `pen-eraser` (thresholds in milliseconds), `keys`
+//! (thresholds for single keys such as `"A:50, 0x20:40"`), `mode` (`"both"`,
+//! `"up-only"` or `"delay"`), `injected` and `lower-il-injected` (`"debounce"`,
+//! `"pass"` or `"block"`), `exempt-absolute`, `exempt-controller`,
+//! `exempt-touch`, `game-mode`, `watchdog`, `mmcss`, `precise-timing`,
+//! `high-resolution-timer`, `logging`, `verbose`, `log-window-title`, `color`
+//! and `timestamps` (`true` or `false`), `device` (only debounce mice whose
+//! name contains this text, for example `"VID_046D&PID_C077"`), `allow-devices`
+//! and `deny-devices` (comma separated vendor and product ids such as
+//! `"046D:C077, 056A:0357"`), `exclude-apps` (comma separated executable names
+//! such as `"osu!.exe, game.exe"`), `double-click-hotkey`,
+//! `false-positive-hotkey`, `pause-hotkey` and `undo-hotkey` (for example
+//! `"Ctrl+Alt+D"`), `bypass-key` (`"Ctrl"`, `"Alt"`, `"Shift"` or a key such as
+//! `"F13"`), `failsafe-rate`, `failsafe-seconds`, `failsafe-percent`,
+//! `min-distance`, `rehook-hours`, `tooltip-refresh` and `max-runtime` (see the
+//! command line arguments with the same names), `dry-run` (`true` or `false`),
+//! `live-feed` (`true` for the default port or a port number), `log-format` (a
+//! template such as `"{timestamp} {button} {direction} {result} {delta}"` for
+//! logged mouse events), `log-file` (a path that the log is also written to),
+//! `log-context` (`"omit"`, `"hash"` or `"full"`) and `log-queue-policy`
+//! (`"drop-newest"` or `"drop-oldest"`). Command line arguments override
+//! settings from the file.
+//!
+//! While the program runs the file is watched and changed thresholds are
+//! applied immediately, other settings are only read at startup.
+//!
+//! Top level settings are always used while a profile's settings are only used
+//! when it is selected with the `--profile <name>` argument:
+//!
+//! ```toml
+//! left = 30
+//!
+//! [profile.gaming]
+//! left = 60
+//! right = 30
+//! ```
+//!
+//! `[app.<executable>]` tables set the thresholds of the buttons (`left`,
+//! `right`, `middle`, `x1` and `x2`) that are used while a window of that
+//! application is in the foreground, with any profile:
+//!
+//! ```toml
+//! [app."mspaint.exe"]
+//! left = 10
+//! ```
+
+use crate::debounce::{MouseButton, SuppressionMode};
+use crate::failsafe;
+use crate::hotkey::{self, HotkeyAction};
+use crate::injected::{self, InjectedKind, InjectedPolicy};
+use crate::log_error;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+/// Thresholds larger than this are most likely mistakes, for example a value
+/// in microseconds instead of milliseconds.
+pub const MAX_THRESHOLD: u32 = 10_000;
+
+/// The profile that was selected with `--profile <name>`, changed settings are
+/// saved to it.
+static PROFILE: Mutex<Option<String>> = Mutex::new(None);
+
+/// Modification time of the config file after the program last saved it, so
+/// that the watcher doesn't apply the program's own changes again.
+static SAVED_TIME: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+/// The mistakes that were last shown in a message box, so that reloading the
+/// file doesn't show the same ones again.
+#[cfg(feature = "tray")]
+static SHOWN_ERRORS: Mutex<String> = Mutex::new(String::new());
+
+/// A `key = value` pair from the config file.
+struct Entry<'a> {
+    /// The 1-based line number of the entry.
+    line: usize,
+    /// The table that the entry is in, empty for top level entries.
+    table: &'a str,
+    key: &'a str,
+    value: &'a str,
+}
+
+/// A mistake in the config file.
+struct ConfigError {
+    /// The 1-based line number where the mistake was found.
+    line: usize,
+    message: String,
+}
+impl core::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Get the path of the config file. `None` if the `APPDATA` environment
+/// variable isn't set.
+pub fn path() -> Option<PathBuf> {
+    let app_data = std::env::var_os("APPDATA")?;
+    Some(
+        PathBuf::from(app_data)
+            .join("click-once")
+            .join("config.toml"),
+    )
+}
+
+/// Get the name of the table if the line is a table header like `[name]`.
+fn table_header(line: &str) -> Option<&str> {
+    line.trim()
+        .strip_prefix('[')
+        .and_then(|line| line.split('#').next())
+        .and_then(|line| line.trim().strip_suffix(']'))
+        .map(str::trim)
+}
+
+fn entries(text: &str) -> impl Iterator<Item = Result<Entry<'_>, ConfigError>> {
+    let mut table = "";
+    text.lines().enumerate().filter_map(move |(ix, line)| {
+        let line_number = ix + 1;
+        let error = |message: String| {
+            Some(Err(ConfigError {
+                line: line_number,
+                message,
+            }))
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        if line.starts_with('[') {
+            return match table_header(line) {
+                Some(name)
+                    if name.strip_prefix("profile.").is_some_and(|p| !p.is_empty())
+                        || app_name(name).is_some_and(|app| !app.is_empty()) =>
+                {
+                    table = name;
+                    None
+                }
+                Some(name) => error(format!(
+                    "unknown table \"[{name}]\", expected \"[profile.<name>]\" or \
+                    \"[app.<executable>]\""
+                )),
+                None => error(format!("malformed table header \"{line}\"")),
+            };
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return error(format!("expected \"key = value\" but found \"{line}\""));
+        };
+        let key = key.trim();
+        let value = value.trim();
+        let value = match value.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, _)) => value,
+                None => return error(format!("the value of \"{key}\" is missing a closing quote")),
+            },
+            None => value.split('#').next().unwrap_or_default().trim(),
+        };
+        Some(Ok(Entry {
+            line: line_number,
+            table,
+            key,
+            value,
+        }))
+    })
+}
+
+/// The executable name of an `[app.<executable>]` table such as
+/// `[app."chrome.exe"]`.
+fn app_name(table: &str) -> Option<&str> {
+    table
+        .strip_prefix("app.")
+        .map(|name| name.trim().trim_matches('"'))
+}
+
+/// Validate a threshold in an `[app.<executable>]` table.
+fn validate_app_threshold(entry: &Entry<'_>) -> Result<(MouseButton, u32), ConfigError> {
+    let error = |message: String| ConfigError {
+        line: entry.line,
+        message,
+    };
+    let button = MouseButton::all()
+        .iter()
+        .copied()
+        .find(|button| button.name() == entry.key)
+        .ok_or_else(|| {
+            error(format!(
+                "unknown key \"{}\" in \"[{}]\", expected \"left\", \"right\", \"middle\", \
+                \"x1\" or \"x2\"",
+                entry.key, entry.table
+            ))
+        })?;
+    let Setting::Threshold(_, threshold) = validate(entry)? else {
+        return Err(error(format!("\"{}\" must be a threshold", entry.key)));
+    };
+    Ok((button, threshold))
+}
+
+/// A valid setting from the config file.
+enum Setting {
+    Threshold(&'static AtomicU32, u32),
+    Mode(SuppressionMode),
+    ExemptAbsolute(bool),
+    ExemptTouch(bool),
+    DeviceFilter(String),
+    AllowedDevices(String),
+    DeniedDevices(String),
+    ExcludedApps(String),
+    KeyThresholds(String),
+    ExemptController(bool),
+    Injected(InjectedKind, InjectedPolicy),
+    Hotkey(HotkeyAction, u32),
+    /// A whole number that is applied by calling a function.
+    Number(fn(u32), u32),
+    /// A feature that is turned on or off by calling a function.
+    Toggle(fn(bool), bool),
+    #[cfg_attr(
+        not(feature = "logging"),
+        allow(dead_code, reason = "nothing is logged")
+    )]
+    LogFormat(String),
+    #[cfg_attr(
+        not(feature = "logging"),
+        allow(dead_code, reason = "nothing is logged")
+    )]
+    LogFile(String),
+    #[cfg_attr(
+        not(feature = "logging"),
+        allow(dead_code, reason = "nothing is logged")
+    )]
+    LogContext(String),
+    #[cfg_attr(
+        not(feature = "logging"),
+        allow(dead_code, reason = "nothing is logged")
+    )]
+    LogQueuePolicy(String),
+}
+impl Setting {
+    fn apply(self) {
+        match self {
+            Setting::Threshold(threshold, value) => threshold.store(value, Relaxed),
+            Setting::Mode(mode) => crate::SUPPRESSION_MODE.store(mode as u8, Relaxed),
+            Setting::ExemptAbsolute(exempt) => crate::EXEMPT_ABSOLUTE.store(exempt, Relaxed),
+            Setting::ExemptTouch(exempt) => crate::EXEMPT_TOUCH.store(exempt, Relaxed),
+            Setting::DeviceFilter(filter) => _ = crate::raw_input::set_device_filter(&filter),
+            Setting::AllowedDevices(list) => _ = crate::raw_input::set_allowed_devices(&list),
+            Setting::DeniedDevices(list) => _ = crate::raw_input::set_denied_devices(&list),
+            Setting::ExcludedApps(list) => crate::foreground_app::set_excluded_apps(&list),
+            Setting::KeyThresholds(list) => _ = crate::keyboard::set_key_thresholds(&list),
+            Setting::ExemptController(exempt) => crate::controller::set_enabled(exempt),
+            Setting::Injected(kind, policy) => injected::set_policy(kind, policy),
+            Setting::Hotkey(action, hotkey) => hotkey::set(action, hotkey),
+            Setting::Number(set, value) => set(value),
+            Setting::Toggle(set, enabled) => set(enabled),
+            #[cfg(feature = "logging")]
+            Setting::LogFormat(template) => crate::logging::set_log_format(&template),
+            #[cfg(not(feature = "logging"))]
+            Setting::LogFormat(_) => {}
+            #[cfg(feature = "logging")]
+            Setting::LogFile(path) => {
+                if !crate::log_file::set_path(&path) {
+                    log_error(format_args!("Failed to open log file \"{path}\""));
+                }
+            }
+            #[cfg(not(feature = "logging"))]
+            Setting::LogFile(_) => {}
+            #[cfg(feature = "logging")]
+            Setting::LogContext(value) => {
+                if let Some(privacy) = crate::logging::ContextPrivacy::parse(&value) {
+                    crate::logging::set_context_privacy(privacy);
+                }
+            }
+            #[cfg(feature = "logging")]
+            Setting::LogQueuePolicy(value) => {
+                if let Some(policy) = crate::logging::QueuePolicy::parse(&value) {
+                    crate::logging::set_queue_policy(policy);
+                }
+            }
+            #[cfg(not(feature = "logging"))]
+            Setting::LogContext(_) | Setting::LogQueuePolicy(_) => {}
+        }
+    }
+}
+
+/// The value of the `mode` key for a suppression mode.
+fn mode_name(mode: SuppressionMode) -> &'static str {
+    match mode {
+        SuppressionMode::Both => "both",
+        SuppressionMode::UpOnly => "up-only",
+        SuppressionMode::Delay => "delay",
+    }
+}
+
+fn parse_bool(key: &str, value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        value => Err(format!(
+            "\"{key}\" must be true or false but was \"{value}\""
+        )),
+    }
+}
+
+/// Check that an entry is valid and return the setting it changes.
+fn validate(entry: &Entry<'_>) -> Result<Setting, ConfigError> {
+    let error = |message: String| ConfigError {
+        line: entry.line,
+        message,
+    };
+    let threshold = match entry.key {
+        "left" => &crate::THRESHOLD_LM,
+        "right" => &crate::THRESHOLD_RM,
+        "middle" => &crate::THRESHOLD_MM,
+        "x1" => &crate::THRESHOLD_X1,
+        "x2" => &crate::THRESHOLD_X2,
+        "left-down" => &crate::THRESHOLD_LM,
+        "right-down" => &crate::THRESHOLD_RM,
+        "middle-down" => &crate::THRESHOLD_MM,
+        "x1-down" => &crate::THRESHOLD_X1,
+        "x2-down" => &crate::THRESHOLD_X2,
+        "left-up" => &crate::UP_THRESHOLDS[MouseButton::Left as usize],
+        "right-up" => &crate::UP_THRESHOLDS[MouseButton::Right as usize],
+        "middle-up" => &crate::UP_THRESHOLDS[MouseButton::Middle as usize],
+        "x1-up" => &crate::UP_THRESHOLDS[MouseButton::X1 as usize],
+        "x2-up" => &crate::UP_THRESHOLDS[MouseButton::X2 as usize],
+        "wheel" => crate::wheel::VERTICAL.threshold(),
+        "hwheel" => crate::wheel::HORIZONTAL.threshold(),
+        "keyboard" => &crate::keyboard::THRESHOLD,
+        "pen-tip" => &crate::PEN_THRESHOLDS[0],
+        "pen-barrel" => &crate::PEN_THRESHOLDS[1],
+        "pen-eraser" => &crate::PEN_THRESHOLDS[2],
+        "mode" => {
+            return [
+                SuppressionMode::Both,
+                SuppressionMode::UpOnly,
+                SuppressionMode::Delay,
+            ]
+                .into_iter()
+                .find(|&mode| mode_name(mode) == entry.value)
+                .map(Setting::Mode)
+                .ok_or_else(|| {
+                    error(format!(
+                        "\"mode\" must be \"both\", \"up-only\" or \"delay\" but was \"{}\"",
+                        entry.value
+                    ))
+                })
+        }
+        key @ ("injected" | "lower-il-injected") => {
+            let kind = if key == "injected" {
+                InjectedKind::Injected
+            } else {
+                InjectedKind::LowerIl
+            };
+            return InjectedPolicy::parse(entry.value)
+                .map(|policy| Setting::Injected(kind, policy))
+                .ok_or_else(|| {
+                    error(format!(
+                        "\"{key}\" must be \"debounce\", \"pass\" or \"block\" but was \"{}\"",
+                        entry.value
+                    ))
+                });
+        }
+        key @ ("failsafe-rate" | "failsafe-seconds" | "failsafe-percent" | "min-distance"
+        | "rehook-hours" | "tooltip-refresh" | "max-runtime") => {
+            let set: fn(u32) = match key {
+                "failsafe-rate" => failsafe::set_max_blocked_per_second,
+                "failsafe-seconds" => failsafe::set_max_seconds,
+                "failsafe-percent" => failsafe::set_max_blocked_percent,
+                "min-distance" => crate::position::set_min_distance,
+                "rehook-hours" => crate::rehook::set_interval_hours,
+                "max-runtime" => |seconds| crate::MAX_RUNTIME_SECS.store(seconds, Relaxed),
+                #[cfg(feature = "tray")]
+                _ => crate::tray::set_tooltip_refresh_interval,
+                // There is no tooltip without the `tray` feature:
+                #[cfg(not(feature = "tray"))]
+                _ => |_| {},
+            };
+            return entry
+                .value
+                .parse::<u32>()
+                .map(|value| Setting::Number(set, value))
+                .map_err(|_| {
+                    error(format!(
+                        "\"{key}\" must be a whole number but was \"{}\"",
+                        entry.value
+                    ))
+                });
+        }
+        "log-format" => {
+            // Nothing is logged without the `logging` feature:
+            #[cfg(feature = "logging")]
+            crate::logging::validate_log_format(entry.value).map_err(error)?;
+            return Ok(Setting::LogFormat(entry.value.to_owned()));
+        }
+        "log-file" => return Ok(Setting::LogFile(entry.value.to_owned())),
+        "log-context" => {
+            // Nothing is logged without the `logging` feature:
+            #[cfg(feature = "logging")]
+            if crate::logging::ContextPrivacy::parse(entry.value).is_none() {
+                return Err(error(format!(
+                    "\"log-context\" must be \"omit\", \"hash\" or \"full\" but was \"{}\"",
+                    entry.value
+                )));
+            }
+            return Ok(Setting::LogContext(entry.value.to_owned()));
+        }
+        "log-queue-policy" => {
+            // Nothing is logged without the `logging` feature:
+            #[cfg(feature = "logging")]
+            if crate::logging::QueuePolicy::parse(entry.value).is_none() {
+                return Err(error(format!(
+                    "\"log-queue-policy\" must be \"drop-newest\" or \"drop-oldest\" but was \"{}\"",
+                    entry.value
+                )));
+            }
+            return Ok(Setting::LogQueuePolicy(entry.value.to_owned()));
+        }
+        "live-feed" => {
+            // `true` serves the feed on the default port:
+            #[cfg(feature = "live-feed")]
+            let default_port = crate::live_feed::DEFAULT_PORT;
+            #[cfg(not(feature = "live-feed"))]
+            let default_port = 0;
+            let port = match parse_bool("live-feed", entry.value) {
+                Ok(true) => default_port,
+                Ok(false) => 0,
+                Err(_) => entry.value.parse::<u16>().map_err(|_| {
+                    error(format!(
+                        "\"live-feed\" must be true, false or a port number but was \"{}\"",
+                        entry.value
+                    ))
+                })?,
+            };
+            #[cfg(feature = "live-feed")]
+            let set: fn(u32) = |port| crate::live_feed::set_port(port as u16);
+            // There is no feed without the `live-feed` feature:
+            #[cfg(not(feature = "live-feed"))]
+            let set: fn(u32) = |_| {};
+            return Ok(Setting::Number(set, port.into()));
+        }
+        key @ ("double-click-hotkey" | "false-positive-hotkey" | "pause-hotkey" | "undo-hotkey") => {
+            let action = match key {
+                "double-click-hotkey" => HotkeyAction::DoubleClick,
+                "false-positive-hotkey" => HotkeyAction::MarkFalsePositive,
+                "pause-hotkey" => HotkeyAction::TogglePause,
+                _ => HotkeyAction::UndoLastBlock,
+            };
+            return hotkey::parse(entry.value)
+                .map(|hotkey| Setting::Hotkey(action, hotkey))
+                .ok_or_else(|| {
+                    error(format!(
+                        "\"{key}\" must be a hotkey such as \"Ctrl+Alt+D\" but was \"{}\"",
+                        entry.value
+                    ))
+                })
+        }
+        key @ ("logging" | "verbose" | "log-window-title" | "color" | "timestamps") => {
+            let enabled = parse_bool(key, entry.value).map_err(error)?;
+            #[cfg(feature = "logging")]
+            let set: fn(bool) = match key {
+                "logging" => crate::logging::set_should_log,
+                "verbose" => |enabled| {
+                    crate::logging::set_verbose(enabled);
+                    if enabled {
+                        crate::logging::set_should_log(true);
+                    }
+                },
+                "log-window-title" => crate::window_title::set_enabled,
+                "timestamps" => crate::logging::set_use_timestamps,
+                _ => crate::logging::set_use_color,
+            };
+            // Nothing is logged without the `logging` feature:
+            #[cfg(not(feature = "logging"))]
+            let set: fn(bool) = |_| {};
+            return Ok(Setting::Toggle(set, enabled));
+        }
+        "device" => {
+            if entry.value.encode_utf16().count() > crate::raw_input::MAX_FILTER_LEN {
+                return Err(error(format!(
+                    "\"device\" can be at most {} characters long",
+                    crate::raw_input::MAX_FILTER_LEN
+                )));
+            }
+            return Ok(Setting::DeviceFilter(entry.value.to_owned()));
+        }
+        "keys" => {
+            crate::keyboard::parse_key_thresholds(entry.value, &mut |_, _| {})
+                .map_err(|e| error(format!("invalid \"keys\": {e}")))?;
+            return Ok(Setting::KeyThresholds(entry.value.to_owned()));
+        }
+        key @ ("allow-devices" | "deny-devices") => {
+            let mut ids = [crate::device_id::DeviceId {
+                vendor: 0,
+                product: 0,
+            }; crate::raw_input::MAX_LISTED_DEVICES];
+            crate::device_id::parse_list(entry.value, &mut ids)
+                .map_err(|e| error(format!("invalid \"{key}\": {e}")))?;
+            let list = entry.value.to_owned();
+            return Ok(if key == "allow-devices" {
+                Setting::AllowedDevices(list)
+            } else {
+                Setting::DeniedDevices(list)
+            });
+        }
+        "bypass-key" => {
+            let key = crate::bypass::parse(entry.value).ok_or_else(|| {
+                error(format!(
+                    "\"bypass-key\" must be \"Ctrl\", \"Alt\", \"Shift\" or a key such as \"F13\" \
+                    but was \"{}\"",
+                    entry.value
+                ))
+            })?;
+            return Ok(Setting::Number(crate::bypass::set_key, key));
+        }
+        "exclude-apps" => return Ok(Setting::ExcludedApps(entry.value.to_owned())),
+        "game-mode" => {
+            let enabled = parse_bool("game-mode", entry.value).map_err(error)?;
+            return Ok(Setting::Toggle(crate::foreground_app::set_game_mode, enabled));
+        }
+        "watchdog" => {
+            let enabled = parse_bool("watchdog", entry.value).map_err(error)?;
+            return Ok(Setting::Toggle(crate::watchdog::set_enabled, enabled));
+        }
+        "dry-run" => {
+            let enabled = parse_bool("dry-run", entry.value).map_err(error)?;
+            return Ok(Setting::Toggle(
+                |enabled| crate::DRY_RUN.store(enabled, Relaxed),
+                enabled,
+            ));
+        }
+        "precise-timing" => {
+            let enabled = parse_bool("precise-timing", entry.value).map_err(error)?;
+            return Ok(Setting::Toggle(crate::set_precise_timing, enabled));
+        }
+        "high-resolution-timer" => {
+            let enabled = parse_bool("high-resolution-timer", entry.value).map_err(error)?;
+            return Ok(Setting::Toggle(crate::timer_resolution::set_enabled, enabled));
+        }
+        "mmcss" => {
+            let enabled = parse_bool("mmcss", entry.value).map_err(error)?;
+            return Ok(Setting::Toggle(crate::priority::set_use_mmcss, enabled));
+        }
+        key @ ("exempt-absolute" | "exempt-controller" | "exempt-touch") => {
+            let exempt = parse_bool(key, entry.value).map_err(error)?;
+            return Ok(match key {
+                "exempt-absolute" => Setting::ExemptAbsolute(exempt),
+                "exempt-controller" => Setting::ExemptController(exempt),
+                _ => Setting::ExemptTouch(exempt),
+            });
+        }
+        key => {
+            return Err(error(format!(
+                "unknown key \"{key}\", expected \"left\", \"right\", \"middle\", \"x1\", \"x2\", \
+                \"<button>-down\", \"<button>-up\", \"wheel\", \"hwheel\", \"keyboard\", \"keys\", \"pen-tip\", \
+                \"pen-barrel\", \"pen-eraser\", \"mode\", \"exempt-absolute\", \"exempt-controller\", \"exempt-touch\", \"device\", \"allow-devices\", \"deny-devices\", \"exclude-apps\", \"game-mode\", \"watchdog\", \"mmcss\", \"precise-timing\", \"high-resolution-timer\", \"bypass-key\", \
+                \"injected\", \"lower-il-injected\", \"double-click-hotkey\", \"false-positive-hotkey\", \
+                \"pause-hotkey\", \"undo-hotkey\", \"failsafe-rate\", \"failsafe-seconds\", \"failsafe-percent\", \"min-distance\", \
+                \"rehook-hours\", \"max-runtime\", \"dry-run\", \"live-feed\", \
+                \"tooltip-refresh\", \"logging\", \"verbose\", \"log-window-title\", \"color\", \"timestamps\", \
+                \"log-format\", \"log-file\", \"log-context\" or \"log-queue-policy\""
+            )))
+        }
+    };
+    let value = entry.value.parse::<u32>().map_err(|_| {
+        error(format!(
+            "\"{}\" must be a threshold in milliseconds but was \"{}\"",
+            entry.key, entry.value
+        ))
+    })?;
+    if value > MAX_THRESHOLD {
+        return Err(error(format!(
+            "\"{}\" is {value} ms which is more than the maximum of {MAX_THRESHOLD} ms",
+            entry.key
+        )));
+    }
+    Ok(Setting::Threshold(threshold, value))
+}
+
+/// Log mistakes in the config file and show a notification about them once so
+/// that they aren't silently ignored. The notification is only shown again if
+/// the mistakes change, since the file is reloaded whenever it is saved.
+fn report_errors(path: &Path, errors: &[ConfigError]) {
+    for error in errors {
+        log_error(format_args!(
+            "Ignored invalid setting in config file \"{}\" at {error}",
+            path.display()
+        ));
+    }
+
+    #[cfg(feature = "tray")]
+    {
+        let text = format!(
+            "The config file \"{}\" contains mistakes, these settings were ignored:{}",
+            path.display(),
+            errors
+                .iter()
+                .map(|error| format!("\r\n{error}"))
+                .collect::<String>()
+        );
+        {
+            let mut shown = SHOWN_ERRORS.lock().unwrap_or_else(|e| e.into_inner());
+            if *shown == text {
+                return;
+            }
+            shown.clone_from(&text);
+        }
+        let text = crate::to_utf16(&text);
+        // Don't block startup while the message box is open:
+        std::thread::spawn(move || {
+            use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONWARNING, MB_OK};
+
+            let title = crate::to_utf16("click-once: invalid config file");
+            let result = unsafe {
+                MessageBoxW(
+                    core::ptr::null_mut(),
+                    text.as_ptr(),
+                    title.as_ptr(),
+                    MB_OK | MB_ICONWARNING,
+                )
+            };
+            if result == 0 {
+                log_error("Failed to open message box");
+            }
+        });
+    }
+}
+
+/// Find the profile that is selected with a `--profile <name>` argument.
+pub fn profile_arg(mut args: impl Iterator<Item = String>) -> Option<String> {
+    args.find(|arg| arg.trim().eq_ignore_ascii_case("--profile"))?;
+    args.next()
+}
+
+/// Apply the settings from the config file, including the settings from the
+/// selected `profile`. Does nothing if the file doesn't exist unless a profile
+/// was requested.
+pub fn load(profile: Option<&str>) -> Result<(), String> {
+    *PROFILE.lock().unwrap_or_else(|e| e.into_inner()) = profile.map(str::to_owned);
+    apply_file(profile, false)
+}
+
+/// Read the config file and apply its settings, or only its thresholds if
+/// `thresholds_only` is `true`.
+fn apply_file(profile: Option<&str>, thresholds_only: bool) -> Result<(), String> {
+    let Some(path) = path() else {
+        return match profile {
+            Some(_) => Err("Failed to locate the config file, APPDATA isn't set".to_owned()),
+            None => Ok(()),
+        };
+    };
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && profile.is_none() => return Ok(()),
+        Err(e) => {
+            return Err(format!(
+                "Failed to read config file \"{}\": {e}",
+                path.display()
+            ))
+        }
+    };
+
+    let profile_table = profile.map(|profile| format!("profile.{profile}"));
+    if let (Some(profile), Some(table)) = (profile, &profile_table) {
+        if !text.lines().any(|line| table_header(line) == Some(table)) {
+            return Err(format!(
+                "The profile \"{profile}\" isn't defined in the config file \"{}\"",
+                path.display()
+            ));
+        }
+    }
+
+    // All entries are validated, even those in profiles that aren't used, but
+    // only the top level and the selected profile are applied. Top level
+    // entries always come before tables so the profile overrides them:
+    let mut errors = Vec::new();
+    let mut app_thresholds = Vec::new();
+    for entry in entries(&text) {
+        if let Ok(entry) = &entry {
+            if let Some(app) = app_name(entry.table) {
+                match validate_app_threshold(entry) {
+                    Ok((button, threshold)) => {
+                        app_thresholds.push(crate::foreground_app::ThresholdOverride {
+                            app: app.to_lowercase(),
+                            button,
+                            threshold,
+                        })
+                    }
+                    Err(error) => errors.push(error),
+                }
+                continue;
+            }
+        }
+        match entry.and_then(|entry| Ok((validate(&entry)?, entry))) {
+            Ok((setting, entry)) => {
+                let used = entry.table.is_empty() || Some(entry.table) == profile_table.as_deref();
+                if used && (!thresholds_only || matches!(setting, Setting::Threshold(..))) {
+                    setting.apply();
+                }
+            }
+            Err(error) => errors.push(error),
+        }
+    }
+    // Applications' thresholds are used with every profile:
+    crate::foreground_app::set_threshold_overrides(app_thresholds);
+    if !errors.is_empty() {
+        report_errors(&path, &errors);
+    } else {
+        // Show the mistakes again if they are made again after being fixed:
+        #[cfg(feature = "tray")]
+        SHOWN_ERRORS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clear();
+    }
+    Ok(())
+}
+
+/// The time when the config file was last modified, `None` if it doesn't exist.
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Watch the config file on a background thread and apply its thresholds
+/// again when it is changed, so that edits take effect without restarting the
+/// program. Thresholds from the file then replace those given as command line
+/// arguments since the file was edited later.
+pub fn watch(profile: Option<String>) {
+    use windows_sys::Win32::Foundation::{INVALID_HANDLE_VALUE, WAIT_OBJECT_0};
+    use windows_sys::Win32::Storage::FileSystem::{
+        FindFirstChangeNotificationW, FindNextChangeNotification, FILE_NOTIFY_CHANGE_FILE_NAME,
+        FILE_NOTIFY_CHANGE_LAST_WRITE,
+    };
+    use windows_sys::Win32::System::Threading::{WaitForSingleObject, INFINITE};
+
+    let Some(path) = path() else {
+        return;
+    };
+    let Some(folder) = path.parent() else {
+        return;
+    };
+    // The folder doesn't exist until a config file is saved, create it so that
+    // a config file that is written later is noticed:
+    if let Err(e) = std::fs::create_dir_all(folder) {
+        log_error(format_args!(
+            "Failed to create the config folder \"{}\" to watch it for changes: {e}",
+            folder.display()
+        ));
+        return;
+    }
+    let folder = crate::to_utf16(&folder.to_string_lossy());
+    // Renames are included since many editors save by replacing the file:
+    let notification = unsafe {
+        FindFirstChangeNotificationW(
+            folder.as_ptr(),
+            0,
+            FILE_NOTIFY_CHANGE_LAST_WRITE | FILE_NOTIFY_CHANGE_FILE_NAME,
+        )
+    };
+    if notification == INVALID_HANDLE_VALUE {
+        log_error(format_args!(
+            "Failed to watch the config file for changes: {}",
+            std::io::Error::last_os_error()
+        ));
+        return;
+    }
+    // The handle is only used by the watcher thread:
+    let notification = notification as usize;
+
+    std::thread::spawn(move || {
+        let notification = notification as windows_sys::Win32::Foundation::HANDLE;
+        let mut last_modified = modified_time(&path);
+        while unsafe { WaitForSingleObject(notification, INFINITE) } == WAIT_OBJECT_0 {
+            // Editors often write a file in several steps, wait until they
+            // are done:
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            let modified = modified_time(&path);
+            let saved = *SAVED_TIME.lock().unwrap_or_else(|e| e.into_inner());
+            if modified.is_some() && modified != last_modified && modified != saved {
+                last_modified = modified;
+                match apply_file(profile.as_deref(), true) {
+                    Ok(()) => {
+                        crate::log![b"Applied thresholds from the changed config file\r\n"];
+                        #[cfg(feature = "tray")]
+                        crate::tray::refresh_tooltip();
+                    }
+                    Err(e) => log_error(e),
+                }
+            }
+            if unsafe { FindNextChangeNotification(notification) } == 0 {
+                break;
+            }
+        }
+        log_error("Stopped watching the config file for changes");
+    });
+}
+
+/// Save settings that were changed while the program runs, for example from
+/// the tray menu, so that they survive a restart. Thresholds are also stored
+/// in the registry if they were read from there, since the registry would
+/// otherwise override them only when the config file doesn't set them.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn persist_changes() {
+    if let Err(e) = save_settings() {
+        log_error(e);
+    }
+    if crate::registry_config::is_in_use() {
+        if let Err(e) = crate::registry_config::save() {
+            log_error(e);
+        }
+    }
+}
+
+/// Write the current thresholds and mode to the config file so that they are
+/// used the next time the program starts. They are written to the selected
+/// profile's table if there is one, since it would otherwise override them,
+/// and to the top level otherwise. Other settings and comments in the file are
+/// kept.
+pub fn save_settings() -> Result<(), String> {
+    let path = path().ok_or("Failed to locate the config file, APPDATA isn't set")?;
+    let text = match std::fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => {
+            return Err(format!(
+                "Failed to read config file \"{}\": {e}",
+                path.display()
+            ))
+        }
+    };
+
+    let mut settings = [
+        ("left", crate::THRESHOLD_LM.load(Relaxed).to_string()),
+        ("right", crate::THRESHOLD_RM.load(Relaxed).to_string()),
+        ("middle", crate::THRESHOLD_MM.load(Relaxed).to_string()),
+        ("x1", crate::THRESHOLD_X1.load(Relaxed).to_string()),
+        ("x2", crate::THRESHOLD_X2.load(Relaxed).to_string()),
+        (
+            "wheel",
+            crate::wheel::VERTICAL.threshold().load(Relaxed).to_string(),
+        ),
+        (
+            "hwheel",
+            crate::wheel::HORIZONTAL
+                .threshold()
+                .load(Relaxed)
+                .to_string(),
+        ),
+        (
+            "pen-tip",
+            crate::PEN_THRESHOLDS[0].load(Relaxed).to_string(),
+        ),
+        (
+            "pen-barrel",
+            crate::PEN_THRESHOLDS[1].load(Relaxed).to_string(),
+        ),
+        (
+            "pen-eraser",
+            crate::PEN_THRESHOLDS[2].load(Relaxed).to_string(),
+        ),
+        (
+            "mode",
+            format!("\"{}\"", mode_name(crate::suppression_mode())),
+        ),
+        (
+            "exempt-absolute",
+            crate::EXEMPT_ABSOLUTE.load(Relaxed).to_string(),
+        ),
+        (
+            "exempt-controller",
+            crate::controller::is_enabled().to_string(),
+        ),
+        (
+            "exempt-touch",
+            crate::EXEMPT_TOUCH.load(Relaxed).to_string(),
+        ),
+        ("game-mode", crate::foreground_app::game_mode().to_string()),
+        ("watchdog", crate::watchdog::is_enabled().to_string()),
+        ("mmcss", crate::priority::use_mmcss().to_string()),
+        (
+            "precise-timing",
+            crate::PRECISE_TIMING.load(Relaxed).to_string(),
+        ),
+        (
+            "high-resolution-timer",
+            crate::timer_resolution::is_enabled().to_string(),
+        ),
+        (
+            "failsafe-rate",
+            failsafe::max_blocked_per_second().to_string(),
+        ),
+        ("failsafe-seconds", failsafe::max_seconds().to_string()),
+        (
+            "failsafe-percent",
+            failsafe::max_blocked_percent().to_string(),
+        ),
+        ("min-distance", crate::position::min_distance().to_string()),
+        (
+            "injected",
+            format!("\"{}\"", injected::policy(InjectedKind::Injected).name()),
+        ),
+        (
+            "lower-il-injected",
+            format!("\"{}\"", injected::policy(InjectedKind::LowerIl).name()),
+        ),
+    ]
+    .map(|(key, value)| (key, Some(value), false))
+    .into_iter()
+    // Up thresholds are only written when they are set, otherwise they are
+    // removed so that up events use the same threshold as down events:
+    .chain(
+        ["left-up", "right-up", "middle-up", "x1-up", "x2-up"]
+            .into_iter()
+            .zip(&crate::UP_THRESHOLDS)
+            .map(|(key, threshold)| {
+                let value = match threshold.load(Relaxed) {
+                    crate::SAME_AS_DOWN => None,
+                    value => Some(value.to_string()),
+                };
+                (key, value, false)
+            }),
+    )
+    .collect::<Vec<_>>();
+
+    let profile_table = PROFILE
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()
+        .map(|profile| format!("profile.{profile}"));
+    let mut output = String::with_capacity(text.len() + 64);
+    // Missing keys are inserted after the profile's header:
+    let mut profile_start = None;
+    let mut table = None;
+    for line in text.lines() {
+        if let Some(header) = table_header(line) {
+            table = Some(header);
+            if Some(header) == profile_table.as_deref() {
+                output.push_str(line);
+                output.push('\n');
+                profile_start = Some(output.len());
+                continue;
+            }
+        }
+        let key = line.split_once('=').map(|(key, _)| key.trim());
+        let in_target = table == profile_table.as_deref();
+        match settings
+            .iter_mut()
+            .find(|(name, _, _)| in_target && Some(*name) == key)
+        {
+            Some((name, value, written)) => {
+                if let Some(value) = value {
+                    output.push_str(&format!("{name} = {value}\n"));
+                }
+                *written = true;
+            }
+            None => {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+    }
+    let missing = settings
+        .iter()
+        .filter(|(_, _, written)| !written)
+        .filter_map(|(name, value, _)| Some(format!("{name} = {}\n", value.as_ref()?)))
+        .collect::<String>();
+    match (&profile_table, profile_start) {
+        // Top level keys must come before any table:
+        (None, _) => output.insert_str(0, &missing),
+        (Some(_), Some(start)) => output.insert_str(start, &missing),
+        // The profile was removed from the file while the program ran:
+        (Some(table), None) => output.push_str(&format!("\n[{table}]\n{missing}")),
+    }
+
+    let write = || {
+        std::fs::create_dir_all(path.parent().expect("config file should be in a folder"))?;
+        std::fs::write(&path, output)
+    };
+    write().map_err(|e| format!("Failed to write config file \"{}\": {e}", path.display()))?;
+    *SAVED_TIME.lock().unwrap_or_else(|e| e.into_inner()) = modified_time(&path);
+    Ok(())
+}
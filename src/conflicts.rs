@@ -0,0 +1,109 @@
+//! Detect other programs that also try to fix mouse double clicks. Two
+//! debouncers will fight each other (and two instances of this program will
+//! install two hooks) so we warn about it at startup.
+
+use crate::logging::{utf16_to_ascii, FgColor};
+use crate::{log, log_error};
+use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows_sys::Win32::System::Threading::GetCurrentProcessId;
+
+/// Start of executable names (compared case-insensitively) for programs that
+/// are known to debounce mouse clicks. Prefixes are used so that for example
+/// `click-once-tray.exe` is also detected.
+const KNOWN_DEBOUNCERS: &[&[u8]] = &[
+    b"click-once",
+    b"DoubleClickFix",
+    b"LeftMouseButtonFix",
+    b"MouseFix",
+];
+
+/// Scan running processes for known double-click fixing tools. The callback
+/// is called with the executable name and process id of each one.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/toolhelp/taking-a-snapshot-and-viewing-processes>
+pub fn find_conflicting_software(mut on_found: impl FnMut(&[u8], u32)) {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        log_error("Failed to list running processes");
+        return;
+    }
+
+    let own_pid = unsafe { GetCurrentProcessId() };
+
+    let mut entry: PROCESSENTRY32W = unsafe { core::mem::zeroed() };
+    entry.dwSize = core::mem::size_of::<PROCESSENTRY32W>() as u32;
+    let mut has_entry = unsafe { Process32FirstW(snapshot, &mut entry) } != 0;
+    while has_entry {
+        let mut name = [0; 260];
+        let name = utf16_to_ascii(&entry.szExeFile, &mut name);
+
+        let is_debouncer = KNOWN_DEBOUNCERS.iter().any(|known| {
+            name.len() >= known.len() && name[..known.len()].eq_ignore_ascii_case(known)
+        });
+        // Instances that debounce other devices were allowed to start:
+        let is_partitioned =
+            crate::single_instance::is_scoped() && name.starts_with(KNOWN_DEBOUNCERS[0]);
+        if is_debouncer && !is_partitioned && entry.th32ProcessID != own_pid {
+            on_found(name, entry.th32ProcessID);
+        }
+
+        has_entry = unsafe { Process32NextW(snapshot, &mut entry) } != 0;
+    }
+    unsafe { CloseHandle(snapshot) };
+}
+
+/// Log a warning for each running program that also filters mouse clicks. In
+/// tray builds a message box is also shown since the console is usually
+/// hidden.
+pub fn warn_about_conflicting_software() {
+    #[cfg(feature = "tray")]
+    let mut found = String::new();
+
+    find_conflicting_software(|name, pid| {
+        log![
+            FgColor::BLOCKED,
+            b"Warning: ",
+            FgColor::Reset,
+            b"\"",
+            name,
+            b"\" (PID ",
+            pid,
+            b") also filters mouse clicks, two debouncers running at the same time will interfere with each other\r\n",
+        ];
+        #[cfg(feature = "tray")]
+        {
+            found.push_str("\r\n");
+            found.push_str(core::str::from_utf8(name).unwrap_or("?"));
+        }
+    });
+
+    #[cfg(feature = "tray")]
+    if !found.is_empty() {
+        // Don't block startup while the message box is open:
+        std::thread::spawn(move || {
+            use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONWARNING, MB_OK};
+
+            let text = crate::to_utf16(&format!(
+                "Other programs that filter mouse clicks are running. \
+                Two debouncers running at the same time will interfere with each other:\r\n{found}"
+            ));
+            let title = crate::to_utf16("click-once: conflicting software");
+            let result = unsafe {
+                MessageBoxW(
+                    core::ptr::null_mut(),
+                    text.as_ptr(),
+                    title.as_ptr(),
+                    MB_OK | MB_ICONWARNING,
+                )
+            };
+            if result == 0 {
+                log_error("Failed to open message box");
+            }
+        });
+    }
+}
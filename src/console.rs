@@ -0,0 +1,121 @@
+//! Prints the results of commands such as `click-once status` or `--uninstall`
+//! that exit right away. The program is built for the Windows subsystem, so it
+//! has no console of its own and must attach to the console of the program
+//! that started it. This works with or without the `logging` feature.
+//!
+//! If there is no such console, for example when the program was started from
+//! a shortcut, the text is shown in a message box instead.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/console/attachconsole>
+//! - <https://learn.microsoft.com/en-us/windows/console/console-handles>
+
+use core::ptr;
+use windows_sys::Win32::Foundation::{GENERIC_WRITE, HANDLE, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::Storage::FileSystem::{
+    CreateFileW, WriteFile, FILE_ATTRIBUTE_NORMAL, FILE_SHARE_WRITE, OPEN_EXISTING,
+};
+use windows_sys::Win32::System::Console::{
+    AttachConsole, GetStdHandle, ATTACH_PARENT_PROCESS, STD_OUTPUT_HANDLE,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    MessageBoxW, IDYES, MB_ICONERROR, MB_ICONINFORMATION, MB_ICONWARNING, MB_OK, MB_YESNO,
+};
+
+/// Standard output if it was redirected or the program has a console,
+/// otherwise the console of the parent process. `None` if there is neither.
+fn output() -> Option<HANDLE> {
+    let handle = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+    if !handle.is_null() && handle != INVALID_HANDLE_VALUE {
+        return Some(handle);
+    }
+    // Fails if the program already has a console, which is fine:
+    unsafe { AttachConsole(ATTACH_PARENT_PROCESS) };
+    let handle = unsafe {
+        CreateFileW(
+            windows_sys::w!("CONOUT$"),
+            GENERIC_WRITE,
+            FILE_SHARE_WRITE,
+            ptr::null(),
+            OPEN_EXISTING,
+            FILE_ATTRIBUTE_NORMAL,
+            ptr::null_mut(),
+        )
+    };
+    (handle != INVALID_HANDLE_VALUE).then_some(handle)
+}
+
+fn write(handle: HANDLE, mut text: &[u8]) -> bool {
+    while !text.is_empty() {
+        let mut written = 0;
+        let result = unsafe {
+            WriteFile(
+                handle,
+                text.as_ptr(),
+                text.len() as u32,
+                &mut written,
+                ptr::null_mut(),
+            )
+        };
+        if result == 0 || written == 0 {
+            return false;
+        }
+        text = &text[written as usize..];
+    }
+    true
+}
+
+fn print(message: &str, is_error: bool) {
+    let line = format!("{message}\r\n");
+    if output().is_some_and(|handle| write(handle, line.as_bytes())) {
+        return;
+    }
+    let text = crate::to_utf16(message);
+    let icon = if is_error {
+        MB_ICONERROR
+    } else {
+        MB_ICONINFORMATION
+    };
+    unsafe {
+        MessageBoxW(
+            ptr::null_mut(),
+            text.as_ptr(),
+            windows_sys::w!("click-once"),
+            MB_OK | icon,
+        )
+    };
+}
+
+/// Print the result of a command.
+pub fn report(message: &str) {
+    print(message, false);
+}
+
+/// Print why a command failed.
+pub fn report_error(message: &str) {
+    print(&format!("Error: {message}"), true);
+}
+
+/// Ask before running a command that can't be undone. Returns `true` if it
+/// should run. The shell that started the program keeps reading from its
+/// console, so in a console the question is only printed and the command
+/// must be run again with `--yes`.
+pub fn confirm(message: &str) -> bool {
+    if let Some(handle) = output() {
+        let text = format!("{message}\r\nRun the command again with --yes to continue.\r\n");
+        if write(handle, text.as_bytes()) {
+            return false;
+        }
+    }
+    let text = crate::to_utf16(message);
+    let result = unsafe {
+        MessageBoxW(
+            ptr::null_mut(),
+            text.as_ptr(),
+            windows_sys::w!("click-once"),
+            MB_YESNO | MB_ICONWARNING,
+        )
+    };
+    result == IDYES
+}
@@ -0,0 +1,116 @@
+//! Recognizes clicks from programs that let a game controller act as a mouse,
+//! for example Steam Input or JoyToKey. These programs inject mouse events
+//! using `SendInput` and rapid clicks from them are intentional, so they can
+//! be exempted from debouncing.
+//!
+//! Injected events don't say which process injected them, so instead we
+//! exempt injected events while a known emulator is running. The list of
+//! running processes is refreshed periodically using a timer on the thread
+//! that runs the message loop.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/toolhelp/taking-a-snapshot-and-viewing-processes>
+
+use crate::log_error;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{CloseHandle, HWND, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{SetTimer, LLMHF_INJECTED};
+
+/// Executable names (compared case-insensitively) of programs that emulate a
+/// mouse using a game controller.
+const KNOWN_EMULATORS: &[&[u8]] = &[
+    b"steam.exe",
+    b"JoyToKey.exe",
+    b"antimicrox.exe",
+    b"DS4Windows.exe",
+];
+
+/// How often the list of running processes is checked, in milliseconds.
+const REFRESH_INTERVAL: u32 = 10_000;
+
+/// Only exempt controller input if the user asked for it.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// `true` if a known emulator was running the last time we checked.
+static EMULATOR_RUNNING: AtomicBool = AtomicBool::new(false);
+
+/// The id of the timer that refreshes [`EMULATOR_RUNNING`], `0` if there is no
+/// timer.
+static TIMER: AtomicUsize = AtomicUsize::new(0);
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Relaxed)
+}
+
+/// Enable or disable the exemption. Call [`start`] afterwards if the program
+/// is already running.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Relaxed);
+}
+
+/// `true` if an event with the given `MSLLHOOKSTRUCT.flags` most likely came
+/// from a controller emulator and the exemption is enabled.
+#[inline(always)]
+pub fn is_controller_input(flags: u32) -> bool {
+    flags & LLMHF_INJECTED != 0 && is_enabled() && EMULATOR_RUNNING.load(Relaxed)
+}
+
+fn is_known_emulator(name: &[u16]) -> bool {
+    let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+    let name = &name[..len];
+    KNOWN_EMULATORS.iter().any(|known| {
+        known.len() == name.len()
+            && known
+                .iter()
+                .zip(name)
+                .all(|(&known, &c)| u8::try_from(c).is_ok_and(|c| c.eq_ignore_ascii_case(&known)))
+    })
+}
+
+/// Check if a known emulator is running.
+fn refresh() {
+    let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPPROCESS, 0) };
+    if snapshot == INVALID_HANDLE_VALUE {
+        log_error("Failed to list running processes");
+        return;
+    }
+    let mut found = false;
+    let mut entry: PROCESSENTRY32W = unsafe { core::mem::zeroed() };
+    entry.dwSize = core::mem::size_of::<PROCESSENTRY32W>() as u32;
+    let mut has_entry = unsafe { Process32FirstW(snapshot, &mut entry) } != 0;
+    while has_entry && !found {
+        found = is_known_emulator(&entry.szExeFile);
+        has_entry = unsafe { Process32NextW(snapshot, &mut entry) } != 0;
+    }
+    unsafe { CloseHandle(snapshot) };
+    EMULATOR_RUNNING.store(found, Relaxed);
+}
+
+unsafe extern "system" fn on_timer(_window: HWND, _message: u32, _id: usize, _time: u32) {
+    if is_enabled() {
+        refresh();
+    }
+}
+
+/// Start checking for controller emulators if the exemption is enabled. Must
+/// be called on the thread that runs the message loop.
+pub fn start() {
+    if !is_enabled() {
+        return;
+    }
+    refresh();
+    if TIMER.load(Relaxed) != 0 {
+        return;
+    }
+    let id = unsafe { SetTimer(ptr::null_mut(), 0, REFRESH_INTERVAL, Some(on_timer)) };
+    if id == 0 {
+        log_error("Failed to create timer for detecting controller emulators");
+        return;
+    }
+    TIMER.store(id, Relaxed);
+}
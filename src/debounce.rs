@@ -0,0 +1,730 @@
+//! Platform independent decision logic for which mouse events to block. Kept
+//! separate from the Windows hook so that it can be reused when simulating
+//! recorded event traces.
+
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering::Relaxed};
+
+macro_rules! all_variants {
+    ($($variant:ident),* $(,)?) => {{
+        _ = |__enum: Self| {
+            match __enum {
+                $(Self::$variant => {},)*
+            }
+        };
+        &[
+            $(Self::$variant,)*
+        ]
+    }};
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseDirection {
+    Up,
+    Down,
+}
+impl MouseDirection {
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub fn all() -> &'static [Self] {
+        all_variants![Up, Down]
+    }
+
+    /// Lowercase name, for example used in JSON output.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    /// The first extended button, usually "back" in browsers.
+    X1,
+    /// The second extended button, usually "forward" in browsers.
+    X2,
+}
+impl MouseButton {
+    /// The number of buttons, for arrays that are indexed by button.
+    pub const COUNT: usize = 5;
+
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub fn all() -> &'static [Self] {
+        all_variants![Left, Right, Middle, X1, X2]
+    }
+
+    /// Lowercase name, for example used in JSON output and config keys.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Left => "left",
+            Self::Right => "right",
+            Self::Middle => "middle",
+            Self::X1 => "x1",
+            Self::X2 => "x2",
+        }
+    }
+}
+
+/// Which kind of chatter is suppressed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[repr(u8)]
+pub enum SuppressionMode {
+    /// Block down and up events that happen too soon after previous events.
+    Both,
+    /// Only suppress chatter when a button is released, useful when a worn
+    /// switch releases unexpectedly while dragging. Up events are delayed by
+    /// the threshold and dropped (together with the following down event) if
+    /// the button is pressed again before the delay has passed. Down events
+    /// are never suppressed on their own.
+    UpOnly,
+    /// Like [`Both`](Self::Both) but down events that happen too soon after
+    /// previous events are delayed by the threshold instead of dropped. A
+    /// delayed down event is replayed unless another event of the button
+    /// happens before the delay has passed, in which case both are dropped.
+    Delay,
+}
+impl SuppressionMode {
+    pub const fn from_u8(value: u8) -> Self {
+        if value == Self::UpOnly as u8 {
+            Self::UpOnly
+        } else if value == Self::Delay as u8 {
+            Self::Delay
+        } else {
+            Self::Both
+        }
+    }
+}
+
+/// Settings that control which events are blocked for a single button.
+#[derive(Clone, Copy, Debug)]
+pub struct ButtonSettings {
+    /// Down events that happen faster than this many milliseconds after
+    /// previous events are suppressed.
+    pub threshold: u32,
+    /// Like `threshold` but for up events, since release bounce often needs a
+    /// different window than press bounce. In [`SuppressionMode::UpOnly`] up
+    /// events are delayed by this many milliseconds.
+    pub up_threshold: u32,
+    pub mode: SuppressionMode,
+    /// How long in milliseconds the button must be held for Windows' ClickLock
+    /// to lock it. `None` if ClickLock is disabled or doesn't apply to this
+    /// button.
+    pub click_lock_time: Option<u32>,
+    /// How many ticks there are in a millisecond, `1` if ticks are
+    /// milliseconds. Thresholds are always in milliseconds.
+    pub ticks_per_ms: u32,
+}
+impl ButtonSettings {
+    /// The threshold that applies to events in `direction`.
+    pub fn threshold_for(&self, direction: MouseDirection) -> u32 {
+        match direction {
+            MouseDirection::Down => self.threshold,
+            MouseDirection::Up => self.up_threshold,
+        }
+    }
+
+    /// Like [`threshold_for`](Self::threshold_for) but in ticks.
+    fn threshold_ticks(&self, direction: MouseDirection) -> u32 {
+        self.threshold_for(direction)
+            .saturating_mul(self.ticks_per_ms)
+    }
+
+    /// Convert ticks to whole milliseconds.
+    fn millis(&self, ticks: u32) -> u32 {
+        ticks / self.ticks_per_ms.max(1)
+    }
+}
+
+/// The outcome of [`ButtonState::on_event`].
+#[derive(Clone, Copy)]
+pub struct Decision {
+    pub blocked: bool,
+    /// The event is blocked for now but should be replayed once the threshold
+    /// has passed, see [`ButtonState::take_deferred`].
+    pub deferred: bool,
+    /// A previously deferred up event should be replayed right before this
+    /// event. The event itself is blocked so that it can be replayed after
+    /// the up event.
+    pub replay_deferred_up: bool,
+    /// Like `replay_deferred_up` but for a deferred down event.
+    pub replay_deferred_down: bool,
+    /// The event is an up event for a button that was already released,
+    /// which some failing switches report. Such events are always blocked.
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub double_up: bool,
+    /// Milliseconds since the previous accepted event that this event was
+    /// compared to.
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub time_since_last_event: u32,
+}
+
+impl Decision {
+    /// `true` if the event is blocked and won't be replayed later.
+    pub fn is_dropped(&self) -> bool {
+        self.blocked && !self.deferred && !self.replay_deferred_up && !self.replay_deferred_down
+    }
+}
+
+/// The outcome of [`ButtonState::take_deferred`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Deferred {
+    /// There is no deferred event.
+    None,
+    /// The deferred event should be replayed after this many more
+    /// milliseconds.
+    Wait(u32),
+    /// The deferred event should be replayed now.
+    Replay(MouseDirection),
+}
+
+/// Ticks from `since` to `tick`, saturated to fit in a `u32`. Ticks are 64
+/// bit so that they never wrap around like `GetTickCount` does every 49.7
+/// days.
+fn elapsed(tick: u64, since: u64) -> u32 {
+    u32::try_from(tick.saturating_sub(since)).unwrap_or(u32::MAX)
+}
+
+/// Remembers when the last accepted events happened for a single mouse
+/// button.
+pub struct ButtonState {
+    last_down: AtomicU64,
+    last_up: AtomicU64,
+    /// When the up event that is currently being delayed happened, only
+    /// meaningful if `has_deferred_up` is `true`.
+    deferred_up: AtomicU64,
+    has_deferred_up: AtomicBool,
+    /// When the down event that is currently being delayed happened, only
+    /// meaningful if `has_deferred_down` is `true`.
+    deferred_down: AtomicU64,
+    has_deferred_down: AtomicBool,
+    /// `true` if the last event that other programs saw for this button was
+    /// an up event. `false` when the state is unknown so that an up event is
+    /// never blocked just because the program started while the button was
+    /// held.
+    released: AtomicBool,
+}
+impl Default for ButtonState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl ButtonState {
+    pub const fn new() -> Self {
+        Self {
+            last_down: AtomicU64::new(0),
+            last_up: AtomicU64::new(0),
+            deferred_up: AtomicU64::new(0),
+            has_deferred_up: AtomicBool::new(false),
+            deferred_down: AtomicU64::new(0),
+            has_deferred_down: AtomicBool::new(false),
+            released: AtomicBool::new(false),
+        }
+    }
+
+    /// Decide if an event that happened at `tick` (in the unit given by
+    /// [`ButtonSettings::ticks_per_ms`]) should be blocked. Accepted events
+    /// are remembered so that later events are compared to them.
+    ///
+    /// A down event is blocked if it happens too soon after the previous
+    /// accepted down or up event while an up event is only compared to the
+    /// previous accepted up event.
+    ///
+    /// An up event is always blocked if the button was already released since
+    /// two up events without a down event in between is never legitimate,
+    /// unless debouncing is disabled by thresholds of `0`.
+    ///
+    /// See [`SuppressionMode::UpOnly`] and [`SuppressionMode::Delay`] for how
+    /// the other modes work.
+    #[inline(always)]
+    pub fn on_event(
+        &self,
+        direction: MouseDirection,
+        tick: u64,
+        settings: ButtonSettings,
+    ) -> Decision {
+        // Checked before anything else since the button isn't pressed as far
+        // as other programs know:
+        if self.has_deferred_down.load(Relaxed) {
+            return self.on_event_after_deferred_down(direction, tick, settings);
+        }
+        let debounced = settings.threshold > 0 || settings.up_threshold > 0;
+        if debounced && direction == MouseDirection::Up && self.released.load(Relaxed) {
+            return Decision {
+                blocked: true,
+                deferred: false,
+                replay_deferred_up: false,
+                replay_deferred_down: false,
+                double_up: true,
+                time_since_last_event: settings.millis(elapsed(tick, self.last_up.load(Relaxed))),
+            };
+        }
+        if settings.mode == SuppressionMode::UpOnly {
+            return self.on_event_up_only(direction, tick, settings);
+        }
+        let threshold = settings.threshold_ticks(direction);
+
+        let last = match direction {
+            MouseDirection::Down => self.last_down.load(Relaxed).max(self.last_up.load(Relaxed)),
+            MouseDirection::Up => self.last_up.load(Relaxed),
+        };
+        let since_last = elapsed(tick, last);
+        let time_since_last_event = settings.millis(since_last);
+
+        let blocked = since_last < threshold;
+        if blocked && direction == MouseDirection::Down && settings.mode == SuppressionMode::Delay {
+            self.deferred_down.store(tick, Relaxed);
+            self.has_deferred_down.store(true, Relaxed);
+            return Decision {
+                blocked: true,
+                deferred: true,
+                replay_deferred_up: false,
+                replay_deferred_down: false,
+                double_up: false,
+                time_since_last_event,
+            };
+        }
+        if !blocked {
+            match direction {
+                MouseDirection::Down => self.last_down.store(tick, Relaxed),
+                MouseDirection::Up => self.last_up.store(tick, Relaxed),
+            }
+            self.released
+                .store(direction == MouseDirection::Up, Relaxed);
+        }
+        Decision {
+            blocked,
+            deferred: false,
+            replay_deferred_up: false,
+            replay_deferred_down: false,
+            double_up: false,
+            time_since_last_event,
+        }
+    }
+
+    fn on_event_up_only(
+        &self,
+        direction: MouseDirection,
+        tick: u64,
+        settings: ButtonSettings,
+    ) -> Decision {
+        // Up events are delayed by the up threshold and a press within that
+        // time means the release was chatter:
+        let threshold = settings.threshold_ticks(MouseDirection::Up);
+        let accepted = |time_since_last_event| Decision {
+            blocked: false,
+            deferred: false,
+            replay_deferred_up: false,
+            replay_deferred_down: false,
+            double_up: false,
+            time_since_last_event,
+        };
+        match direction {
+            MouseDirection::Up => {
+                let held = elapsed(tick, self.last_down.load(Relaxed));
+                let time_since_last_event = settings.millis(held);
+                // Delaying the release makes the press seem longer to Windows,
+                // which must not turn a normal press into one that ClickLock
+                // locks:
+                let would_click_lock = settings.click_lock_time.is_some_and(|lock_time| {
+                    let lock_time = lock_time.saturating_mul(settings.ticks_per_ms);
+                    held < lock_time && held.saturating_add(threshold) >= lock_time
+                });
+                if threshold == 0 || would_click_lock {
+                    self.last_up.store(tick, Relaxed);
+                    self.released.store(true, Relaxed);
+                    return accepted(time_since_last_event);
+                }
+                self.deferred_up.store(tick, Relaxed);
+                self.has_deferred_up.store(true, Relaxed);
+                Decision {
+                    blocked: true,
+                    deferred: true,
+                    replay_deferred_up: false,
+                    replay_deferred_down: false,
+                    double_up: false,
+                    time_since_last_event,
+                }
+            }
+            MouseDirection::Down => {
+                let since_up = if self.has_deferred_up.load(Relaxed) {
+                    elapsed(tick, self.deferred_up.load(Relaxed))
+                } else {
+                    elapsed(tick, self.last_up.load(Relaxed))
+                };
+                let time_since_last_event = settings.millis(since_up);
+                if self.has_deferred_up.swap(false, Relaxed) {
+                    if since_up < threshold {
+                        // The release was chatter, drop it together with this
+                        // press so the button stays held:
+                        return Decision {
+                            blocked: true,
+                            deferred: false,
+                            replay_deferred_up: false,
+                            replay_deferred_down: false,
+                            double_up: false,
+                            time_since_last_event,
+                        };
+                    }
+                    // The deferred up event should already have been replayed
+                    // but its timer was late:
+                    self.last_up.store(self.deferred_up.load(Relaxed), Relaxed);
+                    self.last_down.store(tick, Relaxed);
+                    self.released.store(false, Relaxed);
+                    return Decision {
+                        blocked: true,
+                        deferred: false,
+                        replay_deferred_up: true,
+                        replay_deferred_down: false,
+                        double_up: false,
+                        time_since_last_event,
+                    };
+                }
+                self.last_down.store(tick, Relaxed);
+                self.released.store(false, Relaxed);
+                accepted(time_since_last_event)
+            }
+        }
+    }
+
+    fn on_event_after_deferred_down(
+        &self,
+        direction: MouseDirection,
+        tick: u64,
+        settings: ButtonSettings,
+    ) -> Decision {
+        self.has_deferred_down.store(false, Relaxed);
+        let deferred_down = self.deferred_down.load(Relaxed);
+        let since_down = elapsed(tick, deferred_down);
+        let time_since_last_event = settings.millis(since_down);
+        let decision = |replay_deferred_down| Decision {
+            blocked: true,
+            deferred: false,
+            replay_deferred_up: false,
+            replay_deferred_down,
+            double_up: false,
+            time_since_last_event,
+        };
+        if since_down < settings.threshold_ticks(MouseDirection::Down) {
+            // The press was chatter too, drop it together with this event:
+            return decision(false);
+        }
+        // The deferred down event should already have been replayed but its
+        // timer was late:
+        self.last_down.store(deferred_down, Relaxed);
+        match direction {
+            MouseDirection::Down => self.last_down.store(tick, Relaxed),
+            MouseDirection::Up => self.last_up.store(tick, Relaxed),
+        }
+        self.released
+            .store(direction == MouseDirection::Up, Relaxed);
+        decision(true)
+    }
+
+    /// Check if a deferred event should be replayed at `tick`. Once this
+    /// returns [`Deferred::Replay`] the event is no longer deferred.
+    pub fn take_deferred(&self, tick: u64, settings: ButtonSettings) -> Deferred {
+        let (direction, time, has_deferred) = if self.has_deferred_up.load(Relaxed) {
+            (MouseDirection::Up, &self.deferred_up, &self.has_deferred_up)
+        } else if self.has_deferred_down.load(Relaxed) {
+            (
+                MouseDirection::Down,
+                &self.deferred_down,
+                &self.has_deferred_down,
+            )
+        } else {
+            return Deferred::None;
+        };
+        let time = time.load(Relaxed);
+        let threshold = settings.threshold_ticks(direction);
+        let elapsed = elapsed(tick, time);
+        if elapsed < threshold {
+            return Deferred::Wait((threshold - elapsed).div_ceil(settings.ticks_per_ms.max(1)));
+        }
+        has_deferred.store(false, Relaxed);
+        match direction {
+            MouseDirection::Down => self.last_down.store(time, Relaxed),
+            MouseDirection::Up => self.last_up.store(time, Relaxed),
+        }
+        self.released
+            .store(direction == MouseDirection::Up, Relaxed);
+        Deferred::Replay(direction)
+    }
+
+    /// Forget when the previous accepted events happened so that the next
+    /// event isn't compared to them.
+    pub fn forget_times(&self) {
+        self.last_down.store(0, Relaxed);
+        self.last_up.store(0, Relaxed);
+    }
+
+    /// Remember an event that was let through without being debounced, for
+    /// example because of an exemption rule.
+    pub fn on_passed(&self, direction: MouseDirection) {
+        self.released
+            .store(direction == MouseDirection::Up, Relaxed);
+    }
+}
+
+/// Property based tests that feed random event sequences through
+/// [`ButtonState`] and check invariants of the events that other programs
+/// would see.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    const ALL_MODES: [SuppressionMode; 3] = [
+        SuppressionMode::Both,
+        SuppressionMode::UpOnly,
+        SuppressionMode::Delay,
+    ];
+
+    fn opposite(direction: MouseDirection) -> MouseDirection {
+        match direction {
+            MouseDirection::Down => MouseDirection::Up,
+            MouseDirection::Up => MouseDirection::Down,
+        }
+    }
+
+    /// A random sequence of events from a chattering switch: presses and
+    /// releases alternate but each can be followed by bounces of either
+    /// direction shortly after.
+    fn chattering_events() -> impl Strategy<Value = Vec<(MouseDirection, u32)>> {
+        // Every bounce has a delay and is in the same direction as the event
+        // it follows one time in four:
+        let bounce = (0..40_u32, prop::bool::weighted(0.25));
+        let press = (prop::collection::vec(bounce, 0..4), 0..300_u32);
+        (0..1000_u32, prop::collection::vec(press, 0..40)).prop_map(|(start, presses)| {
+            let mut events = Vec::new();
+            let mut tick = 100_000 + start;
+            let mut direction = MouseDirection::Down;
+            for (bounces, pause) in presses {
+                events.push((direction, tick));
+                for (delay, same_direction) in bounces {
+                    tick += delay;
+                    let bounce = if same_direction {
+                        direction
+                    } else {
+                        opposite(direction)
+                    };
+                    events.push((bounce, tick));
+                }
+                tick += pause;
+                direction = opposite(direction);
+            }
+            events
+        })
+    }
+
+    fn any_settings(modes: &'static [SuppressionMode]) -> impl Strategy<Value = ButtonSettings> {
+        (0..100_u32, 0..100_u32, prop::sample::select(modes)).prop_map(
+            |(threshold, up_threshold, mode)| ButtonSettings {
+                threshold,
+                up_threshold,
+                mode,
+                click_lock_time: None,
+                ticks_per_ms: 1,
+            },
+        )
+    }
+
+    /// Run events through a [`ButtonState`] the same way as the mouse hook
+    /// does, including replaying deferred events when their timer fires,
+    /// and return the events that other programs would see.
+    fn delivered_events(
+        events: &[(MouseDirection, u32)],
+        settings: ButtonSettings,
+    ) -> Vec<(MouseDirection, u32)> {
+        let state = ButtonState::new();
+        let mut delivered = Vec::new();
+        let mut timer: Option<u32> = None;
+        let fire_timer = |due: u32, delivered: &mut Vec<_>, timer: &mut Option<u32>| {
+            *timer = None;
+            match state.take_deferred(u64::from(due), settings) {
+                Deferred::None => {}
+                Deferred::Wait(remaining) => *timer = Some(due + remaining),
+                Deferred::Replay(direction) => delivered.push((direction, due)),
+            }
+        };
+        for &(direction, tick) in events {
+            while let Some(due) = timer.filter(|&due| due <= tick) {
+                fire_timer(due, &mut delivered, &mut timer);
+            }
+            let decision = state.on_event(direction, u64::from(tick), settings);
+            if decision.deferred {
+                timer = Some(tick + settings.threshold_for(direction));
+            } else if decision.replay_deferred_up {
+                delivered.push((MouseDirection::Up, tick));
+                delivered.push((direction, tick));
+            } else if decision.replay_deferred_down {
+                delivered.push((MouseDirection::Down, tick));
+                delivered.push((direction, tick));
+            } else if !decision.blocked {
+                delivered.push((direction, tick));
+            }
+        }
+        while let Some(due) = timer {
+            fire_timer(due, &mut delivered, &mut timer);
+        }
+        delivered
+    }
+
+    #[test]
+    fn delay_mode_replays_press_unless_chatter_follows() {
+        let settings = ButtonSettings {
+            threshold: 30,
+            up_threshold: 30,
+            mode: SuppressionMode::Delay,
+            click_lock_time: None,
+            ticks_per_ms: 1,
+        };
+        use MouseDirection::{Down, Up};
+        // A fast double-click is delivered with the second press delayed:
+        let double_click = [(Down, 1000), (Up, 1050), (Down, 1070), (Up, 1150)];
+        assert_eq!(
+            delivered_events(&double_click, settings),
+            [(Down, 1000), (Up, 1050), (Down, 1100), (Up, 1150)]
+        );
+        // A bounce is a press that is released again right away:
+        let bounce = [(Down, 1000), (Up, 1050), (Down, 1055), (Up, 1060)];
+        assert_eq!(
+            delivered_events(&bounce, settings),
+            [(Down, 1000), (Up, 1050)]
+        );
+    }
+
+    #[test]
+    fn chatter_is_blocked_across_tick_count_wrap() {
+        let settings = ButtonSettings {
+            threshold: 30,
+            up_threshold: 30,
+            mode: SuppressionMode::Both,
+            click_lock_time: None,
+            ticks_per_ms: 1,
+        };
+        // `GetTickCount` would wrap around to 0 here:
+        let wrap = 1_u64 << 32;
+        let state = ButtonState::new();
+        assert!(
+            !state
+                .on_event(MouseDirection::Down, wrap - 100, settings)
+                .blocked
+        );
+        assert!(
+            !state
+                .on_event(MouseDirection::Up, wrap - 20, settings)
+                .blocked
+        );
+        let bounce = state.on_event(MouseDirection::Down, wrap + 5, settings);
+        assert!(bounce.blocked);
+        assert_eq!(bounce.time_since_last_event, 25);
+        assert!(
+            !state
+                .on_event(MouseDirection::Down, wrap + 10, settings)
+                .blocked
+        );
+    }
+
+    #[test]
+    fn repeated_ups_pass_when_thresholds_are_zero() {
+        let settings = ButtonSettings {
+            threshold: 0,
+            up_threshold: 0,
+            mode: SuppressionMode::Both,
+            click_lock_time: None,
+            ticks_per_ms: 1,
+        };
+        let state = ButtonState::new();
+        for (direction, tick) in [
+            (MouseDirection::Down, 1000),
+            (MouseDirection::Up, 1050),
+            (MouseDirection::Up, 1060),
+        ] {
+            let decision = state.on_event(direction, tick, settings);
+            assert!(!decision.blocked && !decision.double_up);
+        }
+    }
+
+    #[test]
+    fn thresholds_apply_to_microsecond_ticks() {
+        let settings = ButtonSettings {
+            threshold: 10,
+            up_threshold: 10,
+            mode: SuppressionMode::Both,
+            click_lock_time: None,
+            ticks_per_ms: 1000,
+        };
+        let state = ButtonState::new();
+        assert!(
+            !state
+                .on_event(MouseDirection::Down, 100_000, settings)
+                .blocked
+        );
+        assert!(
+            !state
+                .on_event(MouseDirection::Up, 102_000, settings)
+                .blocked
+        );
+        // Would be 10 ms apart with millisecond ticks:
+        let bounce = state.on_event(MouseDirection::Down, 111_500, settings);
+        assert!(bounce.blocked);
+        assert_eq!(bounce.time_since_last_event, 9);
+        assert!(
+            !state
+                .on_event(MouseDirection::Down, 112_500, settings)
+                .blocked
+        );
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(2000))]
+
+        /// Only applies when down chatter is suppressed, the up only mode never
+        /// blocks down events on their own.
+        #[test]
+        fn accepted_downs_are_never_within_threshold(
+            events in chattering_events(),
+            settings in any_settings(&[SuppressionMode::Both]),
+        ) {
+            let downs = delivered_events(&events, settings)
+                .into_iter()
+                .filter(|&(direction, _)| direction == MouseDirection::Down)
+                .map(|(_, tick)| tick)
+                .collect::<Vec<_>>();
+            for pair in downs.windows(2) {
+                prop_assert!(pair[1] - pair[0] >= settings.threshold, "downs at {pair:?}");
+            }
+        }
+
+        #[test]
+        fn accepted_ups_are_never_repeated(
+            events in chattering_events(),
+            settings in any_settings(&ALL_MODES),
+        ) {
+            // Nothing is debounced with thresholds of 0:
+            prop_assume!(settings.threshold > 0 || settings.up_threshold > 0);
+            let delivered = delivered_events(&events, settings);
+            for pair in delivered.windows(2) {
+                prop_assert!(
+                    pair[0].0 != MouseDirection::Up || pair[1].0 != MouseDirection::Up,
+                    "ups at {pair:?}"
+                );
+            }
+        }
+
+        #[test]
+        fn every_accepted_down_is_released(
+            mut events in chattering_events(),
+            settings in any_settings(&ALL_MODES),
+        ) {
+            // Release the button long after the last event:
+            let last = events.last().map_or(100_000, |&(_, tick)| tick);
+            events.push((MouseDirection::Up, last + 10_000));
+            let last_delivered = delivered_events(&events, settings).last().copied();
+            prop_assert!(last_delivered.is_none_or(|(direction, _)| direction == MouseDirection::Up));
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Replays mouse events that were blocked by the hook but should be delivered
+//! later, see [`SuppressionMode::UpOnly`](crate::debounce::SuppressionMode)
+//! and [`SuppressionMode::Delay`](crate::debounce::SuppressionMode).
+//!
+//! Replayed events are injected using `SendInput` and are marked so that the
+//! hook lets them through without applying the debounce logic again.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-settimer>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-sendinput>
+
+use crate::debounce::{Deferred, MouseButton, MouseDirection};
+use crate::log_error;
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{HWND, POINT};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_MOUSE, MOUSEEVENTF_ABSOLUTE, MOUSEEVENTF_LEFTDOWN,
+    MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN, MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE,
+    MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP, MOUSEEVENTF_VIRTUALDESK, MOUSEEVENTF_XDOWN,
+    MOUSEEVENTF_XUP, MOUSEINPUT,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    GetCursorPos, GetSystemMetrics, KillTimer, SetTimer, SM_CXVIRTUALSCREEN, SM_CYVIRTUALSCREEN,
+    SM_XVIRTUALSCREEN, SM_YVIRTUALSCREEN, XBUTTON1, XBUTTON2,
+};
+
+/// Stored in `dwExtraInfo` of events that we inject ourselves.
+pub const REPLAY_MARKER: usize = 0x434C_4B31; // "CLK1"
+
+/// The id of the timer that replays a button's deferred event, `0` if there is
+/// no timer.
+static TIMERS: [AtomicUsize; MouseButton::COUNT] =
+    [const { AtomicUsize::new(0) }; MouseButton::COUNT];
+
+const fn mouse_input(flags: u32, dx: i32, dy: i32) -> INPUT {
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx,
+                dy,
+                mouseData: 0,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: REPLAY_MARKER,
+            },
+        },
+    }
+}
+
+fn button_input(button: MouseButton, direction: MouseDirection) -> INPUT {
+    let flags = match (button, direction) {
+        (MouseButton::Left, MouseDirection::Down) => MOUSEEVENTF_LEFTDOWN,
+        (MouseButton::Left, MouseDirection::Up) => MOUSEEVENTF_LEFTUP,
+        (MouseButton::Right, MouseDirection::Down) => MOUSEEVENTF_RIGHTDOWN,
+        (MouseButton::Right, MouseDirection::Up) => MOUSEEVENTF_RIGHTUP,
+        (MouseButton::Middle, MouseDirection::Down) => MOUSEEVENTF_MIDDLEDOWN,
+        (MouseButton::Middle, MouseDirection::Up) => MOUSEEVENTF_MIDDLEUP,
+        (MouseButton::X1 | MouseButton::X2, MouseDirection::Down) => MOUSEEVENTF_XDOWN,
+        (MouseButton::X1 | MouseButton::X2, MouseDirection::Up) => MOUSEEVENTF_XUP,
+    };
+    let mut input = mouse_input(flags, 0, 0);
+    // Extended buttons are identified by `mouseData`:
+    input.Anonymous.mi.mouseData = match button {
+        MouseButton::X1 => u32::from(XBUTTON1),
+        MouseButton::X2 => u32::from(XBUTTON2),
+        _ => 0,
+    };
+    input
+}
+
+/// An event that moves the cursor to a position on the virtual screen.
+fn move_input(x: i32, y: i32) -> INPUT {
+    // Absolute coordinates are normalized to 0..=65535 across the virtual
+    // screen:
+    let normalize = |value: i32, origin: i32, size: i32| {
+        (i64::from(value - origin) * 65535 / i64::from((size - 1).max(1))) as i32
+    };
+    let (dx, dy) = unsafe {
+        (
+            normalize(
+                x,
+                GetSystemMetrics(SM_XVIRTUALSCREEN),
+                GetSystemMetrics(SM_CXVIRTUALSCREEN),
+            ),
+            normalize(
+                y,
+                GetSystemMetrics(SM_YVIRTUALSCREEN),
+                GetSystemMetrics(SM_CYVIRTUALSCREEN),
+            ),
+        )
+    };
+    mouse_input(
+        MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE | MOUSEEVENTF_VIRTUALDESK,
+        dx,
+        dy,
+    )
+}
+
+fn send(inputs: &[INPUT]) {
+    let sent = unsafe {
+        SendInput(
+            inputs.len() as u32,
+            inputs.as_ptr(),
+            core::mem::size_of::<INPUT>() as i32,
+        )
+    };
+    if sent as usize != inputs.len() {
+        log_error("Failed to inject mouse events");
+    }
+}
+
+/// Inject mouse button events, in order. At most 4 events can be injected at
+/// once.
+pub fn inject(events: &[(MouseButton, MouseDirection)]) {
+    let mut inputs = [const { mouse_input(0, 0, 0) }; 4];
+    let inputs = &mut inputs[..events.len().min(4)];
+    for (input, &(button, direction)) in inputs.iter_mut().zip(events) {
+        *input = button_input(button, direction);
+    }
+    send(inputs);
+}
+
+/// Inject a mouse move that doesn't move the cursor, to check that the mouse
+/// hook is still called.
+pub fn inject_heartbeat() {
+    send(&[mouse_input(MOUSEEVENTF_MOVE, 0, 0)]);
+}
+
+/// Inject a click at a position on the screen and then move the cursor back to
+/// where it was. The moves are injected together with the click so that they
+/// can't be reordered with other input.
+pub fn inject_click_at(button: MouseButton, (x, y): (i32, i32)) {
+    let mut cursor = POINT { x, y };
+    unsafe { GetCursorPos(&mut cursor) };
+    send(&[
+        move_input(x, y),
+        button_input(button, MouseDirection::Down),
+        button_input(button, MouseDirection::Up),
+        move_input(cursor.x, cursor.y),
+    ]);
+}
+
+unsafe extern "system" fn on_timer(_window: HWND, _message: u32, id: usize, _time: u32) {
+    KillTimer(ptr::null_mut(), id);
+    let Some(button) = MouseButton::all()
+        .iter()
+        .copied()
+        .find(|&button| TIMERS[button as usize].load(Relaxed) == id)
+    else {
+        return;
+    };
+    TIMERS[button as usize].store(0, Relaxed);
+
+    let settings = crate::button_settings(&crate::system_api::System, button);
+    let tick = crate::current_tick(&crate::system_api::System);
+    match crate::BUTTONS[button as usize].take_deferred(tick, settings) {
+        Deferred::None => {}
+        Deferred::Wait(remaining) => schedule_replay(button, remaining),
+        Deferred::Replay(direction) => inject(&[(button, direction)]),
+    }
+}
+
+/// Replay a button's deferred event after `delay` milliseconds unless it is
+/// dropped before then. Must be called on the thread that runs the message
+/// loop.
+pub fn schedule_replay(button: MouseButton, delay: u32) {
+    // Passing the id of an existing timer replaces it:
+    let existing = TIMERS[button as usize].load(Relaxed);
+    let id = unsafe { SetTimer(ptr::null_mut(), existing, delay, Some(on_timer)) };
+    if id == 0 {
+        log_error("Failed to create timer for delayed mouse event");
+        // Better to replay the event immediately than to leave the button
+        // held or lose the press:
+        if let Some(button_state) = crate::BUTTONS.get(button as usize) {
+            let settings = crate::button_settings(&crate::system_api::System, button);
+            if let Deferred::Replay(direction) = button_state.take_deferred(u64::MAX, settings) {
+                inject(&[(button, direction)]);
+            }
+        }
+        return;
+    }
+    TIMERS[button as usize].store(id, Relaxed);
+}
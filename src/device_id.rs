@@ -0,0 +1,154 @@
+//! Parses USB and Bluetooth vendor and product ids, such as `046D:C077`, from
+//! settings and from Windows device names so that devices can be allowed or
+//! denied by model instead of by their full name, which changes when a mouse
+//! is plugged into another port.
+
+/// A HID device's vendor and product id.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DeviceId {
+    pub vendor: u16,
+    pub product: u16,
+}
+impl DeviceId {
+    /// Store the id in a single integer with the vendor in the high 16 bits.
+    pub const fn pack(self) -> u32 {
+        (self.vendor as u32) << 16 | self.product as u32
+    }
+
+    pub const fn unpack(packed: u32) -> Self {
+        Self {
+            vendor: (packed >> 16) as u16,
+            product: packed as u16,
+        }
+    }
+
+    /// Find the ids in a device name such as
+    /// `\\?\HID#VID_046D&PID_C077#7&1234&0&0000#{...}`. Bluetooth devices use
+    /// `VID&0002046D_PID&C077` where the first 4 digits are the id's source.
+    /// Returns `None` for devices without ids, such as Remote Desktop's mouse.
+    pub fn from_device_name(name: &[u8]) -> Option<Self> {
+        let vendor = hex_after(name, b"VID_", 4).or_else(|| hex_after(name, b"VID&", 8))?;
+        let product = hex_after(name, b"PID_", 4).or_else(|| hex_after(name, b"PID&", 4))?;
+        Some(Self {
+            vendor: vendor as u16,
+            product: product as u16,
+        })
+    }
+}
+
+/// Why a device id couldn't be parsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseDeviceIdError(&'static str);
+impl core::fmt::Display for ParseDeviceIdError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Parse `digits` hexadecimal digits that follow the first case insensitive
+/// occurrence of `prefix`.
+fn hex_after(text: &[u8], prefix: &[u8], digits: usize) -> Option<u32> {
+    let start = text
+        .windows(prefix.len())
+        .position(|part| part.eq_ignore_ascii_case(prefix))?
+        + prefix.len();
+    parse_hex(text.get(start..start + digits)?)
+}
+
+fn parse_hex(digits: &[u8]) -> Option<u32> {
+    if digits.is_empty() || digits.len() > 8 {
+        return None;
+    }
+    digits.iter().try_fold(0, |value, &digit| {
+        Some(value << 4 | (digit as char).to_digit(16)?)
+    })
+}
+
+/// Parse a device id written as `046D:C077` or `VID_046D&PID_C077`.
+pub fn parse(text: &str) -> Result<DeviceId, ParseDeviceIdError> {
+    let text = text.trim();
+    if let Some((vendor, product)) = text.split_once(':') {
+        let parse_part = |part: &str| {
+            let part = part.trim();
+            if part.len() > 4 {
+                return None;
+            }
+            parse_hex(part.as_bytes()).map(|value| value as u16)
+        };
+        return match (parse_part(vendor), parse_part(product)) {
+            (Some(vendor), Some(product)) => Ok(DeviceId { vendor, product }),
+            _ => Err(ParseDeviceIdError(
+                "expected a hexadecimal vendor and product id such as 046D:C077",
+            )),
+        };
+    }
+    DeviceId::from_device_name(text.as_bytes()).ok_or(ParseDeviceIdError(
+        "expected a device id such as 046D:C077 or VID_046D&PID_C077",
+    ))
+}
+
+/// Parse a comma separated list of device ids into `ids`. Returns the number
+/// of ids that were parsed.
+pub fn parse_list(text: &str, ids: &mut [DeviceId]) -> Result<usize, ParseDeviceIdError> {
+    let mut len = 0;
+    for item in text.split(',').filter(|item| !item.trim().is_empty()) {
+        let slot = ids
+            .get_mut(len)
+            .ok_or(ParseDeviceIdError("the list has too many devices"))?;
+        *slot = parse(item)?;
+        len += 1;
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MOUSE: DeviceId = DeviceId {
+        vendor: 0x046D,
+        product: 0xC077,
+    };
+
+    #[test]
+    fn finds_ids_in_device_names() {
+        assert_eq!(
+            DeviceId::from_device_name(b"\\\\?\\HID#VID_046D&PID_C077#7&1234&0&0000#{378de44c}"),
+            Some(MOUSE)
+        );
+        assert_eq!(
+            DeviceId::from_device_name(
+                b"\\\\?\\HID#{00001124-0000-1000-8000-00805f9b34fb}_VID&0002046d_PID&c077&Col01#9"
+            ),
+            Some(MOUSE)
+        );
+        assert_eq!(
+            DeviceId::from_device_name(b"\\\\?\\Root#RDP_MOU#0000#{378de44c}"),
+            None
+        );
+    }
+
+    #[test]
+    fn parses_lists() {
+        let mut ids = [MOUSE; 2];
+        assert_eq!(parse_list("", &mut ids), Ok(0));
+        assert_eq!(parse_list("056a:0357, VID_046D&PID_C077", &mut ids), Ok(2));
+        assert_eq!(
+            ids,
+            [
+                DeviceId {
+                    vendor: 0x056A,
+                    product: 0x0357
+                },
+                MOUSE
+            ]
+        );
+        assert_eq!(DeviceId::unpack(MOUSE.pack()), MOUSE);
+        for text in ["046D", "046D:", "046DX:C077", "12345:1", "1:2,3:4,5:6"] {
+            assert!(
+                parse_list(text, &mut ids).is_err(),
+                "{text:?} should be rejected"
+            );
+        }
+    }
+}
@@ -0,0 +1,540 @@
+//! Implements the `doctor` command that checks for common problems and prints
+//! actionable findings to the console.
+
+use crate::logging::{log_array, utf16_to_ascii, FgColor, LogValue};
+use crate::{conflicts, log};
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{
+    CloseHandle, BOOL, ERROR_NO_MORE_ITEMS, ERROR_SUCCESS, HANDLE, HWND, LPARAM, LRESULT, WPARAM,
+};
+use windows_sys::Win32::Security::{
+    GetTokenInformation, TokenElevation, TOKEN_ELEVATION, TOKEN_QUERY,
+};
+use windows_sys::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows_sys::Win32::System::Registry::{
+    RegCloseKey, RegEnumValueW, RegOpenKeyExW, HKEY, HKEY_CURRENT_USER, HKEY_LOCAL_MACHINE,
+    KEY_READ,
+};
+use windows_sys::Win32::System::SystemInformation::{GetSystemTimeAdjustment, GetTickCount};
+use windows_sys::Win32::System::Threading::{
+    GetCurrentProcess, OpenProcess, OpenProcessToken, QueryFullProcessImageNameW,
+    PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, EnumWindows, GetMessageW, GetWindowThreadProcessId, IsWindowVisible, KillTimer,
+    SetTimer, SetWindowsHookExW, UnhookWindowsHookEx, MSLLHOOKSTRUCT, WH_MOUSE_LL, WM_MOUSEMOVE,
+    WM_TIMER,
+};
+
+#[derive(Clone, Copy)]
+enum Severity {
+    Ok,
+    Info,
+    Warning,
+}
+
+fn report<'a>(severity: Severity, message: impl IntoIterator<Item = LogValue<'a>>) {
+    let _output = crate::logging::lock_output();
+    match severity {
+        Severity::Ok => log![FgColor::Green, b"[OK]      ", FgColor::Reset],
+        Severity::Info => log![FgColor::Cyan, b"[INFO]    ", FgColor::Reset],
+        Severity::Warning => log![FgColor::Yellow, b"[WARNING] ", FgColor::Reset],
+    }
+    message.into_iter().for_each(|value| value.write());
+    log![b"\r\n"];
+}
+
+/// Check if a process is running with elevated privileges. `None` if that
+/// couldn't be determined.
+fn is_process_elevated(process: HANDLE) -> Option<bool> {
+    let mut token: HANDLE = ptr::null_mut();
+    if unsafe { OpenProcessToken(process, TOKEN_QUERY, &mut token) } == 0 {
+        return None;
+    }
+    let mut elevation = TOKEN_ELEVATION { TokenIsElevated: 0 };
+    let mut size = 0;
+    let result = unsafe {
+        GetTokenInformation(
+            token,
+            TokenElevation,
+            (&mut elevation as *mut TOKEN_ELEVATION).cast(),
+            core::mem::size_of::<TOKEN_ELEVATION>() as u32,
+            &mut size,
+        )
+    };
+    unsafe { CloseHandle(token) };
+    (result != 0).then_some(elevation.TokenIsElevated != 0)
+}
+
+fn check_hook_install() {
+    unsafe extern "system" fn pass_through(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+    }
+
+    // The hook is only called while messages are processed so it is safe to
+    // install it temporarily:
+    let hook = unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(pass_through), ptr::null_mut(), 0) };
+    if hook.is_null() {
+        report(
+            Severity::Warning,
+            log_array![
+                b"Failed to install a low level mouse hook, security software might be blocking click-once"
+            ],
+        );
+    } else {
+        unsafe { UnhookWindowsHookEx(hook) };
+        report(
+            Severity::Ok,
+            log_array![b"A low level mouse hook can be installed"],
+        );
+    }
+}
+
+fn check_conflicting_software() {
+    let mut found_any = false;
+    conflicts::find_conflicting_software(|name, pid| {
+        found_any = true;
+        report(
+            Severity::Warning,
+            log_array![
+                b"\"",
+                name,
+                b"\" (PID ",
+                pid,
+                b") also filters mouse clicks, close it or it will interfere with click-once"
+            ],
+        );
+    });
+    if !found_any {
+        report(
+            Severity::Ok,
+            log_array![b"No other known click debouncing software is running"],
+        );
+    }
+}
+
+/// The resolution of `GetTickCount` limits how precise the thresholds are.
+fn check_timer_resolution() {
+    let mut adjustment = 0;
+    let mut increment = 0;
+    let mut disabled: BOOL = 0;
+    if unsafe { GetSystemTimeAdjustment(&mut adjustment, &mut increment, &mut disabled) } == 0 {
+        report(
+            Severity::Warning,
+            log_array![b"Failed to get the system timer interval"],
+        );
+        return;
+    }
+    // The increment is in 100 nanosecond units:
+    let interval_ms = increment / 10_000;
+    let smallest_threshold = [
+        crate::THRESHOLD_LM.load(Relaxed),
+        crate::THRESHOLD_RM.load(Relaxed),
+        crate::THRESHOLD_MM.load(Relaxed),
+        crate::THRESHOLD_X1.load(Relaxed),
+        crate::THRESHOLD_X2.load(Relaxed),
+    ]
+    .into_iter()
+    .filter(|&threshold| threshold != 0)
+    .min();
+    let interval = log_array![
+        b"System timer interval is ",
+        FgColor::TIME,
+        interval_ms,
+        b".",
+        (increment / 1_000) % 10,
+        b" ms",
+        FgColor::Reset,
+    ];
+    match smallest_threshold {
+        Some(threshold) if threshold < interval_ms * 2 => report(
+            Severity::Warning,
+            interval.into_iter().chain(log_array![
+                b", thresholds below ",
+                interval_ms * 2,
+                b" ms (such as ",
+                threshold,
+                b" ms) can't be measured precisely"
+            ]),
+        ),
+        _ => report(Severity::Ok, interval),
+    }
+}
+
+/// Observed behavior of a clock while sampling it repeatedly. Uses atomics so
+/// that it can be updated from a hook callback.
+struct ClockSamples {
+    samples: AtomicU32,
+    /// How many times the clock went backwards.
+    backwards: AtomicU32,
+    /// Smallest and largest change between two samples that weren't equal.
+    smallest_step: AtomicU32,
+    largest_step: AtomicU32,
+}
+impl ClockSamples {
+    const fn new() -> Self {
+        Self {
+            samples: AtomicU32::new(0),
+            backwards: AtomicU32::new(0),
+            smallest_step: AtomicU32::new(u32::MAX),
+            largest_step: AtomicU32::new(0),
+        }
+    }
+    fn add(&self, previous: u64, current: u64) {
+        _ = self.samples.fetch_add(1, Relaxed);
+        if current < previous {
+            _ = self.backwards.fetch_add(1, Relaxed);
+        } else if current != previous {
+            let step = u32::try_from(current - previous).unwrap_or(u32::MAX);
+            _ = self.smallest_step.fetch_min(step, Relaxed);
+            _ = self.largest_step.fetch_max(step, Relaxed);
+        }
+    }
+    fn report(&self, name: &[u8], unit: &[u8]) {
+        let backwards = self.backwards.load(Relaxed);
+        let largest_step = self.largest_step.load(Relaxed);
+        let steps = log_array![
+            name,
+            b" updates in steps of ",
+            FgColor::TIME,
+            self.smallest_step.load(Relaxed),
+            b"-",
+            largest_step,
+            unit,
+            FgColor::Reset,
+        ];
+        if backwards > 0 {
+            report(
+                Severity::Warning,
+                steps
+                    .into_iter()
+                    .chain(log_array![b" but went backwards ", backwards, b" times"]),
+            );
+        } else if largest_step == 0 {
+            report(
+                Severity::Warning,
+                log_array![name, b" never changed while it was measured"],
+            );
+        } else {
+            report(
+                Severity::Ok,
+                steps
+                    .into_iter()
+                    .chain(log_array![b" and never went backwards"]),
+            );
+        }
+    }
+}
+
+/// Compare the clocks that the program could use to measure time between
+/// clicks: `GetTickCount`, the timestamps of mouse events (`MSLLHOOKSTRUCT.time`)
+/// and `QueryPerformanceCounter`.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/sysinfo/acquiring-high-resolution-time-stamps>
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-msllhookstruct>
+fn check_timing_sources() {
+    const SAMPLE_MS: i64 = 200;
+    const MOUSE_SAMPLE_MS: u32 = 3000;
+
+    let mut frequency = 0;
+    unsafe { QueryPerformanceFrequency(&mut frequency) };
+    if frequency <= 0 {
+        report(
+            Severity::Warning,
+            log_array![b"QueryPerformanceCounter is not available"],
+        );
+        return;
+    }
+    let qpc = || {
+        let mut now = 0;
+        unsafe { QueryPerformanceCounter(&mut now) };
+        now
+    };
+
+    // Busy wait while sampling both clocks:
+    let tick_samples = ClockSamples::new();
+    let qpc_samples = ClockSamples::new();
+    let start = qpc();
+    let mut previous_tick = unsafe { GetTickCount() };
+    let mut previous_qpc = start;
+    while previous_qpc - start < frequency * SAMPLE_MS / 1000 {
+        let tick = unsafe { GetTickCount() };
+        let now = qpc();
+        tick_samples.add(previous_tick.into(), tick.into());
+        // Convert to nanoseconds:
+        qpc_samples.add(
+            (previous_qpc as u128 * 1_000_000_000 / frequency as u128) as u64,
+            (now as u128 * 1_000_000_000 / frequency as u128) as u64,
+        );
+        previous_tick = tick;
+        previous_qpc = now;
+    }
+    tick_samples.report(b"GetTickCount", b" ms");
+    qpc_samples.report(b"QueryPerformanceCounter", b" ns");
+
+    // Mouse event timestamps need mouse input, sample them using a temporary
+    // hook:
+    static HOOK_SAMPLES: ClockSamples = ClockSamples::new();
+    static PREVIOUS_TIME: AtomicU32 = AtomicU32::new(0);
+    static MAX_DELAY: AtomicU32 = AtomicU32::new(0);
+    unsafe extern "system" fn sample_hook(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+        if code >= 0 && wparam as u32 == WM_MOUSEMOVE {
+            let info = &*(lparam as *const MSLLHOOKSTRUCT);
+            let previous = PREVIOUS_TIME.swap(info.time, Relaxed);
+            if previous != 0 {
+                HOOK_SAMPLES.add(previous.into(), info.time.into());
+            }
+            let delay = GetTickCount().wrapping_sub(info.time);
+            // Timestamps from the future would wrap around to huge values:
+            if delay <= i32::MAX as u32 {
+                _ = MAX_DELAY.fetch_max(delay, Relaxed);
+            }
+        }
+        CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
+    }
+    log![
+        b"          Move the mouse for ",
+        MOUSE_SAMPLE_MS / 1000,
+        b" seconds to measure mouse event timestamps...\r\n"
+    ];
+    let hook = unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(sample_hook), ptr::null_mut(), 0) };
+    if !hook.is_null() {
+        let timer = unsafe { SetTimer(ptr::null_mut(), 0, MOUSE_SAMPLE_MS, None) };
+        let mut message = unsafe { core::mem::zeroed() };
+        // The hook is called while waiting for messages:
+        while timer != 0 && unsafe { GetMessageW(&mut message, ptr::null_mut(), 0, 0) } > 0 {
+            if message.message == WM_TIMER {
+                break;
+            }
+        }
+        unsafe {
+            KillTimer(ptr::null_mut(), timer);
+            UnhookWindowsHookEx(hook);
+        }
+    }
+    if HOOK_SAMPLES.samples.load(Relaxed) == 0 {
+        report(
+            Severity::Info,
+            log_array![b"No mouse movement, mouse event timestamps were not measured"],
+        );
+    } else {
+        HOOK_SAMPLES.report(b"Mouse event timestamps", b" ms");
+        report(
+            Severity::Info,
+            log_array![
+                b"Mouse events were delivered at most ",
+                FgColor::TIME,
+                MAX_DELAY.load(Relaxed),
+                b" ms",
+                FgColor::Reset,
+                b" after their timestamp"
+            ],
+        );
+    }
+
+    report(
+        Severity::Info,
+        log_array![if crate::PRECISE_TIMING.load(Relaxed) {
+            b"Thresholds are measured using QueryPerformanceCounter because of the \
+                --precise-timing argument"
+                .as_slice()
+        } else {
+            b"Thresholds are measured using GetTickCount since it is cheap to read in the mouse \
+                hook and its resolution is good enough for thresholds of a few tens of milliseconds, \
+                use --precise-timing for smaller thresholds"
+        }],
+    );
+}
+
+/// Low level hooks don't see input to elevated windows unless the program
+/// that installed them is also elevated.
+fn check_elevation() {
+    let self_elevated = is_process_elevated(unsafe { GetCurrentProcess() }).unwrap_or(false);
+    if self_elevated {
+        report(
+            Severity::Ok,
+            log_array![b"click-once is elevated and can filter clicks in all windows"],
+        );
+        return;
+    }
+
+    struct State {
+        seen: [u32; 64],
+        seen_len: usize,
+        found_any: bool,
+    }
+    unsafe extern "system" fn visit_window(window: HWND, state: LPARAM) -> BOOL {
+        let state = &mut *(state as *mut State);
+        if IsWindowVisible(window) == 0 {
+            return 1;
+        }
+        let mut pid = 0;
+        GetWindowThreadProcessId(window, &mut pid);
+        if state.seen[..state.seen_len].contains(&pid) {
+            return 1;
+        }
+        if state.seen_len < state.seen.len() {
+            state.seen[state.seen_len] = pid;
+            state.seen_len += 1;
+        }
+
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid);
+        if process.is_null() {
+            return 1;
+        }
+        if is_process_elevated(process) == Some(true) {
+            state.found_any = true;
+            let mut path = [0_u16; 260];
+            let mut path_len = path.len() as u32;
+            let mut name = [0; 260];
+            let name = if QueryFullProcessImageNameW(
+                process,
+                PROCESS_NAME_WIN32,
+                path.as_mut_ptr(),
+                &mut path_len,
+            ) != 0
+            {
+                let path = &path[..path_len as usize];
+                let file_name = path
+                    .iter()
+                    .rposition(|&c| c == u16::from(b'\\'))
+                    .map_or(path, |ix| &path[ix + 1..]);
+                utf16_to_ascii(file_name, &mut name)
+            } else {
+                b"unknown program"
+            };
+            report(
+                Severity::Warning,
+                log_array![
+                    b"\"",
+                    name,
+                    b"\" (PID ",
+                    pid,
+                    b") is elevated, clicks in its windows won't be filtered unless click-once is run as administrator"
+                ],
+            );
+        }
+        CloseHandle(process);
+        1
+    }
+
+    let mut state = State {
+        seen: [0; 64],
+        seen_len: 0,
+        found_any: false,
+    };
+    unsafe { EnumWindows(Some(visit_window), &mut state as *mut State as LPARAM) };
+    if !state.found_any {
+        report(
+            Severity::Ok,
+            log_array![b"No visible windows belong to elevated programs"],
+        );
+    }
+}
+
+fn check_autostart() {
+    let mut found_any = false;
+    for (root, root_name) in [
+        (HKEY_CURRENT_USER, b"HKCU".as_slice()),
+        (HKEY_LOCAL_MACHINE, b"HKLM"),
+    ] {
+        let mut key: HKEY = ptr::null_mut();
+        let result = unsafe {
+            RegOpenKeyExW(
+                root,
+                windows_sys::w!("Software\\Microsoft\\Windows\\CurrentVersion\\Run"),
+                0,
+                KEY_READ,
+                &mut key,
+            )
+        };
+        if result != ERROR_SUCCESS {
+            continue;
+        }
+        for index in 0.. {
+            let mut name = [0_u16; 256];
+            let mut name_len = name.len() as u32;
+            let mut data = [0_u16; 1024];
+            let mut data_size = core::mem::size_of_val(&data) as u32;
+            let result = unsafe {
+                RegEnumValueW(
+                    key,
+                    index,
+                    name.as_mut_ptr(),
+                    &mut name_len,
+                    ptr::null(),
+                    ptr::null_mut(),
+                    data.as_mut_ptr().cast(),
+                    &mut data_size,
+                )
+            };
+            if result == ERROR_NO_MORE_ITEMS {
+                break;
+            } else if result != ERROR_SUCCESS {
+                continue;
+            }
+            let mut command = [0; 1024];
+            let command = utf16_to_ascii(&data[..data_size as usize / 2], &mut command);
+            let mentions_us = command
+                .windows(b"click-once".len())
+                .any(|part| part.eq_ignore_ascii_case(b"click-once"));
+            if mentions_us {
+                found_any = true;
+                let mut name_ascii = [0; 256];
+                report(
+                    Severity::Ok,
+                    log_array![
+                        b"Autostart entry \"",
+                        utf16_to_ascii(&name[..name_len as usize], &mut name_ascii),
+                        b"\" in ",
+                        root_name,
+                        b"\\...\\Run starts: ",
+                        command
+                    ],
+                );
+            }
+        }
+        unsafe { RegCloseKey(key) };
+    }
+    #[cfg(feature = "std")]
+    if crate::scheduled_task::exists() {
+        found_any = true;
+        report(
+            Severity::Ok,
+            log_array![
+                b"The \"click-once\" scheduled task starts click-once as administrator when you log in"
+            ],
+        );
+    }
+    if !found_any {
+        #[cfg(feature = "tray")]
+        let advice = b"check \"Start with Windows\" in the tray menu or start click-once with --install or --create-elevated-task (to also filter clicks in elevated windows)".as_slice();
+        #[cfg(all(feature = "std", not(feature = "tray")))]
+        let advice = b"start click-once with --install or --create-elevated-task (to also filter clicks in elevated windows)".as_slice();
+        #[cfg(not(feature = "std"))]
+        let advice = b"add a shortcut to the \"shell:startup\" folder".as_slice();
+        report(
+            Severity::Info,
+            log_array![
+                b"No autostart entry or scheduled task found, ",
+                advice,
+                b" to start click-once when you log in"
+            ],
+        );
+    }
+}
+
+/// Check for common problems and print the findings to the console.
+pub fn run() {
+    log![b"\r\nclick-once doctor\r\n\r\n"];
+    check_hook_install();
+    check_conflicting_software();
+    check_timer_resolution();
+    check_timing_sources();
+    check_elevation();
+    check_autostart();
+    log![b"\r\n"];
+}
@@ -0,0 +1,100 @@
+//! Parses thresholds that are written with a unit, such as `30ms` or `0.05s`,
+//! so that shortcuts are easy to read and a value in seconds can't be mistaken
+//! for milliseconds. Numbers without a unit are milliseconds.
+
+/// Why a threshold couldn't be parsed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ParseMillisError(&'static str);
+impl core::fmt::Display for ParseMillisError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+/// Strip a case insensitive suffix.
+fn strip_unit<'a>(text: &'a str, unit: &str) -> Option<&'a str> {
+    let split = text.len().checked_sub(unit.len())?;
+    text.get(split..)
+        .filter(|suffix| suffix.eq_ignore_ascii_case(unit))
+        .map(|_| &text[..split])
+}
+
+/// Parse a threshold such as `50`, `30ms` or `0.05s` into milliseconds.
+pub fn parse_millis(text: &str) -> Result<u32, ParseMillisError> {
+    const NANOS_PER_MILLI: u64 = 1_000_000;
+
+    let text = text.trim();
+    let (number, nanos_per_unit) = if let Some(number) = strip_unit(text, "ms") {
+        (number, NANOS_PER_MILLI)
+    } else if let Some(number) = strip_unit(text, "s") {
+        (number, 1000 * NANOS_PER_MILLI)
+    } else {
+        (text, NANOS_PER_MILLI)
+    };
+    let number = number.trim_end();
+    let (whole, fraction) = number.split_once('.').unwrap_or((number, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(ParseMillisError("expected a number"));
+    }
+    if !whole
+        .bytes()
+        .chain(fraction.bytes())
+        .all(|b| b.is_ascii_digit())
+    {
+        return Err(ParseMillisError(
+            "expected a number of milliseconds optionally followed by \"ms\" or \"s\"",
+        ));
+    }
+
+    let too_large = ParseMillisError("the number is too large");
+    let mut nanos = 0_u64;
+    for digit in whole.bytes() {
+        nanos = nanos
+            .checked_mul(10)
+            .and_then(|n| n.checked_add(u64::from(digit - b'0')))
+            .ok_or(too_large)?;
+    }
+    nanos = nanos.checked_mul(nanos_per_unit).ok_or(too_large)?;
+    let mut place = nanos_per_unit;
+    for digit in fraction.bytes() {
+        place /= 10;
+        let digit = u64::from(digit - b'0');
+        if place == 0 && digit != 0 {
+            return Err(ParseMillisError(
+                "the value is more precise than a millisecond",
+            ));
+        }
+        nanos += digit * place;
+    }
+    if !nanos.is_multiple_of(NANOS_PER_MILLI) {
+        return Err(ParseMillisError(
+            "the value is more precise than a millisecond",
+        ));
+    }
+    u32::try_from(nanos / NANOS_PER_MILLI).map_err(|_| too_large)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_units() {
+        assert_eq!(parse_millis("50"), Ok(50));
+        assert_eq!(parse_millis("30ms"), Ok(30));
+        assert_eq!(parse_millis("30 MS"), Ok(30));
+        assert_eq!(parse_millis("0.05s"), Ok(50));
+        assert_eq!(parse_millis("1s"), Ok(1000));
+        assert_eq!(parse_millis(".5s"), Ok(500));
+        assert_eq!(parse_millis("0.0010s"), Ok(1));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for text in [
+            "", "s", "ms", ".", "-1", "30m", "1.5", "0.0005s", "1e3", "5000000s",
+        ] {
+            assert!(parse_millis(text).is_err(), "{text:?} should be rejected");
+        }
+    }
+}
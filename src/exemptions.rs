@@ -0,0 +1,67 @@
+//! Counts events that bypassed debouncing because of an exemption rule so that
+//! users can verify that their rules actually match something.
+
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+
+/// A rule that lets events through without debouncing them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Exemption {
+    /// Injected by software and the policy for injected events is to pass
+    /// them.
+    Injected,
+    /// Injected from a lower integrity level and the policy for such events
+    /// is to pass them.
+    LowerIlInjected,
+    /// Clicked by a device that reports absolute positions.
+    AbsoluteDevice,
+    /// Injected while a program that emulates a mouse using a game controller
+    /// was running.
+    Controller,
+    /// Generated by Windows from a touchscreen tap.
+    Touch,
+    /// Most likely clicked by a device that doesn't match the device filter.
+    OtherDevice,
+    /// Clicked while the bypass key was held down.
+    BypassKey,
+    /// Clicked while an excluded application was in the foreground.
+    #[cfg_attr(
+        not(feature = "std"),
+        allow(dead_code, reason = "only used by certain features")
+    )]
+    Application,
+    /// Clicked while a fullscreen game was in the foreground in game mode.
+    #[cfg_attr(
+        not(feature = "std"),
+        allow(dead_code, reason = "only used by certain features")
+    )]
+    FullscreenGame,
+}
+impl Exemption {
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub const ALL: [Self; 9] = [
+        Self::Injected,
+        Self::LowerIlInjected,
+        Self::AbsoluteDevice,
+        Self::Controller,
+        Self::Touch,
+        Self::OtherDevice,
+        Self::BypassKey,
+        Self::Application,
+        Self::FullscreenGame,
+    ];
+}
+
+static COUNTS: [AtomicU32; Exemption::ALL.len()] =
+    [const { AtomicU32::new(0) }; Exemption::ALL.len()];
+
+/// Count an event that bypassed debouncing because of a rule.
+#[inline(always)]
+pub fn record(rule: Exemption) {
+    _ = COUNTS[rule as usize].fetch_add(1, Relaxed);
+}
+
+/// How many events bypassed debouncing because of a rule.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn count(rule: Exemption) -> u32 {
+    COUNTS[rule as usize].load(Relaxed)
+}
@@ -0,0 +1,217 @@
+//! A safety valve that stops blocking events if an unusual number of them are
+//! blocked, which suggests a stuck device or a misconfigured threshold. Without
+//! it such a problem could make the mouse unusable.
+//!
+//! A second check resets the thresholds to 0 if most presses are blocked, even
+//! if they don't happen fast enough to trip the first one. A huge threshold
+//! blocks nearly every click while even a badly worn switch bounces less often
+//! than it clicks.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/processthreadsapi/nf-processthreadsapi-createthread>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-settimer>
+
+use crate::debounce::MouseButton;
+use crate::{log, log_error};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed};
+use windows_sys::core::PCWSTR;
+use windows_sys::Win32::Foundation::HWND;
+use windows_sys::Win32::System::Threading::CreateThread;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    KillTimer, MessageBoxW, SetTimer, MB_ICONWARNING, MB_OK,
+};
+
+/// Blocking stops if more than this many events are blocked each second, `0`
+/// disables the fail-safe.
+static MAX_BLOCKED_PER_SECOND: AtomicU32 = AtomicU32::new(20);
+/// ...for this many consecutive seconds.
+static MAX_SECONDS: AtomicU32 = AtomicU32::new(3);
+
+/// `true` once the fail-safe has stopped blocking events.
+static TRIPPED: AtomicBool = AtomicBool::new(false);
+
+/// Tick count when the current one second window started.
+static WINDOW_START: AtomicU32 = AtomicU32::new(0);
+/// Events blocked in the current window.
+static WINDOW_BLOCKED: AtomicU32 = AtomicU32::new(0);
+/// Consecutive windows before the current one where too many events were
+/// blocked.
+static STORM_SECONDS: AtomicU32 = AtomicU32::new(0);
+
+/// Thresholds are reset if more than this percentage of the last
+/// [`PRESS_WINDOW`] presses were blocked, `0` disables the check.
+static MAX_BLOCKED_PERCENT: AtomicU32 = AtomicU32::new(90);
+
+/// How many presses the blocked percentage is measured over.
+pub const PRESS_WINDOW: u32 = 20;
+/// Presses in the current window.
+static WINDOW_PRESSES: AtomicU32 = AtomicU32::new(0);
+/// Blocked presses in the current window.
+static WINDOW_BLOCKED_PRESSES: AtomicU32 = AtomicU32::new(0);
+/// Blocked presses in the window that made us reset the thresholds, reported
+/// once the message loop gets to it.
+static RESET_BLOCKED_PRESSES: AtomicU32 = AtomicU32::new(0);
+
+pub fn set_max_blocked_per_second(max: u32) {
+    MAX_BLOCKED_PER_SECOND.store(max, Relaxed);
+}
+
+pub fn max_blocked_per_second() -> u32 {
+    MAX_BLOCKED_PER_SECOND.load(Relaxed)
+}
+
+pub fn set_max_seconds(seconds: u32) {
+    MAX_SECONDS.store(seconds.max(1), Relaxed);
+}
+
+pub fn max_seconds() -> u32 {
+    MAX_SECONDS.load(Relaxed)
+}
+
+pub fn set_max_blocked_percent(percent: u32) {
+    MAX_BLOCKED_PERCENT.store(percent.min(100), Relaxed);
+}
+
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn max_blocked_percent() -> u32 {
+    MAX_BLOCKED_PERCENT.load(Relaxed)
+}
+
+/// `true` if the fail-safe has stopped blocking events.
+#[inline(always)]
+pub fn is_tripped() -> bool {
+    TRIPPED.load(Relaxed)
+}
+
+/// Count an event that the debounce logic decided to block and trip the
+/// fail-safe if too many have been blocked. Returns `true` if the event
+/// should still be blocked.
+pub fn on_blocked(tick: u32) -> bool {
+    let max = MAX_BLOCKED_PER_SECOND.load(Relaxed);
+    if max == 0 {
+        return true;
+    }
+    let elapsed = tick.wrapping_sub(WINDOW_START.load(Relaxed));
+    if elapsed >= 1000 {
+        // Only windows that directly follow each other count as consecutive:
+        let storm = elapsed < 2000 && WINDOW_BLOCKED.load(Relaxed) > max;
+        if storm {
+            _ = STORM_SECONDS.fetch_add(1, Relaxed);
+        } else {
+            STORM_SECONDS.store(0, Relaxed);
+        }
+        WINDOW_START.store(tick, Relaxed);
+        WINDOW_BLOCKED.store(0, Relaxed);
+    }
+    let blocked = WINDOW_BLOCKED.fetch_add(1, Relaxed) + 1;
+    if blocked > max && STORM_SECONDS.load(Relaxed) + 1 >= MAX_SECONDS.load(Relaxed) {
+        trip();
+        return false;
+    }
+    true
+}
+
+/// Count a press that the debounce logic has decided on and reset the
+/// thresholds if too many of the recent presses were blocked.
+pub fn on_press(blocked: bool) {
+    let max = MAX_BLOCKED_PERCENT.load(Relaxed);
+    if max == 0 {
+        return;
+    }
+    if blocked {
+        _ = WINDOW_BLOCKED_PRESSES.fetch_add(1, Relaxed);
+    }
+    if WINDOW_PRESSES.fetch_add(1, Relaxed) + 1 < PRESS_WINDOW {
+        return;
+    }
+    WINDOW_PRESSES.store(0, Relaxed);
+    let blocked = WINDOW_BLOCKED_PRESSES.swap(0, Relaxed);
+    if blocked * 100 > max * PRESS_WINDOW {
+        reset_thresholds(blocked);
+    }
+}
+
+/// Reset the thresholds right away and leave everything else to the message
+/// loop, since this is called by the mouse hook.
+#[cold]
+fn reset_thresholds(blocked: u32) {
+    for &button in MouseButton::all() {
+        crate::threshold(button).store(0, Relaxed);
+        crate::UP_THRESHOLDS[button as usize].store(crate::SAME_AS_DOWN, Relaxed);
+    }
+    RESET_BLOCKED_PRESSES.store(blocked, Relaxed);
+    // The timer fires once the hook has returned. If it can't be created the
+    // thresholds are still reset, there is just no message about it:
+    unsafe { SetTimer(ptr::null_mut(), 0, 0, Some(on_thresholds_reset)) };
+}
+
+unsafe extern "system" fn on_thresholds_reset(_window: HWND, _message: u32, id: usize, _time: u32) {
+    KillTimer(ptr::null_mut(), id);
+    let blocked = RESET_BLOCKED_PRESSES.load(Relaxed);
+    #[cfg(feature = "std")]
+    crate::foreground_app::set_threshold_overrides(Vec::new());
+    log![
+        b"\r\nFail-safe: ",
+        blocked,
+        b" of the last ",
+        PRESS_WINDOW,
+        b" clicks were blocked, reset all thresholds to 0 ms\r\n"
+    ];
+    #[cfg(feature = "tray")]
+    crate::tray::refresh_tooltip();
+    notify(windows_sys::w!(
+        "click-once reset all thresholds to 0 ms because almost every click was blocked, \
+        which suggests a misconfigured threshold.\r\n\r\n\
+        Check the thresholds in the config file or the command line arguments."
+    ));
+}
+
+#[cold]
+fn trip() {
+    if TRIPPED.swap(true, Relaxed) {
+        return;
+    }
+    log![
+        b"\r\nFail-safe: more than ",
+        max_blocked_per_second(),
+        b" events per second were blocked for ",
+        max_seconds(),
+        b" seconds in a row, stopped blocking events until the program is restarted\r\n"
+    ];
+    notify(windows_sys::w!(
+        "click-once stopped blocking clicks because an unusual number of them were \
+        blocked, which suggests a stuck device or a misconfigured threshold.\r\n\r\n\
+        Restart click-once to block clicks again."
+    ));
+}
+
+/// Show a message box with a static message.
+fn notify(message: PCWSTR) {
+    unsafe extern "system" fn show_message(message: *mut core::ffi::c_void) -> u32 {
+        MessageBoxW(
+            ptr::null_mut(),
+            message.cast_const().cast(),
+            windows_sys::w!("click-once fail-safe"),
+            MB_OK | MB_ICONWARNING,
+        );
+        0
+    }
+    // Don't block the mouse hook while the message is shown:
+    let thread = unsafe {
+        CreateThread(
+            ptr::null(),
+            0,
+            Some(show_message),
+            message.cast(),
+            0,
+            ptr::null_mut(),
+        )
+    };
+    if thread.is_null() {
+        log_error("Failed to show fail-safe notification");
+    } else {
+        unsafe { windows_sys::Win32::Foundation::CloseHandle(thread) };
+    }
+}
@@ -0,0 +1,95 @@
+//! Lets users mark the most recently blocked press as a real double-click
+//! using a hotkey, or undo the block by injecting the click again. The marks
+//! show how short an intentional double-click can be for each button, which is
+//! an upper bound for a good threshold.
+
+use crate::debounce::MouseButton;
+use crate::log;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering::Relaxed};
+
+/// The most recently blocked press with the button plus one in the high 8 bits
+/// and the time since the previous event in the low 24 bits, `0` if there is
+/// no press that can be marked.
+static LAST_BLOCK: AtomicU32 = AtomicU32::new(0);
+
+/// Where the most recently blocked press happened, with the x coordinate in
+/// the high 32 bits.
+static LAST_BLOCK_POSITION: AtomicU64 = AtomicU64::new(0);
+
+/// Number of presses marked as real double-clicks, indexed by button.
+static MARKS: [AtomicU32; MouseButton::COUNT] = [const { AtomicU32::new(0) }; MouseButton::COUNT];
+
+/// The shortest interval of a marked press in milliseconds, indexed by button.
+static SHORTEST: [AtomicU32; MouseButton::COUNT] =
+    [const { AtomicU32::new(u32::MAX) }; MouseButton::COUNT];
+
+/// Remember a blocked down event so that it can be marked later.
+#[inline(always)]
+pub fn record_block(button: MouseButton, time_since_last_event: u32, (x, y): (i32, i32)) {
+    LAST_BLOCK.store(
+        (button as u32 + 1) << 24 | time_since_last_event.min(0x00FF_FFFF),
+        Relaxed,
+    );
+    LAST_BLOCK_POSITION.store(u64::from(x as u32) << 32 | u64::from(y as u32), Relaxed);
+}
+
+/// Take the most recently blocked press as its button and the time since the
+/// previous event, so that it is only handled once.
+fn take_last_block() -> Option<(MouseButton, u32)> {
+    let last = LAST_BLOCK.swap(0, Relaxed);
+    let button = *MouseButton::all().get(((last >> 24) as usize).checked_sub(1)?)?;
+    Some((button, last & 0x00FF_FFFF))
+}
+
+fn mark(button: MouseButton, interval: u32) {
+    _ = MARKS[button as usize].fetch_add(1, Relaxed);
+    _ = SHORTEST[button as usize].fetch_min(interval, Relaxed);
+}
+
+/// Mark the most recently blocked press as a real double-click. Each blocked
+/// press can only be marked once.
+pub fn mark_last_block() {
+    let Some((button, interval)) = take_last_block() else {
+        log![b"No blocked press to mark as a real double-click\r\n"];
+        return;
+    };
+    mark(button, interval);
+    log![
+        b"Marked blocked press ",
+        interval,
+        b" ms after the previous event as a real double-click\r\n"
+    ];
+}
+
+/// Inject the most recently blocked click again at the position where it
+/// happened, so that the user doesn't have to aim again. This also marks it as
+/// a real double-click.
+pub fn undo_last_block() {
+    let Some((button, interval)) = take_last_block() else {
+        log![b"No blocked press to undo\r\n"];
+        return;
+    };
+    let position = LAST_BLOCK_POSITION.load(Relaxed);
+    crate::deferred::inject_click_at(button, ((position >> 32) as i32, position as u32 as i32));
+    mark(button, interval);
+    log![
+        b"Injected blocked press ",
+        interval,
+        b" ms after the previous event again\r\n"
+    ];
+}
+
+/// The number of presses that were marked as real double-clicks and the
+/// shortest interval among them.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn marks(button: MouseButton) -> (u32, Option<u32>) {
+    let count = MARKS[button as usize].load(Relaxed);
+    let shortest = SHORTEST[button as usize].load(Relaxed);
+    (count, (count > 0).then_some(shortest))
+}
+
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn reset(button: MouseButton) {
+    MARKS[button as usize].store(0, Relaxed);
+    SHORTEST[button as usize].store(u32::MAX, Relaxed);
+}
@@ -0,0 +1,301 @@
+//! Disables blocking while certain applications are in the foreground, for
+//! example rhythm games where fast repeated clicks are intentional, or uses
+//! different thresholds for them. The foreground window's process is looked
+//! up when the foreground window changes, so the mouse hook only has to read
+//! a flag and the thresholds.
+//!
+//! In game mode blocking is also disabled while the foreground window covers
+//! its whole monitor without a title bar, which is how both exclusive and
+//! borderless fullscreen games look. The desktop and the shell are never
+//! considered fullscreen.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwineventhook>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-queryfullprocessimagenamew>
+
+use crate::debounce::MouseButton;
+use crate::{log, log_error};
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed};
+use std::sync::Mutex;
+use windows_sys::Win32::Foundation::{CloseHandle, HWND, RECT};
+use windows_sys::Win32::Graphics::Gdi::{
+    GetMonitorInfoW, MonitorFromWindow, MONITORINFO, MONITOR_DEFAULTTONULL,
+};
+use windows_sys::Win32::System::Threading::{
+    OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32, PROCESS_QUERY_LIMITED_INFORMATION,
+};
+use windows_sys::Win32::UI::Accessibility::{SetWinEventHook, HWINEVENTHOOK};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    GetDesktopWindow, GetForegroundWindow, GetShellWindow, GetWindowLongW, GetWindowRect,
+    GetWindowThreadProcessId, EVENT_OBJECT_LOCATIONCHANGE, EVENT_SYSTEM_FOREGROUND, GWL_STYLE,
+    OBJID_WINDOW, WINEVENT_OUTOFCONTEXT, WS_CAPTION,
+};
+
+/// Lowercase executable names of applications where nothing is blocked.
+static EXCLUDED_APPS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// `true` while an excluded application is in the foreground.
+static FOREGROUND_EXCLUDED: AtomicBool = AtomicBool::new(false);
+
+/// If `true` then nothing is blocked while a fullscreen window is in the
+/// foreground.
+static GAME_MODE: AtomicBool = AtomicBool::new(false);
+
+/// `true` while a fullscreen window is in the foreground, only tracked in
+/// game mode.
+static FOREGROUND_FULLSCREEN: AtomicBool = AtomicBool::new(false);
+
+/// `true` after the window event hooks were installed.
+static STARTED: AtomicBool = AtomicBool::new(false);
+
+/// A threshold that is used while an application is in the foreground.
+pub struct ThresholdOverride {
+    /// Lowercase executable name such as `chrome.exe`.
+    pub app: String,
+    pub button: MouseButton,
+    pub threshold: u32,
+}
+
+static THRESHOLD_OVERRIDES: Mutex<Vec<ThresholdOverride>> = Mutex::new(Vec::new());
+
+/// Used in [`FOREGROUND_THRESHOLDS`] for buttons that use their normal
+/// threshold.
+const NO_OVERRIDE: u32 = u32::MAX;
+
+/// The thresholds of the foreground application, indexed by button.
+static FOREGROUND_THRESHOLDS: [AtomicU32; MouseButton::COUNT] =
+    [const { AtomicU32::new(NO_OVERRIDE) }; MouseButton::COUNT];
+
+/// Parse a comma separated list of executable names such as
+/// `osu!.exe, game.exe`.
+fn parse_list(list: &str) -> Vec<String> {
+    list.split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Never block events while one of these applications is in the foreground.
+pub fn set_excluded_apps(list: &str) {
+    *EXCLUDED_APPS.lock().unwrap_or_else(|e| e.into_inner()) = parse_list(list);
+    update(unsafe { GetForegroundWindow() });
+}
+
+/// Use other thresholds while some applications are in the foreground.
+pub fn set_threshold_overrides(overrides: Vec<ThresholdOverride>) {
+    *THRESHOLD_OVERRIDES
+        .lock()
+        .unwrap_or_else(|e| e.into_inner()) = overrides;
+    update(unsafe { GetForegroundWindow() });
+}
+
+pub fn game_mode() -> bool {
+    GAME_MODE.load(Relaxed)
+}
+
+/// Don't block anything while a fullscreen game is in the foreground. The
+/// window events are only received after [`start`] has been called.
+pub fn set_game_mode(enabled: bool) {
+    GAME_MODE.store(enabled, Relaxed);
+    update(unsafe { GetForegroundWindow() });
+}
+
+/// `true` if an application was excluded or has its own thresholds, or game
+/// mode is enabled.
+pub fn is_enabled() -> bool {
+    game_mode()
+        || !EXCLUDED_APPS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+        || !THRESHOLD_OVERRIDES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .is_empty()
+}
+
+/// `true` if events shouldn't be blocked since an excluded application is in
+/// the foreground.
+#[inline(always)]
+pub fn is_excluded() -> bool {
+    FOREGROUND_EXCLUDED.load(Relaxed)
+}
+
+/// `true` if events shouldn't be blocked since game mode is enabled and a
+/// fullscreen window is in the foreground.
+#[inline(always)]
+pub fn is_fullscreen_game() -> bool {
+    FOREGROUND_FULLSCREEN.load(Relaxed)
+}
+
+/// The foreground application's threshold for a button, if it has its own.
+#[inline(always)]
+pub fn threshold(button: MouseButton) -> Option<u32> {
+    match FOREGROUND_THRESHOLDS[button as usize].load(Relaxed) {
+        NO_OVERRIDE => None,
+        threshold => Some(threshold),
+    }
+}
+
+/// The lowercase executable name of the process that owns a window, such as
+/// `game.exe`.
+fn process_name(window: HWND) -> Option<String> {
+    let mut pid = 0;
+    unsafe { GetWindowThreadProcessId(window, &mut pid) };
+    if pid == 0 {
+        return None;
+    }
+    let process = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, 0, pid) };
+    if process.is_null() {
+        return None;
+    }
+    let mut path = [0_u16; 260];
+    let mut path_len = path.len() as u32;
+    let result = unsafe {
+        QueryFullProcessImageNameW(
+            process,
+            PROCESS_NAME_WIN32,
+            path.as_mut_ptr(),
+            &mut path_len,
+        )
+    };
+    unsafe { CloseHandle(process) };
+    if result == 0 {
+        return None;
+    }
+    let path = String::from_utf16_lossy(&path[..path_len as usize]);
+    let name = path.rsplit('\\').next().unwrap_or(&path);
+    Some(name.to_lowercase())
+}
+
+/// `true` if a window covers its whole monitor without a title bar.
+fn is_fullscreen(window: HWND) -> bool {
+    if window.is_null()
+        || window == unsafe { GetDesktopWindow() }
+        || window == unsafe { GetShellWindow() }
+    {
+        return false;
+    }
+    // Maximized windows can also cover the monitor if the taskbar is hidden:
+    let style = unsafe { GetWindowLongW(window, GWL_STYLE) } as u32;
+    if style & WS_CAPTION == WS_CAPTION {
+        return false;
+    }
+    let mut rect: RECT = unsafe { core::mem::zeroed() };
+    if unsafe { GetWindowRect(window, &mut rect) } == 0 {
+        return false;
+    }
+    let monitor = unsafe { MonitorFromWindow(window, MONITOR_DEFAULTTONULL) };
+    let mut info = MONITORINFO {
+        cbSize: size_of::<MONITORINFO>() as u32,
+        ..unsafe { core::mem::zeroed() }
+    };
+    if monitor.is_null() || unsafe { GetMonitorInfoW(monitor, &mut info) } == 0 {
+        return false;
+    }
+    let screen = info.rcMonitor;
+    rect.left <= screen.left
+        && rect.top <= screen.top
+        && rect.right >= screen.right
+        && rect.bottom >= screen.bottom
+}
+
+/// Check if a fullscreen game is in the foreground.
+fn update_fullscreen(window: HWND, name: Option<&str>) {
+    // The desktop's wallpaper window belongs to Explorer and covers the
+    // monitor:
+    let fullscreen =
+        game_mode() && name.is_some_and(|name| name != "explorer.exe") && is_fullscreen(window);
+    if FOREGROUND_FULLSCREEN.swap(fullscreen, Relaxed) != fullscreen {
+        log![if fullscreen {
+            b"\r\nA fullscreen game is in the foreground, nothing is blocked\r\n".as_slice()
+        } else {
+            b"\r\nNo fullscreen game is in the foreground anymore\r\n"
+        }];
+    }
+}
+
+/// Check if the new foreground window belongs to an excluded application, one
+/// with its own thresholds or a fullscreen game.
+fn update(window: HWND) {
+    let name = process_name(window);
+    update_fullscreen(window, name.as_deref());
+    let mut thresholds = [NO_OVERRIDE; MouseButton::COUNT];
+    if let Some(name) = &name {
+        for rule in THRESHOLD_OVERRIDES
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .filter(|rule| rule.app == *name)
+        {
+            thresholds[rule.button as usize] = rule.threshold;
+        }
+    }
+    for (stored, threshold) in FOREGROUND_THRESHOLDS.iter().zip(thresholds) {
+        stored.store(threshold, Relaxed);
+    }
+    let excluded = name.is_some_and(|name| {
+        EXCLUDED_APPS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(&name)
+    });
+    if FOREGROUND_EXCLUDED.swap(excluded, Relaxed) != excluded {
+        log![if excluded {
+            b"\r\nAn excluded application is in the foreground, nothing is blocked\r\n".as_slice()
+        } else {
+            b"\r\nNo excluded application is in the foreground anymore\r\n"
+        }];
+    }
+}
+
+unsafe extern "system" fn on_win_event(
+    _hook: HWINEVENTHOOK,
+    event: u32,
+    window: HWND,
+    object: i32,
+    _child: i32,
+    _thread: u32,
+    _time: u32,
+) {
+    if event == EVENT_SYSTEM_FOREGROUND {
+        update(window);
+        return;
+    }
+    // Location changes are reported for all objects in all windows (including
+    // the cursor), only a game that switches to fullscreen matters:
+    if game_mode() && object == OBJID_WINDOW && window == GetForegroundWindow() {
+        update_fullscreen(window, process_name(window).as_deref());
+    }
+}
+
+/// Start tracking the foreground application if any application was excluded
+/// or has its own thresholds, or game mode is enabled. Does nothing if it is
+/// already tracked. Must be called on the thread that runs the message loop
+/// since that is where the window events are delivered.
+pub fn start() {
+    if !is_enabled() || STARTED.swap(true, Relaxed) {
+        return;
+    }
+    for event in [EVENT_SYSTEM_FOREGROUND, EVENT_OBJECT_LOCATIONCHANGE] {
+        // The hooks are removed automatically when the program exits:
+        let hook = unsafe {
+            SetWinEventHook(
+                event,
+                event,
+                ptr::null_mut(),
+                Some(on_win_event),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            )
+        };
+        if hook.is_null() {
+            log_error("Failed to track the foreground application");
+            return;
+        }
+    }
+    update(unsafe { GetForegroundWindow() });
+}
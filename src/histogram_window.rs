@@ -0,0 +1,253 @@
+//! A native window that draws the histogram of intervals between down events
+//! as a bar chart for each button, so that users can see the cluster of
+//! bounces separately from intentional double-clicks when picking a threshold.
+//!
+//! The window is created on the thread that runs the tray's event loop, which
+//! also dispatches its messages.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/gdi/painting-and-drawing>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-fillrect>
+
+use crate::debounce::MouseButton;
+use crate::log_error;
+use crate::logging::stats::{IntervalHistogram, INTERVALS};
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{COLORREF, HWND, LPARAM, LRESULT, RECT, WPARAM};
+use windows_sys::Win32::Graphics::Gdi::{
+    BeginPaint, CreateSolidBrush, DeleteObject, EndPaint, FillRect, GetStockObject, InvalidateRect,
+    SelectObject, SetBkMode, TextOutW, DEFAULT_GUI_FONT, HDC, PAINTSTRUCT, TRANSPARENT,
+};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, GetClientRect, KillTimer, RegisterClassW, SetForegroundWindow,
+    SetTimer, ShowWindow, CW_USEDEFAULT, SW_RESTORE, WM_DESTROY, WM_PAINT, WM_TIMER, WNDCLASSW,
+    WS_OVERLAPPEDWINDOW, WS_VISIBLE,
+};
+
+/// The open window, null if it isn't open.
+static WINDOW: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(ptr::null_mut());
+
+const CLASS_NAME: windows_sys::core::PCWSTR = windows_sys::w!("click-once-histogram");
+
+/// How often the chart is redrawn to show new events.
+const REFRESH_INTERVAL_MS: u32 = 500;
+const REFRESH_TIMER_ID: usize = 1;
+
+const BACKGROUND: COLORREF = 0x00FF_FFFF;
+const ACCEPTED_COLOR: COLORREF = 0x00D9_904A;
+const BLOCKED_COLOR: COLORREF = 0x004A_4AD9;
+const THRESHOLD_COLOR: COLORREF = 0x0022_2222;
+
+fn fill(dc: HDC, rect: RECT, color: COLORREF) {
+    unsafe {
+        let brush = CreateSolidBrush(color);
+        FillRect(dc, &rect, brush);
+        DeleteObject(brush);
+    }
+}
+
+fn text(dc: HDC, x: i32, y: i32, text: &str) {
+    let wide = text.encode_utf16().collect::<Vec<_>>();
+    unsafe { TextOutW(dc, x, y, wide.as_ptr(), wide.len() as i32) };
+}
+
+/// Draw the histogram of one button inside `area`.
+fn draw_button(dc: HDC, area: RECT, button: MouseButton) {
+    const MARGIN: i32 = 10;
+    const LABEL_HEIGHT: i32 = 18;
+
+    let name = match button {
+        MouseButton::Left => "Left",
+        MouseButton::Right => "Right",
+        MouseButton::Middle => "Middle",
+        MouseButton::X1 => "X1",
+        MouseButton::X2 => "X2",
+    };
+    let threshold = crate::threshold(button).load(Relaxed);
+    let bins = INTERVALS.get(button);
+    let (accepted, blocked) = bins.iter().fold((0, 0), |(a, b), &(accepted, blocked)| {
+        (a + accepted, b + blocked)
+    });
+    text(
+        dc,
+        area.left + MARGIN,
+        area.top + 2,
+        &format!(
+            "{name} button: threshold {threshold} ms, {accepted} accepted and {blocked} blocked presses"
+        ),
+    );
+
+    let chart = RECT {
+        left: area.left + MARGIN,
+        top: area.top + LABEL_HEIGHT + 4,
+        right: area.right - MARGIN,
+        bottom: area.bottom - LABEL_HEIGHT,
+    };
+    let height = (chart.bottom - chart.top).max(1);
+    let bar_width = ((chart.right - chart.left) / IntervalHistogram::BINS as i32).max(1);
+    let max = bins
+        .iter()
+        .map(|&(accepted, blocked)| accepted + blocked)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+    let scale = |count: u32| (u64::from(count) * height as u64 / u64::from(max)) as i32;
+
+    for (index, &(accepted, blocked)) in bins.iter().enumerate() {
+        let left = chart.left + index as i32 * bar_width;
+        let right = left + bar_width - 1;
+        let blocked_top = chart.bottom - scale(blocked);
+        let accepted_top = blocked_top - scale(accepted);
+        fill(
+            dc,
+            RECT {
+                left,
+                top: blocked_top,
+                right,
+                bottom: chart.bottom,
+            },
+            BLOCKED_COLOR,
+        );
+        fill(
+            dc,
+            RECT {
+                left,
+                top: accepted_top,
+                right,
+                bottom: blocked_top,
+            },
+            ACCEPTED_COLOR,
+        );
+        if index % 4 == 0 {
+            let ms = index as u32 * IntervalHistogram::BIN_WIDTH;
+            text(dc, left, chart.bottom + 2, &format!("{ms}"));
+        }
+    }
+
+    // Mark the threshold so that it is easy to see which bins it blocks:
+    let max_ms = IntervalHistogram::BINS as u32 * IntervalHistogram::BIN_WIDTH;
+    if threshold <= max_ms {
+        let x = chart.left + (threshold * bar_width as u32 / IntervalHistogram::BIN_WIDTH) as i32;
+        fill(
+            dc,
+            RECT {
+                left: x - 1,
+                top: chart.top,
+                right: x + 1,
+                bottom: chart.bottom,
+            },
+            THRESHOLD_COLOR,
+        );
+    }
+}
+
+fn paint(window: HWND) {
+    let mut paint: PAINTSTRUCT = unsafe { core::mem::zeroed() };
+    let dc = unsafe { BeginPaint(window, &mut paint) };
+    let mut client: RECT = unsafe { core::mem::zeroed() };
+    unsafe { GetClientRect(window, &mut client) };
+    fill(dc, client, BACKGROUND);
+    unsafe {
+        SelectObject(dc, GetStockObject(DEFAULT_GUI_FONT));
+        SetBkMode(dc, TRANSPARENT as i32);
+    }
+
+    let buttons = MouseButton::all();
+    let row_height = (client.bottom - client.top) / buttons.len() as i32;
+    for (row, &button) in buttons.iter().enumerate() {
+        let top = client.top + row as i32 * row_height;
+        draw_button(
+            dc,
+            RECT {
+                left: client.left,
+                top,
+                right: client.right,
+                bottom: top + row_height,
+            },
+            button,
+        );
+    }
+    unsafe { EndPaint(window, &paint) };
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        WM_PAINT => {
+            paint(window);
+            0
+        }
+        WM_TIMER if wparam == REFRESH_TIMER_ID => {
+            InvalidateRect(window, ptr::null(), 0);
+            0
+        }
+        WM_DESTROY => {
+            KillTimer(window, REFRESH_TIMER_ID);
+            WINDOW.store(ptr::null_mut(), Relaxed);
+            0
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+/// Open the histogram window, or bring it to the front if it is already open.
+/// Must be called on the thread that runs the message loop.
+pub fn show() {
+    let existing = WINDOW.load(Relaxed);
+    if !existing.is_null() {
+        unsafe {
+            ShowWindow(existing, SW_RESTORE);
+            SetForegroundWindow(existing);
+        }
+        return;
+    }
+
+    let instance = unsafe { GetModuleHandleW(ptr::null()) };
+    let class = WNDCLASSW {
+        style: 0,
+        lpfnWndProc: Some(window_proc),
+        cbClsExtra: 0,
+        cbWndExtra: 0,
+        hInstance: instance,
+        hIcon: ptr::null_mut(),
+        hCursor: ptr::null_mut(),
+        hbrBackground: ptr::null_mut(),
+        lpszMenuName: ptr::null(),
+        lpszClassName: CLASS_NAME,
+    };
+    // Fails if the class was registered when the window was opened before,
+    // which is fine:
+    unsafe { RegisterClassW(&class) };
+
+    let window = unsafe {
+        CreateWindowExW(
+            0,
+            CLASS_NAME,
+            windows_sys::w!("click-once: time between presses"),
+            WS_OVERLAPPEDWINDOW | WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            760,
+            600,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            instance,
+            ptr::null(),
+        )
+    };
+    if window.is_null() {
+        log_error("Failed to create histogram window");
+        return;
+    }
+    WINDOW.store(window, Relaxed);
+    if unsafe { SetTimer(window, REFRESH_TIMER_ID, REFRESH_INTERVAL_MS, None) } == 0 {
+        log_error("Failed to create timer for refreshing the histogram window");
+    }
+}
@@ -0,0 +1,156 @@
+//! Global hotkeys that trigger actions, for example injecting a double-click
+//! for users that can't click twice quickly enough.
+//!
+//! Hotkeys are registered for a message-only window so that `WM_HOTKEY` is
+//! handled on the thread that runs the message loop.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-registerhotkey>
+//! - <https://learn.microsoft.com/en-us/windows/win32/inputdev/virtual-key-codes>
+
+use crate::debounce::MouseDirection;
+use crate::log_error;
+use crate::system_api::System;
+use crate::win_api::WinApi;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{
+    RegisterHotKey, MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, VK_F1, VK_INSERT,
+    VK_PAUSE, VK_SCROLL,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{DefWindowProcW, WM_HOTKEY};
+
+/// Something that can be triggered by a hotkey.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum HotkeyAction {
+    /// Inject a double-click with the primary button at the cursor.
+    DoubleClick,
+    /// Mark the most recently blocked press as a real double-click.
+    MarkFalsePositive,
+    /// Inject the most recently blocked click again where it happened.
+    UndoLastBlock,
+    /// Stop or resume blocking events.
+    TogglePause,
+}
+impl HotkeyAction {
+    const ALL: [Self; 4] = [
+        Self::DoubleClick,
+        Self::MarkFalsePositive,
+        Self::UndoLastBlock,
+        Self::TogglePause,
+    ];
+
+    fn run(self) {
+        match self {
+            Self::DoubleClick => {
+                let button = System.primary_button();
+                System.inject(&[
+                    (button, MouseDirection::Down),
+                    (button, MouseDirection::Up),
+                    (button, MouseDirection::Down),
+                    (button, MouseDirection::Up),
+                ]);
+            }
+            Self::MarkFalsePositive => crate::feedback::mark_last_block(),
+            Self::UndoLastBlock => crate::feedback::undo_last_block(),
+            Self::TogglePause => crate::pause::set_paused(!crate::pause::is_paused()),
+        }
+    }
+}
+
+/// The hotkey for each [`HotkeyAction`] with the modifiers in the high 16 bits
+/// and the virtual key code in the low 16 bits, `0` if there is no hotkey.
+static HOTKEYS: [AtomicU32; HotkeyAction::ALL.len()] =
+    [const { AtomicU32::new(0) }; HotkeyAction::ALL.len()];
+
+/// Parse a hotkey such as `Ctrl+Alt+D` or `F9`. Keys can be letters, digits,
+/// `F1` to `F24`, `Pause`, `ScrollLock` or `Insert`, optionally combined with
+/// the modifiers `Ctrl`, `Alt`, `Shift` and `Win`.
+pub fn parse(text: &str) -> Option<u32> {
+    let mut modifiers = 0;
+    let mut key = None;
+    for part in text.trim().split('+') {
+        let part = part.trim();
+        if key.is_some() {
+            // The key must come last:
+            return None;
+        }
+        let modifier = [
+            ("ctrl", MOD_CONTROL),
+            ("alt", MOD_ALT),
+            ("shift", MOD_SHIFT),
+            ("win", MOD_WIN),
+        ]
+        .into_iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(part));
+        if let Some((_, modifier)) = modifier {
+            modifiers |= modifier;
+            continue;
+        }
+        key = Some(match part.as_bytes() {
+            &[c] if c.is_ascii_alphanumeric() => u16::from(c.to_ascii_uppercase()),
+            [b'F' | b'f', number @ ..] if !number.is_empty() => {
+                let number = core::str::from_utf8(number).ok()?.parse::<u16>().ok()?;
+                if !(1..=24).contains(&number) {
+                    return None;
+                }
+                VK_F1 + number - 1
+            }
+            _ if part.eq_ignore_ascii_case("pause") => VK_PAUSE,
+            _ if part.eq_ignore_ascii_case("scrolllock") => VK_SCROLL,
+            _ if part.eq_ignore_ascii_case("insert") => VK_INSERT,
+            _ => return None,
+        });
+    }
+    Some(modifiers << 16 | u32::from(key?))
+}
+
+pub fn set(action: HotkeyAction, hotkey: u32) {
+    HOTKEYS[action as usize].store(hotkey, Relaxed);
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_HOTKEY {
+        if let Some(&action) = HotkeyAction::ALL.get(wparam) {
+            action.run();
+        }
+        return 0;
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Register all configured hotkeys. Must be called on the thread that runs the
+/// message loop.
+pub fn start() {
+    if HOTKEYS.iter().all(|hotkey| hotkey.load(Relaxed) == 0) {
+        return;
+    }
+    let Some(window) =
+        crate::message_window::create(windows_sys::w!("click-once-hotkeys"), Some(window_proc))
+    else {
+        return;
+    };
+    for action in HotkeyAction::ALL {
+        let hotkey = HOTKEYS[action as usize].load(Relaxed);
+        if hotkey == 0 {
+            continue;
+        }
+        let registered = unsafe {
+            RegisterHotKey(
+                window,
+                action as i32,
+                (hotkey >> 16) | MOD_NOREPEAT,
+                hotkey & 0xFFFF,
+            )
+        };
+        if registered == 0 {
+            log_error("Failed to register hotkey, it might already be used by another program");
+        }
+    }
+}
@@ -0,0 +1,106 @@
+//! Policies for mouse events that were injected by software (for example
+//! using `SendInput`) instead of coming from a device.
+//!
+//! Events injected by a process with a lower integrity level than ours are
+//! handled separately since remote-control and accessibility tools differ in
+//! which flag they end up setting.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-msllhookstruct>
+
+use core::sync::atomic::{AtomicU32, AtomicU8, Ordering::Relaxed};
+use windows_sys::Win32::UI::WindowsAndMessaging::{LLMHF_INJECTED, LLMHF_LOWER_IL_INJECTED};
+
+/// The kind of software that injected an event.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum InjectedKind {
+    /// Injected by a process with the same or a higher integrity level.
+    Injected,
+    /// Injected by a process with a lower integrity level.
+    LowerIl,
+}
+impl InjectedKind {
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub const ALL: [Self; 2] = [Self::Injected, Self::LowerIl];
+
+    /// Get the kind of an event from its `MSLLHOOKSTRUCT.flags`. `None` if the
+    /// event wasn't injected.
+    pub fn from_flags(flags: u32) -> Option<Self> {
+        // Events injected from a lower integrity level have both flags set:
+        if flags & LLMHF_LOWER_IL_INJECTED != 0 {
+            Some(Self::LowerIl)
+        } else if flags & LLMHF_INJECTED != 0 {
+            Some(Self::Injected)
+        } else {
+            None
+        }
+    }
+}
+
+/// What to do with injected events.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum InjectedPolicy {
+    /// Handle them just like events from a device.
+    Debounce,
+    /// Never block them.
+    Pass,
+    /// Always block them.
+    Block,
+}
+impl InjectedPolicy {
+    pub const ALL: [Self; 3] = [Self::Debounce, Self::Pass, Self::Block];
+
+    /// The name used for the policy in arguments and the config file.
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Debounce => "debounce",
+            Self::Pass => "pass",
+            Self::Block => "block",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|policy| policy.name().eq_ignore_ascii_case(value.trim()))
+    }
+
+    const fn from_u8(value: u8) -> Self {
+        match value {
+            1 => Self::Pass,
+            2 => Self::Block,
+            _ => Self::Debounce,
+        }
+    }
+}
+
+/// The policy for each [`InjectedKind`].
+static POLICIES: [AtomicU8; 2] = [const { AtomicU8::new(InjectedPolicy::Debounce as u8) }; 2];
+
+/// How many events of each [`InjectedKind`] were handled using each
+/// [`InjectedPolicy`].
+static COUNTS: [[AtomicU32; 3]; 2] = [const { [const { AtomicU32::new(0) }; 3] }; 2];
+
+pub fn policy(kind: InjectedKind) -> InjectedPolicy {
+    InjectedPolicy::from_u8(POLICIES[kind as usize].load(Relaxed))
+}
+
+pub fn set_policy(kind: InjectedKind, policy: InjectedPolicy) {
+    POLICIES[kind as usize].store(policy as u8, Relaxed);
+}
+
+/// Get the policy for an injected event and count it.
+#[inline(always)]
+pub fn handle(kind: InjectedKind) -> InjectedPolicy {
+    let policy = policy(kind);
+    _ = COUNTS[kind as usize][policy as usize].fetch_add(1, Relaxed);
+    policy
+}
+
+/// How many events of a kind were handled using a policy.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn count(kind: InjectedKind, policy: InjectedPolicy) -> u32 {
+    COUNTS[kind as usize][policy as usize].load(Relaxed)
+}
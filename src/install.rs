@@ -0,0 +1,58 @@
+//! Install a copy of the program for the current user so that it is easy to
+//! set up from a downloaded executable: `click-once --install`.
+
+use std::path::PathBuf;
+
+/// Where the program is installed.
+pub fn install_dir() -> Option<PathBuf> {
+    let local_app_data = std::env::var_os("LOCALAPPDATA")?;
+    Some(PathBuf::from(local_app_data).join("click-once"))
+}
+
+/// Copy the executable to `%LOCALAPPDATA%\click-once`, save the current
+/// settings to the config file, start the installed copy when the user logs in
+/// and start it now.
+pub fn install() -> Result<(), String> {
+    // Reported at once so that there is only one message box without a
+    // console:
+    let mut report = Vec::new();
+
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Failed to get the path of the current executable: {e}"))?;
+    let dir = install_dir().ok_or("Failed to locate the install folder, LOCALAPPDATA isn't set")?;
+    let installed_exe = dir.join("click-once.exe");
+
+    let already_installed = std::fs::canonicalize(&exe)
+        .ok()
+        .is_some_and(|exe| std::fs::canonicalize(&installed_exe).ok() == Some(exe));
+    if !already_installed {
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| format!("Failed to create folder \"{}\": {e}", dir.display()))?;
+        std::fs::copy(&exe, &installed_exe).map_err(|e| {
+            format!(
+                "Failed to copy the executable to \"{}\", quit click-once if it is \
+                already running from there: {e}",
+                installed_exe.display()
+            )
+        })?;
+    }
+    report.push(format!("Installed to \"{}\"", installed_exe.display()));
+
+    crate::config::save_settings()?;
+    if let Some(path) = crate::config::path() {
+        report.push(format!(
+            "Saved the current settings to \"{}\"",
+            path.display()
+        ));
+    }
+
+    crate::autostart::enable(&installed_exe, &[])?;
+    report.push("Added autostart entry".to_owned());
+
+    std::process::Command::new(&installed_exe)
+        .spawn()
+        .map_err(|e| format!("Failed to start the installed executable: {e}"))?;
+    report.push("Done, click-once is now running and will start when you log in.".to_owned());
+    crate::console::report(&report.join("\r\n"));
+    Ok(())
+}
@@ -0,0 +1,300 @@
+//! Opt-in named pipe server (`\\.\pipe\click-once-<session id>`) that lets
+//! scripts and other tools control the running program with simple text
+//! commands, one per line:
+//!
+//! ```text
+//! GET STATUS      -> OK paused=false left=30 right=0 middle=0 x1=0 x2=0
+//! GET LEFT        -> OK 30
+//! SET LEFT 40     -> OK 40
+//! PAUSE           -> OK
+//! RESUME          -> OK
+//! QUIT            -> OK
+//! ```
+//!
+//! Every command gets exactly one response line that starts with `OK` or
+//! `ERROR`. Commands are case insensitive. Only one client is served at a time
+//! and remote clients are rejected. Every session has its own pipe, and the
+//! server refuses to start if another program already created it, so that
+//! clients can't be answered by an impostor.
+//!
+//! The same commands are available from the command line as subcommands, for
+//! example `click-once set left 40`, which send the command to the running
+//! instance and print its response.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-createnamedpipew>
+//! - <https://learn.microsoft.com/en-us/windows/win32/ipc/named-pipe-server-using-overlapped-i-o>
+
+use crate::debounce::MouseButton;
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use windows_sys::Win32::Foundation::{
+    GetLastError, ERROR_PIPE_BUSY, ERROR_PIPE_CONNECTED, INVALID_HANDLE_VALUE,
+};
+use windows_sys::Win32::Storage::FileSystem::{
+    FlushFileBuffers, FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX,
+};
+use windows_sys::Win32::System::Pipes::{
+    ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe, WaitNamedPipeW, PIPE_READMODE_BYTE,
+    PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_WAIT,
+};
+use windows_sys::Win32::System::RemoteDesktop::ProcessIdToSessionId;
+use windows_sys::Win32::System::Threading::{GetCurrentProcessId, GetCurrentThreadId};
+
+/// The name of the pipe for the current session, such as
+/// `\\.\pipe\click-once-1`.
+pub fn pipe_name() -> String {
+    let mut session = 0;
+    unsafe { ProcessIdToSessionId(GetCurrentProcessId(), &mut session) };
+    format!(r"\\.\pipe\click-once-{session}")
+}
+
+/// Longest command that is accepted, so that a client can't make the program
+/// buffer an unlimited amount of text.
+const MAX_COMMAND_LEN: u64 = 256;
+
+/// The thread that runs the message loop, which is told to quit by the `QUIT`
+/// command when there is no tray.
+#[cfg_attr(
+    feature = "tray",
+    allow(dead_code, reason = "only used by certain features")
+)]
+static MAIN_THREAD_ID: AtomicU32 = AtomicU32::new(0);
+
+fn parse_button(name: &str) -> Result<MouseButton, String> {
+    MouseButton::all()
+        .iter()
+        .copied()
+        .find(|button| button.name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("unknown button \"{name}\""))
+}
+
+fn status() -> String {
+    let mut status = format!("paused={}", crate::pause::is_paused());
+    for &button in MouseButton::all() {
+        let threshold = crate::threshold(button).load(Relaxed);
+        status.push_str(&format!(" {}={threshold}", button.name()));
+    }
+    #[cfg(feature = "logging")]
+    {
+        let (blocked, total) = crate::logging::stats::total_blocked();
+        status.push_str(&format!(" blocked={blocked} total={total}"));
+    }
+    status
+}
+
+/// Exit the program the same way as when it is quit from the tray.
+fn quit() {
+    log![b"\r\nExiting since a QUIT command was received\r\n"];
+    #[cfg(feature = "tray")]
+    crate::tray::quit();
+    #[cfg(not(feature = "tray"))]
+    unsafe {
+        use windows_sys::Win32::UI::WindowsAndMessaging::{PostThreadMessageW, WM_QUIT};
+        PostThreadMessageW(MAIN_THREAD_ID.load(Relaxed), WM_QUIT, 0, 0);
+    }
+}
+
+/// Run a command and return the text that follows `OK` in the response.
+fn run_command(command: &str) -> Result<String, String> {
+    let words = command.split_whitespace().collect::<Vec<_>>();
+    match words.as_slice() {
+        [get, status]
+            if get.eq_ignore_ascii_case("GET") && status.eq_ignore_ascii_case("STATUS") =>
+        {
+            Ok(self::status())
+        }
+        [get, button] if get.eq_ignore_ascii_case("GET") => {
+            let button = parse_button(button)?;
+            Ok(crate::threshold(button).load(Relaxed).to_string())
+        }
+        [set, button, value] if set.eq_ignore_ascii_case("SET") => {
+            let button = parse_button(button)?;
+            let threshold = crate::duration::parse_millis(value)
+                .map_err(|e| format!("invalid threshold \"{value}\": {e}"))?;
+            if threshold > crate::config::MAX_THRESHOLD {
+                return Err(format!(
+                    "the threshold can be at most {} ms",
+                    crate::config::MAX_THRESHOLD
+                ));
+            }
+            crate::threshold(button).store(threshold, Relaxed);
+            log![
+                b"\r\nChanged threshold through named pipe: ",
+                button.name().as_bytes(),
+                b" = ",
+                threshold,
+                b" ms\r\n"
+            ];
+            #[cfg(feature = "tray")]
+            crate::tray::refresh_tooltip();
+            Ok(threshold.to_string())
+        }
+        [pause] if pause.eq_ignore_ascii_case("PAUSE") => {
+            crate::pause::set_paused(true);
+            Ok(String::new())
+        }
+        [resume] if resume.eq_ignore_ascii_case("RESUME") => {
+            crate::pause::set_paused(false);
+            Ok(String::new())
+        }
+        [quit] if quit.eq_ignore_ascii_case("QUIT") => Ok(String::new()),
+        [] => Err("empty command".to_owned()),
+        _ => Err(format!("unknown command \"{command}\"")),
+    }
+}
+
+/// Answer the commands of a connected client until it disconnects.
+fn serve_client(pipe: &File) -> io::Result<()> {
+    let mut reader = BufReader::new(pipe);
+    let mut writer = pipe;
+    loop {
+        let mut line = String::new();
+        if (&mut reader).take(MAX_COMMAND_LEN).read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let command = line.trim();
+        let response = match run_command(command) {
+            Ok(text) if text.is_empty() => "OK\n".to_owned(),
+            Ok(text) => format!("OK {text}\n"),
+            Err(e) => format!("ERROR {e}\n"),
+        };
+        writer.write_all(response.as_bytes())?;
+        if command.eq_ignore_ascii_case("QUIT") {
+            unsafe { FlushFileBuffers(pipe.as_raw_handle()) };
+            quit();
+            return Ok(());
+        }
+    }
+}
+
+fn run_server() {
+    let pipe_name = pipe_name();
+    let name = crate::to_utf16(&pipe_name);
+    let pipe = unsafe {
+        CreateNamedPipeW(
+            name.as_ptr(),
+            // Fails if another program already created the pipe:
+            PIPE_ACCESS_DUPLEX | FILE_FLAG_FIRST_PIPE_INSTANCE,
+            PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+            1,
+            4096,
+            4096,
+            0,
+            core::ptr::null(),
+        )
+    };
+    if pipe == INVALID_HANDLE_VALUE {
+        log_error(format_args!(
+            "Failed to create named pipe {pipe_name}: {}",
+            io::Error::last_os_error()
+        ));
+        return;
+    }
+    let pipe = unsafe { File::from_raw_handle(pipe) };
+    loop {
+        let connected = unsafe { ConnectNamedPipe(pipe.as_raw_handle(), core::ptr::null_mut()) };
+        // A client that connected before the call is also fine:
+        if connected == 0 && unsafe { GetLastError() } != ERROR_PIPE_CONNECTED {
+            log_error(format_args!(
+                "Failed to wait for a named pipe client: {}",
+                io::Error::last_os_error()
+            ));
+            return;
+        }
+        if let Err(e) = serve_client(&pipe) {
+            log![
+                b"Named pipe client failed: ",
+                e.to_string().as_bytes(),
+                b"\r\n"
+            ];
+        }
+        unsafe { DisconnectNamedPipe(pipe.as_raw_handle()) };
+    }
+}
+
+/// Connect to the running instance, waiting a while if it is busy with
+/// another client.
+fn connect() -> io::Result<File> {
+    loop {
+        match OpenOptions::new().read(true).write(true).open(pipe_name()) {
+            Err(e) if e.raw_os_error() == Some(ERROR_PIPE_BUSY as i32) => {
+                let name = crate::to_utf16(&pipe_name());
+                if unsafe { WaitNamedPipeW(name.as_ptr(), 2000) } == 0 {
+                    return Err(io::Error::last_os_error());
+                }
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Send a command to the running instance and return its response.
+fn send_command(command: &str) -> io::Result<String> {
+    let pipe = connect()?;
+    (&pipe).write_all(format!("{command}\n").as_bytes())?;
+    let mut response = String::new();
+    BufReader::new(&pipe)
+        .take(MAX_COMMAND_LEN * 4)
+        .read_line(&mut response)?;
+    Ok(response.trim().to_owned())
+}
+
+/// Translate command line arguments such as `set left 40` to a pipe command.
+fn subcommand(args: &[String]) -> Option<String> {
+    let words = args.iter().map(|arg| arg.trim()).collect::<Vec<_>>();
+    let command = match words.as_slice() {
+        [status] if status.eq_ignore_ascii_case("status") => "GET STATUS".to_owned(),
+        [get, button] if get.eq_ignore_ascii_case("get") => format!("GET {button}"),
+        [set, button, value] if set.eq_ignore_ascii_case("set") => {
+            format!("SET {button} {value}")
+        }
+        [pause] if pause.eq_ignore_ascii_case("pause") => "PAUSE".to_owned(),
+        [resume] if resume.eq_ignore_ascii_case("resume") => "RESUME".to_owned(),
+        [quit] if quit.eq_ignore_ascii_case("quit") => "QUIT".to_owned(),
+        _ => return None,
+    };
+    Some(command)
+}
+
+/// If the arguments are a subcommand such as `status` or `set left 40` then
+/// send it to the running instance, print the response and exit. Otherwise
+/// return so that the program starts normally.
+pub fn run_subcommand(args: impl Iterator<Item = String>) {
+    let Some(command) = subcommand(&args.collect::<Vec<_>>()) else {
+        return;
+    };
+    let response = send_command(&command).unwrap_or_else(|e| {
+        crate::console::report_error(&format!(
+            "Failed to connect to the running click-once instance \
+            (is it running with the ipc feature?): {e}"
+        ));
+        crate::std_polyfill::exit(1);
+    });
+    if let Some(error) = response.strip_prefix("ERROR") {
+        crate::console::report_error(error.trim());
+        crate::std_polyfill::exit(1);
+    }
+    let text = response.strip_prefix("OK").unwrap_or(&response).trim();
+    // Print one status field per line:
+    let text = if command == "GET STATUS" {
+        text.replace(' ', "\r\n").replace('=', ": ")
+    } else if text.is_empty() {
+        "OK".to_owned()
+    } else {
+        text.to_owned()
+    };
+    crate::console::report(&text);
+    crate::std_polyfill::exit(0);
+}
+
+/// Start the named pipe server. Must be called on the thread that runs the
+/// message loop.
+pub fn start() {
+    MAIN_THREAD_ID.store(unsafe { GetCurrentThreadId() }, Relaxed);
+    std::thread::spawn(run_server);
+}
@@ -0,0 +1,199 @@
+//! Debounces keyboard keys with a low level keyboard hook. A worn key switch
+//! chatters just like a mouse switch and types a letter twice, so a key press
+//! is blocked if it starts within a threshold after the same key was released.
+//! The release that belongs to a blocked press is blocked as well.
+//!
+//! Holding a key down sends repeated key down events without releases, those
+//! are never blocked. Injected keys, for example from AutoHotkey, are passed
+//! through. The hook is only installed if a keyboard threshold is set.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelkeyboardproc>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-kbdllhookstruct>
+
+use crate::log;
+use core::ffi::c_void;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, AtomicU8, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, SetWindowsHookExW, UnhookWindowsHookEx, KBDLLHOOKSTRUCT, LLKHF_INJECTED,
+    LLKHF_UP, WH_KEYBOARD_LL,
+};
+
+/// Number of virtual key codes.
+const KEY_COUNT: usize = 256;
+
+/// Used in [`KEY_THRESHOLDS`] for keys that use the global [`THRESHOLD`].
+const USE_GLOBAL: u32 = u32::MAX;
+
+/// Threshold in milliseconds for keys without their own threshold, `0`
+/// disables debouncing them.
+pub static THRESHOLD: AtomicU32 = AtomicU32::new(0);
+
+/// Threshold in milliseconds for each virtual key code.
+static KEY_THRESHOLDS: [AtomicU32; KEY_COUNT] = [const { AtomicU32::new(USE_GLOBAL) }; KEY_COUNT];
+
+const KEY_RELEASED: u8 = 0;
+const KEY_DOWN: u8 = 1;
+/// The press was blocked so its release must be blocked too.
+const KEY_BLOCKED: u8 = 2;
+
+static KEY_STATES: [AtomicU8; KEY_COUNT] = [const { AtomicU8::new(KEY_RELEASED) }; KEY_COUNT];
+
+/// `KBDLLHOOKSTRUCT.time` of the most recent release of each key.
+static LAST_RELEASE: [AtomicU32; KEY_COUNT] = [const { AtomicU32::new(0) }; KEY_COUNT];
+
+/// `true` after the first release of a key, so that the first press after
+/// startup is never blocked.
+static RELEASED_ONCE: [AtomicBool; KEY_COUNT] = [const { AtomicBool::new(false) }; KEY_COUNT];
+
+static ACCEPTED_PRESSES: AtomicU32 = AtomicU32::new(0);
+static BLOCKED_PRESSES: AtomicU32 = AtomicU32::new(0);
+
+static KEYBOARD_HOOK: AtomicPtr<c_void> = AtomicPtr::new(core::ptr::null_mut());
+
+fn threshold(key: u8) -> u32 {
+    match KEY_THRESHOLDS[usize::from(key)].load(Relaxed) {
+        USE_GLOBAL => THRESHOLD.load(Relaxed),
+        threshold => threshold,
+    }
+}
+
+/// `true` if any key is debounced, which requires the keyboard hook.
+pub fn is_enabled() -> bool {
+    THRESHOLD.load(Relaxed) != 0
+        || KEY_THRESHOLDS
+            .iter()
+            .any(|threshold| !matches!(threshold.load(Relaxed), 0 | USE_GLOBAL))
+}
+
+/// Parse a virtual key such as `A`, `F5` or a key code such as `0x20`.
+pub fn parse_key(text: &str) -> Option<u8> {
+    let text = text.trim();
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return u8::from_str_radix(hex, 16).ok();
+    }
+    match crate::hotkey::parse(text)? {
+        // Modifiers don't make sense here:
+        hotkey @ 0..=0xFF => Some(hotkey as u8),
+        _ => None,
+    }
+}
+
+/// Parse a comma separated list of per-key thresholds such as `A:50, 0x20:40`
+/// and call `set` for each of them.
+pub fn parse_key_thresholds(text: &str, set: &mut dyn FnMut(u8, u32)) -> Result<(), &'static str> {
+    for item in text.split(',').filter(|item| !item.trim().is_empty()) {
+        let (key, threshold) = item
+            .split_once(':')
+            .ok_or("expected a key and a threshold such as \"A:50\"")?;
+        let key = parse_key(key)
+            .ok_or("expected a key such as \"A\", \"F5\" or a virtual key code such as \"0x20\"")?;
+        let threshold = crate::duration::parse_millis(threshold)
+            .map_err(|_| "expected a threshold in milliseconds such as \"A:50\"")?;
+        set(key, threshold);
+    }
+    Ok(())
+}
+
+/// Use separate thresholds for some keys, see [`parse_key_thresholds`].
+pub fn set_key_thresholds(text: &str) -> Result<(), &'static str> {
+    parse_key_thresholds(text, &mut |_, _| {})?;
+    for threshold in &KEY_THRESHOLDS {
+        threshold.store(USE_GLOBAL, Relaxed);
+    }
+    parse_key_thresholds(text, &mut |key, threshold| {
+        KEY_THRESHOLDS[usize::from(key)].store(threshold, Relaxed)
+    })
+}
+
+/// The number of accepted and blocked key presses.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn stats() -> (u32, u32) {
+    (
+        ACCEPTED_PRESSES.load(Relaxed),
+        BLOCKED_PRESSES.load(Relaxed),
+    )
+}
+
+/// Decide if a key event should be blocked.
+fn should_block(event: &KBDLLHOOKSTRUCT) -> bool {
+    if event.flags & LLKHF_INJECTED != 0 || crate::pause::is_paused() {
+        return false;
+    }
+    let key = event.vkCode as u8;
+    let ix = usize::from(key);
+    let state = &KEY_STATES[ix];
+    if event.flags & LLKHF_UP != 0 {
+        if state.swap(KEY_RELEASED, Relaxed) == KEY_BLOCKED {
+            return true;
+        }
+        LAST_RELEASE[ix].store(event.time, Relaxed);
+        RELEASED_ONCE[ix].store(true, Relaxed);
+        return false;
+    }
+    if state.load(Relaxed) != KEY_RELEASED {
+        // Auto-repeat while the key is held down (or a blocked press):
+        return state.load(Relaxed) == KEY_BLOCKED;
+    }
+    let elapsed = event.time.wrapping_sub(LAST_RELEASE[ix].load(Relaxed));
+    if RELEASED_ONCE[ix].load(Relaxed) && elapsed < threshold(key) {
+        state.store(KEY_BLOCKED, Relaxed);
+        _ = BLOCKED_PRESSES.fetch_add(1, Relaxed);
+        log![
+            b"Key ",
+            u32::from(key),
+            b" press ignored (",
+            elapsed,
+            b" ms after release)\r\n"
+        ];
+        return true;
+    }
+    state.store(KEY_DOWN, Relaxed);
+    _ = ACCEPTED_PRESSES.fetch_add(1, Relaxed);
+    false
+}
+
+unsafe extern "system" fn low_level_keyboard_proc(
+    code: i32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if code >= 0
+        && should_block(&*(lparam as *const KBDLLHOOKSTRUCT))
+        && !crate::DRY_RUN.load(Relaxed)
+    {
+        return 1;
+    }
+    CallNextHookEx(core::ptr::null_mut(), code, wparam, lparam)
+}
+
+/// Install the keyboard hook if any key is debounced. Must be called on the
+/// thread that runs the message loop.
+pub fn start() {
+    if !is_enabled() {
+        return;
+    }
+    let hook = unsafe {
+        SetWindowsHookExW(
+            WH_KEYBOARD_LL,
+            Some(low_level_keyboard_proc),
+            core::ptr::null_mut(),
+            0,
+        )
+    };
+    if hook.is_null() {
+        crate::log_error("Failed to install keyboard hook");
+        return;
+    }
+    KEYBOARD_HOOK.store(hook, Relaxed);
+}
+
+/// Remove the keyboard hook if it was installed.
+pub fn stop() {
+    let hook = KEYBOARD_HOOK.swap(core::ptr::null_mut(), Relaxed);
+    if !hook.is_null() {
+        unsafe { UnhookWindowsHookEx(hook) };
+    }
+}
@@ -0,0 +1,11 @@
+//! Platform independent parts of click-once that don't need Windows, so that
+//! they can be compiled and tested on any target. The program itself is the
+//! `click-once` binary.
+#![cfg_attr(not(test), no_std)]
+
+pub mod debounce;
+pub mod device_id;
+pub mod duration;
+pub mod ring_buffer;
+pub mod suggest;
+pub mod win_api;
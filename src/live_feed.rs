@@ -0,0 +1,387 @@
+//! Streams every decision of the mouse hook as JSON over a WebSocket on
+//! localhost, for example to drive a live visualizer in a browser:
+//!
+//! ```js
+//! const socket = new WebSocket("ws://127.0.0.1:8765/events");
+//! socket.onmessage = (message) => console.log(JSON.parse(message.data));
+//! ```
+//!
+//! Each message describes one mouse event:
+//!
+//! ```json
+//! {"button":"left","direction":"down","time":1234567,"blocked":true,"dropped":true,"deferred":false,"double_up":false,"time_since_last_event":12,"click_id":41}
+//! ```
+//!
+//! Events that belong to the same click, meaning an accepted down event, its
+//! up event and any blocked bounces, have the same `click_id`.
+//!
+//! The same server also hosts a dashboard at `http://127.0.0.1:8765/` that
+//! shows live statistics, a histogram of the time between clicks and controls
+//! for the thresholds.
+//!
+//! Events are handed to a background thread through a bounded channel so that
+//! the hook never waits for the network. Events are dropped if clients can't
+//! keep up.
+//!
+//! # References
+//!
+//! - <https://datatracker.ietf.org/doc/html/rfc6455>
+
+use crate::debounce::{Decision, MouseButton, MouseDirection};
+use crate::log_error;
+use core::sync::atomic::{AtomicU16, Ordering::Relaxed};
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use windows_sys::Win32::Security::Cryptography::{BCryptHash, BCRYPT_SHA1_ALG_HANDLE};
+
+pub const DEFAULT_PORT: u16 = 8765;
+
+/// A page that shows live statistics and controls, see `--live-feed`.
+const DASHBOARD: &str = include_str!("dashboard.html");
+
+/// Appended to the client's key when computing the handshake response.
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// The port to listen on, `0` if the feed is disabled.
+static PORT: AtomicU16 = AtomicU16::new(0);
+
+/// Sends events to the thread that writes them to clients, only set once the
+/// server is running.
+static SENDER: OnceLock<SyncSender<FeedEvent>> = OnceLock::new();
+
+/// Clients that completed the WebSocket handshake.
+static CLIENTS: Mutex<Vec<TcpStream>> = Mutex::new(Vec::new());
+
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn port() -> u16 {
+    PORT.load(Relaxed)
+}
+
+pub fn set_port(port: u16) {
+    PORT.store(port, Relaxed);
+}
+
+/// A single decision made by the mouse hook.
+#[derive(Clone, Copy)]
+pub struct FeedEvent {
+    pub button: MouseButton,
+    pub direction: MouseDirection,
+    /// Timestamp in milliseconds.
+    pub time: u32,
+    pub decision: Decision,
+    /// Groups the events of a click, see [`crate::click_id`].
+    pub click_id: u32,
+}
+impl FeedEvent {
+    fn to_json(self) -> String {
+        format!(
+            "{{\"button\":\"{}\",\"direction\":\"{}\",\"time\":{},\"blocked\":{},\
+            \"dropped\":{},\"deferred\":{},\"double_up\":{},\"time_since_last_event\":{},\"click_id\":{}}}",
+            self.button.name(),
+            self.direction.name(),
+            self.time,
+            self.decision.blocked,
+            self.decision.is_dropped(),
+            self.decision.deferred,
+            self.decision.double_up,
+            self.decision.time_since_last_event,
+            self.click_id,
+        )
+    }
+}
+
+/// Send an event to all connected clients if the feed is running.
+#[inline(always)]
+pub fn record(event: FeedEvent) {
+    if let Some(sender) = SENDER.get() {
+        // Never block the hook, drop the event if the channel is full:
+        _ = sender.try_send(event);
+    }
+}
+
+fn sha1(data: &[u8]) -> Result<[u8; 20], String> {
+    let mut hash = [0; 20];
+    let status = unsafe {
+        BCryptHash(
+            BCRYPT_SHA1_ALG_HANDLE,
+            core::ptr::null(),
+            0,
+            data.as_ptr(),
+            data.len() as u32,
+            hash.as_mut_ptr(),
+            hash.len() as u32,
+        )
+    };
+    if status != 0 {
+        return Err(format!(
+            "Failed to compute SHA-1 hash: NTSTATUS {status:#x}"
+        ));
+    }
+    Ok(hash)
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut text = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for index in 0..4 {
+            if index <= chunk.len() {
+                text.push(ALPHABET[(bits >> (18 - 6 * index) & 0x3f) as usize] as char);
+            } else {
+                text.push('=');
+            }
+        }
+    }
+    text
+}
+
+/// Encode a WebSocket text frame. Frames sent by servers are never masked.
+fn text_frame(text: &str) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(text.len() + 10);
+    // FIN bit and the text opcode:
+    frame.push(0x81);
+    if text.len() < 126 {
+        frame.push(text.len() as u8);
+    } else if let Ok(len) = u16::try_from(text.len()) {
+        frame.push(126);
+        frame.extend_from_slice(&len.to_be_bytes());
+    } else {
+        frame.push(127);
+        frame.extend_from_slice(&(text.len() as u64).to_be_bytes());
+    }
+    frame.extend_from_slice(text.as_bytes());
+    frame
+}
+
+/// A parsed HTTP request, only the parts that the server uses.
+struct Request {
+    method: String,
+    path: String,
+    websocket_key: Option<String>,
+    origin: Option<String>,
+    body: String,
+}
+
+fn read_request(stream: &mut TcpStream) -> Option<Request> {
+    const MAX_SIZE: usize = 16 * 1024;
+
+    let mut data = Vec::new();
+    let mut buffer = [0; 1024];
+    let header_end = loop {
+        if let Some(index) = data.windows(4).position(|window| window == b"\r\n\r\n") {
+            break index + 4;
+        }
+        let read = stream.read(&mut buffer).ok().filter(|&read| read > 0)?;
+        data.extend_from_slice(&buffer[..read]);
+        if data.len() > MAX_SIZE {
+            return None;
+        }
+    };
+    let head = String::from_utf8_lossy(&data[..header_end]).into_owned();
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split(' ');
+    let mut request = Request {
+        method: request_line.next()?.to_owned(),
+        path: request_line.next()?.to_owned(),
+        websocket_key: None,
+        origin: None,
+        body: String::new(),
+    };
+    let mut content_length = 0;
+    for line in lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("Sec-WebSocket-Key") {
+            request.websocket_key = Some(value.to_owned());
+        } else if name.eq_ignore_ascii_case("Origin") {
+            request.origin = Some(value.to_owned());
+        } else if name.eq_ignore_ascii_case("Content-Length") {
+            content_length = value.parse::<usize>().ok().filter(|&len| len <= MAX_SIZE)?;
+        }
+    }
+    while data.len() < header_end + content_length {
+        let read = stream.read(&mut buffer).ok().filter(|&read| read > 0)?;
+        data.extend_from_slice(&buffer[..read]);
+    }
+    request.body =
+        String::from_utf8_lossy(&data[header_end..header_end + content_length]).into_owned();
+    Some(request)
+}
+
+fn response(status: &str, content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\n\
+        Content-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+}
+
+/// The current settings that the dashboard displays, as JSON.
+fn settings_json() -> String {
+    let thresholds = MouseButton::all()
+        .iter()
+        .map(|&button| {
+            format!(
+                "\"{}\":{}",
+                button.name(),
+                crate::threshold(button).load(Relaxed)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    format!(
+        "{{\"paused\":{},\"thresholds\":{{{thresholds}}}}}",
+        crate::pause::is_paused()
+    )
+}
+
+/// Apply a change from the dashboard's controls. The body is a single
+/// `name=value` pair, for example `pause=true` or `left=50`.
+fn apply_control(body: &str) -> Result<(), String> {
+    let (name, value) = body
+        .trim()
+        .split_once('=')
+        .ok_or_else(|| format!("Expected name=value but got \"{body}\""))?;
+    if name == "pause" {
+        let paused = value
+            .parse::<bool>()
+            .map_err(|_| format!("Invalid value for pause: \"{value}\""))?;
+        crate::pause::set_paused(paused);
+        return Ok(());
+    }
+    let button = MouseButton::all()
+        .iter()
+        .copied()
+        .find(|button| button.name() == name)
+        .ok_or_else(|| format!("Unknown setting \"{name}\""))?;
+    let threshold = value
+        .parse::<u32>()
+        .map_err(|_| format!("Invalid threshold: \"{value}\""))?;
+    crate::threshold(button).store(threshold, Relaxed);
+    Ok(())
+}
+
+/// `true` if the origin is the dashboard served by this server.
+fn is_own_origin(origin: &str, port: u16) -> bool {
+    [
+        format!("http://127.0.0.1:{port}"),
+        format!("http://localhost:{port}"),
+    ]
+    .iter()
+    .any(|allowed| origin.eq_ignore_ascii_case(allowed))
+}
+
+/// Web pages from other sites must not be able to watch the user's clicks, so
+/// only allow the dashboard served by this server and clients that aren't
+/// browsers. The `null` origin is rejected since sandboxed frames on any site
+/// also send it, not only local files.
+fn is_allowed_origin(origin: Option<&str>, port: u16) -> bool {
+    origin.is_none_or(|origin| is_own_origin(origin, port))
+}
+
+fn handle_connection(mut stream: TcpStream, port: u16) -> Result<(), String> {
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .and_then(|()| stream.set_write_timeout(Some(Duration::from_secs(1))))
+        .map_err(|e| format!("Failed to configure live feed connection: {e}"))?;
+    let Some(request) = read_request(&mut stream) else {
+        _ = stream.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n");
+        return Ok(());
+    };
+    let response = match (
+        request.method.as_str(),
+        request.path.as_str(),
+        &request.websocket_key,
+    ) {
+        _ if !is_allowed_origin(request.origin.as_deref(), port) => {
+            response("403 Forbidden", "text/plain", "")
+        }
+        ("GET", "/events", Some(key)) => {
+            let accept = base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes())?);
+            format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+            )
+        }
+        ("GET", "/", _) => response("200 OK", "text/html; charset=utf-8", DASHBOARD),
+        ("GET", "/settings", _) => response("200 OK", "application/json", &settings_json()),
+        // Changes must come from the dashboard itself, browsers always send
+        // the origin for POST requests:
+        ("POST", "/control", _)
+            if request
+                .origin
+                .as_deref()
+                .is_some_and(|origin| is_own_origin(origin, port)) =>
+        {
+            match apply_control(&request.body) {
+                Ok(()) => response("200 OK", "application/json", &settings_json()),
+                Err(e) => response("400 Bad Request", "text/plain", &e),
+            }
+        }
+        ("POST", "/control", _) => response("403 Forbidden", "text/plain", ""),
+        _ => response("404 Not Found", "text/plain", ""),
+    };
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|e| format!("Failed to respond to live feed client: {e}"))?;
+    if response.starts_with("HTTP/1.1 101") {
+        CLIENTS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(stream);
+    }
+    Ok(())
+}
+
+/// Start serving the live feed if it is enabled and not already running.
+pub fn start() {
+    let port = PORT.load(Relaxed);
+    if port == 0 || SENDER.get().is_some() {
+        return;
+    }
+    let listener = match TcpListener::bind((Ipv4Addr::LOCALHOST, port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log_error(format_args!(
+                "Failed to serve the live feed on port {port}: {e}"
+            ));
+            return;
+        }
+    };
+
+    let (sender, receiver) = sync_channel::<FeedEvent>(1024);
+    if SENDER.set(sender).is_err() {
+        return;
+    }
+    std::thread::spawn(move || {
+        for event in receiver {
+            let frame = text_frame(&event.to_json());
+            // Clients that disconnected (or stopped reading) are removed:
+            CLIENTS
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .retain_mut(|client| client.write_all(&frame).is_ok());
+        }
+    });
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(|e| format!("Failed to accept live feed client: {e}"))
+                .and_then(|stream| handle_connection(stream, port));
+            if let Err(e) = result {
+                log_error(e);
+            }
+        }
+    });
+}
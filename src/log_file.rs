@@ -0,0 +1,205 @@
+//! Writes the log to a file, in addition to or instead of a console window.
+//! When the file grows larger than [`MAX_SIZE`] it is renamed by appending
+//! `.1` to its name, replacing the previous such file, and a new file is
+//! started. So at most twice that size is used by long running sessions.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/fileapi/nf-fileapi-createfilew>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winbase/nf-winbase-movefileexw>
+
+use crate::log_error;
+use core::hint::spin_loop;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU16, AtomicU64, AtomicUsize, Ordering::*};
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::Storage::FileSystem::{
+    CreateFileW, DeleteFileW, GetFileSizeEx, MoveFileExW, WriteFile, FILE_APPEND_DATA,
+    FILE_ATTRIBUTE_NORMAL, FILE_SHARE_DELETE, FILE_SHARE_READ, MOVEFILE_REPLACE_EXISTING,
+    OPEN_ALWAYS,
+};
+
+/// The file is rotated when it grows larger than this many bytes.
+const MAX_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Suffix of the file that holds the previous part of the log.
+const BACKUP_SUFFIX: &str = ".1";
+
+/// Longest supported path, in UTF-16 code units.
+const MAX_PATH_LEN: usize = 260;
+
+/// UTF-16 path of the log file, only written before logging starts.
+static PATH: [AtomicU16; MAX_PATH_LEN] = [const { AtomicU16::new(0) }; MAX_PATH_LEN];
+static PATH_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Handle of the open log file, null if there is none.
+static FILE: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Bytes in the log file, used to decide when to rotate it.
+static SIZE: AtomicU64 = AtomicU64::new(0);
+
+/// Held while writing so that lines from different threads aren't mixed with
+/// a rotation.
+static WRITING: AtomicBool = AtomicBool::new(false);
+
+/// The null terminated path of the log file, optionally with a suffix.
+fn path(suffix: &str) -> [u16; MAX_PATH_LEN + 4] {
+    let mut path = [0; MAX_PATH_LEN + 4];
+    let len = PATH_LEN.load(Relaxed);
+    for (unit, stored) in path.iter_mut().zip(&PATH[..len]) {
+        *unit = stored.load(Relaxed);
+    }
+    for (unit, suffix) in path[len..].iter_mut().zip(suffix.encode_utf16()) {
+        *unit = suffix;
+    }
+    path
+}
+
+fn open() -> Option<HANDLE> {
+    let file = unsafe {
+        CreateFileW(
+            path("").as_ptr(),
+            FILE_APPEND_DATA,
+            // Allow reading the log and rotating it while it is open:
+            FILE_SHARE_READ | FILE_SHARE_DELETE,
+            ptr::null(),
+            OPEN_ALWAYS,
+            FILE_ATTRIBUTE_NORMAL,
+            ptr::null_mut(),
+        )
+    };
+    if file == INVALID_HANDLE_VALUE {
+        return None;
+    }
+    let mut size = 0;
+    if unsafe { GetFileSizeEx(file, &mut size) } == 0 {
+        size = 0;
+    }
+    SIZE.store(size.max(0) as u64, Relaxed);
+    Some(file)
+}
+
+/// Write the log to a file at `path`. Returns `false` if the path is too long
+/// or the file couldn't be opened.
+pub fn set_path(path: &str) -> bool {
+    let len = path.encode_utf16().count();
+    if path.is_empty() || len >= MAX_PATH_LEN {
+        return false;
+    }
+    for (stored, unit) in PATH.iter().zip(path.encode_utf16()) {
+        stored.store(unit, Relaxed);
+    }
+    PATH_LEN.store(len, Relaxed);
+    let Some(file) = open() else {
+        return false;
+    };
+    let previous = FILE.swap(file, AcqRel);
+    if !previous.is_null() {
+        unsafe { CloseHandle(previous) };
+    }
+    true
+}
+
+/// The paths of the log file and its previous part, empty if no log file was
+/// set.
+#[cfg(feature = "std")]
+pub fn paths() -> Vec<String> {
+    if PATH_LEN.load(Relaxed) == 0 {
+        return Vec::new();
+    }
+    ["", BACKUP_SUFFIX]
+        .into_iter()
+        .map(|suffix| {
+            let path = path(suffix);
+            let len = path
+                .iter()
+                .position(|&unit| unit == 0)
+                .unwrap_or(path.len());
+            String::from_utf16_lossy(&path[..len])
+        })
+        .collect()
+}
+
+/// `true` if the log is written to a file.
+pub fn is_open() -> bool {
+    !FILE.load(Acquire).is_null()
+}
+
+/// Stop writing to the log file and delete it together with its previous
+/// part. Returns the number of deleted files.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn delete() -> usize {
+    while WRITING
+        .compare_exchange_weak(false, true, Acquire, Relaxed)
+        .is_err()
+    {
+        spin_loop();
+    }
+    let file = FILE.swap(ptr::null_mut(), AcqRel);
+    if !file.is_null() {
+        unsafe { CloseHandle(file) };
+    }
+    WRITING.store(false, Release);
+    if PATH_LEN.load(Relaxed) == 0 {
+        return 0;
+    }
+    ["", BACKUP_SUFFIX]
+        .into_iter()
+        .filter(|suffix| unsafe { DeleteFileW(path(suffix).as_ptr()) } != 0)
+        .count()
+}
+
+/// Start a new file after renaming the current one. Must only be called while
+/// [`WRITING`] is held.
+fn rotate(file: HANDLE) {
+    unsafe { CloseHandle(file) };
+    let moved = unsafe {
+        MoveFileExW(
+            path("").as_ptr(),
+            path(BACKUP_SUFFIX).as_ptr(),
+            MOVEFILE_REPLACE_EXISTING,
+        )
+    };
+    let file = open().unwrap_or(ptr::null_mut());
+    FILE.store(file, Release);
+    if file.is_null() {
+        log_error("Failed to open a new log file, no longer logging to a file");
+    } else if moved == 0 {
+        log_error("Failed to rotate the log file");
+    }
+}
+
+/// Append text to the log file. The file is only rotated after a line ends.
+pub fn write(mut text: &[u8]) {
+    while WRITING
+        .compare_exchange_weak(false, true, Acquire, Relaxed)
+        .is_err()
+    {
+        spin_loop();
+    }
+    let file = FILE.load(Acquire);
+    if !file.is_null() {
+        let ends_line = text.ends_with(b"\n");
+        while !text.is_empty() {
+            let mut written = 0;
+            let result = unsafe {
+                WriteFile(
+                    file,
+                    text.as_ptr(),
+                    text.len() as u32,
+                    &mut written,
+                    ptr::null_mut(),
+                )
+            };
+            if result == 0 || written == 0 {
+                break;
+            }
+            SIZE.fetch_add(written.into(), Relaxed);
+            text = &text[written as usize..];
+        }
+        if ends_line && SIZE.load(Relaxed) >= MAX_SIZE {
+            rotate(file);
+        }
+    }
+    WRITING.store(false, Release);
+}
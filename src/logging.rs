@@ -10,8 +10,9 @@ macro_rules! log_array {
         ]
     };
 }
+pub(crate) use log_array;
 
-#[cfg(feature = "tray")] // Note: implies "std" feature
+#[cfg(any(feature = "tray", feature = "com", feature = "ipc"))] // Note: all imply "std" feature
 pub mod stats {
     //! Track statistics and allow printing them. This module is only useful
     //! when we have a system tray since otherwise there is no way to interact
@@ -41,6 +42,11 @@ pub mod stats {
                 _ = self.unblocked.fetch_add(1, Relaxed);
             }
         }
+        #[allow(dead_code, reason = "only used by certain features")]
+        pub fn reset(&self) {
+            self.unblocked.store(0, Relaxed);
+            self.blocked.store(0, Relaxed);
+        }
         pub fn get(button: MouseButton, direction: MouseDirection) -> &'static Self {
             macro_rules! define_stats {
                 () => {{
@@ -55,6 +61,10 @@ pub mod stats {
                 (MouseButton::Right, MouseDirection::Down) => define_stats!(),
                 (MouseButton::Middle, MouseDirection::Up) => define_stats!(),
                 (MouseButton::Middle, MouseDirection::Down) => define_stats!(),
+                (MouseButton::X1, MouseDirection::Up) => define_stats!(),
+                (MouseButton::X1, MouseDirection::Down) => define_stats!(),
+                (MouseButton::X2, MouseDirection::Up) => define_stats!(),
+                (MouseButton::X2, MouseDirection::Down) => define_stats!(),
             }
         }
         fn sum_stats(
@@ -116,9 +126,269 @@ pub mod stats {
         }
     }
 
+    /// Statistics about how long it takes for mouse events to be delivered to
+    /// our hook, measured as the difference between the event's timestamp
+    /// (`MSLLHOOKSTRUCT.time`) and the tick count when our hook is called.
+    /// High values indicate that other hooks or system load is adding input
+    /// lag.
+    pub struct DeliveryLatencyStats {
+        max: AtomicU32,
+        /// The first bucket counts latencies of 0 ms and bucket `i` counts
+        /// latencies in the range `2^(i-1)..2^i` ms. The last bucket also
+        /// counts all larger latencies.
+        buckets: [AtomicU32; Self::BUCKETS],
+    }
+    impl DeliveryLatencyStats {
+        const BUCKETS: usize = 12;
+
+        pub const fn new() -> Self {
+            Self {
+                max: AtomicU32::new(0),
+                buckets: [const { AtomicU32::new(0) }; Self::BUCKETS],
+            }
+        }
+        #[inline(always)]
+        pub fn record(&self, latency: u32) {
+            // Event timestamps from the future would wrap around to huge
+            // values, treat them as no delay:
+            let latency = if latency > i32::MAX as u32 {
+                0
+            } else {
+                latency
+            };
+
+            let bucket = match latency.checked_ilog2() {
+                None => 0,
+                Some(log2) => (log2 as usize + 1).min(Self::BUCKETS - 1),
+            };
+            _ = self.buckets[bucket].fetch_add(1, Relaxed);
+            _ = self.max.fetch_max(latency, Relaxed);
+        }
+        /// The largest latency that is counted by a bucket.
+        fn bucket_upper_bound(bucket: usize) -> u32 {
+            (1 << bucket) - 1
+        }
+        /// Get an upper bound for the latency that `percent` of the events
+        /// were below. `None` if the percentile falls within the last bucket
+        /// that has no upper bound.
+        fn percentile(&self, percent: u64) -> Option<u32> {
+            let counts = self
+                .buckets
+                .each_ref()
+                .map(|count| count.load(Relaxed) as u64);
+            let total: u64 = counts.iter().sum();
+            let mut cumulative = 0;
+            for (bucket, count) in counts.iter().enumerate() {
+                cumulative += count;
+                if cumulative * 100 >= total * percent {
+                    return if bucket == Self::BUCKETS - 1 {
+                        None
+                    } else {
+                        Some(Self::bucket_upper_bound(bucket))
+                    };
+                }
+            }
+            None
+        }
+        fn log(&self, log_write: LogWriteCallback) {
+            log_write(b"Hook delivery latency: max ".into());
+            log_write(self.max.load(Relaxed).into());
+            log_write(b" ms".into());
+            for (text, percent) in [(b", p50 ", 50), (b", p95 ", 95), (b", p99 ", 99)] {
+                log_write(text.into());
+                match self.percentile(percent) {
+                    Some(upper_bound) => {
+                        log_write(b"<= ".into());
+                        log_write(upper_bound.into());
+                    }
+                    None => {
+                        log_write(b"> ".into());
+                        log_write(Self::bucket_upper_bound(Self::BUCKETS - 2).into());
+                    }
+                }
+                log_write(b" ms".into());
+            }
+            log_write(b"\r\n".into());
+        }
+    }
+    pub static DELIVERY_LATENCY: DeliveryLatencyStats = DeliveryLatencyStats::new();
+
+    /// Histogram of the time between a down event and the previous event of
+    /// the same button. Bounces show up as a cluster of very short intervals
+    /// that is separate from intentional double-clicks.
+    pub struct IntervalHistogram {
+        /// Indexed by button, then bin and finally `0` for accepted and `1`
+        /// for blocked events.
+        bins: [[[AtomicU32; 2]; Self::BINS]; MouseButton::COUNT],
+    }
+    impl IntervalHistogram {
+        /// Milliseconds covered by each bin.
+        pub const BIN_WIDTH: u32 = 5;
+        /// Longer intervals aren't counted since they can't be bounces.
+        pub const BINS: usize = 40;
+
+        pub const fn new() -> Self {
+            Self {
+                bins: [const { [const { [const { AtomicU32::new(0) }; 2] }; Self::BINS] };
+                    MouseButton::COUNT],
+            }
+        }
+        #[inline(always)]
+        pub fn record(&self, button: MouseButton, interval: u32, blocked: bool) {
+            if let Some(bin) = self.bins[button as usize].get((interval / Self::BIN_WIDTH) as usize)
+            {
+                _ = bin[usize::from(blocked)].fetch_add(1, Relaxed);
+            }
+        }
+        /// The number of accepted and blocked events in each bin.
+        #[allow(dead_code, reason = "only used by certain features")]
+        pub fn get(&self, button: MouseButton) -> [(u32, u32); Self::BINS] {
+            self.bins[button as usize]
+                .each_ref()
+                .map(|[accepted, blocked]| (accepted.load(Relaxed), blocked.load(Relaxed)))
+        }
+        fn reset(&self, button: MouseButton) {
+            self.bins[button as usize]
+                .iter()
+                .flatten()
+                .for_each(|count| count.store(0, Relaxed));
+        }
+    }
+    pub static INTERVALS: IntervalHistogram = IntervalHistogram::new();
+
+    /// Suggest a threshold for a button from the observed intervals. It is
+    /// kept below the shortest press that was marked as a real double-click.
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub fn suggested_threshold(button: MouseButton) -> Option<u32> {
+        let counts = INTERVALS
+            .get(button)
+            .map(|(accepted, blocked)| accepted + blocked);
+        let suggestion =
+            click_once::suggest::suggest_threshold(&counts, IntervalHistogram::BIN_WIDTH)?;
+        match crate::feedback::marks(button) {
+            (_, Some(shortest)) => Some(suggestion.min(shortest.saturating_sub(1))),
+            (_, None) => Some(suggestion),
+        }
+    }
+
+    /// The number of blocked events and the total number of events for all
+    /// buttons.
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub fn total_blocked() -> (u32, u32) {
+        let sum =
+            MouseEventStats::sum_stats(MouseButton::all().iter().copied().flat_map(|button| {
+                [button]
+                    .into_iter()
+                    .cycle()
+                    .zip(MouseDirection::all().iter().copied())
+            }));
+        let blocked = sum.blocked.load(Relaxed);
+        (blocked, sum.unblocked.load(Relaxed) + blocked)
+    }
+
+    /// Reset the counters for a single button, for example after replacing
+    /// its switch. Other buttons and the delivery latency statistics are kept.
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub fn reset_button(button: MouseButton) {
+        for &direction in MouseDirection::all() {
+            MouseEventStats::get(button, direction).reset();
+        }
+        INTERVALS.reset(button);
+        crate::feedback::reset(button);
+    }
+
+    fn log_injected_stats(log_write: LogWriteCallback) {
+        use crate::injected::{count, InjectedKind, InjectedPolicy};
+
+        for kind in InjectedKind::ALL {
+            log_write(match kind {
+                InjectedKind::Injected => b"Injected events: ".into(),
+                InjectedKind::LowerIl => b"Lower integrity level injected events: ".into(),
+            });
+            for (ix, policy) in InjectedPolicy::ALL.into_iter().enumerate() {
+                if ix != 0 {
+                    log_write(b", ".into());
+                }
+                log_write(count(kind, policy).into());
+                log_write(match policy {
+                    InjectedPolicy::Debounce => b" debounced".into(),
+                    InjectedPolicy::Pass => b" passed".into(),
+                    InjectedPolicy::Block => b" blocked".into(),
+                });
+            }
+            log_write(b"\r\n".into());
+        }
+    }
+
+    fn log_exemption_stats(log_write: LogWriteCallback) {
+        use crate::exemptions::{count, Exemption};
+
+        log_write(b"Exempt events (not debounced): ".into());
+        for (ix, rule) in Exemption::ALL.into_iter().enumerate() {
+            if ix != 0 {
+                log_write(b", ".into());
+            }
+            log_write(count(rule).into());
+            log_write(match rule {
+                Exemption::Injected => b" injected".into(),
+                Exemption::LowerIlInjected => b" lower integrity level injected".into(),
+                Exemption::AbsoluteDevice => b" from absolute pointing devices".into(),
+                Exemption::Controller => b" from controller emulators".into(),
+                Exemption::Touch => b" from touch input".into(),
+                Exemption::OtherDevice => b" from other devices".into(),
+                Exemption::BypassKey => b" while the bypass key was held".into(),
+                Exemption::Application => b" in excluded applications".into(),
+                Exemption::FullscreenGame => b" in fullscreen games".into(),
+            });
+        }
+        log_write(b"\r\n".into());
+    }
+
+    fn log_wheel_stats(log_write: LogWriteCallback) {
+        use crate::wheel::{HORIZONTAL, VERTICAL};
+
+        for (text, wheel) in [
+            (b"Blocked wheel reversals: ".as_slice(), &VERTICAL),
+            (b"Blocked horizontal wheel reversals: ", &HORIZONTAL),
+        ] {
+            if wheel.threshold().load(Relaxed) != 0 {
+                log_array![text, wheel.blocked(), b"\r\n"]
+                    .into_iter()
+                    .for_each(&mut *log_write);
+            }
+        }
+    }
+
+    fn log_dropped_stats(log_write: LogWriteCallback) {
+        log_array![
+            b"Events not logged since the log queue was full: ",
+            super::dropped_events(),
+            b"\r\n"
+        ]
+        .into_iter()
+        .for_each(&mut *log_write);
+    }
+
+    fn log_keyboard_stats(log_write: LogWriteCallback) {
+        if !crate::keyboard::is_enabled() {
+            return;
+        }
+        let (accepted, blocked) = crate::keyboard::stats();
+        log_array![
+            b"Blocked key presses: ",
+            blocked,
+            b" of ",
+            accepted + blocked,
+            b"\r\n"
+        ]
+        .into_iter()
+        .for_each(&mut *log_write);
+    }
+
     /// This function prints statistics about blocked clicks when a logging session
     /// is started via the tray icon.
     pub fn log_current_stats(log_write: LogWriteCallback) {
+        let _output = super::lock_output();
         fn log_stats_total_clicks(log_write: LogWriteCallback) {
             let sum =
                 MouseEventStats::sum_stats(MouseButton::all().iter().copied().flat_map(|button| {
@@ -137,6 +407,8 @@ pub mod stats {
                 MouseButton::Left => b"\tLeft button:   ",
                 MouseButton::Right => b"\tRight button:  ",
                 MouseButton::Middle => b"\tMiddle button: ",
+                MouseButton::X1 => b"\tX1 button:     ",
+                MouseButton::X2 => b"\tX2 button:     ",
             };
             log_write(button_text.into());
 
@@ -147,6 +419,18 @@ pub mod stats {
                     .zip(MouseDirection::all().iter().copied()),
             );
             all_dirs.log(log_write);
+            let (marks, shortest) = crate::feedback::marks(button);
+            if let Some(shortest) = shortest {
+                log_array![
+                    b", ",
+                    marks,
+                    b" marked as real double-clicks (shortest ",
+                    shortest,
+                    b" ms, keep the threshold below it)",
+                ]
+                .into_iter()
+                .for_each(&mut *log_write);
+            }
             log_write(b"\r\n".into());
         }
         fn log_stats_for_button_with_direction(
@@ -167,6 +451,12 @@ pub mod stats {
         log_write(b"\r\nStatistics:\r\n".into());
 
         log_stats_total_clicks(log_write);
+        DELIVERY_LATENCY.log(log_write);
+        log_injected_stats(log_write);
+        log_exemption_stats(log_write);
+        log_wheel_stats(log_write);
+        log_keyboard_stats(log_write);
+        log_dropped_stats(log_write);
         for &button in MouseButton::all() {
             log_stats_for_button(button, log_write);
             for &dir in MouseDirection::all() {
@@ -178,23 +468,228 @@ pub mod stats {
     }
 }
 
+pub use crate::debounce::{MouseButton, MouseDirection};
+
 use crate::{log, log_error};
-use core::sync::atomic::{AtomicBool, Ordering::*};
+use click_once::ring_buffer::RingBuffer;
+use core::cell::UnsafeCell;
+use core::ffi::c_void;
+use core::marker::PhantomData;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicI64, AtomicPtr, AtomicU32, AtomicU8, Ordering::*};
+use windows_sys::Wdk::System::SystemServices::RtlGetVersion;
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, SYSTEMTIME};
+use windows_sys::Win32::Storage::FileSystem::WriteFile;
 use windows_sys::Win32::System::Console::{
     AllocConsole, AttachConsole, FreeConsole, GetStdHandle, SetConsoleTextAttribute, WriteConsoleA,
     ATTACH_PARENT_PROCESS, FOREGROUND_BLUE, FOREGROUND_GREEN, FOREGROUND_INTENSITY, FOREGROUND_RED,
     STD_OUTPUT_HANDLE,
 };
+use windows_sys::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows_sys::Win32::System::SystemInformation::{GetLocalTime, OSVERSIONINFOW};
+use windows_sys::Win32::System::Threading::{
+    CreateEventW, CreateThread, GetCurrentThreadId, SetEvent, SwitchToThread, WaitForSingleObject,
+    INFINITE,
+};
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::GetDoubleClickTime;
+use windows_sys::Win32::UI::Input::{GetRawInputDeviceList, RAWINPUTDEVICELIST, RIM_TYPEMOUSE};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    GetSystemMetrics, SystemParametersInfoW, SM_CMOUSEBUTTONS, SM_CXDOUBLECLK, SM_CYDOUBLECLK,
+    SM_SWAPBUTTON, SPI_GETMOUSECLICKLOCK, SPI_GETMOUSECLICKLOCKTIME,
+};
 
 /// The console window only exists in debug builds with `std` feature since that
 /// is when we disable the: windows_subsystem = `windows` (also see the build
 /// script were we also specify this subsystem).
 static SHOULD_LOG: AtomicBool = AtomicBool::new(cfg!(all(debug_assertions, feature = "std")));
 
+/// `true` if log messages are written to a console window or a file.
 pub fn is_logging() -> bool {
+    has_console() || crate::log_file::is_open()
+}
+
+/// `true` if log messages are written to a console window.
+pub fn has_console() -> bool {
     SHOULD_LOG.load(Acquire)
 }
 
+/// If `true` then log messages include extra details, such as a high resolution
+/// time delta measured using `QueryPerformanceCounter`.
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn is_verbose() -> bool {
+    VERBOSE.load(Relaxed)
+}
+
+pub fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, Relaxed);
+}
+
+/// If `false` then colors are never written, see <https://no-color.org/>.
+static USE_COLOR: AtomicBool = AtomicBool::new(true);
+
+pub fn set_use_color(enabled: bool) {
+    USE_COLOR.store(enabled, Relaxed);
+}
+
+/// If `false` then log lines don't start with the local time.
+static USE_TIMESTAMPS: AtomicBool = AtomicBool::new(true);
+
+/// Set once the program has started, so that the output of commands such as
+/// `doctor` doesn't include timestamps.
+static TIMESTAMPS_STARTED: AtomicBool = AtomicBool::new(false);
+
+pub fn set_use_timestamps(enabled: bool) {
+    USE_TIMESTAMPS.store(enabled, Relaxed);
+}
+
+/// Start lines that are logged from now on with the local time.
+pub fn start_timestamps() {
+    TIMESTAMPS_STARTED.store(true, Relaxed);
+}
+
+/// The local time as `HH:MM:SS.mmm ` using `GetLocalTime`.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/sysinfoapi/nf-sysinfoapi-getlocaltime>
+fn timestamp() -> [u8; 13] {
+    let mut time: SYSTEMTIME = unsafe { core::mem::zeroed() };
+    unsafe { GetLocalTime(&mut time) };
+    let mut text = *b"00:00:00.000 ";
+    for (digits, mut value) in [
+        (0..2, time.wHour),
+        (3..5, time.wMinute),
+        (6..8, time.wSecond),
+        (9..12, time.wMilliseconds),
+    ] {
+        for digit in text[digits].iter_mut().rev() {
+            *digit = b'0' + (value % 10) as u8;
+            value /= 10;
+        }
+    }
+    text
+}
+
+/// Controls how context that might contain private information, such as
+/// process names and window titles, is written to the log. Logs are often
+/// shared in issues so such context is only written as-is if the user
+/// explicitly opts in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ContextPrivacy {
+    /// Don't write the context at all.
+    Omit,
+    /// Write a short hash of the context. This makes it possible to tell if two
+    /// events happened in the same window without revealing its title.
+    Hash,
+    /// Write the context as-is.
+    Full,
+}
+impl ContextPrivacy {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            v if v.eq_ignore_ascii_case("omit") => Self::Omit,
+            v if v.eq_ignore_ascii_case("hash") => Self::Hash,
+            v if v.eq_ignore_ascii_case("full") => Self::Full,
+            _ => return None,
+        })
+    }
+
+    /// Get the text that should be logged for some private context. The
+    /// `buffer` is used to store the hash.
+    fn apply<'a>(self, text: &'a [u8], buffer: &'a mut [u8; 9]) -> &'a [u8] {
+        match self {
+            Self::Omit => b"<hidden>",
+            Self::Hash => {
+                // 32-bit FNV-1a, not a cryptographic hash so short and well
+                // known names can still be guessed:
+                let hash = text.iter().fold(0x811c_9dc5_u32, |hash, &byte| {
+                    (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+                });
+                buffer[0] = b'#';
+                for (ix, digit) in buffer[1..].iter_mut().enumerate() {
+                    *digit = b"0123456789abcdef"[(hash >> (28 - ix * 4)) as usize & 0xf];
+                }
+                buffer
+            }
+            Self::Full => text,
+        }
+    }
+}
+
+static CONTEXT_PRIVACY: AtomicU8 = AtomicU8::new(ContextPrivacy::Hash as u8);
+
+pub fn context_privacy() -> ContextPrivacy {
+    match CONTEXT_PRIVACY.load(Relaxed) {
+        0 => ContextPrivacy::Omit,
+        1 => ContextPrivacy::Hash,
+        _ => ContextPrivacy::Full,
+    }
+}
+
+pub fn set_context_privacy(privacy: ContextPrivacy) {
+    CONTEXT_PRIVACY.store(privacy as u8, Relaxed);
+}
+
+/// Placeholders that can be used in a log format template, see
+/// [`set_log_format`].
+#[cfg(feature = "std")]
+pub const LOG_FORMAT_PLACEHOLDERS: [&str; 10] = [
+    "button",
+    "direction",
+    "result",
+    "delta",
+    "delta_us",
+    "latency",
+    "timestamp",
+    "threshold",
+    "title",
+    "click_id",
+];
+
+/// Template used to format mouse events, empty to use the default format.
+#[cfg(feature = "std")]
+static LOG_FORMAT: std::sync::RwLock<String> = std::sync::RwLock::new(String::new());
+
+/// Check that a log format template only uses known placeholders.
+#[cfg(feature = "std")]
+pub fn validate_log_format(template: &str) -> Result<(), String> {
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else {
+            return Err(format!(
+                "the log format \"{template}\" has a \"{{\" without a closing \"}}\""
+            ));
+        };
+        let name = &rest[start + 1..start + end];
+        if !LOG_FORMAT_PLACEHOLDERS.contains(&name) {
+            return Err(format!(
+                "unknown placeholder \"{{{name}}}\" in the log format, expected one of {}",
+                LOG_FORMAT_PLACEHOLDERS
+                    .iter()
+                    .map(|name| format!("{{{name}}}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(())
+}
+
+/// Use a template such as `"{timestamp} {button} {direction} {result} {delta}"`
+/// to format logged mouse events so that the log can be parsed by existing
+/// scripts. An empty template restores the default format. The template
+/// should be checked with [`validate_log_format`] first, unknown placeholders
+/// are written as-is.
+#[cfg(feature = "std")]
+pub fn set_log_format(template: &str) {
+    let mut format = LOG_FORMAT.write().unwrap_or_else(|e| e.into_inner());
+    format.clear();
+    format.push_str(template);
+}
+
 /// Create or destroy a console window.
 ///
 /// # References
@@ -227,8 +722,124 @@ pub fn set_should_log(enabled: bool) {
     }
 }
 
+/// Get info about the environment the program is running in, written when
+/// logging starts so that shared logs are self-describing. Lazy so does nothing
+/// by itself.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows-hardware/drivers/ddi/wdm/nf-wdm-rtlgetversion>
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getrawinputdevicelist>
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getsystemmetrics>
+pub fn log_session_header() -> [LogValue<'static>; 23] {
+    let mut version: OSVERSIONINFOW = unsafe { core::mem::zeroed() };
+    version.dwOSVersionInfoSize = core::mem::size_of::<OSVERSIONINFOW>() as u32;
+    if unsafe { RtlGetVersion(&mut version) } != 0 {
+        log_error("Failed to get Windows version");
+    }
+
+    let mouse_devices = {
+        let mut devices = [RAWINPUTDEVICELIST {
+            hDevice: core::ptr::null_mut(),
+            dwType: 0,
+        }; 64];
+        let mut count = devices.len() as u32;
+        let result = unsafe {
+            GetRawInputDeviceList(
+                devices.as_mut_ptr(),
+                &mut count,
+                core::mem::size_of::<RAWINPUTDEVICELIST>() as u32,
+            )
+        };
+        if result == u32::MAX {
+            // Likely more devices than fit in our buffer:
+            log_error("Failed to list input devices");
+            0
+        } else {
+            devices[..(result as usize).min(devices.len())]
+                .iter()
+                .filter(|device| device.dwType == RIM_TYPEMOUSE)
+                .count() as u32
+        }
+    };
+
+    log_array![
+        b"\r\nclick-once v",
+        crate::version::VERSION.as_bytes(),
+        b" (",
+        crate::version::GIT_HASH.as_bytes(),
+        b", ",
+        crate::version::TARGET.as_bytes(),
+        b")\r\nFeatures: ",
+        crate::version::FEATURES.as_bytes(),
+        b"\r\nWindows version: ",
+        version.dwMajorVersion,
+        b".",
+        version.dwMinorVersion,
+        b".",
+        version.dwBuildNumber,
+        if crate::wine::is_wine() {
+            b"\r\nWine version: ".as_slice()
+        } else {
+            b""
+        },
+        crate::wine::version().unwrap_or_default(),
+        b"\r\nMouse devices: ",
+        mouse_devices,
+        b"\r\nMouse buttons: ",
+        unsafe { GetSystemMetrics(SM_CMOUSEBUTTONS) }.max(0) as u32,
+        b"\r\nSwapped mouse buttons: ",
+        if unsafe { GetSystemMetrics(SM_SWAPBUTTON) } != 0 {
+            b"Yes".as_slice()
+        } else {
+            b"No"
+        },
+        b"\r\n",
+    ]
+}
+
+/// Log the buttons whose up events use a different threshold than their down
+/// events.
+pub fn log_up_thresholds() {
+    for &button in MouseButton::all() {
+        let up = crate::UP_THRESHOLDS[button as usize].load(Relaxed);
+        if up != crate::SAME_AS_DOWN {
+            log![
+                b"Up threshold for the ",
+                button.name().as_bytes(),
+                b" button: ",
+                FgColor::TIME,
+                up,
+                b" ms",
+                FgColor::Reset,
+                b"\r\n"
+            ];
+        }
+    }
+}
+
 /// Get info about the current program configuration. Lazy so does nothing by itself.
-pub fn log_program_config() -> [LogValue<'static>; 19] {
+///
+/// This also includes system mouse settings since they interact with the chosen
+/// thresholds.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getdoubleclicktime>
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-systemparametersinfow>
+pub fn log_program_config() -> [LogValue<'static>; 58] {
+    let get_system_parameter = |action| {
+        let mut value: u32 = 0;
+        let result =
+            unsafe { SystemParametersInfoW(action, 0, (&mut value as *mut u32).cast(), 0) };
+        if result == 0 {
+            log_error("Failed to get system parameter");
+        }
+        value
+    };
+    let click_lock = get_system_parameter(SPI_GETMOUSECLICKLOCK) != 0;
+    let click_lock_time = get_system_parameter(SPI_GETMOUSECLICKLOCKTIME);
+
     log_array![
         b"\r\nProgram Config:\r\nLeft Click:  ",
         FgColor::TIME,
@@ -260,66 +871,260 @@ pub fn log_program_config() -> [LogValue<'static>; 19] {
         } else {
             b""
         },
-        b"\r\n\r\n",
+        b"\r\nX1 Click: ",
+        FgColor::TIME,
+        crate::THRESHOLD_X1.load(Relaxed),
+        b" ms",
+        FgColor::Reset,
+        if crate::THRESHOLD_X1.load(Relaxed) == 0 {
+            b" (Disabled)".as_slice()
+        } else {
+            b""
+        },
+        b"\r\nX2 Click: ",
+        FgColor::TIME,
+        crate::THRESHOLD_X2.load(Relaxed),
+        b" ms",
+        FgColor::Reset,
+        if crate::THRESHOLD_X2.load(Relaxed) == 0 {
+            b" (Disabled)".as_slice()
+        } else {
+            b""
+        },
+        b"\r\nMode: ",
+        match crate::suppression_mode() {
+            crate::SuppressionMode::Both => b"suppress down and up chatter".as_slice(),
+            crate::SuppressionMode::UpOnly => b"only suppress up chatter",
+            crate::SuppressionMode::Delay => b"delay down chatter and replay it unless it bounces",
+        },
+        b"\r\nFail-safe: ",
+        if crate::failsafe::max_blocked_per_second() == 0 {
+            b"Disabled".as_slice()
+        } else {
+            b"stop blocking after more than "
+        },
+        crate::failsafe::max_blocked_per_second(),
+        b" blocked events per second for ",
+        crate::failsafe::max_seconds(),
+        b" seconds",
+        b"\r\nThreshold fail-safe: ",
+        if crate::failsafe::max_blocked_percent() == 0 {
+            b"Disabled".as_slice()
+        } else {
+            b"reset thresholds after more than "
+        },
+        crate::failsafe::max_blocked_percent(),
+        b"% of ",
+        crate::failsafe::PRESS_WINDOW,
+        b" clicks were blocked",
+        b"\r\n\r\nSystem Mouse Settings:\r\nDouble-click time: ",
+        FgColor::TIME,
+        unsafe { GetDoubleClickTime() },
+        b" ms",
+        FgColor::Reset,
+        b"\r\nDouble-click area: ",
+        unsafe { GetSystemMetrics(SM_CXDOUBLECLK) }.max(0) as u32,
+        b"x",
+        unsafe { GetSystemMetrics(SM_CYDOUBLECLK) }.max(0) as u32,
+        b" pixels\r\nClickLock: ",
+        if click_lock {
+            b"Enabled".as_slice()
+        } else {
+            b"Disabled"
+        },
+        b" (",
+        click_lock_time,
+        b" ms)\r\n\r\n",
     ]
 }
 
-macro_rules! all_variants {
-    ($($variant:ident),* $(,)?) => {{
-        _ = |__enum: Self| {
-            match __enum {
-                $(Self::$variant => {},)*
-            }
-        };
-        &[
-            $(Self::$variant,)*
-        ]
-    }};
-}
-
-#[derive(Clone, Copy)]
-pub enum MouseDirection {
-    Up,
-    Down,
-}
-impl MouseDirection {
-    #[allow(dead_code, reason = "only used by certain features")]
-    pub fn all() -> &'static [Self] {
-        all_variants![Up, Down]
-    }
-}
-
-#[derive(Clone, Copy)]
-pub enum MouseButton {
-    Left,
-    Right,
-    Middle,
-}
-impl MouseButton {
-    #[allow(dead_code, reason = "only used by certain features")]
-    pub fn all() -> &'static [Self] {
-        all_variants![Left, Right, Middle]
-    }
-}
-
 #[derive(Clone, Copy)]
 pub struct MouseEvent {
     pub button: MouseButton,
     pub direction: MouseDirection,
     pub blocked: bool,
+    /// The event is delayed and will be replayed unless the button is pressed
+    /// again soon.
+    pub deferred: bool,
+    /// The event is an up event for a button that was already released.
+    pub double_up: bool,
     pub time_since_last_event: u32,
+    /// Milliseconds between when the event was generated and when our hook
+    /// was called.
+    pub delivery_latency: u32,
+    /// The tick count when the event was handled.
+    #[cfg_attr(
+        not(feature = "std"),
+        allow(dead_code, reason = "only used by log formats")
+    )]
+    pub timestamp: u32,
+    /// The threshold that was used to decide if the event should be blocked.
+    #[cfg_attr(
+        not(feature = "std"),
+        allow(dead_code, reason = "only used by log formats")
+    )]
+    pub threshold: u32,
+    /// Groups the events of a click, see [`crate::click_id`].
+    #[cfg_attr(
+        not(feature = "std"),
+        allow(dead_code, reason = "only used by log formats")
+    )]
+    pub click_id: u32,
 }
 impl MouseEvent {
     pub fn log(self) {
-        #[cfg(feature = "tray")]
-        stats::MouseEventStats::get(self.button, self.direction).increment(self.blocked);
+        // Events that happen while the session is locked aren't the user's
+        // clicks:
+        #[cfg(any(feature = "tray", feature = "com", feature = "ipc"))]
+        if !crate::session_lock::is_locked() {
+            stats::MouseEventStats::get(self.button, self.direction).increment(self.blocked);
+            stats::DELIVERY_LATENCY.record(self.delivery_latency);
+            if self.direction == MouseDirection::Down {
+                stats::INTERVALS.record(self.button, self.time_since_last_event, self.blocked);
+            }
+        }
+
+        #[cfg(feature = "shared-stats")]
+        crate::shared_stats::increment(self.button, self.direction, self.blocked);
+        #[cfg(feature = "perf-counters")]
+        crate::perf_counters::increment(self.button, self.blocked);
+
+        check_delivery_latency(self.delivery_latency);
+
+        // Track precise time even when not logging so that the first logged
+        // event after logging is enabled has a correct delta:
+        let precise_time = if is_verbose() {
+            Some(self.precise_time_since_last_event())
+        } else {
+            None
+        };
 
         if is_logging() {
-            self.log_write();
+            self.queue_write(precise_time);
+        }
+    }
+    /// Let the writer thread write the event so that the mouse hook doesn't
+    /// wait for the console. Writes it right away if there is no writer
+    /// thread.
+    fn queue_write(self, precise_time: Option<u32>) {
+        if !queue(Queued::Event(self, precise_time)) {
+            self.log_write(precise_time);
         }
     }
+    /// Measure the time since the previous relevant event in microseconds
+    /// using `QueryPerformanceCounter`, which has much higher resolution than
+    /// `GetTickCount` (that usually only updates every 10-16 ms).
+    ///
+    /// Uses the same rules as the mouse hook for what counts as the previous
+    /// event: a down event is compared to both the previous accepted down and
+    /// up events while an up event is only compared to the previous accepted
+    /// up event.
+    ///
+    /// # References
+    ///
+    /// - <https://learn.microsoft.com/en-us/windows/win32/sysinfo/acquiring-high-resolution-time-stamps>
+    #[cold]
+    fn precise_time_since_last_event(self) -> u32 {
+        /// Performance counter value at the last accepted event, indexed by
+        /// button and then direction.
+        static LAST_ACCEPTED: [[AtomicI64; 2]; MouseButton::COUNT] =
+            [const { [const { AtomicI64::new(0) }; 2] }; MouseButton::COUNT];
+
+        let mut now = 0;
+        let mut frequency = 0;
+        unsafe {
+            QueryPerformanceCounter(&mut now);
+            QueryPerformanceFrequency(&mut frequency);
+        }
+
+        let last = &LAST_ACCEPTED[self.button as usize];
+        let previous = match self.direction {
+            MouseDirection::Down => last[MouseDirection::Down as usize]
+                .load(Relaxed)
+                .max(last[MouseDirection::Up as usize].load(Relaxed)),
+            MouseDirection::Up => last[MouseDirection::Up as usize].load(Relaxed),
+        };
+        if !self.blocked {
+            last[self.direction as usize].store(now, Relaxed);
+        }
+
+        if frequency <= 0 {
+            return 0;
+        }
+        let micros = (now.saturating_sub(previous).max(0) as u128 * 1_000_000) / frequency as u128;
+        u32::try_from(micros).unwrap_or(u32::MAX)
+    }
+    /// Short description of what happened to the event, used by the
+    /// `{result}` placeholder of log format templates.
+    #[cfg(feature = "std")]
+    fn result_name(self) -> &'static [u8] {
+        if self.double_up {
+            b"already-released"
+        } else if self.deferred {
+            b"delayed"
+        } else if self.blocked {
+            b"blocked"
+        } else {
+            b"accepted"
+        }
+    }
+    /// Write the event using a user provided template, see [`set_log_format`].
+    #[cfg(feature = "std")]
     #[cold]
-    fn log_write(self) {
+    fn log_template(self, template: &str, precise_time: Option<u32>) {
+        if self.blocked {
+            log![FgColor::BLOCKED];
+        }
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            log![&rest.as_bytes()[..start]];
+            rest = &rest[start..];
+            let Some(end) = rest.find('}') else {
+                break;
+            };
+            match &rest[1..end] {
+                "button" => log![self.button.name().as_bytes()],
+                "direction" => log![self.direction.name().as_bytes()],
+                "result" => log![self.result_name()],
+                "delta" => log![self.time_since_last_event],
+                "delta_us" => match precise_time {
+                    Some(micros) => log![micros],
+                    // Only measured in verbose mode:
+                    None => log![b"-"],
+                },
+                "latency" => log![self.delivery_latency],
+                "timestamp" => log![self.timestamp],
+                "threshold" => log![self.threshold],
+                "click_id" => log![self.click_id],
+                "title" => {
+                    if crate::window_title::is_enabled() {
+                        crate::window_title::with_title(|title| {
+                            log![LogValue::PrivateText(title)];
+                        });
+                    }
+                }
+                _ => log![&rest.as_bytes()[..=end]],
+            }
+            rest = &rest[end + 1..];
+        }
+        log![rest.as_bytes()];
+        if self.blocked {
+            log![FgColor::Reset];
+        }
+        log![b"\r\n"];
+    }
+    #[cold]
+    fn log_write(self, precise_time: Option<u32>) {
+        let _output = lock_output();
+        #[cfg(feature = "std")]
+        {
+            let template = LOG_FORMAT.read().unwrap_or_else(|e| e.into_inner());
+            if !template.is_empty() {
+                self.log_template(&template, precise_time);
+                return;
+            }
+        }
+
         if self.blocked {
             log![FgColor::BLOCKED];
         }
@@ -331,31 +1136,331 @@ impl MouseEvent {
             (MouseButton::Right, MouseDirection::Down) => log![b"Right click "],
             (MouseButton::Middle, MouseDirection::Up) => log![b"\tMiddle button up event "],
             (MouseButton::Middle, MouseDirection::Down) => log![b"Middle click "],
+            (MouseButton::X1, MouseDirection::Up) => log![b"\tX1 button up event "],
+            (MouseButton::X1, MouseDirection::Down) => log![b"X1 click "],
+            (MouseButton::X2, MouseDirection::Up) => log![b"\tX2 button up event "],
+            (MouseButton::X2, MouseDirection::Down) => log![b"X2 click "],
         }
 
-        if self.blocked {
-            log![
-                b"ignored (too frequent, within ",
-                FgColor::TIME,
-                self.time_since_last_event,
-                b" ms",
-                FgColor::BLOCKED,
-                b")\r\n",
-                FgColor::Reset,
-            ];
+        if self.double_up {
+            log![b"ignored (already released, after ", FgColor::TIME];
+        } else if self.deferred {
+            log![b"delayed to check for chatter (after ", FgColor::TIME];
+        } else if self.blocked {
+            log![b"ignored (too frequent, within ", FgColor::TIME];
         } else {
+            log![b"accepted (after ", FgColor::TIME];
+        }
+        log![self.time_since_last_event, b" ms"];
+        if let Some(micros) = precise_time {
             log![
-                b"accepted (after ",
-                FgColor::TIME,
-                self.time_since_last_event,
-                b" ms",
-                FgColor::Reset,
-                b")\r\n",
+                b", ",
+                micros,
+                b" us, delivered after ",
+                self.delivery_latency,
+                b" ms"
             ];
         }
+        if self.blocked {
+            log![FgColor::BLOCKED, b")", FgColor::Reset];
+        } else {
+            log![FgColor::Reset, b")"];
+        }
+        if crate::window_title::is_enabled() {
+            crate::window_title::with_title(|title| {
+                log![b" in \"", LogValue::PrivateText(title), b"\""];
+            });
+        }
+        log![b"\r\n"];
     }
 }
 
+/// How many bytes of text fit in one queued record.
+const TEXT_CHUNK: usize = 64;
+
+/// Something that the hook thread logged and that the writer thread writes.
+#[derive(Clone, Copy)]
+enum Queued {
+    /// A mouse event together with its time in microseconds in verbose mode.
+    Event(MouseEvent, Option<u32>),
+    /// Part of a message.
+    Text { len: u8, text: [u8; TEXT_CHUNK] },
+    /// A color change inside a message.
+    Color(FgColor),
+    /// Mouse events were consistently delivered late, see
+    /// [`check_delivery_latency`].
+    HighLatency,
+}
+
+/// Records that are waiting to be written by the writer thread.
+static EVENT_QUEUE: RingBuffer<Queued, 256> = RingBuffer::new();
+
+/// Event that is signaled when records were queued, null until the writer
+/// thread was started.
+static EVENT_QUEUED: AtomicPtr<c_void> = AtomicPtr::new(ptr::null_mut());
+
+/// Records that weren't logged since the queue was full, reset when a warning
+/// about them is written.
+static DROPPED_EVENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Records that weren't logged since the queue was full, since the program
+/// started.
+static TOTAL_DROPPED_EVENTS: AtomicU32 = AtomicU32::new(0);
+
+/// Let the writer thread write a record. Returns `false` if there is no
+/// writer thread, the record is dropped if the queue is full.
+fn queue(record: Queued) -> bool {
+    let queued = EVENT_QUEUED.load(Acquire);
+    if queued.is_null() {
+        return false;
+    }
+    if !EVENT_QUEUE.push(record) {
+        let dropped = match queue_policy() {
+            QueuePolicy::DropNewest => true,
+            // Make room by dropping the oldest record, unless the writer
+            // thread already did:
+            QueuePolicy::DropOldest => {
+                let dropped = EVENT_QUEUE.pop().is_some();
+                _ = EVENT_QUEUE.push(record);
+                dropped
+            }
+        };
+        if dropped {
+            DROPPED_EVENTS.fetch_add(1, Relaxed);
+            TOTAL_DROPPED_EVENTS.fetch_add(1, Relaxed);
+        }
+    }
+    unsafe { SetEvent(queued) };
+    true
+}
+
+/// Id of the thread that runs the mouse hook, `0` if text isn't queued. The
+/// hook is only called while that thread waits for messages, so text that it
+/// logs is queued for the writer thread instead of waiting for the output.
+static HOOK_THREAD: AtomicU32 = AtomicU32::new(0);
+
+/// Text that the hook thread logged but didn't queue yet.
+struct HookText {
+    len: UnsafeCell<usize>,
+    buffer: UnsafeCell<[u8; TEXT_CHUNK]>,
+}
+
+// Only accessed by the hook thread:
+unsafe impl Sync for HookText {}
+
+static HOOK_TEXT: HookText = HookText {
+    len: UnsafeCell::new(0),
+    buffer: UnsafeCell::new([0; TEXT_CHUNK]),
+};
+
+/// Queue everything that the current thread logs from now on, should be
+/// called by the thread that installs the mouse hook. Has no effect if the
+/// writer thread isn't running.
+pub fn set_hook_thread() {
+    if !EVENT_QUEUED.load(Relaxed).is_null() {
+        HOOK_THREAD.store(unsafe { GetCurrentThreadId() }, Relaxed);
+    }
+}
+
+/// Write text from the hook thread directly again, for example before the
+/// queue is written one last time.
+pub fn clear_hook_thread() {
+    HOOK_THREAD.store(0, Relaxed);
+}
+
+fn is_hook_thread() -> bool {
+    let hook_thread = HOOK_THREAD.load(Relaxed);
+    hook_thread != 0 && hook_thread == unsafe { GetCurrentThreadId() }
+}
+
+/// Add text to the hook thread's buffer. Must only be called by the hook
+/// thread.
+fn push_hook_text(mut text: &[u8]) {
+    while !text.is_empty() {
+        let (len, buffer) = unsafe { (&mut *HOOK_TEXT.len.get(), &mut *HOOK_TEXT.buffer.get()) };
+        let count = text.len().min(TEXT_CHUNK - *len);
+        buffer[*len..][..count].copy_from_slice(&text[..count]);
+        *len += count;
+        text = &text[count..];
+        if *len == TEXT_CHUNK {
+            flush_hook_text();
+        }
+    }
+}
+
+/// Queue the text in the hook thread's buffer. Must only be called by the hook
+/// thread.
+fn flush_hook_text() {
+    let (len, buffer) = unsafe { (&mut *HOOK_TEXT.len.get(), &*HOOK_TEXT.buffer.get()) };
+    if *len != 0 {
+        _ = queue(Queued::Text {
+            len: *len as u8,
+            text: *buffer,
+        });
+        *len = 0;
+    }
+}
+
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn dropped_events() -> u32 {
+    TOTAL_DROPPED_EVENTS.load(Relaxed)
+}
+
+/// Decides which mouse events are dropped when they are logged faster than
+/// they can be written. The mouse hook never waits for the log.
+#[derive(Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum QueuePolicy {
+    /// Keep the queued events and drop the new one.
+    DropNewest,
+    /// Drop the oldest queued event to make room for the new one, so that the
+    /// log shows the most recent events.
+    DropOldest,
+}
+impl QueuePolicy {
+    pub fn parse(value: &str) -> Option<Self> {
+        Some(match value.trim() {
+            v if v.eq_ignore_ascii_case("drop-newest") => Self::DropNewest,
+            v if v.eq_ignore_ascii_case("drop-oldest") => Self::DropOldest,
+            _ => return None,
+        })
+    }
+}
+
+static QUEUE_POLICY: AtomicU8 = AtomicU8::new(QueuePolicy::DropNewest as u8);
+
+pub fn queue_policy() -> QueuePolicy {
+    match QUEUE_POLICY.load(Relaxed) {
+        0 => QueuePolicy::DropNewest,
+        _ => QueuePolicy::DropOldest,
+    }
+}
+
+pub fn set_queue_policy(policy: QueuePolicy) {
+    QUEUE_POLICY.store(policy as u8, Relaxed);
+}
+
+/// Write the records that were queued by the hook thread.
+pub fn write_queued_events() {
+    let _output = lock_output();
+    while let Some(record) = EVENT_QUEUE.pop() {
+        match record {
+            Queued::Event(event, precise_time) => event.log_write(precise_time),
+            Queued::Text { len, text } => LogValue::Text(&text[..usize::from(len)]).write(),
+            Queued::Color(color) => LogValue::Color(color).write(),
+            Queued::HighLatency => write_high_latency_warning(),
+        }
+    }
+    let dropped = DROPPED_EVENTS.swap(0, Relaxed);
+    if dropped != 0 {
+        log![
+            FgColor::BLOCKED,
+            b"Warning: ",
+            FgColor::Reset,
+            dropped,
+            b" log records were not written since they were logged faster than they could be written\r\n"
+        ];
+    }
+}
+
+unsafe extern "system" fn event_writer(queued: *mut c_void) -> u32 {
+    loop {
+        WaitForSingleObject(queued, INFINITE);
+        write_queued_events();
+    }
+}
+
+/// Start a thread that writes the mouse events and messages that the mouse
+/// hook logs, so that the hook never waits for the console. Everything is
+/// written by the hook itself if the thread can't be started.
+pub fn start_event_writer() {
+    if !EVENT_QUEUED.load(Relaxed).is_null() {
+        return;
+    }
+    // Auto-reset so that the writer waits again after it has been woken:
+    let queued = unsafe { CreateEventW(ptr::null(), 0, 0, ptr::null()) };
+    if queued.is_null() {
+        log_error("Failed to create event for writing log messages");
+        return;
+    }
+    let thread = unsafe {
+        CreateThread(
+            ptr::null(),
+            0,
+            Some(event_writer),
+            queued,
+            0,
+            ptr::null_mut(),
+        )
+    };
+    if thread.is_null() {
+        log_error("Failed to start thread for writing log messages");
+        unsafe { CloseHandle(queued) };
+        return;
+    }
+    unsafe { CloseHandle(thread) };
+    EVENT_QUEUED.store(queued, Release);
+}
+
+/// Latency in milliseconds that is considered high. Note that the tick count
+/// usually only has a resolution of 10-16 ms.
+const HIGH_LATENCY: u32 = 50;
+/// How many events in a row must be delayed before we warn about it.
+const CONSECUTIVE_DELAYED_EVENTS: u32 = 10;
+
+/// Warn if mouse events consistently take a long time to be delivered to our
+/// hook. That usually means that another low level hook is slow to process
+/// events or that the system is overloaded, which would be the real cause of
+/// any input lag. The warning is written by the writer thread.
+fn check_delivery_latency(latency: u32) {
+    static DELAYED_EVENTS: AtomicU32 = AtomicU32::new(0);
+
+    // Event timestamps from the future would wrap around to huge values:
+    if (HIGH_LATENCY..=i32::MAX as u32).contains(&latency) {
+        // Only warn once for each streak of delayed events:
+        if DELAYED_EVENTS.fetch_add(1, Relaxed) + 1 == CONSECUTIVE_DELAYED_EVENTS
+            && is_logging()
+            && !queue(Queued::HighLatency)
+        {
+            write_high_latency_warning();
+        }
+    } else {
+        DELAYED_EVENTS.store(0, Relaxed);
+    }
+}
+
+fn write_high_latency_warning() {
+    log![
+        FgColor::BLOCKED,
+        b"Warning: ",
+        FgColor::Reset,
+        b"the last ",
+        CONSECUTIVE_DELAYED_EVENTS,
+        b" mouse events were delivered to click-once at least ",
+        FgColor::TIME,
+        HIGH_LATENCY,
+        b" ms",
+        FgColor::Reset,
+        b" late, another program's low level mouse hook or an overloaded system is likely adding input lag\r\n",
+    ];
+}
+
+/// Convert a (possibly null terminated) UTF-16 string to ASCII so that it can
+/// be logged, replacing other characters with `?`. Text that doesn't fit in
+/// the buffer is truncated.
+pub fn utf16_to_ascii<'a>(wide: &[u16], buffer: &'a mut [u8]) -> &'a [u8] {
+    let mut len = 0;
+    for (&wide, byte) in wide
+        .iter()
+        .take_while(|&&wide| wide != 0)
+        .zip(buffer.iter_mut())
+    {
+        *byte = if wide < 0x80 { wide as u8 } else { b'?' };
+        len += 1;
+    }
+    &buffer[..len]
+}
+
 /// A value that can be written to a console window.
 #[derive(Clone, Copy)]
 #[must_use = "Call write() to actually log something"]
@@ -364,10 +1469,13 @@ pub enum LogValue<'a> {
     Number(u32),
     /// ASCII text.
     Text(&'a [u8]),
+    /// ASCII text that might contain private information. Written according to
+    /// the current [`ContextPrivacy`] setting.
+    PrivateText(&'a [u8]),
     Color(FgColor),
 }
 impl<'a> LogValue<'a> {
-    #[cfg(feature = "tray")]
+    #[cfg(any(feature = "tray", feature = "com"))]
     pub fn write_to_string(self, buffer: &mut String) {
         match self {
             LogValue::Number(number) => {
@@ -382,10 +1490,14 @@ impl<'a> LogValue<'a> {
                     crate::std_polyfill::exit(1);
                 }));
             }
+            LogValue::PrivateText(text) => {
+                let mut hash = [0; 9];
+                LogValue::Text(context_privacy().apply(text, &mut hash)).write_to_string(buffer);
+            }
             LogValue::Color(_) => {}
         }
     }
-    /// Write this value to the console.
+    /// Write this value to the console and the log file.
     ///
     /// # References
     ///
@@ -396,46 +1508,239 @@ impl<'a> LogValue<'a> {
         if let LogValue::Text(b"") = self {
             return;
         }
-        if !SHOULD_LOG.load(Acquire) {
+        if !is_logging() {
             return;
         }
-        let handle = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
-        if handle.is_null() {
-            log_error("Failed to get handle to console window");
-        }
+        let _output = lock_output();
 
         let mut buffer = itoa::Buffer::new();
-        let mut ascii = match self {
+        let mut hash = [0; 9];
+        let ascii = match self {
             LogValue::Number(number) => buffer.format(number).as_bytes(),
             LogValue::Text(ascii) => ascii,
+            LogValue::PrivateText(text) => context_privacy().apply(text, &mut hash),
+            // Colors are only written to the console:
+            LogValue::Color(_) if !has_console() || !USE_COLOR.load(Relaxed) => return,
+            LogValue::Color(color) if is_hook_thread() => {
+                flush_hook_text();
+                _ = queue(Queued::Color(color));
+                return;
+            }
             LogValue::Color(color) => {
-                let result =
-                    unsafe { SetConsoleTextAttribute(handle, color.windows_text_attribute()) };
+                // Text that was written before must keep the previous color:
+                flush_output();
+                let result = unsafe {
+                    SetConsoleTextAttribute(console_handle(), color.windows_text_attribute())
+                };
                 if result == 0 {
-                    log_error("Failed to set text color");
+                    if crate::wine::is_wine() {
+                        // Output to a Unix terminal doesn't support console
+                        // text attributes:
+                        set_use_color(false);
+                    } else {
+                        log_error("Failed to set text color");
+                    }
                 }
                 return;
             }
         };
-        while !ascii.is_empty() {
-            let mut written: u32 = 0;
-            let result = unsafe {
-                WriteConsoleA(
-                    handle,
-                    ascii.as_ptr(),
-                    ascii.len() as u32,
-                    &mut written,
-                    core::ptr::null(),
-                )
-            };
-            if result == 0 {
-                log_error("WriteConsoleA failed");
-                return;
-            }
-            ascii = &ascii[written as usize..];
+        if is_hook_thread() {
+            push_hook_text(ascii);
+        } else {
+            push_lines(ascii);
+        }
+    }
+}
+
+/// Serializes writing to the console and the log file so that lines that are
+/// logged by different threads at the same time aren't mixed. Text is
+/// collected while the lock is held and written with a single call when it is
+/// released, or earlier if the color changes or the buffer is full.
+struct Output {
+    /// Id of the thread that holds the lock, `0` if it is free.
+    owner: AtomicU32,
+    state: UnsafeCell<OutputState>,
+}
+
+struct OutputState {
+    /// How many times the owner has locked the output. The lock is reentrant
+    /// so that a whole record can be written while its parts use `log!`.
+    depth: u32,
+    /// `true` if the next text that is written starts a new line.
+    at_line_start: bool,
+    len: usize,
+    buffer: [u8; 512],
+}
+
+// The state is only accessed by the thread that holds the lock:
+unsafe impl Sync for Output {}
+
+static OUTPUT: Output = Output {
+    owner: AtomicU32::new(0),
+    state: UnsafeCell::new(OutputState {
+        depth: 0,
+        at_line_start: true,
+        len: 0,
+        buffer: [0; 512],
+    }),
+};
+
+/// Keeps other threads from logging until it is dropped, see [`lock_output`].
+#[must_use]
+pub struct OutputGuard {
+    /// `true` if the text is queued for the writer thread instead.
+    hook_thread: bool,
+    /// The lock belongs to the thread that acquired it.
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Keep other threads from logging until the returned guard is dropped, so
+/// that several values are written as one piece. Can be called again while
+/// the guard is held. Never waits on the hook thread, its text is queued
+/// until the guard is dropped instead.
+pub fn lock_output() -> OutputGuard {
+    if is_hook_thread() {
+        return OutputGuard {
+            hook_thread: true,
+            _not_send: PhantomData,
+        };
+    }
+    let thread = unsafe { GetCurrentThreadId() };
+    if OUTPUT.owner.load(Relaxed) != thread {
+        while OUTPUT
+            .owner
+            .compare_exchange_weak(0, thread, Acquire, Relaxed)
+            .is_err()
+        {
+            unsafe { SwitchToThread() };
+        }
+    }
+    unsafe { (*OUTPUT.state.get()).depth += 1 };
+    OutputGuard {
+        hook_thread: false,
+        _not_send: PhantomData,
+    }
+}
+
+impl Drop for OutputGuard {
+    fn drop(&mut self) {
+        if self.hook_thread {
+            flush_hook_text();
+            return;
+        }
+        let depth = unsafe {
+            let state = &mut *OUTPUT.state.get();
+            state.depth -= 1;
+            state.depth
+        };
+        if depth == 0 {
+            flush_output();
+            OUTPUT.owner.store(0, Release);
+        }
+    }
+}
+
+/// Write several values as one piece.
+pub fn write_all(values: &[LogValue<'_>]) {
+    let _output = lock_output();
+    values.iter().for_each(|value| value.write());
+}
+
+/// Add text to the output buffer and start every line that has text with the
+/// time. Must only be called while the output is locked.
+fn push_lines(mut rest: &[u8]) {
+    while !rest.is_empty() {
+        let end = rest
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map_or(rest.len(), |ix| ix + 1);
+        let (line, next) = rest.split_at(end);
+        let at_line_start = unsafe { (*OUTPUT.state.get()).at_line_start };
+        if at_line_start
+            && !matches!(line[0], b'\r' | b'\n')
+            && TIMESTAMPS_STARTED.load(Relaxed)
+            && USE_TIMESTAMPS.load(Relaxed)
+        {
+            push_output(&timestamp());
         }
+        push_output(line);
+        unsafe { (*OUTPUT.state.get()).at_line_start = line.ends_with(b"\n") };
+        rest = next;
     }
 }
+
+/// Add text to the output buffer. Must only be called while the output is
+/// locked.
+fn push_output(text: &[u8]) {
+    let state = unsafe { &mut *OUTPUT.state.get() };
+    if state.len + text.len() > state.buffer.len() {
+        flush_output();
+    }
+    if text.len() > state.buffer.len() {
+        write_text(text);
+        return;
+    }
+    state.buffer[state.len..][..text.len()].copy_from_slice(text);
+    state.len += text.len();
+}
+
+/// Write the text in the output buffer. Must only be called while the output
+/// is locked.
+fn flush_output() {
+    let state = unsafe { &mut *OUTPUT.state.get() };
+    if state.len != 0 {
+        write_text(&state.buffer[..state.len]);
+        state.len = 0;
+    }
+}
+
+fn console_handle() -> HANDLE {
+    let handle = unsafe { GetStdHandle(STD_OUTPUT_HANDLE) };
+    if handle.is_null() {
+        log_error("Failed to get handle to console window");
+    }
+    handle
+}
+
+/// Write text to the log file and the console.
+fn write_text(mut ascii: &[u8]) {
+    crate::log_file::write(ascii);
+    if !has_console() {
+        return;
+    }
+    let handle = console_handle();
+    while !ascii.is_empty() {
+        let mut written: u32 = 0;
+        let result = unsafe {
+            WriteConsoleA(
+                handle,
+                ascii.as_ptr(),
+                ascii.len() as u32,
+                &mut written,
+                core::ptr::null(),
+            )
+        };
+        // Under Wine the output might be a Unix terminal or pipe instead
+        // of a console:
+        if result == 0
+            && !(crate::wine::is_wine()
+                && unsafe {
+                    WriteFile(
+                        handle,
+                        ascii.as_ptr(),
+                        ascii.len() as u32,
+                        &mut written,
+                        core::ptr::null_mut(),
+                    )
+                } != 0)
+        {
+            log_error("WriteConsoleA failed");
+            return;
+        }
+        ascii = &ascii[written as usize..];
+    }
+}
+
 impl<'a> From<&'a [u8]> for LogValue<'a> {
     fn from(value: &'a [u8]) -> Self {
         LogValue::Text(value)
@@ -15,6 +15,7 @@ mod std_polyfill {
     use core::{panic, slice, str};
     use windows_sys::Win32::System::Environment::GetCommandLineA;
     use windows_sys::Win32::System::Threading::ExitProcess;
+    use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONERROR, MB_OK};
 
     // Need to link to some libraries to get required symbols like memcpy:
     // https://users.rust-lang.org/t/unresolved-external-symbol-s-when-trying-to-link-a-no-std-binary-to-a-windows-dll/54306/3
@@ -61,9 +62,23 @@ mod std_polyfill {
                     pcmdline = pcmdline.add(1);
                 }
             } else {
+                let program_start = pcmdline;
                 while *pcmdline != NULL && *pcmdline != SPACE && *pcmdline != TAB {
                     pcmdline = pcmdline.add(1);
                 }
+                if crate::wine::is_wine() {
+                    let mut end = pcmdline;
+                    while *end != NULL {
+                        end = end.add(1);
+                    }
+                    let line = slice::from_raw_parts(
+                        program_start,
+                        end.offset_from(program_start) as usize,
+                    );
+                    if let Some(len) = crate::wine::unquoted_program_len(line) {
+                        pcmdline = program_start.add(len);
+                    }
+                }
             }
             pcmdline = pcmdline.add(1);
             while *pcmdline == SPACE || *pcmdline == TAB {
@@ -92,8 +107,59 @@ mod std_polyfill {
         crate::program_start();
     }
 
+    /// Append ASCII text to a UTF-16 buffer, stopping before the last element
+    /// so that the buffer stays nul terminated.
+    fn push_wide(buffer: &mut [u16], len: &mut usize, text: &[u8]) {
+        for &byte in text {
+            if *len + 1 >= buffer.len() {
+                return;
+            }
+            buffer[*len] = u16::from(byte);
+            *len += 1;
+        }
+    }
+
+    /// Show where the program panicked since the `windows` subsystem has no
+    /// console and the exit code is easy to miss. `core::fmt` isn't used to
+    /// keep the binary small, which also means the panic message is omitted.
     #[panic_handler]
-    fn panic(_info: &panic::PanicInfo) -> ! {
+    fn panic(info: &panic::PanicInfo) -> ! {
+        // Never block the user's clicks while the message box is shown:
+        crate::free_mouse_hook();
+
+        let mut text = [0_u16; 512];
+        let mut len = 0;
+        push_wide(&mut text, &mut len, b"click-once crashed");
+        if let Some(location) = info.location() {
+            push_wide(&mut text, &mut len, b" at ");
+            push_wide(&mut text, &mut len, location.file().as_bytes());
+            push_wide(&mut text, &mut len, b":");
+            let mut digits = [0_u8; 10];
+            let mut line = location.line();
+            let mut start = digits.len();
+            loop {
+                start -= 1;
+                digits[start] = b'0' + (line % 10) as u8;
+                line /= 10;
+                if line == 0 {
+                    break;
+                }
+            }
+            push_wide(&mut text, &mut len, &digits[start..]);
+        }
+        push_wide(
+            &mut text,
+            &mut len,
+            b"\r\n\r\nPlease report this at https://github.com/Lej77/click-once/issues",
+        );
+        unsafe {
+            MessageBoxW(
+                core::ptr::null_mut(),
+                text.as_ptr(),
+                windows_sys::w!("click-once"),
+                MB_ICONERROR | MB_OK,
+            )
+        };
         exit(1)
     }
 }
@@ -112,32 +178,144 @@ mod std_polyfill {
     /// Wrapper around [`std::env::args`] that skips the first argument (which
     /// would otherwise be the executable's path).
     pub fn args() -> impl Iterator<Item = String> {
-        std::env::args().skip(1)
+        let program_args = if crate::wine::is_wine() {
+            wine_program_arg_count()
+        } else {
+            1
+        };
+        std::env::args().skip(program_args)
+    }
+
+    /// Wine launchers might not quote the executable's path even if it
+    /// contains spaces, in which case it is split into several arguments.
+    fn wine_program_arg_count() -> usize {
+        use windows_sys::Win32::System::Environment::GetCommandLineA;
+
+        let line = unsafe { core::ffi::CStr::from_ptr(GetCommandLineA().cast()) }.to_bytes();
+        if line.starts_with(b"\"") {
+            return 1;
+        }
+        crate::wine::unquoted_program_len(line).map_or(1, |len| {
+            line[..len]
+                .split(|&byte| byte == b' ' || byte == b'\t')
+                .filter(|part| !part.is_empty())
+                .count()
+        })
     }
 }
 
+#[cfg(feature = "std")]
+mod autostart;
+mod bypass;
+#[cfg(any(feature = "logging", feature = "live-feed"))]
+mod click_id;
+#[cfg(feature = "com")]
+mod com_server;
+#[cfg(feature = "std")]
+mod config;
+#[cfg(feature = "logging")]
+mod conflicts;
+#[cfg(feature = "std")]
+mod console;
+mod controller;
+mod deferred;
+#[cfg(feature = "logging")]
+mod doctor;
+mod exemptions;
+mod failsafe;
+mod feedback;
+#[cfg(feature = "std")]
+mod foreground_app;
+#[cfg(all(feature = "tray", feature = "logging"))]
+mod histogram_window;
+mod hotkey;
+mod injected;
+#[cfg(feature = "std")]
+mod install;
+#[cfg(feature = "ipc")]
+mod ipc;
+mod keyboard;
+#[cfg(feature = "live-feed")]
+mod live_feed;
+#[cfg(feature = "logging")]
+mod log_file;
 #[cfg(feature = "logging")]
 mod logging;
+mod message_window;
+mod pause;
+#[cfg(feature = "perf-counters")]
+mod perf_counters;
+mod position;
+mod power;
+mod priority;
+mod raw_input;
+#[cfg(feature = "std")]
+mod registry_config;
+mod rehook;
+#[cfg(feature = "std")]
+mod scheduled_task;
+#[cfg(feature = "self-update")]
+mod self_update;
+#[cfg(feature = "multi-session")]
+mod session_launcher;
+mod session_lock;
+#[cfg(feature = "shared-stats")]
+mod shared_stats;
+mod shutdown;
+#[cfg(feature = "std")]
+mod simulate;
+mod single_instance;
+#[cfg(feature = "std")]
+mod stress;
+mod system_api;
+#[cfg(feature = "tray")]
+mod threshold_window;
+mod timer_resolution;
+#[cfg(feature = "std")]
+mod trace;
 #[cfg(feature = "tray")]
 mod tray;
+#[cfg(feature = "std")]
+mod uninstall;
+mod usage;
+mod version;
+mod watchdog;
+mod wheel;
+#[cfg(feature = "logging")]
+mod window_title;
+mod wine;
 
-use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering::Relaxed};
+use click_once::{debounce, device_id, duration, win_api};
+use core::sync::atomic::{
+    AtomicBool, AtomicPtr, AtomicU32, AtomicU8,
+    Ordering::{Acquire, Relaxed, Release},
+};
 use core::*;
+use debounce::{ButtonSettings, ButtonState, MouseButton, MouseDirection, SuppressionMode};
+use win_api::WinApi;
 use windows_sys::Win32::Foundation::{LPARAM, LRESULT, WPARAM};
-use windows_sys::Win32::System::SystemInformation::GetTickCount;
 use windows_sys::Win32::UI::WindowsAndMessaging::{
-    CallNextHookEx, SetWindowsHookExW, UnhookWindowsHookEx, WH_MOUSE_LL, WM_LBUTTONDOWN,
-    WM_LBUTTONUP, WM_MBUTTONDOWN, WM_MBUTTONUP, WM_RBUTTONDOWN, WM_RBUTTONUP,
+    CallNextHookEx, SetWindowsHookExW, SystemParametersInfoW, UnhookWindowsHookEx,
+    SPI_GETMOUSECLICKLOCK, SPI_GETMOUSECLICKLOCKTIME, WH_MOUSE_LL, WM_LBUTTONDOWN, WM_LBUTTONUP,
+    WM_MBUTTONDOWN, WM_MBUTTONUP, WM_MOUSEHWHEEL, WM_MOUSEWHEEL, WM_RBUTTONDOWN, WM_RBUTTONUP,
+    WM_XBUTTONDOWN, WM_XBUTTONUP, XBUTTON1, XBUTTON2,
 };
 
 macro_rules! log_mouse_event {
-    ($button:ident, $direction:ident, $blocked:expr, $time_since_last_event:expr) => {
+    ($button:expr, $direction:expr, $decision:expr, $settings:expr, $tick:expr, $delivery_latency:expr, $click_id:expr) => {
         #[cfg(feature = "logging")]
         $crate::logging::MouseEvent {
-            button: $crate::logging::MouseButton::$button,
-            direction: $crate::logging::MouseDirection::$direction,
-            blocked: $blocked,
-            time_since_last_event: $time_since_last_event,
+            button: $button,
+            direction: $direction,
+            blocked: $decision.is_dropped(),
+            deferred: $decision.deferred,
+            double_up: $decision.double_up,
+            time_since_last_event: $decision.time_since_last_event,
+            delivery_latency: $delivery_latency,
+            // Logged in the same time base as `GetTickCount`:
+            timestamp: $tick as u32,
+            threshold: $settings.threshold_for($direction),
+            click_id: $click_id,
         }
         .log();
     };
@@ -150,6 +328,7 @@ macro_rules! _log {
         #[cfg(feature = "logging")]
         {
             if $crate::logging::is_logging() {
+                let _output = $crate::logging::lock_output();
                 $(
                     $crate::logging::LogValue::from($value).write();
                 )*
@@ -170,6 +349,20 @@ macro_rules! _log {
 )]
 use _log as log;
 
+/// Convert a string to a null terminated UTF-16 string for use with Windows
+/// APIs.
+#[cfg(feature = "std")]
+#[allow(dead_code, reason = "only used by certain features")]
+fn to_utf16(s: &str) -> Vec<u16> {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+
+    OsStr::new(s)
+        .encode_wide()
+        .chain(core::iter::once(0u16))
+        .collect()
+}
+
 #[inline(always)] // <- so that the argument can be removed when this is a noop
 fn log_error(_error: impl core::fmt::Display) {
     #[cfg(all(feature = "std", debug_assertions, not(feature = "logging")))]
@@ -184,17 +377,178 @@ fn log_error(_error: impl core::fmt::Display) {
     }
 }
 
+/// Built-in thresholds for the left, right, middle and extended mouse buttons
+/// that are used when nothing else is configured.
+const DEFAULT_THRESHOLDS: [u32; MouseButton::COUNT] = [30, 0, 0, 0, 0];
+
 /// If a left mouse button event happens faster than this many milliseconds
 /// then it is suppressed.
-static THRESHOLD_LM: AtomicU32 = AtomicU32::new(30);
+static THRESHOLD_LM: AtomicU32 = AtomicU32::new(DEFAULT_THRESHOLDS[0]);
 
 /// If a right mouse button event happens faster than this many milliseconds
 /// then it is suppressed.
-static THRESHOLD_RM: AtomicU32 = AtomicU32::new(0);
+static THRESHOLD_RM: AtomicU32 = AtomicU32::new(DEFAULT_THRESHOLDS[1]);
 
 /// If a middle mouse button event happens faster than this many milliseconds
 /// then it is suppressed.
-static THRESHOLD_MM: AtomicU32 = AtomicU32::new(0);
+static THRESHOLD_MM: AtomicU32 = AtomicU32::new(DEFAULT_THRESHOLDS[2]);
+
+/// If an X1 (usually "back") mouse button event happens faster than this many
+/// milliseconds then it is suppressed.
+static THRESHOLD_X1: AtomicU32 = AtomicU32::new(DEFAULT_THRESHOLDS[3]);
+
+/// If an X2 (usually "forward") mouse button event happens faster than this
+/// many milliseconds then it is suppressed.
+static THRESHOLD_X2: AtomicU32 = AtomicU32::new(DEFAULT_THRESHOLDS[4]);
+
+/// Marks a button's up threshold as unset so that its up events use the same
+/// threshold as its down events.
+const SAME_AS_DOWN: u32 = u32::MAX;
+
+/// Separate thresholds for up events, indexed by button. Unset by default, see
+/// [`SAME_AS_DOWN`].
+static UP_THRESHOLDS: [AtomicU32; MouseButton::COUNT] =
+    [const { AtomicU32::new(SAME_AS_DOWN) }; MouseButton::COUNT];
+
+/// Which kind of chatter is suppressed, stored as a [`SuppressionMode`].
+static SUPPRESSION_MODE: AtomicU8 = AtomicU8::new(SuppressionMode::Both as u8);
+
+fn suppression_mode() -> SuppressionMode {
+    SuppressionMode::from_u8(SUPPRESSION_MODE.load(Relaxed))
+}
+
+/// Thresholds for mouse events generated by a pen, indexed by the mouse
+/// button that the pen emulates: the tip clicks the left button, the barrel
+/// button clicks the right button and the eraser clicks the middle button.
+/// Pens don't have worn switches like mice so they aren't debounced by
+/// default.
+static PEN_THRESHOLDS: [AtomicU32; 3] = [const { AtomicU32::new(0) }; 3];
+
+/// Debounce state for pen input, kept separate from mouse input so that the
+/// two don't affect each other.
+static PEN_BUTTONS: [ButtonState; MouseButton::COUNT] =
+    [const { ButtonState::new() }; MouseButton::COUNT];
+
+/// Signature that Windows stores in `dwExtraInfo` of mouse events that were
+/// generated from pen or touch input. Touch input also sets the `0x80` bit.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/tablet/system-events-and-mouse-messages>
+const PEN_OR_TOUCH_SIGNATURE: usize = 0xFF51_5700;
+
+/// `true` if a mouse event was generated by a pen (and not by touch input).
+fn is_pen_event(extra_info: usize) -> bool {
+    extra_info & 0xFFFF_FF80 == PEN_OR_TOUCH_SIGNATURE
+}
+
+/// `true` if a mouse event was generated by touch input.
+fn is_touch_event(extra_info: usize) -> bool {
+    extra_info & 0xFFFF_FF80 == PEN_OR_TOUCH_SIGNATURE | 0x80
+}
+
+/// If `true` then mouse events generated from touchscreen taps are never
+/// blocked since a touchscreen doesn't have a switch that could bounce.
+static EXEMPT_TOUCH: AtomicBool = AtomicBool::new(true);
+
+/// If `true` then clicks from devices that report absolute positions (drawing
+/// tablets, some KVM switches) are never blocked since their tap handling
+/// generates legitimate rapid clicks.
+static EXEMPT_ABSOLUTE: AtomicBool = AtomicBool::new(false);
+
+/// If `true` then events are never blocked, but they are still debounced and
+/// logged so that the thresholds can be verified before they are used.
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// How long the primary button must be held for Windows' ClickLock to lock it,
+/// `0` if ClickLock is disabled. Detected when the program starts.
+static CLICK_LOCK_TIME: AtomicU32 = AtomicU32::new(0);
+
+/// Check if Windows' ClickLock feature is enabled so that the debounce logic
+/// can avoid interfering with it.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-systemparametersinfow>
+fn detect_click_lock() {
+    let get_system_parameter = |action| {
+        let mut value: u32 = 0;
+        let result =
+            unsafe { SystemParametersInfoW(action, 0, (&mut value as *mut u32).cast(), 0) };
+        if result == 0 {
+            log_error("Failed to get system parameter");
+        }
+        value
+    };
+    let time = if get_system_parameter(SPI_GETMOUSECLICKLOCK) != 0 {
+        get_system_parameter(SPI_GETMOUSECLICKLOCKTIME).max(1)
+    } else {
+        0
+    };
+    CLICK_LOCK_TIME.store(time, Relaxed);
+}
+
+/// The foreground application's threshold for a button, if it has its own.
+fn app_threshold(_button: MouseButton) -> Option<u32> {
+    #[cfg(feature = "std")]
+    {
+        foreground_app::threshold(_button)
+    }
+    #[cfg(not(feature = "std"))]
+    None
+}
+
+/// Get the current settings for a button.
+fn button_settings(api: &impl WinApi, button: MouseButton) -> ButtonSettings {
+    // ClickLock only applies to the primary button:
+    let primary = api.primary_button();
+    // The foreground application's own threshold is used for both directions:
+    let app_threshold = app_threshold(button);
+    ButtonSettings {
+        threshold: app_threshold.unwrap_or_else(|| threshold(button).load(Relaxed)),
+        up_threshold: app_threshold.unwrap_or_else(|| up_threshold(button)),
+        mode: suppression_mode(),
+        click_lock_time: match CLICK_LOCK_TIME.load(Relaxed) {
+            0 => None,
+            time => (button == primary).then_some(time),
+        },
+        ticks_per_ms: ticks_per_ms(),
+    }
+}
+
+/// If `true` then the time between mouse button events is measured in
+/// microseconds using `QueryPerformanceCounter`, instead of using the events'
+/// own timestamps which only have the resolution of the system timer.
+static PRECISE_TIMING: AtomicBool = AtomicBool::new(false);
+
+fn set_precise_timing(enabled: bool) {
+    PRECISE_TIMING.store(enabled, Relaxed);
+}
+
+/// The resolution of the system timer, usually 15.6 ms.
+const SYSTEM_TIMER_RESOLUTION_MS: u64 = 16;
+
+/// Ticks per millisecond of the time that the debounce logic is given.
+fn ticks_per_ms() -> u32 {
+    if PRECISE_TIMING.load(Relaxed) {
+        1000
+    } else {
+        1
+    }
+}
+
+/// The current time in the unit given by [`ticks_per_ms`].
+fn current_tick(api: &impl WinApi) -> u64 {
+    if PRECISE_TIMING.load(Relaxed) {
+        api.precise_micros()
+    } else {
+        api.tick_count()
+    }
+}
+
+/// Debounce state for each mouse button.
+static BUTTONS: [ButtonState; MouseButton::COUNT] =
+    [const { ButtonState::new() }; MouseButton::COUNT];
 
 const WM_LBUTTONDOWNU: usize = WM_LBUTTONDOWN as _;
 const WM_LBUTTONUPU: usize = WM_LBUTTONUP as _;
@@ -202,125 +556,876 @@ const WM_RBUTTONDOWNU: usize = WM_RBUTTONDOWN as _;
 const WM_RBUTTONUPU: usize = WM_RBUTTONUP as _;
 const WM_MBUTTONDOWNU: usize = WM_MBUTTONDOWN as _;
 const WM_MBUTTONUPU: usize = WM_MBUTTONUP as _;
+const WM_XBUTTONDOWNU: usize = WM_XBUTTONDOWN as _;
+const WM_XBUTTONUPU: usize = WM_XBUTTONUP as _;
+const WM_MOUSEWHEELU: usize = WM_MOUSEWHEEL as _;
+const WM_MOUSEHWHEELU: usize = WM_MOUSEHWHEEL as _;
 
 unsafe extern "system" fn low_level_mouse_proc(
     code: i32,
     wparam: WPARAM,
     lparam: LPARAM,
 ) -> LRESULT {
-    static LAST_DOWN_L: AtomicU32 = AtomicU32::new(0);
-    static LAST_UP_L: AtomicU32 = AtomicU32::new(0);
-    static LAST_DOWN_R: AtomicU32 = AtomicU32::new(0);
-    static LAST_UP_R: AtomicU32 = AtomicU32::new(0);
-    static LAST_DOWN_M: AtomicU32 = AtomicU32::new(0);
-    static LAST_UP_M: AtomicU32 = AtomicU32::new(0);
-
-    if code >= 0 {
-        match wparam {
-            WM_LBUTTONDOWNU => {
-                let tick = GetTickCount();
-                let time_since_last_event =
-                    tick.saturating_sub(LAST_DOWN_L.load(Relaxed).max(LAST_UP_L.load(Relaxed)));
-
-                if time_since_last_event < THRESHOLD_LM.load(Relaxed) {
-                    log_mouse_event!(Left, Down, true, time_since_last_event);
-                    return 1;
-                } else {
-                    LAST_DOWN_L.store(tick, Relaxed);
-                    log_mouse_event!(Left, Down, false, time_since_last_event);
-                }
-            }
-            WM_LBUTTONUPU => {
-                let tick = GetTickCount();
-                let time_since_last_event = tick.saturating_sub(LAST_UP_L.load(Relaxed));
-
-                if time_since_last_event < THRESHOLD_LM.load(Relaxed) {
-                    log_mouse_event!(Left, Up, true, time_since_last_event);
-                    return 1;
-                } else {
-                    LAST_UP_L.store(tick, Relaxed);
-                    log_mouse_event!(Left, Up, false, time_since_last_event);
-                }
-            }
-            WM_RBUTTONDOWNU => {
-                let tick = GetTickCount();
-                let time_since_last_event =
-                    tick.saturating_sub(LAST_DOWN_R.load(Relaxed).max(LAST_UP_R.load(Relaxed)));
-
-                if time_since_last_event < THRESHOLD_RM.load(Relaxed) {
-                    log_mouse_event!(Right, Down, true, time_since_last_event);
-                    return 1;
-                } else {
-                    LAST_DOWN_R.store(tick, Relaxed);
-                    log_mouse_event!(Right, Down, false, time_since_last_event);
-                }
-            }
-            WM_RBUTTONUPU => {
-                let tick = GetTickCount();
-                let time_since_last_event = tick.saturating_sub(LAST_UP_R.load(Relaxed));
-
-                if time_since_last_event < THRESHOLD_RM.load(Relaxed) {
-                    log_mouse_event!(Right, Up, true, time_since_last_event);
-                    return 1;
-                } else {
-                    LAST_UP_R.store(tick, Relaxed);
-                    log_mouse_event!(Right, Up, false, time_since_last_event);
-                }
-            }
-            WM_MBUTTONDOWNU => {
-                let tick = GetTickCount();
-                let time_since_last_event =
-                    tick.saturating_sub(LAST_DOWN_M.load(Relaxed).max(LAST_UP_M.load(Relaxed)));
-
-                if time_since_last_event < THRESHOLD_MM.load(Relaxed) {
-                    log_mouse_event!(Middle, Down, true, time_since_last_event);
-                    return 1;
-                } else {
-                    LAST_DOWN_M.store(tick, Relaxed);
-                    log_mouse_event!(Middle, Down, false, time_since_last_event);
-                }
-            }
-            WM_MBUTTONUPU => {
-                let tick = GetTickCount();
-                let time_since_last_event = tick.saturating_sub(LAST_UP_M.load(Relaxed));
-
-                if time_since_last_event < THRESHOLD_MM.load(Relaxed) {
-                    log_mouse_event!(Middle, Up, true, time_since_last_event);
-                    return 1;
-                } else {
-                    LAST_UP_M.store(tick, Relaxed);
-                    log_mouse_event!(Middle, Up, false, time_since_last_event);
-                }
-            }
-            _ => (),
+    /// Set while the hook is handling an event. If the hook is ever re-entered
+    /// (for example because of events that we synthesize ourselves) then the
+    /// inner call passes the event through immediately to prevent feedback
+    /// loops.
+    ///
+    /// Note: low level hooks are always called on the thread that installed
+    /// them so this is effectively a thread local.
+    static IN_HOOK: AtomicBool = AtomicBool::new(false);
+
+    watchdog::on_hook_called();
+    if code >= 0 && !IN_HOOK.swap(true, Acquire) {
+        let block = should_block_mouse_event(wparam, lparam);
+        IN_HOOK.store(false, Release);
+        if block {
+            return 1;
         }
     }
 
     CallNextHookEx(ptr::null_mut(), code, wparam, lparam)
 }
 
-#[cfg_attr(
-    not(feature = "logging"),
-    expect(
-        clippy::unnecessary_filter_map,
-        reason = "Only use None case when parsing \"logging\" argument"
-    )
-)]
+/// The threshold in milliseconds for a mouse button.
+fn threshold(button: MouseButton) -> &'static AtomicU32 {
+    match button {
+        MouseButton::Left => &THRESHOLD_LM,
+        MouseButton::Right => &THRESHOLD_RM,
+        MouseButton::Middle => &THRESHOLD_MM,
+        MouseButton::X1 => &THRESHOLD_X1,
+        MouseButton::X2 => &THRESHOLD_X2,
+    }
+}
+
+/// The threshold in milliseconds for a mouse button's up events.
+fn up_threshold(button: MouseButton) -> u32 {
+    match UP_THRESHOLDS[button as usize].load(Relaxed) {
+        SAME_AS_DOWN => threshold(button).load(Relaxed),
+        up => up,
+    }
+}
+
+/// A mouse button event as seen by the low level mouse hook.
+#[derive(Clone, Copy)]
+struct HookEvent {
+    button: MouseButton,
+    direction: MouseDirection,
+    /// `MSLLHOOKSTRUCT.flags`
+    flags: u32,
+    /// `MSLLHOOKSTRUCT.time`, when the event happened in the same time base as
+    /// `GetTickCount`.
+    time: u32,
+    /// `MSLLHOOKSTRUCT.dwExtraInfo`
+    extra_info: usize,
+    /// `MSLLHOOKSTRUCT.pt`
+    position: (i32, i32),
+}
+
+/// Decide if a mouse event should be blocked (and track it if it isn't).
+///
+/// # Safety
+///
+/// Must only be called from the low level mouse hook with its arguments.
+unsafe fn should_block_mouse_event(wparam: WPARAM, lparam: LPARAM) -> bool {
+    let info = &*(lparam as *const windows_sys::Win32::UI::WindowsAndMessaging::MSLLHOOKSTRUCT);
+    let (button, direction) = match wparam {
+        WM_LBUTTONDOWNU => (MouseButton::Left, MouseDirection::Down),
+        WM_LBUTTONUPU => (MouseButton::Left, MouseDirection::Up),
+        WM_RBUTTONDOWNU => (MouseButton::Right, MouseDirection::Down),
+        WM_RBUTTONUPU => (MouseButton::Right, MouseDirection::Up),
+        WM_MBUTTONDOWNU => (MouseButton::Middle, MouseDirection::Down),
+        WM_MBUTTONUPU => (MouseButton::Middle, MouseDirection::Up),
+        WM_XBUTTONDOWNU | WM_XBUTTONUPU => {
+            // The high word of `mouseData` says which extended button changed:
+            let button = match (info.mouseData >> 16) as u16 {
+                XBUTTON1 => MouseButton::X1,
+                XBUTTON2 => MouseButton::X2,
+                _ => return false,
+            };
+            let direction = if wparam == WM_XBUTTONDOWNU {
+                MouseDirection::Down
+            } else {
+                MouseDirection::Up
+            };
+            (button, direction)
+        }
+        WM_MOUSEWHEELU | WM_MOUSEHWHEELU => {
+            let wheel = if wparam == WM_MOUSEWHEELU {
+                &wheel::VERTICAL
+            } else {
+                &wheel::HORIZONTAL
+            };
+            // The high word of `mouseData` is the signed wheel rotation:
+            let delta = (info.mouseData >> 16) as i16;
+            return handle_wheel_event(wheel, delta, info.flags, info.time, info.dwExtraInfo);
+        }
+        _ => return false,
+    };
+    let event = HookEvent {
+        button,
+        direction,
+        flags: info.flags,
+        time: info.time,
+        extra_info: info.dwExtraInfo,
+        position: (info.pt.x, info.pt.y),
+    };
+    handle_mouse_event(&system_api::System, &BUTTONS, &PEN_BUTTONS, event)
+}
+
+/// What the exemption rules decided for an event before it is debounced.
+enum Exempt {
+    /// No rule matched.
+    Debounce,
+    /// Let the event through because of a rule.
+    Pass(exemptions::Exemption),
+    /// Block the event because of the policy for injected events.
+    Block,
+}
+
+/// Check the rules that let a mouse event through without debouncing it, they
+/// apply to both button and wheel events.
+fn check_exemptions(flags: u32, extra_info: usize) -> Exempt {
+    use exemptions::Exemption;
+
+    if EXEMPT_TOUCH.load(Relaxed) && is_touch_event(extra_info) {
+        return Exempt::Pass(Exemption::Touch);
+    }
+    if controller::is_controller_input(flags) {
+        return Exempt::Pass(Exemption::Controller);
+    }
+    if let Some(kind) = injected::InjectedKind::from_flags(flags) {
+        match injected::handle(kind) {
+            injected::InjectedPolicy::Debounce => {}
+            injected::InjectedPolicy::Pass => {
+                return Exempt::Pass(match kind {
+                    injected::InjectedKind::Injected => Exemption::Injected,
+                    injected::InjectedKind::LowerIl => Exemption::LowerIlInjected,
+                });
+            }
+            injected::InjectedPolicy::Block => return Exempt::Block,
+        }
+    }
+    if EXEMPT_ABSOLUTE.load(Relaxed) && raw_input::last_move_was_absolute() {
+        return Exempt::Pass(Exemption::AbsoluteDevice);
+    }
+    if !raw_input::last_device_is_debounced() {
+        return Exempt::Pass(Exemption::OtherDevice);
+    }
+    #[cfg(feature = "std")]
+    if foreground_app::is_excluded() {
+        return Exempt::Pass(Exemption::Application);
+    }
+    #[cfg(feature = "std")]
+    if foreground_app::is_fullscreen_game() {
+        return Exempt::Pass(Exemption::FullscreenGame);
+    }
+    if bypass::is_held() {
+        return Exempt::Pass(Exemption::BypassKey);
+    }
+    Exempt::Debounce
+}
+
+/// Decide if a wheel event with the given rotation should be blocked, using
+/// the same exemptions and dry-run rules as button events.
+fn handle_wheel_event(
+    wheel: &wheel::Wheel,
+    delta: i16,
+    flags: u32,
+    time: u32,
+    extra_info: usize,
+) -> bool {
+    match check_exemptions(flags, extra_info) {
+        Exempt::Debounce => {}
+        Exempt::Pass(rule) => {
+            exemptions::record(rule);
+            return false;
+        }
+        Exempt::Block => return !DRY_RUN.load(Relaxed),
+    }
+    if failsafe::is_tripped() || pause::is_paused() {
+        return false;
+    }
+    // In a dry run the reversal is still counted and logged but delivered:
+    let blocked = wheel.should_block(delta, time);
+    blocked && !DRY_RUN.load(Relaxed) && failsafe::on_blocked(time)
+}
+
+/// Decide if a mouse button event should be blocked using the given debounce
+/// state for mice and pens.
+fn handle_mouse_event(
+    api: &impl WinApi,
+    buttons: &[ButtonState; MouseButton::COUNT],
+    pen_buttons: &[ButtonState; MouseButton::COUNT],
+    event: HookEvent,
+) -> bool {
+    let HookEvent {
+        button, direction, ..
+    } = event;
+    if event.extra_info == deferred::REPLAY_MARKER {
+        // Injected by us, replayed events were already handled when they were
+        // deferred:
+        buttons[button as usize].on_passed(direction);
+        return false;
+    }
+
+    match check_exemptions(event.flags, event.extra_info) {
+        Exempt::Debounce => {}
+        Exempt::Pass(rule) => {
+            exemptions::record(rule);
+            buttons[button as usize].on_passed(direction);
+            return false;
+        }
+        Exempt::Block => return !DRY_RUN.load(Relaxed),
+    }
+
+    if failsafe::is_tripped() || pause::is_paused() {
+        buttons[button as usize].on_passed(direction);
+        return false;
+    }
+
+    // Use the event's own timestamp rather than the current time so that an
+    // event that was delivered late isn't measured as happening later:
+    let event_tick = win_api::extend_tick(api.tick_count(), event.time);
+    let tick = if PRECISE_TIMING.load(Relaxed) {
+        // The timestamp only has the resolution of the system timer, so it is
+        // only used to correct for events that were clearly delivered late:
+        let late = api
+            .tick_count()
+            .saturating_sub(event_tick)
+            .saturating_sub(SYSTEM_TIMER_RESOLUTION_MS);
+        api.precise_micros().saturating_sub(late * 1000)
+    } else {
+        event_tick
+    };
+    let pen_threshold = PEN_THRESHOLDS.get(button as usize);
+    let (buttons, mut settings) =
+        if let Some(pen_threshold) = pen_threshold.filter(|_| is_pen_event(event.extra_info)) {
+            // Pens have their own thresholds and never delay events:
+            let settings = ButtonSettings {
+                threshold: pen_threshold.load(Relaxed),
+                up_threshold: pen_threshold.load(Relaxed),
+                mode: SuppressionMode::Both,
+                click_lock_time: None,
+                ticks_per_ms: ticks_per_ms(),
+            };
+            (pen_buttons, settings)
+        } else {
+            (buttons, button_settings(api, button))
+        };
+    if position::moved_away(button, event.position) {
+        // Fast clicks on different spots are intentional, a bouncing switch
+        // clicks at the same spot:
+        settings.threshold = 0;
+        settings.up_threshold = 0;
+    }
+    let mut decision = buttons[button as usize].on_event(direction, tick, settings);
+    if !decision.is_dropped() {
+        position::record(button, event.position);
+    }
+    // In a dry run the event is delivered anyway, so nothing is really blocked
+    // and there is nothing to release or replay later:
+    let dry_run = DRY_RUN.load(Relaxed);
+    if decision.is_dropped() && !dry_run && !failsafe::on_blocked(event.time) {
+        buttons[button as usize].on_passed(direction);
+        decision.blocked = false;
+    }
+    if direction == MouseDirection::Down && !dry_run {
+        failsafe::on_press(decision.is_dropped());
+    }
+    if decision.is_dropped() && direction == MouseDirection::Down {
+        feedback::record_block(button, decision.time_since_last_event, event.position);
+    }
+    if decision.deferred && !dry_run {
+        api.schedule_replay(button, settings.threshold_for(direction));
+    } else if decision.replay_deferred_up && !dry_run {
+        api.inject(&[(button, MouseDirection::Up), (button, direction)]);
+    } else if decision.replay_deferred_down && !dry_run {
+        api.inject(&[(button, MouseDirection::Down), (button, direction)]);
+    }
+
+    #[cfg(any(feature = "logging", feature = "live-feed"))]
+    let click_id = click_id::assign(button, direction, decision.is_dropped());
+    // The current time is used to measure how long it took for the event to be
+    // delivered to our hook:
+    log_mouse_event!(
+        button,
+        direction,
+        decision,
+        settings,
+        event_tick,
+        u32::try_from(api.tick_count().saturating_sub(event_tick)).unwrap_or(u32::MAX),
+        click_id
+    );
+
+    #[cfg(feature = "std")]
+    trace::record(trace::TraceEvent {
+        button,
+        direction,
+        time: event.time,
+        flags: event.flags,
+    });
+    #[cfg(feature = "live-feed")]
+    live_feed::record(live_feed::FeedEvent {
+        button,
+        direction,
+        time: event.time,
+        decision,
+        click_id,
+    });
+
+    decision.blocked && !dry_run
+}
+
+/// Get the value of an argument like `--name=value`. The name is case
+/// insensitive.
+fn flag_value<'a>(arg: &'a str, flag: &str) -> Option<&'a str> {
+    let arg = arg.trim();
+    arg.get(..flag.len())
+        .filter(|prefix| prefix.eq_ignore_ascii_case(flag))
+        .map(|_| &arg[flag.len()..])
+}
+
+/// Also write the log to a file, or exit if it can't be opened.
+#[cfg(feature = "logging")]
+fn open_log_file(path: &str) {
+    if !log_file::set_path(path) {
+        log_error(format_args!("Failed to open log file \"{path}\""));
+        std_polyfill::exit(2);
+    }
+}
+
+/// Parse the value of a threshold argument such as `--left`, for example `30`,
+/// `30ms` or `0.03s`, or exit if it isn't valid.
+fn parse_threshold(flag: &str, value: &str) -> u32 {
+    duration::parse_millis(value).unwrap_or_else(|e| {
+        log_error(format_args!(
+            "Invalid {flag} argument \"{value}\", expected a threshold such as 30, 30ms or 0.03s: {e}"
+        ));
+        std_polyfill::exit(2);
+    })
+}
+
 fn parse_and_save_args() {
+    // Settings from the registry are overridden by the config file and both
+    // are overridden by CLI arguments, so they are loaded first:
+    #[cfg(feature = "std")]
+    registry_config::load();
+    #[cfg(feature = "std")]
+    if let Err(e) = config::load(config::profile_arg(std_polyfill::args()).as_deref()) {
+        log_error(e);
+        std_polyfill::exit(2);
+    }
+
     let args = std_polyfill::args();
+    #[cfg(feature = "self-update")]
+    let mut update_hash = None;
+    #[cfg(feature = "logging")]
+    let mut run_doctor = false;
+    #[cfg(feature = "std")]
+    let mut simulate_trace = None;
+    #[cfg(feature = "std")]
+    let mut record_trace = None;
+    #[cfg(feature = "std")]
+    let mut install = false;
+    #[cfg(feature = "std")]
+    let mut save_to_registry = false;
+    #[cfg(feature = "std")]
+    let mut uninstall = false;
+    #[cfg(feature = "std")]
+    let mut confirmed = false;
+    #[cfg(feature = "multi-session")]
+    let mut session_launcher = false;
+    #[cfg(feature = "std")]
+    let mut stress_events = None;
+    // Set to an argument that should be followed by a value:
+    #[cfg(any(feature = "std", feature = "logging"))]
+    let mut pending_flag = None;
+    // Set to a threshold argument like `--left` that should be followed by its
+    // value:
+    let mut pending_threshold: Option<(&str, &AtomicU32)> = None;
+    let named_thresholds = [
+        ("--left", &THRESHOLD_LM),
+        ("--right", &THRESHOLD_RM),
+        ("--middle", &THRESHOLD_MM),
+        ("--x1", &THRESHOLD_X1),
+        ("--x2", &THRESHOLD_X2),
+        ("--left-down", &THRESHOLD_LM),
+        ("--right-down", &THRESHOLD_RM),
+        ("--middle-down", &THRESHOLD_MM),
+        ("--left-up", &UP_THRESHOLDS[MouseButton::Left as usize]),
+        ("--right-up", &UP_THRESHOLDS[MouseButton::Right as usize]),
+        ("--middle-up", &UP_THRESHOLDS[MouseButton::Middle as usize]),
+        ("--x1-down", &THRESHOLD_X1),
+        ("--x2-down", &THRESHOLD_X2),
+        ("--x1-up", &UP_THRESHOLDS[MouseButton::X1 as usize]),
+        ("--x2-up", &UP_THRESHOLDS[MouseButton::X2 as usize]),
+    ];
 
     let mut args = args.enumerate().filter_map(|(ix, arg)| {
+        if let Some((flag, threshold)) = pending_threshold.take() {
+            threshold.store(parse_threshold(flag, arg.trim()), Relaxed);
+            return None;
+        }
+        if let Some(&named) = named_thresholds
+            .iter()
+            .find(|(flag, _)| arg.trim().eq_ignore_ascii_case(flag))
+        {
+            pending_threshold = Some(named);
+            return None;
+        }
+        #[cfg(any(feature = "std", feature = "logging"))]
+        if let Some(flag) = pending_flag.take() {
+            match flag {
+                #[cfg(feature = "std")]
+                "--simulate" => simulate_trace = Some(arg),
+                #[cfg(feature = "std")]
+                "--record" => record_trace = Some(arg),
+                // Already used to load the config file:
+                #[cfg(feature = "std")]
+                "--profile" => {}
+                #[cfg(feature = "logging")]
+                "--log-file" => open_log_file(arg.trim()),
+                // Only the flags listed below are ever pending:
+                _ => {}
+            }
+            return None;
+        }
+        #[cfg(any(feature = "std", feature = "logging"))]
+        if let Some(flag) = [
+            #[cfg(feature = "std")]
+            "--simulate",
+            #[cfg(feature = "std")]
+            "--record",
+            #[cfg(feature = "std")]
+            "--profile",
+            #[cfg(feature = "logging")]
+            "--log-file",
+        ]
+        .into_iter()
+        .find(|flag| arg.trim().eq_ignore_ascii_case(flag))
+        {
+            pending_flag = Some(flag);
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--stress") {
+            stress_events = Some(stress::DEFAULT_EVENTS);
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if let Some(value) = flag_value(arg.trim(), "--stress=") {
+            stress_events = Some(value.parse::<u64>().unwrap_or_else(|e| {
+                log_error(format_args!("Invalid --stress argument \"{value}\": {e}"));
+                std_polyfill::exit(2);
+            }));
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--version") {
+            version::show();
+            std_polyfill::exit(0);
+        }
+        if ["--help", "-h", "/?"]
+            .iter()
+            .any(|flag| arg.trim().eq_ignore_ascii_case(flag))
+        {
+            usage::show();
+            std_polyfill::exit(0);
+        }
         #[cfg(feature = "logging")]
-        if arg.trim().eq_ignore_ascii_case("logging") {
+        if ["logging", "--logging"]
+            .iter()
+            .any(|flag| arg.trim().eq_ignore_ascii_case(flag))
+        {
+            logging::set_should_log(true);
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if arg.trim().eq_ignore_ascii_case("doctor") {
+            run_doctor = true;
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if arg.trim().eq_ignore_ascii_case("verbose") {
+            logging::set_verbose(true);
             logging::set_should_log(true);
             return None;
         }
+        #[cfg(feature = "logging")]
+        if let Some(value) = flag_value(arg.trim(), "--log-context=") {
+            let privacy = logging::ContextPrivacy::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid --log-context argument \"{value}\", \
+                    expected \"omit\", \"hash\" or \"full\""
+                ));
+                std_polyfill::exit(2);
+            });
+            logging::set_context_privacy(privacy);
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if let Some(value) = flag_value(arg.trim(), "--log-queue-policy=") {
+            let policy = logging::QueuePolicy::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid --log-queue-policy argument \"{value}\", \
+                    expected \"drop-newest\" or \"drop-oldest\""
+                ));
+                std_polyfill::exit(2);
+            });
+            logging::set_queue_policy(policy);
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if let Some(path) = flag_value(arg.trim(), "--log-file=") {
+            open_log_file(path);
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if arg.trim().eq_ignore_ascii_case("--no-timestamps") {
+            logging::set_use_timestamps(false);
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if arg.trim().eq_ignore_ascii_case("--no-color") {
+            logging::set_use_color(false);
+            return None;
+        }
+        #[cfg(feature = "logging")]
+        if arg.trim().eq_ignore_ascii_case("--log-window-title") {
+            window_title::set_enabled(true);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--precise-timing") {
+            set_precise_timing(true);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--high-resolution-timer") {
+            timer_resolution::set_enabled(true);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--mmcss") {
+            priority::set_use_mmcss(true);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--watchdog") {
+            watchdog::set_enabled(true);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--exempt-absolute") {
+            EXEMPT_ABSOLUTE.store(true, Relaxed);
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--game-mode") {
+            foreground_app::set_game_mode(true);
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if let Some(value) = flag_value(arg.trim(), "--exclude-apps=") {
+            foreground_app::set_excluded_apps(value);
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--device=") {
+            if !raw_input::set_device_filter(value) {
+                log_error(format_args!(
+                    "Invalid --device argument \"{value}\", it can be at most 64 characters long"
+                ));
+                std_polyfill::exit(2);
+            }
+            return None;
+        }
+        for (flag, set) in [
+            (
+                "--allow-devices=",
+                raw_input::set_allowed_devices as fn(&str) -> _,
+            ),
+            ("--deny-devices=", raw_input::set_denied_devices),
+        ] {
+            if let Some(value) = flag_value(arg.trim(), flag) {
+                if let Err(e) = set(value) {
+                    log_error(format_args!(
+                        "Invalid {} argument \"{value}\": {e}",
+                        flag.trim_end_matches('=')
+                    ));
+                    std_polyfill::exit(2);
+                }
+                return None;
+            }
+        }
+        #[cfg(feature = "logging")]
+        if arg.trim().eq_ignore_ascii_case("--list-devices") {
+            logging::set_should_log(true);
+            raw_input::list_devices();
+            std_polyfill::exit(0);
+        }
+        if let Some(value) = flag_value(arg.trim(), "--bypass-key=") {
+            let key = bypass::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid --bypass-key argument \"{value}\", expected \"Ctrl\", \"Alt\", \
+                    \"Shift\" or a key such as \"F13\""
+                ));
+                std_polyfill::exit(2);
+            });
+            bypass::set_key(key);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--dry-run") {
+            DRY_RUN.store(true, Relaxed);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--debounce-touch") {
+            EXEMPT_TOUCH.store(false, Relaxed);
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--double-click-hotkey=") {
+            let hotkey = hotkey::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid hotkey \"{value}\", expected for example \"Ctrl+Alt+D\" or \"F9\""
+                ));
+                std_polyfill::exit(2);
+            });
+            hotkey::set(hotkey::HotkeyAction::DoubleClick, hotkey);
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--false-positive-hotkey=") {
+            let hotkey = hotkey::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid hotkey \"{value}\", expected for example \"Ctrl+Alt+M\" or \"F10\""
+                ));
+                std_polyfill::exit(2);
+            });
+            hotkey::set(hotkey::HotkeyAction::MarkFalsePositive, hotkey);
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--pause-hotkey=") {
+            let hotkey = hotkey::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid hotkey \"{value}\", expected for example \"Ctrl+Alt+P\" or \"Pause\""
+                ));
+                std_polyfill::exit(2);
+            });
+            hotkey::set(hotkey::HotkeyAction::TogglePause, hotkey);
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--undo-hotkey=") {
+            let hotkey = hotkey::parse(value).unwrap_or_else(|| {
+                log_error(format_args!(
+                    "Invalid hotkey \"{value}\", expected for example \"Ctrl+Alt+Z\" or \"F11\""
+                ));
+                std_polyfill::exit(2);
+            });
+            hotkey::set(hotkey::HotkeyAction::UndoLastBlock, hotkey);
+            return None;
+        }
+        for (flag, set) in [
+            (
+                "--failsafe-rate=",
+                failsafe::set_max_blocked_per_second as fn(u32),
+            ),
+            ("--failsafe-seconds=", failsafe::set_max_seconds),
+            ("--failsafe-percent=", failsafe::set_max_blocked_percent),
+            ("--min-distance=", position::set_min_distance),
+        ] {
+            if let Some(value) = flag_value(arg.trim(), flag) {
+                set(value.parse::<u32>().unwrap_or_else(|e| {
+                    log_error(format_args!("Invalid {flag} argument \"{value}\": {e}"));
+                    std_polyfill::exit(2);
+                }));
+                return None;
+            }
+        }
+        if arg.trim().eq_ignore_ascii_case("--exempt-controller") {
+            controller::set_enabled(true);
+            return None;
+        }
+        for (flag, kind) in [
+            ("--injected=", injected::InjectedKind::Injected),
+            ("--lower-il-injected=", injected::InjectedKind::LowerIl),
+        ] {
+            if let Some(value) = flag_value(arg.trim(), flag) {
+                let policy = injected::InjectedPolicy::parse(value).unwrap_or_else(|| {
+                    log_error(format_args!(
+                        "Invalid {flag} argument \"{value}\", \
+                        expected \"debounce\", \"pass\" or \"block\""
+                    ));
+                    std_polyfill::exit(2);
+                });
+                injected::set_policy(kind, policy);
+                return None;
+            }
+        }
+        for (flag, threshold) in ["--pen-tip=", "--pen-barrel=", "--pen-eraser="]
+            .into_iter()
+            .zip(&PEN_THRESHOLDS)
+            .chain([
+                ("--left=", &THRESHOLD_LM),
+                ("--right=", &THRESHOLD_RM),
+                ("--middle=", &THRESHOLD_MM),
+                ("--x1=", &THRESHOLD_X1),
+                ("--x2=", &THRESHOLD_X2),
+                ("--left-down=", &THRESHOLD_LM),
+                ("--right-down=", &THRESHOLD_RM),
+                ("--middle-down=", &THRESHOLD_MM),
+                ("--x1-down=", &THRESHOLD_X1),
+                ("--x2-down=", &THRESHOLD_X2),
+                ("--left-up=", &UP_THRESHOLDS[MouseButton::Left as usize]),
+                ("--right-up=", &UP_THRESHOLDS[MouseButton::Right as usize]),
+                ("--middle-up=", &UP_THRESHOLDS[MouseButton::Middle as usize]),
+                ("--x1-up=", &UP_THRESHOLDS[MouseButton::X1 as usize]),
+                ("--x2-up=", &UP_THRESHOLDS[MouseButton::X2 as usize]),
+                ("--wheel=", wheel::VERTICAL.threshold()),
+                ("--hwheel=", wheel::HORIZONTAL.threshold()),
+                ("--keyboard=", &keyboard::THRESHOLD),
+            ])
+        {
+            if let Some(value) = flag_value(arg.trim(), flag) {
+                threshold.store(parse_threshold(flag, value), Relaxed);
+                return None;
+            }
+        }
+        if let Some(value) = flag_value(arg.trim(), "--keys=") {
+            if let Err(e) = keyboard::set_key_thresholds(value) {
+                log_error(format_args!("Invalid --keys argument \"{value}\": {e}"));
+                std_polyfill::exit(2);
+            }
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--up-only") {
+            SUPPRESSION_MODE.store(SuppressionMode::UpOnly as u8, Relaxed);
+            return None;
+        }
+        if arg.trim().eq_ignore_ascii_case("--delay") {
+            SUPPRESSION_MODE.store(SuppressionMode::Delay as u8, Relaxed);
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--rehook-hours=") {
+            rehook::set_interval_hours(value.parse().unwrap_or_else(|e| {
+                log_error(format_args!(
+                    "Invalid --rehook-hours argument \"{value}\", \
+                    expected a number of hours: {e}"
+                ));
+                std_polyfill::exit(2);
+            }));
+            return None;
+        }
+        if let Some(value) = flag_value(arg.trim(), "--max-runtime=") {
+            MAX_RUNTIME_SECS.store(
+                value.parse().unwrap_or_else(|e| {
+                    log_error(format_args!(
+                        "Invalid --max-runtime argument \"{value}\", \
+                        expected a number of seconds: {e}"
+                    ));
+                    std_polyfill::exit(2);
+                }),
+                Relaxed,
+            );
+            return None;
+        }
+        #[cfg(feature = "tray")]
+        if let Some(value) = flag_value(arg.trim(), "--tooltip-refresh=") {
+            tray::set_tooltip_refresh_interval(value.parse().unwrap_or_else(|e| {
+                log_error(format_args!(
+                    "Invalid --tooltip-refresh argument \"{value}\", \
+                    expected a number of seconds: {e}"
+                ));
+                std_polyfill::exit(2);
+            }));
+            return None;
+        }
+        #[cfg(feature = "self-update")]
+        if arg.trim().eq_ignore_ascii_case("--update") {
+            log_error(
+                "The --update argument requires the expected SHA-256 hash of the \
+                release binary, for example: --update=<hash>",
+            );
+            std_polyfill::exit(2);
+        }
+        #[cfg(feature = "self-update")]
+        if let Some(hash) = flag_value(arg.trim(), "--update=") {
+            update_hash = Some(self_update::parse_sha256(hash).unwrap_or_else(|e| {
+                log_error(format_args!("Invalid --update argument: {e}"));
+                std_polyfill::exit(2);
+            }));
+            return None;
+        }
+        #[cfg(feature = "live-feed")]
+        if arg.trim().eq_ignore_ascii_case("--live-feed") {
+            live_feed::set_port(live_feed::DEFAULT_PORT);
+            return None;
+        }
+        #[cfg(feature = "live-feed")]
+        if let Some(value) = flag_value(arg.trim(), "--live-feed=") {
+            let port = value.parse::<u16>().unwrap_or_else(|e| {
+                log_error(format_args!("Invalid --live-feed port \"{value}\": {e}"));
+                std_polyfill::exit(2);
+            });
+            live_feed::set_port(port);
+            return None;
+        }
+        #[cfg(feature = "com")]
+        if ["--com", "-Embedding", "/Embedding"]
+            .iter()
+            .any(|flag| arg.trim().eq_ignore_ascii_case(flag))
+        {
+            // COM adds "-Embedding" when it starts the program for a script:
+            com_server::set_enabled(true);
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--install") {
+            install = true;
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--save-to-registry") {
+            save_to_registry = true;
+            return None;
+        }
+        #[cfg(feature = "multi-session")]
+        if arg.trim().eq_ignore_ascii_case("--session-launcher") {
+            session_launcher = true;
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--create-elevated-task") {
+            // The task starts the program with the other arguments:
+            let task_args = std_polyfill::args()
+                .filter(|other| !other.trim().eq_ignore_ascii_case("--create-elevated-task"))
+                .collect::<Vec<_>>();
+            if let Err(e) = scheduled_task::create(&task_args) {
+                log_error(e);
+                std_polyfill::exit(1);
+            }
+            console::report(
+                "Created a scheduled task that starts click-once as administrator when you log in",
+            );
+            std_polyfill::exit(0);
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--remove-elevated-task") {
+            if let Err(e) = scheduled_task::remove() {
+                log_error(e);
+                std_polyfill::exit(1);
+            }
+            console::report("Removed the scheduled task");
+            std_polyfill::exit(0);
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--uninstall") {
+            uninstall = true;
+            return None;
+        }
+        #[cfg(feature = "std")]
+        if arg.trim().eq_ignore_ascii_case("--yes") {
+            confirmed = true;
+            return None;
+        }
         Some(
-            arg.parse::<u32>()
+            duration::parse_millis(arg.trim())
                 .inspect_err(|e| {
                     log_error(format_args!(
                         "CLI argument \"{arg}\" at position {} is invalid, \
-                        could not parse it as positive integer: {e}",
+                        could not parse it as a threshold such as 30, 30ms or 0.03s: {e}",
                         ix + 1
                     ))
                 })
@@ -328,21 +1433,194 @@ fn parse_and_save_args() {
         )
     });
 
-    if let Some(arg_lm) = args.next() {
+    let arg_lm = args.next();
+    let arg_rm = args.next();
+    let arg_mm = args.next();
+    if let Some(extra_arg) = args.next() {
+        log_error(format_args!(
+            "Too many integers provided as arguments, could not use: {extra_arg}"
+        ));
+        std_polyfill::exit(2);
+    }
+    drop(args);
+
+    if let Some((flag, _)) = pending_threshold {
+        log_error(format_args!(
+            "The {flag} argument requires a threshold in milliseconds"
+        ));
+        std_polyfill::exit(2);
+    }
+
+    #[cfg(any(feature = "std", feature = "logging"))]
+    if let Some(flag) = pending_flag {
+        log_error(format_args!(
+            "The {flag} argument requires {}",
+            match flag {
+                "--profile" => "the name of a profile from the config file",
+                "--log-file" => "the path to a log file",
+                _ => "the path to a trace file",
+            }
+        ));
+        std_polyfill::exit(2);
+    }
+
+    if let Some(arg_lm) = arg_lm {
         THRESHOLD_LM.store(arg_lm, Relaxed);
     }
-    if let Some(arg_rm) = args.next() {
+    if let Some(arg_rm) = arg_rm {
         THRESHOLD_RM.store(arg_rm, Relaxed);
     }
-    if let Some(arg_mm) = args.next() {
+    if let Some(arg_mm) = arg_mm {
         THRESHOLD_MM.store(arg_mm, Relaxed);
     }
-    if let Some(extra_arg) = args.next() {
-        log_error(format_args!(
-            "Too many integers provided as arguments, could not use: {extra_arg}"
-        ));
-        std_polyfill::exit(2);
+    #[cfg(feature = "std")]
+    if uninstall {
+        uninstall::uninstall(confirmed);
+        std_polyfill::exit(0);
+    }
+    // Install after all settings are known so that they can be saved:
+    #[cfg(feature = "std")]
+    if install {
+        if let Err(e) = install::install() {
+            console::report_error(&e);
+            std_polyfill::exit(1);
+        }
+        std_polyfill::exit(0);
+    }
+    #[cfg(feature = "std")]
+    if save_to_registry {
+        if let Err(e) = registry_config::save() {
+            log_error(e);
+            std_polyfill::exit(1);
+        }
+        console::report("Saved the thresholds to HKEY_CURRENT_USER\\Software\\click-once");
+        std_polyfill::exit(0);
+    }
+    #[cfg(feature = "multi-session")]
+    if session_launcher {
+        // Workers get the same arguments except for the one that started the
+        // launcher:
+        let worker_args = std_polyfill::args()
+            .filter(|arg| !arg.trim().eq_ignore_ascii_case("--session-launcher"))
+            .collect::<Vec<_>>();
+        if let Err(e) = session_launcher::run(&worker_args) {
+            log_error(e);
+            std_polyfill::exit(1);
+        }
+        std_polyfill::exit(0);
+    }
+    #[cfg(feature = "std")]
+    if let Some(path) = record_trace {
+        if let Err(e) = trace::start_recording(&path) {
+            log_error(e);
+            std_polyfill::exit(1);
+        }
+    }
+    #[cfg(feature = "std")]
+    if let Some(path) = simulate_trace {
+        #[cfg(feature = "logging")]
+        logging::set_should_log(true);
+
+        if let Err(e) = simulate::run(&path) {
+            log_error(e);
+            std_polyfill::exit(1);
+        }
+        std_polyfill::exit(0);
+    }
+    #[cfg(feature = "std")]
+    if let Some(events) = stress_events {
+        if let Err(e) = stress::run(events) {
+            log_error(e);
+            std_polyfill::exit(1);
+        }
+        std_polyfill::exit(0);
+    }
+    #[cfg(feature = "logging")]
+    if run_doctor {
+        logging::set_should_log(true);
+        doctor::run();
+        std_polyfill::exit(0);
     }
+
+    #[cfg(feature = "self-update")]
+    if let Some(update_hash) = update_hash {
+        let restart_args = std_polyfill::args()
+            .filter(|arg| {
+                !arg.trim()
+                    .get(.."--update".len())
+                    .is_some_and(|prefix| prefix.eq_ignore_ascii_case("--update"))
+            })
+            .collect::<Vec<_>>();
+        match self_update::update(&update_hash, &restart_args) {
+            Ok(()) => std_polyfill::exit(0),
+            Err(e) => {
+                log_error(e);
+                std_polyfill::exit(1);
+            }
+        }
+    }
+}
+
+/// Replace the mouse hook with a newly installed one, returns `false` and keeps
+/// the old hook if that failed. Must be called on the thread that installed the
+/// hook, so that no events are handled while the hooks are swapped.
+fn reinstall_mouse_hook() -> bool {
+    let mouse_hook =
+        unsafe { SetWindowsHookExW(WH_MOUSE_LL, Some(low_level_mouse_proc), ptr::null_mut(), 0) };
+    if mouse_hook.is_null() {
+        return false;
+    }
+    let old_hook = MOUSE_HOOK.swap(mouse_hook, Relaxed);
+    if !old_hook.is_null() {
+        unsafe { UnhookWindowsHookEx(old_hook) };
+    }
+    true
+}
+
+/// Forget when the previous mouse button events happened, so that the next
+/// events are never blocked because of events from before a gap in time such
+/// as sleep or a locked session.
+fn reset_debounce_state() {
+    for button in BUTTONS.iter().chain(&PEN_BUTTONS) {
+        button.forget_times();
+    }
+}
+
+/// Exit cleanly after this many seconds, `0` to run until the program is
+/// closed. Useful for scripted comparisons of thresholds and for soak tests.
+static MAX_RUNTIME_SECS: AtomicU32 = AtomicU32::new(0);
+
+/// Called before the event loop exits because the maximum runtime was reached.
+fn on_max_runtime_reached() {
+    #[cfg(feature = "logging")]
+    log![
+        b"Exiting since the maximum runtime of ",
+        MAX_RUNTIME_SECS.load(Relaxed),
+        b" seconds was reached\r\n"
+    ];
+    #[cfg(all(
+        feature = "logging",
+        any(feature = "tray", feature = "com", feature = "ipc")
+    ))]
+    logging::stats::log_current_stats(&mut |v| v.write());
+}
+
+/// Called when Windows logs off or shuts down, after which the process is
+/// terminated without the event loop returning.
+fn on_end_session() {
+    #[cfg(feature = "logging")]
+    logging::write_queued_events();
+    log![b"\r\nExiting since Windows is ending the session\r\n"];
+    #[cfg(all(
+        feature = "logging",
+        any(feature = "tray", feature = "com", feature = "ipc")
+    ))]
+    logging::stats::log_current_stats(&mut |v| v.write());
+    #[cfg(feature = "tray")]
+    tray::hide_icon();
+    free_mouse_hook();
+    keyboard::stop();
+    timer_resolution::end();
 }
 
 static MOUSE_HOOK: AtomicPtr<ffi::c_void> = AtomicPtr::new(ptr::null_mut());
@@ -360,15 +1638,92 @@ fn program_start() {
         if std::env::var_os("CLICK_ONCE_LOGGING").is_some_and(|value| !value.is_empty()) {
             logging::set_should_log(true);
         }
+        // Follow the NO_COLOR convention: <https://no-color.org/>
+        if std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty()) {
+            logging::set_use_color(false);
+        }
     }
 
+    #[cfg(feature = "self-update")]
+    self_update::remove_old_executable();
+
+    #[cfg(feature = "ipc")]
+    ipc::run_subcommand(std_polyfill::args());
+
+    detect_click_lock();
+
     parse_and_save_args();
 
+    match single_instance::claim() {
+        single_instance::Claim::Claimed => {}
+        single_instance::Claim::AlreadyRunning => {
+            // Starting the program again with arguments changes the thresholds
+            // of the running instance:
+            if std_polyfill::args().next().is_some() && !single_instance::forward_thresholds() {
+                log_error("click-once is already running but didn't accept the new thresholds");
+                std_polyfill::exit(1);
+            }
+            std_polyfill::exit(0);
+        }
+        single_instance::Claim::Overlaps(Some(device)) => {
+            log_error(format_args!(
+                "Another click-once instance already debounces the device {:04X}:{:04X}, \
+                the --allow-devices lists of instances that run at the same time must not overlap",
+                device.vendor, device.product
+            ));
+            std_polyfill::exit(1);
+        }
+        single_instance::Claim::Overlaps(None) => {
+            log_error(
+                "Another click-once instance debounces some of the same devices, \
+                only instances with --allow-devices lists that don't overlap can run at the same time",
+            );
+            std_polyfill::exit(1);
+        }
+    }
+
+    #[cfg(feature = "logging")]
+    logging::start_timestamps();
+    #[cfg(feature = "logging")]
+    logging::write_all(&logging::log_session_header());
+    #[cfg(feature = "logging")]
+    logging::write_all(&logging::log_program_config());
+    #[cfg(feature = "logging")]
+    logging::log_up_thresholds();
+    if DRY_RUN.load(Relaxed) {
+        log![b"Dry run: events that would be blocked are only logged\r\n"];
+    }
+
     #[cfg(feature = "logging")]
-    logging::log_program_config()
-        .iter()
-        .for_each(|value| value.write());
+    conflicts::warn_about_conflicting_software();
+
+    #[cfg(feature = "shared-stats")]
+    shared_stats::init();
+    #[cfg(feature = "perf-counters")]
+    perf_counters::init();
+
+    if EXEMPT_ABSOLUTE.load(Relaxed) || raw_input::has_device_filter() {
+        raw_input::start();
+    }
+    controller::start();
+    #[cfg(feature = "std")]
+    foreground_app::start();
+    hotkey::start();
+    single_instance::start();
+    #[cfg(feature = "std")]
+    config::watch(config::profile_arg(std_polyfill::args()));
+    #[cfg(feature = "com")]
+    com_server::start();
+    #[cfg(feature = "live-feed")]
+    live_feed::start();
+    #[cfg(feature = "ipc")]
+    ipc::start();
 
+    // The hook runs on this thread:
+    priority::raise();
+    timer_resolution::begin();
+    #[cfg(feature = "logging")]
+    logging::start_event_writer();
     let guard = {
         let mouse_hook = unsafe {
             SetWindowsHookExW(WH_MOUSE_LL, Some(low_level_mouse_proc), ptr::null_mut(), 0)
@@ -386,11 +1741,29 @@ fn program_start() {
             unsafe { UnhookWindowsHookEx(mouse_hook) };
             std_polyfill::exit(1);
         }
+        #[cfg(feature = "logging")]
+        logging::set_hook_thread();
+
+        #[cfg(feature = "logging")]
+        window_title::start();
+        rehook::start();
+        watchdog::start();
+        power::start();
+        session_lock::start();
+        shutdown::start();
+        keyboard::start();
 
         struct FinallyFreeHook;
         impl Drop for FinallyFreeHook {
             fn drop(&mut self) {
                 free_mouse_hook();
+                keyboard::stop();
+                timer_resolution::end();
+                #[cfg(feature = "logging")]
+                {
+                    logging::clear_hook_thread();
+                    logging::write_queued_events();
+                }
             }
         }
         FinallyFreeHook
@@ -402,9 +1775,32 @@ fn program_start() {
     // Simples event loop replacement:
     #[cfg(not(feature = "tray"))]
     unsafe {
-        use windows_sys::Win32::UI::WindowsAndMessaging::GetMessageW;
+        use windows_sys::Win32::UI::WindowsAndMessaging::{
+            DispatchMessageW, GetMessageW, SetTimer, MSG, USER_TIMER_MAXIMUM, WM_TIMER,
+        };
 
-        GetMessageW(&mut mem::zeroed(), ptr::null_mut(), 0, 0);
+        // Timers and Raw Input arrive as posted messages that must be
+        // dispatched, stop on `WM_QUIT` (0) or errors (-1):
+        let max_runtime = MAX_RUNTIME_SECS.load(Relaxed);
+        let runtime_timer = if max_runtime == 0 {
+            0
+        } else {
+            SetTimer(
+                ptr::null_mut(),
+                0,
+                max_runtime.saturating_mul(1000).min(USER_TIMER_MAXIMUM),
+                None,
+            )
+        };
+        let mut message: MSG = mem::zeroed();
+        while GetMessageW(&mut message, ptr::null_mut(), 0, 0) > 0 {
+            if runtime_timer != 0 && message.message == WM_TIMER && message.wParam == runtime_timer
+            {
+                on_max_runtime_reached();
+                break;
+            }
+            DispatchMessageW(&message);
+        }
     }
 
     drop(guard);
@@ -414,3 +1810,388 @@ fn program_start() {
 fn main() {
     program_start();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::{Cell, RefCell};
+    use std::sync::Mutex;
+
+    /// Tests that change global settings must not run at the same time.
+    static SETTINGS_LOCK: Mutex<()> = Mutex::new(());
+
+    /// Records what the hook asked Windows to do instead of doing it.
+    #[derive(Default)]
+    struct FakeApi {
+        tick: Cell<u64>,
+        swapped: bool,
+        injected: RefCell<Vec<(MouseButton, MouseDirection)>>,
+        scheduled: RefCell<Vec<(MouseButton, u32)>>,
+    }
+    impl FakeApi {
+        fn new() -> Self {
+            Self {
+                tick: Cell::new(100_000),
+                ..Self::default()
+            }
+        }
+        fn advance(&self, millis: u32) {
+            self.tick.set(self.tick.get() + u64::from(millis));
+        }
+        /// Let the hook handle a button event at the current tick.
+        fn send(
+            &self,
+            buttons: &[ButtonState; MouseButton::COUNT],
+            button: MouseButton,
+            direction: MouseDirection,
+            extra_info: usize,
+        ) -> bool {
+            let event = HookEvent {
+                button,
+                direction,
+                flags: 0,
+                time: self.tick.get() as u32,
+                extra_info,
+                position: (0, 0),
+            };
+            let pen_buttons = &[const { ButtonState::new() }; MouseButton::COUNT];
+            handle_mouse_event(self, buttons, pen_buttons, event)
+        }
+    }
+    impl WinApi for FakeApi {
+        fn tick_count(&self) -> u64 {
+            self.tick.get()
+        }
+        fn buttons_swapped(&self) -> bool {
+            self.swapped
+        }
+        fn inject(&self, events: &[(MouseButton, MouseDirection)]) {
+            self.injected.borrow_mut().extend_from_slice(events);
+        }
+        fn schedule_replay(&self, button: MouseButton, delay: u32) {
+            self.scheduled.borrow_mut().push((button, delay));
+        }
+    }
+
+    fn new_buttons() -> [ButtonState; MouseButton::COUNT] {
+        [const { ButtonState::new() }; MouseButton::COUNT]
+    }
+
+    #[test]
+    fn blocks_left_button_chatter_with_default_threshold() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        api.advance(50);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Up, 0));
+        api.advance(DEFAULT_THRESHOLDS[0] - 1);
+        assert!(api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        api.advance(1);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+    }
+
+    #[test]
+    fn late_delivery_does_not_hide_chatter() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        let event = |direction, time| HookEvent {
+            button: MouseButton::Left,
+            direction,
+            flags: 0,
+            time,
+            extra_info: 0,
+            position: (0, 0),
+        };
+        let pen_buttons = &new_buttons();
+        let start = api.tick.get() as u32;
+        assert!(!handle_mouse_event(
+            &api,
+            &buttons,
+            pen_buttons,
+            event(MouseDirection::Down, start)
+        ));
+        assert!(!handle_mouse_event(
+            &api,
+            &buttons,
+            pen_buttons,
+            event(MouseDirection::Up, start + 50)
+        ));
+        // The bounce happened right after the release but the hook was busy
+        // and only saw it much later:
+        api.advance(200);
+        assert!(handle_mouse_event(
+            &api,
+            &buttons,
+            pen_buttons,
+            event(MouseDirection::Down, start + 55)
+        ));
+    }
+
+    #[test]
+    fn fast_clicks_far_apart_are_not_blocked() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        position::set_min_distance(20);
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        let pen_buttons = &new_buttons();
+        let send = |direction, position| {
+            api.advance(10);
+            let event = HookEvent {
+                button: MouseButton::Left,
+                direction,
+                flags: 0,
+                time: api.tick.get() as u32,
+                extra_info: 0,
+                position,
+            };
+            handle_mouse_event(&api, &buttons, pen_buttons, event)
+        };
+        assert!(!send(MouseDirection::Down, (100, 100)));
+        assert!(!send(MouseDirection::Up, (100, 100)));
+        assert!(send(MouseDirection::Down, (110, 100)));
+        assert!(!send(MouseDirection::Down, (300, 100)));
+        position::set_min_distance(0);
+    }
+
+    #[test]
+    fn replayed_events_are_never_blocked() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        for direction in [
+            MouseDirection::Down,
+            MouseDirection::Up,
+            MouseDirection::Down,
+        ] {
+            assert!(!api.send(
+                &buttons,
+                MouseButton::Left,
+                direction,
+                deferred::REPLAY_MARKER
+            ));
+        }
+    }
+
+    #[test]
+    fn up_only_mode_delays_release_and_replays_it_before_late_press() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        SUPPRESSION_MODE.store(SuppressionMode::UpOnly as u8, Relaxed);
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        let threshold = DEFAULT_THRESHOLDS[0];
+
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        api.advance(100);
+        assert!(api.send(&buttons, MouseButton::Left, MouseDirection::Up, 0));
+        assert_eq!(*api.scheduled.borrow(), [(MouseButton::Left, threshold)]);
+
+        // The timer didn't fire in time so the release is replayed before the
+        // press:
+        api.advance(threshold);
+        assert!(api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        assert_eq!(
+            *api.injected.borrow(),
+            [
+                (MouseButton::Left, MouseDirection::Up),
+                (MouseButton::Left, MouseDirection::Down)
+            ]
+        );
+        SUPPRESSION_MODE.store(SuppressionMode::Both as u8, Relaxed);
+    }
+
+    #[test]
+    fn delay_mode_replays_press_after_threshold() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        SUPPRESSION_MODE.store(SuppressionMode::Delay as u8, Relaxed);
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        let threshold = DEFAULT_THRESHOLDS[0];
+
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        api.advance(100);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Up, 0));
+        api.advance(threshold - 10);
+        assert!(api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        assert_eq!(*api.scheduled.borrow(), [(MouseButton::Left, threshold)]);
+
+        // The timer didn't fire in time so the press is replayed before the
+        // release:
+        api.advance(threshold);
+        assert!(api.send(&buttons, MouseButton::Left, MouseDirection::Up, 0));
+        assert_eq!(
+            *api.injected.borrow(),
+            [
+                (MouseButton::Left, MouseDirection::Down),
+                (MouseButton::Left, MouseDirection::Up)
+            ]
+        );
+        SUPPRESSION_MODE.store(SuppressionMode::Both as u8, Relaxed);
+    }
+
+    #[test]
+    fn pen_events_use_pen_thresholds() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        let pen = PEN_OR_TOUCH_SIGNATURE | 0x01;
+        // Pen debouncing is disabled by default:
+        for direction in [
+            MouseDirection::Down,
+            MouseDirection::Up,
+            MouseDirection::Down,
+        ] {
+            assert!(!api.send(&buttons, MouseButton::Left, direction, pen));
+        }
+    }
+
+    #[test]
+    fn touch_events_are_exempt() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        let touch = PEN_OR_TOUCH_SIGNATURE | 0x80;
+        for direction in [
+            MouseDirection::Down,
+            MouseDirection::Up,
+            MouseDirection::Down,
+        ] {
+            assert!(!api.send(&buttons, MouseButton::Left, direction, touch));
+        }
+
+        EXEMPT_TOUCH.store(false, Relaxed);
+        api.advance(1000);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Up, touch));
+        api.advance(5);
+        assert!(api.send(&buttons, MouseButton::Left, MouseDirection::Down, touch));
+        EXEMPT_TOUCH.store(true, Relaxed);
+    }
+
+    #[test]
+    fn wheel_events_use_exemptions_and_dry_run() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let wheel = &wheel::VERTICAL;
+        wheel.threshold().store(100, Relaxed);
+        let touch = PEN_OR_TOUCH_SIGNATURE | 0x80;
+        assert!(!handle_wheel_event(wheel, 120, 0, 1000, 0));
+        assert!(!handle_wheel_event(wheel, -120, 0, 1010, touch));
+
+        DRY_RUN.store(true, Relaxed);
+        let blocked = wheel.blocked();
+        assert!(!handle_wheel_event(wheel, -120, 0, 1020, 0));
+        assert_eq!(wheel.blocked(), blocked + 1);
+        DRY_RUN.store(false, Relaxed);
+
+        assert!(handle_wheel_event(wheel, -120, 0, 1030, 0));
+        wheel.threshold().store(0, Relaxed);
+    }
+
+    #[test]
+    fn dry_run_never_blocks() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        DRY_RUN.store(true, Relaxed);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        api.advance(100);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Up, 0));
+        api.advance(5);
+        assert!(!api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0));
+        DRY_RUN.store(false, Relaxed);
+        assert!(api.injected.borrow().is_empty());
+        assert!(api.scheduled.borrow().is_empty());
+    }
+
+    #[test]
+    fn extended_buttons_use_their_own_thresholds() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        // Extended buttons aren't debounced by default:
+        for direction in [
+            MouseDirection::Down,
+            MouseDirection::Up,
+            MouseDirection::Down,
+        ] {
+            assert!(!api.send(&buttons, MouseButton::X1, direction, 0));
+        }
+
+        THRESHOLD_X2.store(30, Relaxed);
+        assert!(!api.send(&buttons, MouseButton::X2, MouseDirection::Down, 0));
+        api.advance(50);
+        assert!(!api.send(&buttons, MouseButton::X2, MouseDirection::Up, 0));
+        api.advance(5);
+        assert!(api.send(&buttons, MouseButton::X2, MouseDirection::Down, 0));
+        THRESHOLD_X2.store(DEFAULT_THRESHOLDS[4], Relaxed);
+    }
+
+    #[test]
+    fn up_events_use_their_own_threshold() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        THRESHOLD_RM.store(10, Relaxed);
+        UP_THRESHOLDS[MouseButton::Right as usize].store(50, Relaxed);
+        let api = FakeApi::new();
+        let buttons = new_buttons();
+        assert!(!api.send(&buttons, MouseButton::Right, MouseDirection::Down, 0));
+        api.advance(20);
+        assert!(!api.send(&buttons, MouseButton::Right, MouseDirection::Up, 0));
+        // The press is outside of the down threshold but the release is still
+        // within the up threshold of the previous release:
+        api.advance(15);
+        assert!(!api.send(&buttons, MouseButton::Right, MouseDirection::Down, 0));
+        api.advance(5);
+        assert!(api.send(&buttons, MouseButton::Right, MouseDirection::Up, 0));
+        THRESHOLD_RM.store(DEFAULT_THRESHOLDS[1], Relaxed);
+        UP_THRESHOLDS[MouseButton::Right as usize].store(SAME_AS_DOWN, Relaxed);
+    }
+
+    #[test]
+    fn click_lock_only_applies_to_primary_button() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        CLICK_LOCK_TIME.store(1200, Relaxed);
+        let api = FakeApi {
+            swapped: true,
+            ..FakeApi::new()
+        };
+        assert_eq!(
+            button_settings(&api, MouseButton::Right).click_lock_time,
+            Some(1200)
+        );
+        assert_eq!(
+            button_settings(&api, MouseButton::Left).click_lock_time,
+            None
+        );
+        CLICK_LOCK_TIME.store(0, Relaxed);
+    }
+
+    #[cfg(feature = "logging")]
+    #[test]
+    fn hook_thread_does_not_wait_for_log_output() {
+        let _lock = SETTINGS_LOCK.lock().unwrap();
+        let path = std::env::temp_dir().join("click-once-hook-test.log");
+        assert!(log_file::set_path(path.to_str().unwrap()));
+        logging::start_event_writer();
+
+        // Another thread is writing a long message:
+        let output = logging::lock_output();
+        let (done, hook_returned) = std::sync::mpsc::channel();
+        let hook = std::thread::spawn(move || {
+            logging::set_hook_thread();
+            log![b"Logged by the hook\r\n"];
+            let api = FakeApi::new();
+            let buttons = new_buttons();
+            api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0);
+            api.send(&buttons, MouseButton::Left, MouseDirection::Down, 0);
+            logging::clear_hook_thread();
+            done.send(()).unwrap();
+        });
+        let returned = hook_returned
+            .recv_timeout(std::time::Duration::from_secs(5))
+            .is_ok();
+        drop(output);
+        hook.join().unwrap();
+        log_file::delete();
+        assert!(returned, "the hook waited for another thread's log output");
+    }
+}
@@ -0,0 +1,72 @@
+//! Creates hidden message-only windows that receive messages which can't be
+//! delivered to a thread directly, such as Raw Input and hotkeys.
+//!
+//! Message-only windows don't receive broadcast messages, so there are also
+//! hidden top-level windows for messages such as `WM_ENDSESSION`.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/winmsg/window-features#message-only-windows>
+
+use crate::log_error;
+use core::ptr;
+use windows_sys::core::PCWSTR;
+use windows_sys::Win32::Foundation::HWND;
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, RegisterClassW, HWND_MESSAGE, WNDCLASSW, WNDPROC,
+};
+
+/// Create a message-only window with its own window class. Messages for the
+/// window are handled on the thread that calls this function, so it must be
+/// the thread that runs the message loop.
+pub fn create(class_name: PCWSTR, window_proc: WNDPROC) -> Option<HWND> {
+    create_with_parent(class_name, window_proc, HWND_MESSAGE)
+}
+
+/// Like [`create`] but the window is a hidden top-level window that also
+/// receives broadcast messages.
+pub fn create_top_level(class_name: PCWSTR, window_proc: WNDPROC) -> Option<HWND> {
+    create_with_parent(class_name, window_proc, ptr::null_mut())
+}
+
+fn create_with_parent(class_name: PCWSTR, window_proc: WNDPROC, parent: HWND) -> Option<HWND> {
+    let instance = unsafe { GetModuleHandleW(ptr::null()) };
+    let class = WNDCLASSW {
+        style: 0,
+        lpfnWndProc: window_proc,
+        cbClsExtra: 0,
+        cbWndExtra: 0,
+        hInstance: instance,
+        hIcon: ptr::null_mut(),
+        hCursor: ptr::null_mut(),
+        hbrBackground: ptr::null_mut(),
+        lpszMenuName: ptr::null(),
+        lpszClassName: class_name,
+    };
+    if unsafe { RegisterClassW(&class) } == 0 {
+        log_error("Failed to register window class for hidden window");
+        return None;
+    }
+    let window = unsafe {
+        CreateWindowExW(
+            0,
+            class_name,
+            ptr::null(),
+            0,
+            0,
+            0,
+            0,
+            0,
+            parent,
+            ptr::null_mut(),
+            instance,
+            ptr::null(),
+        )
+    };
+    if window.is_null() {
+        log_error("Failed to create hidden window");
+        return None;
+    }
+    Some(window)
+}
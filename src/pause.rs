@@ -0,0 +1,24 @@
+//! Temporarily stop blocking events without exiting the program, for example
+//! while playing a game that relies on rapid clicks. The tray icon shows when
+//! the program is paused.
+
+use crate::log;
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+
+static PAUSED: AtomicBool = AtomicBool::new(false);
+
+pub fn is_paused() -> bool {
+    PAUSED.load(Relaxed)
+}
+
+pub fn set_paused(paused: bool) {
+    if PAUSED.swap(paused, Relaxed) != paused {
+        log![if paused {
+            b"\r\nPaused, events are not blocked until resumed\r\n".as_slice()
+        } else {
+            b"\r\nResumed blocking events\r\n"
+        }];
+        #[cfg(feature = "tray")]
+        crate::tray::refresh_tooltip();
+    }
+}
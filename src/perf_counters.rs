@@ -0,0 +1,132 @@
+//! Publish statistics as Windows performance counters so that Performance
+//! Monitor and monitoring agents can chart them alongside other system
+//! metrics.
+//!
+//! The program is a perflib V2 provider with a `click-once` counter set that
+//! has one instance per button (`left`, `right`, `middle`, `x1` and `x2`). The counters
+//! refer directly to the atomic counters in this module, so incrementing them
+//! costs the same as for the other statistics. Consumers only see the counter
+//! set after the `perf-counters.man` manifest has been registered with
+//! `lodctr /m:perf-counters.man`.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/perfctrs/providing-counter-data-using-version-2-0>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/perflib/nf-perflib-perfsetcounterrefvalue>
+
+use crate::log_error;
+use crate::logging::MouseButton;
+use core::ptr;
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use windows_sys::core::GUID;
+use windows_sys::Win32::Foundation::{ERROR_SUCCESS, HANDLE};
+use windows_sys::Win32::System::Performance::{
+    PerfCreateInstance, PerfSetCounterRefValue, PerfSetCounterSetInfo, PerfStartProvider,
+    PERF_ATTRIB_BY_REFERENCE, PERF_COUNTERSET_INFO, PERF_COUNTERSET_MULTI_INSTANCES,
+    PERF_COUNTER_INFO, PERF_DETAIL_NOVICE,
+};
+
+/// Must match `providerGuid` in `perf-counters.man`.
+const PROVIDER: GUID = GUID::from_u128(0x2fbd4cff_4216_4576_959a_a008e9a26bf0);
+/// Must match the `guid` of the counter set in `perf-counters.man`.
+const COUNTER_SET: GUID = GUID::from_u128(0x331b6290_d994_45a5_afce_ce01bda878e9);
+
+/// Counter types from `winperf.h`.
+const PERF_COUNTER_RAWCOUNT: u32 = 0x0001_0000;
+const PERF_COUNTER_COUNTER: u32 = 0x1041_0400;
+
+/// The counters in the counter set as `(id, type, blocked)` where `blocked`
+/// selects which of the button's event counts the counter shows. The ids must
+/// match the `id` attributes in `perf-counters.man`.
+const COUNTERS: [(u32, u32, bool); 4] = [
+    (1, PERF_COUNTER_RAWCOUNT, false),
+    (2, PERF_COUNTER_RAWCOUNT, true),
+    (3, PERF_COUNTER_COUNTER, false),
+    (4, PERF_COUNTER_COUNTER, true),
+];
+
+/// Number of accepted (`0`) and blocked (`1`) events, indexed by button.
+static EVENTS: [[AtomicU32; 2]; MouseButton::COUNT] =
+    [const { [const { AtomicU32::new(0) }; 2] }; MouseButton::COUNT];
+
+/// Layout expected by `PerfSetCounterSetInfo`.
+#[repr(C)]
+struct CounterSetTemplate {
+    info: PERF_COUNTERSET_INFO,
+    counters: [PERF_COUNTER_INFO; COUNTERS.len()],
+}
+
+/// Register the provider and create an instance for each button. The provider
+/// is kept running for the rest of the program's lifetime.
+pub fn init() {
+    let mut provider: HANDLE = ptr::null_mut();
+    if unsafe { PerfStartProvider(&PROVIDER, None, &mut provider) } != ERROR_SUCCESS {
+        log_error("Failed to start performance counter provider");
+        return;
+    }
+
+    let mut template = CounterSetTemplate {
+        info: PERF_COUNTERSET_INFO {
+            CounterSetGuid: COUNTER_SET,
+            ProviderGuid: PROVIDER,
+            NumCounters: COUNTERS.len() as u32,
+            InstanceType: PERF_COUNTERSET_MULTI_INSTANCES,
+        },
+        counters: core::array::from_fn(|ix| PERF_COUNTER_INFO {
+            CounterId: COUNTERS[ix].0,
+            Type: COUNTERS[ix].1,
+            Attrib: PERF_ATTRIB_BY_REFERENCE,
+            Size: size_of::<u32>() as u32,
+            DetailLevel: PERF_DETAIL_NOVICE,
+            Scale: 0,
+            // The instance's data stores a pointer for each counter:
+            Offset: (ix * size_of::<*const u32>()) as u32,
+        }),
+    };
+    let result = unsafe {
+        PerfSetCounterSetInfo(
+            provider,
+            ptr::from_mut(&mut template).cast(),
+            size_of::<CounterSetTemplate>() as u32,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        log_error(format_args!(
+            "Failed to define performance counters (error {result})"
+        ));
+        return;
+    }
+
+    for &button in MouseButton::all() {
+        let name = match button {
+            MouseButton::Left => windows_sys::w!("left"),
+            MouseButton::Right => windows_sys::w!("right"),
+            MouseButton::Middle => windows_sys::w!("middle"),
+            MouseButton::X1 => windows_sys::w!("x1"),
+            MouseButton::X2 => windows_sys::w!("x2"),
+        };
+        let instance = unsafe { PerfCreateInstance(provider, &COUNTER_SET, name, button as u32) };
+        if instance.is_null() {
+            log_error(format_args!(
+                "Failed to create performance counter instance for the {} button",
+                button.name()
+            ));
+            continue;
+        }
+        for (id, _, blocked) in COUNTERS {
+            let counter = &EVENTS[button as usize][usize::from(blocked)];
+            let result =
+                unsafe { PerfSetCounterRefValue(provider, instance, id, counter.as_ptr().cast()) };
+            if result != ERROR_SUCCESS {
+                log_error(format_args!(
+                    "Failed to set performance counter {id} (error {result})"
+                ));
+            }
+        }
+    }
+}
+
+#[inline(always)]
+pub fn increment(button: MouseButton, blocked: bool) {
+    _ = EVENTS[button as usize][usize::from(blocked)].fetch_add(1, Relaxed);
+}
@@ -0,0 +1,45 @@
+//! Tells two fast clicks on different spots apart from a bouncing switch, which
+//! repeats a click at the same spot. A rapid event is only suppressed if the
+//! cursor moved less than a minimum distance since the button's previous
+//! accepted event.
+
+use crate::debounce::MouseButton;
+use core::sync::atomic::{AtomicU32, AtomicU64, Ordering::Relaxed};
+
+/// Rapid events are let through if the cursor moved at least this many pixels,
+/// `0` disables the check so that rapid events are always suppressed.
+static MIN_DISTANCE: AtomicU32 = AtomicU32::new(0);
+
+/// Where each button's last accepted event happened, with the x coordinate in
+/// the high 32 bits.
+static LAST_POSITIONS: [AtomicU64; MouseButton::COUNT] =
+    [const { AtomicU64::new(0) }; MouseButton::COUNT];
+
+pub fn set_min_distance(pixels: u32) {
+    MIN_DISTANCE.store(pixels, Relaxed);
+}
+
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn min_distance() -> u32 {
+    MIN_DISTANCE.load(Relaxed)
+}
+
+/// `true` if the cursor moved far enough from the button's previous accepted
+/// event that a rapid event is intentional.
+#[inline(always)]
+pub fn moved_away(button: MouseButton, (x, y): (i32, i32)) -> bool {
+    let min_distance = i64::from(MIN_DISTANCE.load(Relaxed));
+    if min_distance == 0 {
+        return false;
+    }
+    let last = LAST_POSITIONS[button as usize].load(Relaxed);
+    let dx = i64::from(x) - i64::from((last >> 32) as i32);
+    let dy = i64::from(y) - i64::from(last as u32 as i32);
+    dx * dx + dy * dy >= min_distance * min_distance
+}
+
+/// Remember where an accepted event happened.
+#[inline(always)]
+pub fn record(button: MouseButton, (x, y): (i32, i32)) {
+    LAST_POSITIONS[button as usize].store(u64::from(x as u32) << 32 | u64::from(y as u32), Relaxed);
+}
@@ -0,0 +1,56 @@
+//! Re-installs the mouse hook when the system resumes from sleep or
+//! hibernation, since low level hooks are sometimes no longer called after
+//! standby. The debounce state is reset as well so that the first click after
+//! resuming is never compared to one from before the system slept.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-registersuspendresumenotification>
+//! - <https://learn.microsoft.com/en-us/windows/win32/power/pbt-apmresumeautomatic>
+
+use crate::{log, log_error};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::Power::RegisterSuspendResumeNotification;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    DefWindowProcW, DEVICE_NOTIFY_WINDOW_HANDLE, PBT_APMRESUMEAUTOMATIC, WM_POWERBROADCAST,
+};
+
+fn on_resume() {
+    crate::reset_debounce_state();
+    if crate::reinstall_mouse_hook() {
+        log![b"\r\nRe-installed the mouse hook after the system resumed\r\n"];
+    } else {
+        log_error("Failed to re-install the mouse hook after the system resumed");
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    // Sent once for every resume, also when the user isn't present:
+    if message == WM_POWERBROADCAST && wparam == PBT_APMRESUMEAUTOMATIC as WPARAM {
+        on_resume();
+        return 1;
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Start listening for the system resuming. Must be called on the thread that
+/// installed the hook and runs the message loop.
+pub fn start() {
+    let Some(window) =
+        crate::message_window::create(windows_sys::w!("click-once-power"), Some(window_proc))
+    else {
+        return;
+    };
+    // Message-only windows don't receive broadcasts, so the notification must
+    // be requested:
+    let registration =
+        unsafe { RegisterSuspendResumeNotification(window, DEVICE_NOTIFY_WINDOW_HANDLE) };
+    if registration == 0 {
+        log_error("Failed to register for suspend and resume notifications");
+    }
+}
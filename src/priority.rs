@@ -0,0 +1,48 @@
+//! Raises the priority of the thread that runs the mouse hook. Windows removes
+//! a low level hook that doesn't respond in time, which can happen when the
+//! thread is starved by other programs during load spikes.
+//!
+//! Optionally the thread is also registered with the Multimedia Class
+//! Scheduler Service (MMCSS), which boosts it above normal high priority
+//! threads while it has work to do.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/processthreadsapi/nf-processthreadsapi-setthreadpriority>
+//! - <https://learn.microsoft.com/en-us/windows/win32/procthread/multimedia-class-scheduler-service>
+
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use windows_sys::Win32::System::Threading::{
+    AvSetMmThreadCharacteristicsW, GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_HIGHEST,
+};
+
+/// If `true` then the thread is also registered with MMCSS.
+static USE_MMCSS: AtomicBool = AtomicBool::new(false);
+
+pub fn set_use_mmcss(enabled: bool) {
+    USE_MMCSS.store(enabled, Relaxed);
+}
+
+pub fn use_mmcss() -> bool {
+    USE_MMCSS.load(Relaxed)
+}
+
+/// Raise the priority of the current thread, which must be the thread that
+/// installs the mouse hook.
+pub fn raise() {
+    if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_HIGHEST) } == 0 {
+        log_error("Failed to raise the priority of the mouse hook's thread");
+    }
+    if !use_mmcss() {
+        return;
+    }
+    // The registration ends when the thread exits:
+    let mut task_index = 0;
+    let task = unsafe { AvSetMmThreadCharacteristicsW(windows_sys::w!("Games"), &mut task_index) };
+    if task.is_null() {
+        log_error("Failed to register the mouse hook's thread with MMCSS");
+    } else {
+        log![b"Registered the mouse hook's thread with MMCSS\r\n"];
+    }
+}
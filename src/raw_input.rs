@@ -0,0 +1,341 @@
+//! Receives Raw Input for mice to learn which device is moving the pointer,
+//! information that the low level mouse hook doesn't provide.
+//!
+//! Raw Input is delivered to a message-only window on the thread that runs the
+//! message loop, the same thread that the mouse hook runs on. A click is
+//! attributed to the device that most recently sent input, since a mouse is
+//! usually moved before it clicks. This lets only a faulty mouse be debounced
+//! while another mouse is used normally.
+//!
+//! Devices can be selected by a part of their name or by lists of vendor and
+//! product ids that are allowed or denied.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/inputdev/raw-input>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/ns-winuser-rawmouse>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getrawinputdeviceinfow>
+
+use crate::device_id::{self, DeviceId, ParseDeviceIdError};
+use crate::log_error;
+use core::sync::atomic::{
+    AtomicBool, AtomicU16, AtomicU32, AtomicU8, AtomicUsize, Ordering::Relaxed,
+};
+use windows_sys::Win32::Foundation::{HANDLE, HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::UI::Input::{
+    GetRawInputData, GetRawInputDeviceInfoW, RegisterRawInputDevices, HRAWINPUT,
+    MOUSE_MOVE_ABSOLUTE, RAWINPUT, RAWINPUTDEVICE, RAWINPUTHEADER, RIDEV_INPUTSINK,
+    RIDI_DEVICENAME, RID_INPUT, RIM_TYPEMOUSE,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{DefWindowProcW, WM_INPUT};
+
+/// `true` if the device that most recently moved the pointer reports absolute
+/// positions, for example a drawing tablet or some KVM switches.
+static LAST_MOVE_WAS_ABSOLUTE: AtomicBool = AtomicBool::new(false);
+
+/// `true` if the most recent pointer movement came from a device that reports
+/// absolute positions. Since pointer devices move the pointer before clicking,
+/// a click is most likely from the same device.
+pub fn last_move_was_absolute() -> bool {
+    LAST_MOVE_WAS_ABSOLUTE.load(Relaxed)
+}
+
+/// Maximum length of a device filter in UTF-16 code units.
+pub const MAX_FILTER_LEN: usize = 64;
+
+/// Only devices whose name contains this text (ignoring case) are debounced,
+/// for example `VID_046D&PID_C077`.
+static DEVICE_FILTER: [AtomicU16; MAX_FILTER_LEN] = [const { AtomicU16::new(0) }; MAX_FILTER_LEN];
+static DEVICE_FILTER_LEN: AtomicUsize = AtomicUsize::new(0);
+
+/// Maximum number of devices in the allow and deny lists.
+pub const MAX_LISTED_DEVICES: usize = 16;
+
+/// A list of devices as packed [`DeviceId`]s.
+struct DeviceList {
+    ids: [AtomicU32; MAX_LISTED_DEVICES],
+    len: AtomicUsize,
+}
+impl DeviceList {
+    const fn new() -> Self {
+        Self {
+            ids: [const { AtomicU32::new(0) }; MAX_LISTED_DEVICES],
+            len: AtomicUsize::new(0),
+        }
+    }
+
+    fn set(&self, text: &str) -> Result<(), ParseDeviceIdError> {
+        let mut ids = [DeviceId {
+            vendor: 0,
+            product: 0,
+        }; MAX_LISTED_DEVICES];
+        let len = device_id::parse_list(text, &mut ids)?;
+        for (slot, id) in self.ids.iter().zip(&ids[..len]) {
+            slot.store(id.pack(), Relaxed);
+        }
+        self.len.store(len, Relaxed);
+        Ok(())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len.load(Relaxed) == 0
+    }
+
+    fn contains(&self, id: DeviceId) -> bool {
+        self.ids[..self.len.load(Relaxed)]
+            .iter()
+            .any(|slot| slot.load(Relaxed) == id.pack())
+    }
+}
+
+/// If not empty, only these devices are debounced.
+static ALLOWED_DEVICES: DeviceList = DeviceList::new();
+/// These devices are never debounced.
+static DENIED_DEVICES: DeviceList = DeviceList::new();
+
+/// The device that most recently sent input, `0` if unknown.
+static LAST_DEVICE: AtomicUsize = AtomicUsize::new(0);
+
+const DEVICE_UNKNOWN: u8 = 0;
+const DEVICE_DEBOUNCED: u8 = 1;
+const DEVICE_IGNORED: u8 = 2;
+
+/// If the device that most recently sent input should be debounced.
+static LAST_DEVICE_STATE: AtomicU8 = AtomicU8::new(DEVICE_UNKNOWN);
+
+/// Only debounce devices whose name contains `filter`. Returns `false` if the
+/// filter is too long.
+pub fn set_device_filter(filter: &str) -> bool {
+    let filter = filter.trim();
+    if filter.encode_utf16().count() > MAX_FILTER_LEN {
+        return false;
+    }
+    let mut len = 0;
+    for (slot, unit) in DEVICE_FILTER.iter().zip(filter.encode_utf16()) {
+        slot.store(unit, Relaxed);
+        len += 1;
+    }
+    DEVICE_FILTER_LEN.store(len, Relaxed);
+    true
+}
+
+/// Only debounce devices in a comma separated list of ids such as
+/// `046D:C077, 056A:0357`.
+pub fn set_allowed_devices(list: &str) -> Result<(), ParseDeviceIdError> {
+    ALLOWED_DEVICES.set(list)
+}
+
+/// The devices in the allow list.
+pub fn allowed_devices() -> impl Iterator<Item = DeviceId> {
+    ALLOWED_DEVICES.ids[..ALLOWED_DEVICES.len.load(Relaxed)]
+        .iter()
+        .map(|slot| DeviceId::unpack(slot.load(Relaxed)))
+}
+
+/// Never debounce devices in a comma separated list of ids.
+pub fn set_denied_devices(list: &str) -> Result<(), ParseDeviceIdError> {
+    DENIED_DEVICES.set(list)
+}
+
+/// `true` if only some devices are debounced, which requires Raw Input.
+pub fn has_device_filter() -> bool {
+    DEVICE_FILTER_LEN.load(Relaxed) != 0
+        || !ALLOWED_DEVICES.is_empty()
+        || !DENIED_DEVICES.is_empty()
+}
+
+/// Get a device's name, such as
+/// `\\?\HID#VID_046D&PID_C077#7&1234&0&0000#{...}`. Returns the number of
+/// UTF-16 code units that were written to `name`.
+fn device_name(device: HANDLE, name: &mut [u16; 256]) -> Option<usize> {
+    let mut len = name.len() as u32;
+    let result = unsafe {
+        GetRawInputDeviceInfoW(device, RIDI_DEVICENAME, name.as_mut_ptr().cast(), &mut len)
+    };
+    if result == u32::MAX || result == 0 {
+        return None;
+    }
+    // The returned length includes the nul terminator:
+    Some(
+        name.iter()
+            .position(|&unit| unit == 0)
+            .unwrap_or(name.len()),
+    )
+}
+
+/// Get a device's name as ASCII, since device names don't use other
+/// characters. Returns the name's length.
+fn ascii_device_name(device: HANDLE, text: &mut [u8; 256]) -> Option<usize> {
+    let mut name = [0; 256];
+    let len = device_name(device, &mut name)?;
+    for (byte, &unit) in text.iter_mut().zip(&name[..len]) {
+        *byte = u8::try_from(unit).unwrap_or(b'?');
+    }
+    Some(len)
+}
+
+/// `true` if a device's name contains the device filter.
+fn matches_filter(name: &[u8]) -> bool {
+    let filter = &DEVICE_FILTER[..DEVICE_FILTER_LEN.load(Relaxed)];
+    name.windows(filter.len()).any(|part| {
+        part.iter()
+            .zip(filter)
+            .all(|(&a, b)| u8::try_from(b.load(Relaxed)).is_ok_and(|b| a.eq_ignore_ascii_case(&b)))
+    })
+}
+
+/// `true` if a device passes the device filter and the allow and deny lists.
+fn should_debounce(device: HANDLE) -> bool {
+    let mut name = [0; 256];
+    let Some(len) = ascii_device_name(device, &mut name) else {
+        // Input from unknown devices is debounced like before:
+        return true;
+    };
+    let name = &name[..len];
+    match DeviceId::from_device_name(name) {
+        Some(id) if DENIED_DEVICES.contains(id) => return false,
+        Some(id) if !ALLOWED_DEVICES.is_empty() && !ALLOWED_DEVICES.contains(id) => return false,
+        // Devices without ids, such as Remote Desktop's mouse, can't be in
+        // the allow list:
+        None if !ALLOWED_DEVICES.is_empty() => return false,
+        _ => {}
+    }
+    matches_filter(name)
+}
+
+/// `true` if clicks should be debounced because they most likely come from a
+/// device that matches the device filter, or if there is no filter.
+#[inline(always)]
+pub fn last_device_is_debounced() -> bool {
+    if !has_device_filter() {
+        return true;
+    }
+    LAST_DEVICE_STATE.load(Relaxed) != DEVICE_IGNORED
+}
+
+/// Remember which device sent input and if it should be debounced.
+fn on_device_input(device: HANDLE) {
+    if !has_device_filter() || LAST_DEVICE.swap(device as usize, Relaxed) == device as usize {
+        return;
+    }
+    LAST_DEVICE_STATE.store(
+        if should_debounce(device) {
+            DEVICE_DEBOUNCED
+        } else {
+            DEVICE_IGNORED
+        },
+        Relaxed,
+    );
+}
+
+/// Log the names and ids of all mice so that users can find a device filter
+/// for their faulty mouse.
+#[cfg(feature = "logging")]
+pub fn list_devices() {
+    use windows_sys::Win32::UI::Input::{GetRawInputDeviceList, RAWINPUTDEVICELIST};
+
+    let mut devices = [RAWINPUTDEVICELIST {
+        hDevice: core::ptr::null_mut(),
+        dwType: 0,
+    }; 64];
+    let mut count = devices.len() as u32;
+    let result = unsafe {
+        GetRawInputDeviceList(
+            devices.as_mut_ptr(),
+            &mut count,
+            core::mem::size_of::<RAWINPUTDEVICELIST>() as u32,
+        )
+    };
+    if result == u32::MAX {
+        log_error("Failed to list input devices");
+        return;
+    }
+    crate::log![b"Mice:\r\n"];
+    for device in devices.iter().take(result as usize) {
+        if device.dwType != RIM_TYPEMOUSE {
+            continue;
+        }
+        let mut name = [0; 256];
+        let Some(len) = ascii_device_name(device.hDevice, &mut name) else {
+            continue;
+        };
+        crate::log![b"  ", &name[..len], b"\r\n"];
+        if let Some(id) = DeviceId::from_device_name(&name[..len]) {
+            const HEX: &[u8; 16] = b"0123456789ABCDEF";
+            let mut text = *b"    id 0000:0000\r\n";
+            for (ix, digit) in [id.vendor, id.product]
+                .iter()
+                .flat_map(|part| (0..4).rev().map(move |shift| (part >> (shift * 4)) & 0xF))
+                .enumerate()
+            {
+                text[7 + ix + ix / 4] = HEX[usize::from(digit)];
+            }
+            crate::log![&text[..]];
+        }
+    }
+}
+
+fn on_raw_input(handle: HRAWINPUT) {
+    let mut input = core::mem::MaybeUninit::<RAWINPUT>::zeroed();
+    let mut size = core::mem::size_of::<RAWINPUT>() as u32;
+    let result = unsafe {
+        GetRawInputData(
+            handle,
+            RID_INPUT,
+            input.as_mut_ptr().cast(),
+            &mut size,
+            core::mem::size_of::<RAWINPUTHEADER>() as u32,
+        )
+    };
+    if result == u32::MAX || result == 0 {
+        return;
+    }
+    let input = unsafe { input.assume_init() };
+    if input.header.dwType != RIM_TYPEMOUSE {
+        return;
+    }
+    on_device_input(input.header.hDevice);
+    let mouse = unsafe { input.data.mouse };
+    // Button events without movement don't tell us anything about the device:
+    let moved = mouse.usFlags & MOUSE_MOVE_ABSOLUTE != 0 || mouse.lLastX != 0 || mouse.lLastY != 0;
+    if moved {
+        LAST_MOVE_WAS_ABSOLUTE.store(mouse.usFlags & MOUSE_MOVE_ABSOLUTE != 0, Relaxed);
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_INPUT {
+        on_raw_input(lparam as HRAWINPUT);
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Start receiving Raw Input for mice. Must be called on the thread that runs
+/// the message loop.
+pub fn start() {
+    let Some(window) =
+        crate::message_window::create(windows_sys::w!("click-once-raw-input"), Some(window_proc))
+    else {
+        return;
+    };
+
+    // Generic desktop controls (usage page 1), mouse (usage 2). Input sink so
+    // that input is received even though the window is never focused:
+    let device = RAWINPUTDEVICE {
+        usUsagePage: 0x01,
+        usUsage: 0x02,
+        dwFlags: RIDEV_INPUTSINK,
+        hwndTarget: window,
+    };
+    let result = unsafe {
+        RegisterRawInputDevices(&device, 1, core::mem::size_of::<RAWINPUTDEVICE>() as u32)
+    };
+    if result == 0 {
+        log_error("Failed to register for Raw Input from mice");
+    }
+}
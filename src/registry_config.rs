@@ -0,0 +1,108 @@
+//! Reads and writes thresholds as `REG_DWORD` values under
+//! `HKEY_CURRENT_USER\Software\click-once`, for machines where the config file
+//! can't be used. The values have the same names as the keys in the config
+//! file, for example `reg add HKCU\Software\click-once /v left /t REG_DWORD /d 30`.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winreg/nf-winreg-reggetvaluew>
+
+use crate::log_error;
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_SUCCESS};
+use windows_sys::Win32::System::Registry::{
+    RegDeleteTreeW, RegGetValueW, RegSetKeyValueW, HKEY_CURRENT_USER, REG_DWORD, RRF_RT_REG_DWORD,
+};
+
+const KEY: windows_sys::core::PCWSTR = windows_sys::w!("Software\\click-once");
+
+/// `true` if any threshold was read from the registry.
+static IN_USE: AtomicBool = AtomicBool::new(false);
+
+/// `true` if thresholds were read from the registry, so that changed
+/// thresholds should be stored there as well.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn is_in_use() -> bool {
+    IN_USE.load(Relaxed)
+}
+
+/// The thresholds that are stored in the registry and their value names.
+fn thresholds() -> [(&'static str, &'static AtomicU32); 7] {
+    [
+        ("left", &crate::THRESHOLD_LM),
+        ("right", &crate::THRESHOLD_RM),
+        ("middle", &crate::THRESHOLD_MM),
+        ("x1", &crate::THRESHOLD_X1),
+        ("x2", &crate::THRESHOLD_X2),
+        ("wheel", crate::wheel::VERTICAL.threshold()),
+        ("hwheel", crate::wheel::HORIZONTAL.threshold()),
+    ]
+}
+
+/// Apply the thresholds that are stored in the registry. Missing values keep
+/// their current threshold.
+pub fn load() {
+    for (name, threshold) in thresholds() {
+        let value_name = crate::to_utf16(name);
+        let mut value = 0_u32;
+        let mut size = size_of::<u32>() as u32;
+        let result = unsafe {
+            RegGetValueW(
+                HKEY_CURRENT_USER,
+                KEY,
+                value_name.as_ptr(),
+                RRF_RT_REG_DWORD,
+                core::ptr::null_mut(),
+                core::ptr::from_mut(&mut value).cast(),
+                &mut size,
+            )
+        };
+        if result == ERROR_FILE_NOT_FOUND {
+            continue;
+        } else if result != ERROR_SUCCESS {
+            log_error(format_args!(
+                "Failed to read the {name} threshold from the registry (error {result}), \
+                it must be a REG_DWORD value"
+            ));
+        } else if value > crate::config::MAX_THRESHOLD {
+            log_error(format_args!(
+                "Ignored the {name} threshold of {value} ms in the registry, \
+                the threshold can be at most {} ms",
+                crate::config::MAX_THRESHOLD
+            ));
+        } else {
+            threshold.store(value, Relaxed);
+            IN_USE.store(true, Relaxed);
+        }
+    }
+}
+
+/// Store the current thresholds in the registry so that they are used the
+/// next time the program starts.
+pub fn save() -> Result<(), String> {
+    for (name, threshold) in thresholds() {
+        let value_name = crate::to_utf16(name);
+        let value = threshold.load(Relaxed);
+        let result = unsafe {
+            RegSetKeyValueW(
+                HKEY_CURRENT_USER,
+                KEY,
+                value_name.as_ptr(),
+                REG_DWORD,
+                core::ptr::from_ref(&value).cast(),
+                size_of::<u32>() as u32,
+            )
+        };
+        if result != ERROR_SUCCESS {
+            return Err(format!(
+                "Failed to store the {name} threshold in the registry (error {result})"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Remove the stored thresholds, returns `false` if there were none.
+pub fn remove() -> bool {
+    unsafe { RegDeleteTreeW(HKEY_CURRENT_USER, KEY) == ERROR_SUCCESS }
+}
@@ -0,0 +1,100 @@
+//! Periodically removes and re-installs the mouse hook as a workaround for
+//! environments where low level hooks stop being called after a long uptime,
+//! for example after Windows silently removed the hook because it once timed
+//! out.
+//!
+//! The hook is only replaced once the user has been idle for a while so that
+//! no events are in flight.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelmouseproc#remarks>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getlastinputinfo>
+
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::SystemInformation::GetTickCount;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows_sys::Win32::UI::WindowsAndMessaging::{DefWindowProcW, SetTimer, WM_TIMER};
+
+/// Re-install the hook after this many hours, `0` disables re-installing.
+static INTERVAL_HOURS: AtomicU32 = AtomicU32::new(0);
+
+/// Minutes since the hook was last installed.
+static MINUTES_SINCE_INSTALL: AtomicU32 = AtomicU32::new(0);
+
+/// How often the timer checks if the hook should be re-installed.
+const CHECK_INTERVAL_MS: u32 = 60_000;
+const CHECK_TIMER_ID: usize = 1;
+
+/// The user must not have used the mouse or keyboard for this long before the
+/// hook is replaced.
+const MIN_IDLE_MS: u32 = 30_000;
+
+pub fn set_interval_hours(hours: u32) {
+    INTERVAL_HOURS.store(hours, Relaxed);
+}
+
+pub fn interval_hours() -> u32 {
+    INTERVAL_HOURS.load(Relaxed)
+}
+
+fn is_user_idle() -> bool {
+    let mut info = LASTINPUTINFO {
+        cbSize: size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    if unsafe { GetLastInputInfo(&mut info) } == 0 {
+        return false;
+    }
+    unsafe { GetTickCount() }.wrapping_sub(info.dwTime) >= MIN_IDLE_MS
+}
+
+fn on_timer() {
+    let minutes = MINUTES_SINCE_INSTALL.fetch_add(1, Relaxed) + 1;
+    // Keep checking every minute once the interval has passed until the user
+    // is idle:
+    if minutes < interval_hours().saturating_mul(60) || !is_user_idle() {
+        return;
+    }
+    if crate::reinstall_mouse_hook() {
+        MINUTES_SINCE_INSTALL.store(0, Relaxed);
+        log![
+            b"Re-installed the mouse hook after ",
+            minutes / 60,
+            b" hours\r\n"
+        ];
+    } else {
+        log_error("Failed to re-install the mouse hook, keeping the old one");
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_TIMER && wparam == CHECK_TIMER_ID {
+        on_timer();
+        return 0;
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Start the timer that re-installs the hook if that is enabled. Must be
+/// called on the thread that installed the hook and runs the message loop.
+pub fn start() {
+    if interval_hours() == 0 {
+        return;
+    }
+    let Some(window) =
+        crate::message_window::create(windows_sys::w!("click-once-rehook"), Some(window_proc))
+    else {
+        return;
+    };
+    if unsafe { SetTimer(window, CHECK_TIMER_ID, CHECK_INTERVAL_MS, None) } == 0 {
+        log_error("Failed to create timer for re-installing the mouse hook");
+    }
+}
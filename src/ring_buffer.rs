@@ -0,0 +1,158 @@
+//! A fixed size queue that can be used from several threads without locks or
+//! allocations, so that the mouse hook can hand work to other threads without
+//! risking being blocked.
+//!
+//! Every slot has a stamp that says which lap of the buffer it belongs to and
+//! if it holds a value: `2 * lap` when it is free to be written in that lap and
+//! `2 * lap + 1` when it holds the value that was written in that lap.
+//!
+//! # References
+//!
+//! - <https://www.1024cores.net/home/lock-free-algorithms/queues/bounded-mpmc-queue>
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicUsize, Ordering::*};
+
+struct Slot<T> {
+    stamp: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A lock-free queue that holds at most `N` values. Values that don't fit are
+/// rejected instead of overwriting older values.
+pub struct RingBuffer<T, const N: usize> {
+    slots: [Slot<T>; N],
+    /// Position where the next value is written.
+    tail: AtomicUsize,
+    /// Position where the next value is read.
+    head: AtomicUsize,
+}
+
+// Values are only accessed by the thread that claimed their slot:
+unsafe impl<T: Send, const N: usize> Sync for RingBuffer<T, N> {}
+
+impl<T: Copy, const N: usize> RingBuffer<T, N> {
+    pub const fn new() -> Self {
+        assert!(N > 0, "a ring buffer must have at least one slot");
+        Self {
+            slots: [const {
+                Slot {
+                    stamp: AtomicUsize::new(0),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                }
+            }; N],
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+        }
+    }
+
+    /// Add a value to the end of the queue. Returns `false` if the queue is
+    /// full.
+    pub fn push(&self, value: T) -> bool {
+        loop {
+            let position = self.tail.load(Relaxed);
+            let slot = &self.slots[position % N];
+            let free = (position / N).wrapping_mul(2);
+            let stamp = slot.stamp.load(Acquire);
+            let diff = stamp.wrapping_sub(free) as isize;
+            // The value from the previous lap hasn't been read yet:
+            if diff < 0 {
+                return false;
+            }
+            // Otherwise another thread wrote to the slot, try the next position:
+            if diff == 0
+                && self
+                    .tail
+                    .compare_exchange_weak(position, position.wrapping_add(1), Relaxed, Relaxed)
+                    .is_ok()
+            {
+                unsafe { (*slot.value.get()).write(value) };
+                slot.stamp.store(free.wrapping_add(1), Release);
+                return true;
+            }
+        }
+    }
+
+    /// Remove the value at the front of the queue. Returns `None` if the queue
+    /// is empty.
+    pub fn pop(&self) -> Option<T> {
+        loop {
+            let position = self.head.load(Relaxed);
+            let slot = &self.slots[position % N];
+            let full = (position / N).wrapping_mul(2).wrapping_add(1);
+            let stamp = slot.stamp.load(Acquire);
+            let diff = stamp.wrapping_sub(full) as isize;
+            // Nothing was written to the slot in this lap yet:
+            if diff < 0 {
+                return None;
+            }
+            // Otherwise another thread read the slot, try the next position:
+            if diff == 0
+                && self
+                    .head
+                    .compare_exchange_weak(position, position.wrapping_add(1), Relaxed, Relaxed)
+                    .is_ok()
+            {
+                let value = unsafe { (*slot.value.get()).assume_init() };
+                slot.stamp.store(full.wrapping_add(1), Release);
+                return Some(value);
+            }
+        }
+    }
+}
+
+impl<T: Copy, const N: usize> Default for RingBuffer<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_are_returned_in_order_across_laps() {
+        let buffer = RingBuffer::<u32, 3>::new();
+        assert_eq!(buffer.pop(), None);
+        for lap in 0..4 {
+            assert!(buffer.push(lap * 10));
+            assert!(buffer.push(lap * 10 + 1));
+            assert_eq!(buffer.pop(), Some(lap * 10));
+            assert!(buffer.push(lap * 10 + 2));
+            assert!(buffer.push(lap * 10 + 3));
+            // Full:
+            assert!(!buffer.push(99));
+            for i in 1..4 {
+                assert_eq!(buffer.pop(), Some(lap * 10 + i));
+            }
+            assert_eq!(buffer.pop(), None);
+        }
+    }
+
+    #[test]
+    fn values_are_handed_between_threads() {
+        static BUFFER: RingBuffer<u32, 8> = RingBuffer::new();
+        const COUNT: u32 = 10_000;
+        let producer = std::thread::spawn(|| {
+            for i in 0..COUNT {
+                while !BUFFER.push(i) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+        let mut expected = 0;
+        while expected < COUNT {
+            match BUFFER.pop() {
+                Some(value) => {
+                    assert_eq!(value, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        producer.join().unwrap();
+        assert_eq!(BUFFER.pop(), None);
+    }
+}
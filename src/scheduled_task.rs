@@ -0,0 +1,166 @@
+//! Start the program with the highest privileges when the user logs in using a
+//! Task Scheduler task. Low level hooks don't see input for elevated windows
+//! unless the hook itself runs elevated, and the `Run` key used by
+//! [`crate::autostart`] can't start programs elevated without a UAC prompt.
+//!
+//! The task is created with `schtasks.exe` from an XML definition, since the
+//! command line options can't turn off the default 3 day time limit after
+//! which Task Scheduler stops the program. Creating a task that runs with the
+//! highest privileges requires elevation, so `schtasks.exe` is started with a
+//! UAC prompt.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows-server/administration/windows-commands/schtasks-create>
+//! - <https://learn.microsoft.com/en-us/windows/win32/taskschd/task-scheduler-schema>
+
+use crate::autostart::quote;
+use crate::to_utf16;
+use std::io;
+use std::os::windows::process::CommandExt;
+use windows_sys::Win32::Foundation::{CloseHandle, WAIT_OBJECT_0};
+use windows_sys::Win32::System::Threading::{
+    GetExitCodeProcess, WaitForSingleObject, CREATE_NO_WINDOW, INFINITE,
+};
+use windows_sys::Win32::UI::Shell::{ShellExecuteExW, SEE_MASK_NOCLOSEPROCESS, SHELLEXECUTEINFOW};
+use windows_sys::Win32::UI::WindowsAndMessaging::SW_HIDE;
+
+const TASK_NAME: &str = "click-once";
+
+/// Escape text for an XML element.
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// The task definition that starts `exe` with `args` when the current user
+/// logs in.
+fn task_xml(exe: &str, args: &[String]) -> Result<String, String> {
+    let user = match (std::env::var("USERDOMAIN"), std::env::var("USERNAME")) {
+        (Ok(domain), Ok(name)) => format!("{domain}\\{name}"),
+        _ => return Err("Failed to get the current user, USERNAME isn't set".to_owned()),
+    };
+    let user = escape_xml(&user);
+    let args = args
+        .iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    Ok(format!(
+        r#"<?xml version="1.0" encoding="UTF-16"?>
+<Task version="1.2" xmlns="http://schemas.microsoft.com/windows/2004/02/mit/task">
+  <Triggers>
+    <LogonTrigger>
+      <Enabled>true</Enabled>
+      <UserId>{user}</UserId>
+    </LogonTrigger>
+  </Triggers>
+  <Principals>
+    <Principal id="Author">
+      <UserId>{user}</UserId>
+      <LogonType>InteractiveToken</LogonType>
+      <RunLevel>HighestAvailable</RunLevel>
+    </Principal>
+  </Principals>
+  <Settings>
+    <MultipleInstancesPolicy>IgnoreNew</MultipleInstancesPolicy>
+    <DisallowStartIfOnBatteries>false</DisallowStartIfOnBatteries>
+    <StopIfGoingOnBatteries>false</StopIfGoingOnBatteries>
+    <ExecutionTimeLimit>PT0S</ExecutionTimeLimit>
+    <Priority>4</Priority>
+  </Settings>
+  <Actions Context="Author">
+    <Exec>
+      <Command>{}</Command>
+      <Arguments>{}</Arguments>
+    </Exec>
+  </Actions>
+</Task>
+"#,
+        escape_xml(exe),
+        escape_xml(&args),
+    ))
+}
+
+/// Run `schtasks.exe` elevated, which shows a UAC prompt unless the program
+/// already runs elevated.
+fn run_schtasks_elevated(args: &[&str]) -> Result<(), String> {
+    let parameters = args
+        .iter()
+        .map(|arg| quote(arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let parameters = to_utf16(&parameters);
+    let mut info = SHELLEXECUTEINFOW {
+        cbSize: size_of::<SHELLEXECUTEINFOW>() as u32,
+        fMask: SEE_MASK_NOCLOSEPROCESS,
+        lpVerb: windows_sys::w!("runas"),
+        lpFile: windows_sys::w!("schtasks.exe"),
+        lpParameters: parameters.as_ptr(),
+        nShow: SW_HIDE,
+        ..unsafe { core::mem::zeroed() }
+    };
+    if unsafe { ShellExecuteExW(&mut info) } == 0 {
+        return Err(format!(
+            "Failed to start schtasks.exe as administrator: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    let mut exit_code = 1;
+    unsafe {
+        if WaitForSingleObject(info.hProcess, INFINITE) == WAIT_OBJECT_0 {
+            GetExitCodeProcess(info.hProcess, &mut exit_code);
+        }
+        CloseHandle(info.hProcess);
+    }
+    if exit_code != 0 {
+        return Err(format!("schtasks.exe failed with exit code {exit_code}"));
+    }
+    Ok(())
+}
+
+/// Create or replace the task that starts the current executable with `args`
+/// and the highest privileges when the current user logs in.
+pub fn create(args: &[String]) -> Result<(), String> {
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Failed to get the path of the current executable: {e}"))?;
+    let xml = task_xml(&exe.display().to_string(), args)?;
+    // Task Scheduler expects UTF-16 with a byte order mark:
+    let bytes = core::iter::once(0xFEFF)
+        .chain(xml.encode_utf16())
+        .flat_map(u16::to_le_bytes)
+        .collect::<Vec<_>>();
+    let path = std::env::temp_dir().join("click-once-task.xml");
+    std::fs::write(&path, bytes).map_err(|e| {
+        format!(
+            "Failed to write task definition \"{}\": {e}",
+            path.display()
+        )
+    })?;
+    let result = run_schtasks_elevated(&[
+        "/Create",
+        "/F",
+        "/TN",
+        TASK_NAME,
+        "/XML",
+        &path.display().to_string(),
+    ]);
+    _ = std::fs::remove_file(&path);
+    result
+}
+
+/// `true` if the task exists.
+pub fn exists() -> bool {
+    std::process::Command::new("schtasks.exe")
+        .args(["/Query", "/TN", TASK_NAME])
+        .creation_flags(CREATE_NO_WINDOW)
+        .output()
+        .is_ok_and(|output| output.status.success())
+}
+
+/// Remove the task.
+pub fn remove() -> Result<(), String> {
+    run_schtasks_elevated(&["/Delete", "/F", "/TN", TASK_NAME])
+}
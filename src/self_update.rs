@@ -0,0 +1,264 @@
+//! Opt-in updater that replaces the current executable with the latest release
+//! from GitHub and then restarts it.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/winhttp/winhttp-sessions-overview>
+//! - <https://docs.github.com/en/repositories/releasing-projects-on-github/linking-to-releases>
+
+use crate::to_utf16;
+use std::{ffi::c_void, path::Path};
+use windows_sys::Win32::Networking::WinHttp::{
+    WinHttpCloseHandle, WinHttpConnect, WinHttpOpen, WinHttpOpenRequest, WinHttpQueryHeaders,
+    WinHttpReadData, WinHttpReceiveResponse, WinHttpSendRequest, INTERNET_DEFAULT_HTTPS_PORT,
+    WINHTTP_ACCESS_TYPE_DEFAULT_PROXY, WINHTTP_FLAG_SECURE, WINHTTP_QUERY_FLAG_NUMBER,
+    WINHTTP_QUERY_STATUS_CODE,
+};
+use windows_sys::Win32::Security::Cryptography::{BCryptHash, BCRYPT_SHA256_ALG_HANDLE};
+
+const RELEASE_HOST: &str = "github.com";
+
+/// The release asset that was built with the same features as this program.
+const ASSET_NAME: &str = if cfg!(feature = "tray") {
+    "click-once-tray.exe"
+} else if cfg!(feature = "logging") {
+    "click-once-logging.exe"
+} else {
+    "click-once.exe"
+};
+
+/// Closes a WinHTTP handle when dropped.
+struct WinHttpHandle(*mut c_void);
+impl WinHttpHandle {
+    fn new(handle: *mut c_void, action: &str) -> Result<Self, String> {
+        if handle.is_null() {
+            Err(format!(
+                "Failed to {action}: {}",
+                std::io::Error::last_os_error()
+            ))
+        } else {
+            Ok(Self(handle))
+        }
+    }
+}
+impl Drop for WinHttpHandle {
+    fn drop(&mut self) {
+        unsafe { WinHttpCloseHandle(self.0) };
+    }
+}
+
+/// Download a file using HTTPS. Redirects are followed automatically.
+fn download(host: &str, path: &str) -> Result<Vec<u8>, String> {
+    let check = |result: i32, action: &str| {
+        if result == 0 {
+            Err(format!(
+                "Failed to {action}: {}",
+                std::io::Error::last_os_error()
+            ))
+        } else {
+            Ok(())
+        }
+    };
+
+    let session = WinHttpHandle::new(
+        unsafe {
+            WinHttpOpen(
+                windows_sys::w!("click-once"),
+                WINHTTP_ACCESS_TYPE_DEFAULT_PROXY,
+                core::ptr::null(),
+                core::ptr::null(),
+                0,
+            )
+        },
+        "open HTTP session",
+    )?;
+    let host = to_utf16(host);
+    let connection = WinHttpHandle::new(
+        unsafe { WinHttpConnect(session.0, host.as_ptr(), INTERNET_DEFAULT_HTTPS_PORT, 0) },
+        "connect to server",
+    )?;
+    let path = to_utf16(path);
+    let request = WinHttpHandle::new(
+        unsafe {
+            WinHttpOpenRequest(
+                connection.0,
+                windows_sys::w!("GET"),
+                path.as_ptr(),
+                core::ptr::null(),
+                core::ptr::null(),
+                core::ptr::null(),
+                WINHTTP_FLAG_SECURE,
+            )
+        },
+        "create HTTP request",
+    )?;
+    check(
+        unsafe { WinHttpSendRequest(request.0, core::ptr::null(), 0, core::ptr::null(), 0, 0, 0) },
+        "send HTTP request",
+    )?;
+    check(
+        unsafe { WinHttpReceiveResponse(request.0, core::ptr::null_mut()) },
+        "receive HTTP response",
+    )?;
+
+    let mut status: u32 = 0;
+    let mut status_size = core::mem::size_of::<u32>() as u32;
+    check(
+        unsafe {
+            WinHttpQueryHeaders(
+                request.0,
+                WINHTTP_QUERY_STATUS_CODE | WINHTTP_QUERY_FLAG_NUMBER,
+                core::ptr::null(),
+                (&mut status as *mut u32).cast(),
+                &mut status_size,
+                core::ptr::null_mut(),
+            )
+        },
+        "get HTTP status code",
+    )?;
+    if status != 200 {
+        return Err(format!("Download failed with HTTP status code {status}"));
+    }
+
+    let mut data = Vec::new();
+    let mut buffer = vec![0_u8; 64 * 1024];
+    loop {
+        let mut read: u32 = 0;
+        check(
+            unsafe {
+                WinHttpReadData(
+                    request.0,
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len() as u32,
+                    &mut read,
+                )
+            },
+            "read HTTP response",
+        )?;
+        if read == 0 {
+            break;
+        }
+        data.extend_from_slice(&buffer[..read as usize]);
+    }
+    Ok(data)
+}
+
+/// Parse a SHA-256 hash written as 64 hexadecimal digits.
+pub fn parse_sha256(hex: &str) -> Result<[u8; 32], String> {
+    let hex = hex.trim().as_bytes();
+    let mut hash = [0; 32];
+    if hex.len() != hash.len() * 2 {
+        return Err("A SHA-256 hash must be 64 hexadecimal digits long".to_owned());
+    }
+    for (byte, digits) in hash.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = core::str::from_utf8(digits)
+            .ok()
+            .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+            .ok_or_else(|| "A SHA-256 hash must only contain hexadecimal digits".to_owned())?;
+    }
+    Ok(hash)
+}
+
+/// Compute the SHA-256 hash of some data.
+///
+/// # References
+///
+/// - <https://learn.microsoft.com/en-us/windows/win32/api/bcrypt/nf-bcrypt-bcrypthash>
+/// - <https://learn.microsoft.com/en-us/windows/win32/seccng/cng-algorithm-pseudo-handles>
+fn sha256(data: &[u8]) -> Result<[u8; 32], String> {
+    let mut hash = [0; 32];
+    let status = unsafe {
+        BCryptHash(
+            BCRYPT_SHA256_ALG_HANDLE,
+            core::ptr::null(),
+            0,
+            data.as_ptr(),
+            data.len() as u32,
+            hash.as_mut_ptr(),
+            hash.len() as u32,
+        )
+    };
+    if status != 0 {
+        return Err(format!(
+            "Failed to hash downloaded file (NTSTATUS {status:#x})"
+        ));
+    }
+    Ok(hash)
+}
+
+/// Check that the downloaded data looks like an executable and that it has the
+/// expected hash. Since this program installs a global input hook we refuse
+/// to run anything that the user hasn't explicitly pinned.
+fn verify(executable: &[u8], expected_sha256: &[u8; 32]) -> Result<(), String> {
+    if executable.len() < 1024 || !executable.starts_with(b"MZ") {
+        return Err("Downloaded file is not a Windows executable".to_owned());
+    }
+    let actual = sha256(executable)?;
+    if &actual != expected_sha256 {
+        let actual = actual
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        return Err(format!(
+            "Downloaded file has the SHA-256 hash {actual} which is not the expected hash, \
+            refusing to use it"
+        ));
+    }
+    Ok(())
+}
+
+/// Path used for the previous executable while it is being replaced. Windows
+/// allows renaming a running executable but not deleting it.
+fn old_exe_path(exe: &Path) -> std::path::PathBuf {
+    exe.with_extension("exe.old")
+}
+
+/// Remove the executable left behind by a previous update. Does nothing if
+/// there was no previous update.
+pub fn remove_old_executable() {
+    if let Ok(exe) = std::env::current_exe() {
+        _ = std::fs::remove_file(old_exe_path(&exe));
+    }
+}
+
+/// Download the latest release, verify that it has the expected SHA-256 hash,
+/// replace the current executable with it and then start the new executable
+/// with the provided arguments.
+pub fn update(expected_sha256: &[u8; 32], restart_args: &[String]) -> Result<(), String> {
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Failed to get the path of the current executable: {e}"))?;
+
+    let executable = download(
+        RELEASE_HOST,
+        &format!("/Lej77/click-once/releases/latest/download/{ASSET_NAME}"),
+    )?;
+    verify(&executable, expected_sha256)?;
+
+    let new_exe = exe.with_extension("exe.new");
+    std::fs::write(&new_exe, &executable)
+        .map_err(|e| format!("Failed to write downloaded executable: {e}"))?;
+
+    let old_exe = old_exe_path(&exe);
+    _ = std::fs::remove_file(&old_exe);
+    std::fs::rename(&exe, &old_exe)
+        .map_err(|e| format!("Failed to move current executable out of the way: {e}"))?;
+    if let Err(e) = std::fs::rename(&new_exe, &exe) {
+        // Try to restore the previous executable:
+        _ = std::fs::rename(&old_exe, &exe);
+        return Err(format!("Failed to replace executable: {e}"));
+    }
+
+    // Otherwise the new executable would find the running instance and only
+    // forward its thresholds to it:
+    if !crate::single_instance::quit_running_instances() {
+        return Err(
+            "Updated the executable but the running instance didn't exit, restart it manually"
+                .to_owned(),
+        );
+    }
+    std::process::Command::new(&exe)
+        .args(restart_args)
+        .spawn()
+        .map_err(|e| format!("Failed to start updated executable: {e}"))?;
+    Ok(())
+}
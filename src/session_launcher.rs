@@ -0,0 +1,202 @@
+//! Launcher for terminal servers and other machines where several users are
+//! logged in at the same time. A low level mouse hook only receives input from
+//! the session it runs in, so the launcher runs as `SYSTEM` (for example from a
+//! scheduled task that runs at startup) and starts a worker process as the user
+//! of each interactive session. Workers run as their user so they read that
+//! user's config file.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/wtsapi32/nf-wtsapi32-wtsqueryusertoken>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/processthreadsapi/nf-processthreadsapi-createprocessasuserw>
+//! - <https://learn.microsoft.com/en-us/windows/win32/termserv/wm-wtssession-change>
+
+use crate::{log, log_error};
+use std::io;
+use std::sync::{Mutex, OnceLock};
+use windows_sys::Win32::Foundation::{
+    CloseHandle, HANDLE, HWND, LPARAM, LRESULT, WAIT_TIMEOUT, WPARAM,
+};
+use windows_sys::Win32::System::Environment::{CreateEnvironmentBlock, DestroyEnvironmentBlock};
+use windows_sys::Win32::System::RemoteDesktop::{
+    WTSActive, WTSEnumerateSessionsW, WTSFreeMemory, WTSQueryUserToken,
+    WTSRegisterSessionNotification, NOTIFY_FOR_ALL_SESSIONS, WTS_CURRENT_SERVER_HANDLE,
+    WTS_SESSION_INFOW,
+};
+use windows_sys::Win32::System::Threading::{
+    CreateProcessAsUserW, WaitForSingleObject, CREATE_UNICODE_ENVIRONMENT, PROCESS_INFORMATION,
+    STARTUPINFOW,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    DefWindowProcW, DispatchMessageW, GetMessageW, SetTimer, WM_TIMER, WM_WTSSESSION_CHANGE,
+};
+
+/// How often workers that exited are restarted even if no session changed.
+const RESYNC_INTERVAL_MS: u32 = 30_000;
+const RESYNC_TIMER_ID: usize = 1;
+
+/// Command line used to start workers.
+static WORKER_COMMAND_LINE: OnceLock<Vec<u16>> = OnceLock::new();
+
+/// Running workers as session id and process handle.
+static WORKERS: Mutex<Vec<(u32, usize)>> = Mutex::new(Vec::new());
+
+/// Ids of the sessions that a user is logged in to and is using.
+fn active_sessions() -> Vec<u32> {
+    let mut sessions: *mut WTS_SESSION_INFOW = core::ptr::null_mut();
+    let mut count = 0;
+    if unsafe { WTSEnumerateSessionsW(WTS_CURRENT_SERVER_HANDLE, 0, 1, &mut sessions, &mut count) }
+        == 0
+    {
+        log_error(format_args!(
+            "Failed to list sessions: {}",
+            io::Error::last_os_error()
+        ));
+        return Vec::new();
+    }
+    let active = unsafe { core::slice::from_raw_parts(sessions, count as usize) }
+        .iter()
+        .filter(|session| session.State == WTSActive)
+        .map(|session| session.SessionId)
+        .collect();
+    unsafe { WTSFreeMemory(sessions.cast()) };
+    active
+}
+
+/// Start a worker as the user of a session. Returns the worker's process
+/// handle.
+fn launch(session: u32, command_line: &[u16]) -> Result<HANDLE, String> {
+    let mut token: HANDLE = core::ptr::null_mut();
+    if unsafe { WTSQueryUserToken(session, &mut token) } == 0 {
+        return Err(format!(
+            "Failed to get the user of session {session}, the launcher must run as SYSTEM: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    // Use the user's environment so that `%APPDATA%` points to their config:
+    let mut environment = core::ptr::null_mut();
+    if unsafe { CreateEnvironmentBlock(&mut environment, token, 0) } == 0 {
+        environment = core::ptr::null_mut();
+    }
+
+    let mut desktop = crate::to_utf16("winsta0\\default");
+    let startup = STARTUPINFOW {
+        cb: size_of::<STARTUPINFOW>() as u32,
+        lpDesktop: desktop.as_mut_ptr(),
+        ..unsafe { core::mem::zeroed() }
+    };
+    let mut info: PROCESS_INFORMATION = unsafe { core::mem::zeroed() };
+    // The command line buffer may be modified:
+    let mut command_line = command_line.to_vec();
+    let result = unsafe {
+        CreateProcessAsUserW(
+            token,
+            core::ptr::null(),
+            command_line.as_mut_ptr(),
+            core::ptr::null(),
+            core::ptr::null(),
+            0,
+            CREATE_UNICODE_ENVIRONMENT,
+            environment,
+            core::ptr::null(),
+            &startup,
+            &mut info,
+        )
+    };
+    let error = io::Error::last_os_error();
+    unsafe {
+        if !environment.is_null() {
+            DestroyEnvironmentBlock(environment);
+        }
+        CloseHandle(token);
+    }
+    if result == 0 {
+        return Err(format!(
+            "Failed to start worker in session {session}: {error}"
+        ));
+    }
+    unsafe { CloseHandle(info.hThread) };
+    Ok(info.hProcess)
+}
+
+/// Start workers for active sessions that don't have a running worker.
+fn sync_workers() {
+    let Some(command_line) = WORKER_COMMAND_LINE.get() else {
+        return;
+    };
+    let mut workers = WORKERS.lock().unwrap_or_else(|e| e.into_inner());
+    workers.retain(|&(session, process)| {
+        let running = unsafe { WaitForSingleObject(process as HANDLE, 0) } == WAIT_TIMEOUT;
+        if !running {
+            log![b"Worker in session ", session, b" exited\r\n"];
+            unsafe { CloseHandle(process as HANDLE) };
+        }
+        running
+    });
+    for session in active_sessions() {
+        if workers.iter().any(|&(worker, _)| worker == session) {
+            continue;
+        }
+        match launch(session, command_line) {
+            Ok(process) => {
+                log![b"Started worker in session ", session, b"\r\n"];
+                workers.push((session, process as usize));
+            }
+            Err(e) => log_error(e),
+        }
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        WM_WTSSESSION_CHANGE => {
+            sync_workers();
+            0
+        }
+        WM_TIMER if wparam == RESYNC_TIMER_ID => {
+            sync_workers();
+            0
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+/// Start a worker with `worker_args` for each interactive session and keep
+/// doing so as users log in, until the launcher is stopped.
+pub fn run(worker_args: &[String]) -> Result<(), String> {
+    let exe = std::env::current_exe()
+        .map_err(|e| format!("Failed to get the path of the current executable: {e}"))?;
+    let command_line = core::iter::once(exe.display().to_string())
+        .chain(worker_args.iter().cloned())
+        .map(|arg| crate::autostart::quote(&arg))
+        .collect::<Vec<_>>()
+        .join(" ");
+    _ = WORKER_COMMAND_LINE.set(crate::to_utf16(&command_line));
+
+    let window = crate::message_window::create(
+        windows_sys::w!("click-once-session-launcher"),
+        Some(window_proc),
+    )
+    .ok_or("Failed to create window for session notifications")?;
+    if unsafe { WTSRegisterSessionNotification(window, NOTIFY_FOR_ALL_SESSIONS) } == 0 {
+        return Err(format!(
+            "Failed to register for session notifications: {}",
+            io::Error::last_os_error()
+        ));
+    }
+    if unsafe { SetTimer(window, RESYNC_TIMER_ID, RESYNC_INTERVAL_MS, None) } == 0 {
+        log_error("Failed to create timer for restarting workers");
+    }
+    sync_workers();
+
+    let mut message = unsafe { core::mem::zeroed() };
+    while unsafe { GetMessageW(&mut message, core::ptr::null_mut(), 0, 0) } > 0 {
+        unsafe { DispatchMessageW(&message) };
+    }
+    Ok(())
+}
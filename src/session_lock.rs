@@ -0,0 +1,74 @@
+//! Resets the debounce state when the session is locked or unlocked and when
+//! the user switches to another session and back, since the first click after
+//! such a transition must never be compared to one from before it. Statistics
+//! aren't recorded while the session is locked.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/wtsapi32/nf-wtsapi32-wtsregistersessionnotification>
+//! - <https://learn.microsoft.com/en-us/windows/win32/termserv/wm-wtssession-change>
+
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::RemoteDesktop::{
+    WTSRegisterSessionNotification, NOTIFY_FOR_THIS_SESSION,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    DefWindowProcW, WM_WTSSESSION_CHANGE, WTS_CONSOLE_CONNECT, WTS_CONSOLE_DISCONNECT,
+    WTS_REMOTE_CONNECT, WTS_REMOTE_DISCONNECT, WTS_SESSION_LOCK, WTS_SESSION_UNLOCK,
+};
+
+/// `true` while the session is locked or another session is active.
+static LOCKED: AtomicBool = AtomicBool::new(false);
+
+/// `true` if the session is locked, statistics aren't recorded then.
+#[allow(dead_code, reason = "only used by certain features")]
+#[inline(always)]
+pub fn is_locked() -> bool {
+    LOCKED.load(Relaxed)
+}
+
+fn on_session_change(change: u32) {
+    let locked = match change {
+        WTS_SESSION_LOCK | WTS_CONSOLE_DISCONNECT | WTS_REMOTE_DISCONNECT => true,
+        WTS_SESSION_UNLOCK | WTS_CONSOLE_CONNECT | WTS_REMOTE_CONNECT => false,
+        _ => return,
+    };
+    crate::reset_debounce_state();
+    if LOCKED.swap(locked, Relaxed) != locked {
+        log![if locked {
+            b"\r\nThe session was locked or disconnected\r\n".as_slice()
+        } else {
+            b"\r\nThe session was unlocked or reconnected\r\n"
+        }];
+    }
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_WTSSESSION_CHANGE {
+        on_session_change(wparam as u32);
+        return 0;
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Start listening for session changes. Must be called on the thread that runs
+/// the message loop.
+pub fn start() {
+    let Some(window) = crate::message_window::create(
+        windows_sys::w!("click-once-session-lock"),
+        Some(window_proc),
+    ) else {
+        return;
+    };
+    // The registration is removed automatically when the window is destroyed:
+    if unsafe { WTSRegisterSessionNotification(window, NOTIFY_FOR_THIS_SESSION) } == 0 {
+        log_error("Failed to register for session lock notifications");
+    }
+}
@@ -0,0 +1,97 @@
+//! Publish live statistics in a named shared memory section so that external
+//! monitoring tools can read them without any IPC round-trips or text parsing.
+//!
+//! The section is named `Local\click-once-stats` and contains a single
+//! [`SharedStats`] struct. See its documentation for the layout.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/memory/creating-named-shared-memory>
+
+use crate::log_error;
+use crate::logging::{MouseButton, MouseDirection};
+use core::sync::atomic::{AtomicPtr, AtomicU32, Ordering::*};
+use windows_sys::Win32::Foundation::INVALID_HANDLE_VALUE;
+use windows_sys::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, FILE_MAP_WRITE, PAGE_READWRITE,
+};
+use windows_sys::Win32::System::Threading::GetCurrentProcessId;
+
+/// Layout of the shared memory section. All fields are little endian `u32`
+/// values:
+///
+/// | Offset | Field                                                  |
+/// |--------|--------------------------------------------------------|
+/// | 0      | Layout version, currently `2`                          |
+/// | 4      | Process id of the click-once instance that writes here |
+/// | 8      | Counters, see [`SharedStats::counters`]                |
+///
+/// The counters are only ever incremented (wrapping on overflow) and can be
+/// read at any time without synchronization.
+#[repr(C)]
+pub struct SharedStats {
+    pub version: AtomicU32,
+    pub process_id: AtomicU32,
+    /// Event counters indexed by button (left, right, middle, X1, X2), then
+    /// direction (up, down) and finally if the event was accepted (`0`) or
+    /// blocked (`1`).
+    pub counters: [[[AtomicU32; 2]; 2]; MouseButton::COUNT],
+}
+impl SharedStats {
+    /// Version `1` didn't have counters for the X1 and X2 buttons.
+    pub const VERSION: u32 = 2;
+}
+
+static SHARED_STATS: AtomicPtr<SharedStats> = AtomicPtr::new(core::ptr::null_mut());
+
+/// Create the shared memory section. The section is kept open for the rest of
+/// the program's lifetime.
+pub fn init() {
+    let size = core::mem::size_of::<SharedStats>() as u32;
+    let mapping = unsafe {
+        CreateFileMappingW(
+            INVALID_HANDLE_VALUE,
+            core::ptr::null(),
+            PAGE_READWRITE,
+            0,
+            size,
+            windows_sys::w!("Local\\click-once-stats"),
+        )
+    };
+    if mapping.is_null() {
+        log_error("Failed to create shared memory for statistics");
+        return;
+    }
+    let view = unsafe { MapViewOfFile(mapping, FILE_MAP_WRITE, 0, 0, size as usize) };
+    let stats = view.Value.cast::<SharedStats>();
+    if stats.is_null() {
+        log_error("Failed to map shared memory for statistics");
+        return;
+    }
+
+    // The section might have been used by a previous instance:
+    let shared = unsafe { &*stats };
+    shared
+        .counters
+        .iter()
+        .flatten()
+        .flatten()
+        .for_each(|counter| counter.store(0, Relaxed));
+    shared
+        .process_id
+        .store(unsafe { GetCurrentProcessId() }, Relaxed);
+    shared.version.store(SharedStats::VERSION, Release);
+
+    SHARED_STATS.store(stats, Release);
+}
+
+#[inline(always)]
+pub fn increment(button: MouseButton, direction: MouseDirection, blocked: bool) {
+    let stats = SHARED_STATS.load(Acquire);
+    if stats.is_null() {
+        return;
+    }
+    let counter =
+        &unsafe { &*stats }.counters[button as usize][direction as usize][usize::from(blocked)];
+    _ = counter.fetch_add(1, Relaxed);
+}
@@ -0,0 +1,43 @@
+//! Cleans up when Windows logs off or shuts down. The process is terminated
+//! soon after `WM_ENDSESSION` is handled, without the event loop exiting, so
+//! the hooks and the tray icon are removed here.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/shutdown/wm-queryendsession>
+//! - <https://learn.microsoft.com/en-us/windows/win32/shutdown/wm-endsession>
+
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    DefWindowProcW, WM_ENDSESSION, WM_QUERYENDSESSION,
+};
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        // Never delay logging off:
+        WM_QUERYENDSESSION => 1,
+        // `wparam` is `FALSE` if the session isn't ending after all:
+        WM_ENDSESSION => {
+            if wparam != 0 {
+                crate::on_end_session();
+            }
+            0
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+/// Start listening for the session ending. Must be called on the thread that
+/// installed the hooks and runs the message loop.
+pub fn start() {
+    // The end session messages are only sent to top-level windows:
+    _ = crate::message_window::create_top_level(
+        windows_sys::w!("click-once-shutdown"),
+        Some(window_proc),
+    );
+}
@@ -0,0 +1,101 @@
+//! Replay a recorded event trace through the debounce logic to see which events
+//! would have been blocked with the current thresholds. Useful for tuning
+//! thresholds offline.
+
+use crate::debounce::{ButtonState, Decision, MouseButton, MouseDirection};
+use crate::trace;
+use core::sync::atomic::Ordering::Relaxed;
+
+/// Simulate the events in a trace file and print a report to stdout.
+pub fn run(path: &str) -> Result<(), String> {
+    let data = std::fs::read(path).map_err(|e| format!("Failed to read \"{path}\": {e}"))?;
+    let events = trace::decode(&data).map_err(|e| format!("Failed to read \"{path}\": {e}"))?;
+
+    let buttons = [const { ButtonState::new() }; MouseButton::COUNT];
+    // Counts of [accepted, blocked] events per button:
+    let mut counts = [[0_u32; 2]; MouseButton::COUNT];
+    // Time and direction of a deferred event that hasn't been resolved yet:
+    let mut deferred = [None; MouseButton::COUNT];
+    // Recorded times are 32 bit and can wrap around during a long trace:
+    let mut previous_time = None;
+
+    let print_blocked = |time: u32, button, direction, decision: Decision| {
+        let button = match button {
+            MouseButton::Left => "Left",
+            MouseButton::Right => "Right",
+            MouseButton::Middle => "Middle",
+            MouseButton::X1 => "X1",
+            MouseButton::X2 => "X2",
+        };
+        let direction = match direction {
+            MouseDirection::Up => "up",
+            MouseDirection::Down => "down",
+        };
+        let reason = if decision.double_up {
+            "already released, after"
+        } else {
+            "within"
+        };
+        println!(
+            "  {time:>10} ms: {button} {direction} event would be blocked ({reason} {} ms)",
+            decision.time_since_last_event
+        );
+    };
+
+    println!("Simulating \"{path}\":");
+    for event in events {
+        let event = event.map_err(|e| format!("Failed to read \"{path}\": {e}"))?;
+        let index = event.button as usize;
+        let mut settings = crate::button_settings(&crate::system_api::System, event.button);
+        // Recorded times are in milliseconds:
+        settings.ticks_per_ms = 1;
+        let time = previous_time.map_or(u64::from(event.time), |previous| {
+            crate::win_api::extend_tick(previous, event.time)
+        });
+        previous_time = Some(time);
+        let decision = buttons[index].on_event(event.direction, time, settings);
+
+        // A deferred event is dropped together with the next event if it
+        // follows before the deferred event is replayed, otherwise it is
+        // eventually accepted:
+        if let Some((deferred_time, deferred_direction)) = deferred[index].take() {
+            let dropped = decision.is_dropped() && event.direction != deferred_direction;
+            counts[index][usize::from(dropped)] += 1;
+            if dropped {
+                print_blocked(deferred_time, event.button, deferred_direction, decision);
+            }
+        }
+        if decision.deferred {
+            deferred[index] = Some((event.time, event.direction));
+            continue;
+        }
+
+        counts[index][usize::from(decision.is_dropped())] += 1;
+        if decision.is_dropped() {
+            print_blocked(event.time, event.button, event.direction, decision);
+        }
+    }
+    for (index, deferred) in deferred.iter().enumerate() {
+        if deferred.is_some() {
+            counts[index][0] += 1;
+        }
+    }
+
+    println!("\nSummary:");
+    for &button in MouseButton::all() {
+        let [accepted, blocked] = counts[button as usize];
+        let name = match button {
+            MouseButton::Left => "Left button:  ",
+            MouseButton::Right => "Right button: ",
+            MouseButton::Middle => "Middle button:",
+            MouseButton::X1 => "X1 button:    ",
+            MouseButton::X2 => "X2 button:    ",
+        };
+        println!(
+            "  {name} {blocked} / {} blocked (threshold {} ms)",
+            accepted + blocked,
+            crate::threshold(button).load(Relaxed)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,266 @@
+//! Makes sure that only one instance of the program runs in a session, since
+//! two mouse hooks would debounce every click twice. A second instance sends
+//! the thresholds from its arguments to the running instance and exits, so
+//! starting the program again with new thresholds changes them without a
+//! restart.
+//!
+//! The running instance owns a named mutex and a message-only window that
+//! receives the thresholds as `WM_COPYDATA`.
+//!
+//! Instances that only debounce the devices in their `--allow-devices` list
+//! can run at the same time as long as their lists don't share a device. Such
+//! an instance owns a mutex for each of its devices instead, together with a
+//! mutex that tells instances without a list (which debounce every device)
+//! that some devices are taken.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-createmutexw>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/synchapi/nf-synchapi-openmutexw>
+//! - <https://learn.microsoft.com/en-us/windows/win32/dataxchg/wm-copydata>
+
+use crate::device_id::DeviceId;
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use windows_sys::core::PCWSTR;
+use windows_sys::Win32::Foundation::{
+    CloseHandle, GetLastError, ERROR_ALREADY_EXISTS, HWND, LPARAM, LRESULT, WAIT_OBJECT_0, WPARAM,
+};
+use windows_sys::Win32::System::DataExchange::COPYDATASTRUCT;
+use windows_sys::Win32::System::Threading::{
+    CreateMutexW, OpenMutexW, OpenProcess, WaitForSingleObject, SYNCHRONIZATION_SYNCHRONIZE,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    DefWindowProcW, FindWindowExW, GetWindowThreadProcessId, SendMessageW, HWND_MESSAGE,
+    WM_COPYDATA,
+};
+
+const CLASS_NAME: windows_sys::core::PCWSTR = windows_sys::w!("click-once-instance");
+
+/// Identifies the layout of the forwarded thresholds in `COPYDATASTRUCT.dwData`.
+const THRESHOLDS_MESSAGE: usize = 0x434F_0001;
+
+/// Asks the running instance to exit, carries no data.
+#[allow(dead_code, reason = "only used by certain features")]
+const QUIT_MESSAGE: usize = 0x434F_0002;
+
+/// The thresholds that are forwarded, in the order they are sent.
+fn thresholds() -> [&'static AtomicU32; 12] {
+    [
+        &crate::THRESHOLD_LM,
+        &crate::THRESHOLD_RM,
+        &crate::THRESHOLD_MM,
+        &crate::THRESHOLD_X1,
+        &crate::THRESHOLD_X2,
+        &crate::UP_THRESHOLDS[0],
+        &crate::UP_THRESHOLDS[1],
+        &crate::UP_THRESHOLDS[2],
+        &crate::UP_THRESHOLDS[3],
+        &crate::UP_THRESHOLDS[4],
+        crate::wheel::VERTICAL.threshold(),
+        crate::wheel::HORIZONTAL.threshold(),
+    ]
+}
+
+/// Owned by an instance that debounces every device.
+const INSTANCE_MUTEX: PCWSTR = windows_sys::w!("Local\\click-once-instance");
+
+/// Owned by every instance that only debounces some devices.
+const SCOPED_MUTEX: PCWSTR = windows_sys::w!("Local\\click-once-scoped");
+
+/// The result of [`claim`].
+pub enum Claim {
+    /// No other instance debounces the same devices.
+    Claimed,
+    /// Another instance debounces every device, just like this one would.
+    AlreadyRunning,
+    /// Another instance debounces some of the devices that this instance
+    /// would. Contains the device if it is known.
+    Overlaps(Option<DeviceId>),
+}
+
+/// Create a named mutex that is released by Windows when the process exits.
+/// Returns `false` if it already existed.
+fn create_mutex(name: PCWSTR) -> bool {
+    let mutex = unsafe { CreateMutexW(core::ptr::null(), 0, name) };
+    if mutex.is_null() {
+        log_error("Failed to create mutex to detect other instances");
+        return true;
+    }
+    let error = unsafe { GetLastError() };
+    error != ERROR_ALREADY_EXISTS
+}
+
+/// `true` if another process owns a mutex with this name.
+fn mutex_exists(name: PCWSTR) -> bool {
+    let mutex = unsafe { OpenMutexW(SYNCHRONIZATION_SYNCHRONIZE, 0, name) };
+    if mutex.is_null() {
+        return false;
+    }
+    unsafe { CloseHandle(mutex) };
+    true
+}
+
+/// The null terminated name of the mutex for a device, such as
+/// `Local\click-once-device-046DC077`.
+fn device_mutex(device: DeviceId) -> [u16; 34] {
+    let mut name = [0; 34];
+    let prefix = "Local\\click-once-device-";
+    for (unit, prefix) in name.iter_mut().zip(prefix.encode_utf16()) {
+        *unit = prefix;
+    }
+    let packed = device.pack();
+    for (ix, digit) in name[prefix.len()..][..8].iter_mut().enumerate() {
+        *digit = u16::from(b"0123456789ABCDEF"[(packed >> (28 - ix * 4)) as usize & 0xf]);
+    }
+    name
+}
+
+/// `true` if this instance only debounces the devices in its
+/// `--allow-devices` list, so other instances can debounce other devices.
+pub fn is_scoped() -> bool {
+    crate::raw_input::allowed_devices().next().is_some()
+}
+
+/// Claim the session, or the devices in the `--allow-devices` list, for this
+/// instance.
+pub fn claim() -> Claim {
+    if !is_scoped() {
+        if !create_mutex(INSTANCE_MUTEX) {
+            return Claim::AlreadyRunning;
+        }
+        if mutex_exists(SCOPED_MUTEX) {
+            return Claim::Overlaps(None);
+        }
+        return Claim::Claimed;
+    }
+    _ = create_mutex(SCOPED_MUTEX);
+    if mutex_exists(INSTANCE_MUTEX) {
+        return Claim::Overlaps(None);
+    }
+    for device in crate::raw_input::allowed_devices() {
+        if !create_mutex(device_mutex(device).as_ptr()) {
+            return Claim::Overlaps(Some(device));
+        }
+    }
+    Claim::Claimed
+}
+
+/// The message window of a running instance, if there is one.
+fn find_running_instance() -> Option<HWND> {
+    let window = unsafe {
+        FindWindowExW(
+            HWND_MESSAGE,
+            core::ptr::null_mut(),
+            CLASS_NAME,
+            core::ptr::null(),
+        )
+    };
+    (!window.is_null()).then_some(window)
+}
+
+/// Send this instance's thresholds to the running instance. Returns `false`
+/// if they couldn't be delivered.
+pub fn forward_thresholds() -> bool {
+    let Some(window) = find_running_instance() else {
+        return false;
+    };
+    let values = thresholds().map(|threshold| threshold.load(Relaxed));
+    let data = COPYDATASTRUCT {
+        dwData: THRESHOLDS_MESSAGE,
+        cbData: size_of_val(&values) as u32,
+        lpData: values.as_ptr().cast_mut().cast(),
+    };
+    unsafe { SendMessageW(window, WM_COPYDATA, 0, core::ptr::from_ref(&data) as LPARAM) != 0 }
+}
+
+/// Ask the running instances to exit and wait until they have, so that a new
+/// executable can take their place. Returns `false` if an instance didn't exit
+/// in time.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn quit_running_instances() -> bool {
+    /// How long to wait for an instance to exit, in milliseconds.
+    const TIMEOUT: u32 = 10_000;
+
+    // Instances with separate devices can run at the same time, so stop
+    // after a reasonable number of them in case one keeps its window:
+    for _ in 0..16 {
+        let Some(window) = find_running_instance() else {
+            return true;
+        };
+        let mut pid = 0;
+        unsafe { GetWindowThreadProcessId(window, &mut pid) };
+        let process = unsafe { OpenProcess(SYNCHRONIZATION_SYNCHRONIZE, 0, pid) };
+        let data = COPYDATASTRUCT {
+            dwData: QUIT_MESSAGE,
+            cbData: 0,
+            lpData: core::ptr::null_mut(),
+        };
+        let accepted = unsafe {
+            SendMessageW(window, WM_COPYDATA, 0, core::ptr::from_ref(&data) as LPARAM) != 0
+        };
+        if process.is_null() {
+            return false;
+        }
+        let exited = accepted && unsafe { WaitForSingleObject(process, TIMEOUT) } == WAIT_OBJECT_0;
+        unsafe { CloseHandle(process) };
+        if !exited {
+            return false;
+        }
+    }
+    false
+}
+
+/// Exit the program the same way as when it is quit from the tray.
+fn quit() {
+    log![b"\r\nExiting since another instance asked this one to quit\r\n"];
+    #[cfg(feature = "tray")]
+    crate::tray::quit();
+    // The window belongs to the thread that runs the message loop:
+    #[cfg(not(feature = "tray"))]
+    unsafe {
+        windows_sys::Win32::UI::WindowsAndMessaging::PostQuitMessage(0)
+    };
+}
+
+fn on_copy_data(data: &COPYDATASTRUCT) -> bool {
+    if data.dwData == QUIT_MESSAGE {
+        quit();
+        return true;
+    }
+    let targets = thresholds();
+    if data.dwData != THRESHOLDS_MESSAGE
+        || data.cbData as usize != size_of::<[u32; 12]>()
+        || data.lpData.is_null()
+    {
+        return false;
+    }
+    let values = unsafe { data.lpData.cast::<[u32; 12]>().read_unaligned() };
+    for (threshold, value) in targets.into_iter().zip(values) {
+        threshold.store(value, Relaxed);
+    }
+    log![b"\r\nApplied thresholds from another instance\r\n"];
+    #[cfg(feature = "logging")]
+    crate::logging::write_all(&crate::logging::log_program_config());
+    #[cfg(feature = "tray")]
+    crate::tray::refresh_tooltip();
+    true
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_COPYDATA {
+        return on_copy_data(&*(lparam as *const COPYDATASTRUCT)).into();
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Start receiving thresholds from other instances. Must be called on the
+/// thread that runs the message loop.
+pub fn start() {
+    _ = crate::message_window::create(CLASS_NAME, Some(window_proc));
+}
@@ -0,0 +1,103 @@
+//! Hidden `--stress` mode that drives the mouse hook's logic with many
+//! synthetic events from several threads at once. Verifies that no event is
+//! lost from the counters that other threads read (the tray's statistics) and
+//! measures throughput.
+//!
+//! The real hook only runs on one thread so decisions can differ from a single
+//! threaded run, but every event must still be counted exactly once.
+
+use crate::debounce::{ButtonState, MouseButton, MouseDirection};
+use crate::win_api::{NoopApi, WinApi};
+use crate::HookEvent;
+use std::time::Instant;
+
+/// Events to generate if no count was specified.
+pub const DEFAULT_EVENTS: u64 = 10_000_000;
+
+/// Generate `events` events for a thread and return how many were blocked.
+fn drive(
+    seed: u64,
+    events: u64,
+    buttons: &[ButtonState; MouseButton::COUNT],
+    pen_buttons: &[ButtonState; MouseButton::COUNT],
+) -> u64 {
+    let api = NoopApi::new();
+    let mut rng = seed | 1;
+    let mut next = move || {
+        // xorshift:
+        rng ^= rng << 13;
+        rng ^= rng >> 7;
+        rng ^= rng << 17;
+        rng
+    };
+    let mut blocked = 0;
+    for ix in 0..events {
+        let random = next();
+        api.advance((random % 40) as u32);
+        let event = HookEvent {
+            button: MouseButton::all()[(random >> 8) as usize % MouseButton::COUNT],
+            // Mostly alternate but sometimes repeat a direction like chatter:
+            direction: if ix % 2 == 0 || (random >> 16) % 8 == 0 {
+                MouseDirection::Down
+            } else {
+                MouseDirection::Up
+            },
+            flags: 0,
+            time: api.tick_count() as u32,
+            extra_info: 0,
+            position: (0, 0),
+        };
+        blocked += u64::from(crate::handle_mouse_event(&api, buttons, pen_buttons, event));
+    }
+    blocked
+}
+
+pub fn run(total_events: u64) -> Result<(), String> {
+    let threads = std::thread::available_parallelism().map_or(4, |count| count.get()) as u64;
+    let per_thread = total_events / threads;
+    let buttons = &[const { ButtonState::new() }; MouseButton::COUNT];
+    let pen_buttons = &[const { ButtonState::new() }; MouseButton::COUNT];
+
+    #[cfg(all(feature = "tray", feature = "logging"))]
+    let (stats_blocked_before, stats_total_before) = crate::logging::stats::total_blocked();
+
+    println!(
+        "Stress testing with {} events on {threads} threads...",
+        per_thread * threads
+    );
+    let start = Instant::now();
+    let blocked = std::thread::scope(|scope| {
+        let handles = (0..threads)
+            .map(|seed| scope.spawn(move || drive(seed + 1, per_thread, buttons, pen_buttons)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("stress thread panicked"))
+            .sum::<u64>()
+    });
+    let elapsed = start.elapsed();
+    let events = per_thread * threads;
+
+    println!(
+        "Handled {events} events in {:.3} s ({:.1} million events per second), {blocked} blocked",
+        elapsed.as_secs_f64(),
+        events as f64 / elapsed.as_secs_f64() / 1_000_000.0,
+    );
+
+    #[cfg(all(feature = "tray", feature = "logging"))]
+    {
+        let (stats_blocked, stats_total) = crate::logging::stats::total_blocked();
+        let counted = u64::from(stats_total.wrapping_sub(stats_total_before));
+        // Counters are 32 bit so only compare the low bits:
+        if counted != events % (1 << 32) {
+            return Err(format!(
+                "Statistics counted {counted} events but {events} were handled"
+            ));
+        }
+        println!(
+            "Statistics counted all events ({} blocked)",
+            stats_blocked.wrapping_sub(stats_blocked_before)
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,76 @@
+//! Suggests a threshold from a histogram of the time between a press and the
+//! previous event of the same button. A bouncing switch produces a cluster of
+//! very short intervals that is separated from intentional double-clicks by a
+//! range of intervals that never happen, and the threshold should be placed
+//! in that range just above the bounces.
+
+/// Intervals of bounces are shorter than this many milliseconds, if the
+/// shortest interval is longer then no chatter was observed.
+const MAX_BOUNCE: u32 = 50;
+
+/// Empty milliseconds that separate the bounces from intentional clicks.
+const MIN_GAP: u32 = 10;
+
+/// How far above the longest bounce the suggested threshold is placed, at
+/// most. Leaves room for bounces that are a bit slower than those observed.
+const MARGIN: u32 = 20;
+
+/// Suggest a threshold in milliseconds from the number of presses in each bin
+/// of `bin_width` milliseconds, starting at `0`. Longer intervals than the
+/// bins cover are assumed to be intentional. Returns `None` if no bounces
+/// were observed or they can't be told apart from intentional clicks.
+pub fn suggest_threshold(counts: &[u32], bin_width: u32) -> Option<u32> {
+    let first = counts.iter().position(|&count| count != 0)?;
+    if first as u32 * bin_width >= MAX_BOUNCE {
+        return None;
+    }
+    let min_gap_bins = MIN_GAP.div_ceil(bin_width) as usize;
+    let mut gap_start = None;
+    for (ix, &count) in counts.iter().enumerate().skip(first) {
+        match (count, gap_start) {
+            (0, None) => gap_start = Some(ix),
+            (0, Some(_)) => {}
+            (_, Some(start)) if ix - start >= min_gap_bins => {
+                let gap = (ix - start) as u32 * bin_width;
+                return Some(start as u32 * bin_width + (gap / 2).min(MARGIN));
+            }
+            (_, _) => gap_start = None,
+        }
+    }
+    // The gap continues past the end of the histogram:
+    let start = gap_start?;
+    Some(start as u32 * bin_width + MARGIN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn places_threshold_between_bounces_and_clicks() {
+        // Bounces at 0-15 ms and double-clicks at 40-60 ms:
+        let mut counts = [0; 40];
+        counts[0] = 3;
+        counts[2] = 5;
+        counts[8..12].fill(2);
+        assert_eq!(suggest_threshold(&counts, 5), Some(27));
+        // Only bounces:
+        counts[8..12].fill(0);
+        assert_eq!(suggest_threshold(&counts, 5), Some(35));
+        // A wide gap uses the margin:
+        counts[35] = 1;
+        assert_eq!(suggest_threshold(&counts, 5), Some(35));
+    }
+
+    #[test]
+    fn needs_observed_bounces() {
+        let mut counts = [0; 40];
+        assert_eq!(suggest_threshold(&counts, 5), None);
+        // Only intentional clicks:
+        counts[20..30].fill(4);
+        assert_eq!(suggest_threshold(&counts, 5), None);
+        // No gap between bounces and clicks:
+        counts.fill(1);
+        assert_eq!(suggest_threshold(&counts, 5), None);
+    }
+}
@@ -0,0 +1,36 @@
+//! The real implementation of [`WinApi`] that the mouse hook uses.
+
+use crate::debounce::{MouseButton, MouseDirection};
+use crate::win_api::WinApi;
+use windows_sys::Win32::System::Performance::{QueryPerformanceCounter, QueryPerformanceFrequency};
+use windows_sys::Win32::System::SystemInformation::GetTickCount64;
+use windows_sys::Win32::UI::WindowsAndMessaging::{GetSystemMetrics, SM_SWAPBUTTON};
+
+/// The real Windows API.
+pub struct System;
+impl WinApi for System {
+    #[inline(always)]
+    fn tick_count(&self) -> u64 {
+        unsafe { GetTickCount64() }
+    }
+    fn precise_micros(&self) -> u64 {
+        let (mut counter, mut frequency) = (0, 0);
+        unsafe {
+            QueryPerformanceCounter(&mut counter);
+            QueryPerformanceFrequency(&mut frequency);
+        }
+        if frequency <= 0 {
+            return self.tick_count().saturating_mul(1000);
+        }
+        (counter as u128 * 1_000_000 / frequency as u128) as u64
+    }
+    fn buttons_swapped(&self) -> bool {
+        unsafe { GetSystemMetrics(SM_SWAPBUTTON) != 0 }
+    }
+    fn inject(&self, events: &[(MouseButton, MouseDirection)]) {
+        crate::deferred::inject(events);
+    }
+    fn schedule_replay(&self, button: MouseButton, delay: u32) {
+        crate::deferred::schedule_replay(button, delay);
+    }
+}
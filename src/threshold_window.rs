@@ -0,0 +1,269 @@
+//! A small native window with an edit box for each button's threshold, so
+//! that thresholds can be tuned while the program runs. New thresholds are
+//! used by the mouse hook as soon as they are applied.
+//!
+//! The window is created on the thread that runs the tray's event loop, which
+//! also dispatches its messages.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/controls/edit-controls>
+//! - <https://learn.microsoft.com/en-us/windows/win32/controls/bn-clicked>
+
+use crate::debounce::MouseButton;
+use crate::{log, log_error, to_utf16};
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::Graphics::Gdi::{GetStockObject, COLOR_BTNFACE, DEFAULT_GUI_FONT, HBRUSH};
+use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CreateWindowExW, DefWindowProcW, DestroyWindow, GetDlgItem, GetWindowTextW, MessageBoxW,
+    RegisterClassW, SendMessageW, SetForegroundWindow, ShowWindow, BN_CLICKED, BS_DEFPUSHBUTTON,
+    BS_PUSHBUTTON, CW_USEDEFAULT, ES_NUMBER, MB_ICONWARNING, MB_OK, SW_RESTORE, WINDOW_STYLE,
+    WM_CLOSE, WM_COMMAND, WM_DESTROY, WM_SETFONT, WNDCLASSW, WS_BORDER, WS_CAPTION, WS_CHILD,
+    WS_EX_CLIENTEDGE, WS_SYSMENU, WS_TABSTOP, WS_VISIBLE,
+};
+
+/// The open window, null if it isn't open.
+static WINDOW: AtomicPtr<core::ffi::c_void> = AtomicPtr::new(ptr::null_mut());
+
+const CLASS_NAME: windows_sys::core::PCWSTR = windows_sys::w!("click-once-thresholds");
+
+/// Control ids, the edit boxes use `EDIT_ID + button as i32`.
+const EDIT_ID: i32 = 100;
+const APPLY_ID: i32 = 1;
+const CLOSE_ID: i32 = 2;
+
+const ROW_HEIGHT: i32 = 28;
+
+fn button_name(button: MouseButton) -> &'static str {
+    match button {
+        MouseButton::Left => "Left:",
+        MouseButton::Right => "Right:",
+        MouseButton::Middle => "Middle:",
+        MouseButton::X1 => "X1:",
+        MouseButton::X2 => "X2:",
+    }
+}
+
+/// Create a child control and give it the same font as other dialogs.
+fn create_control(
+    parent: HWND,
+    class: windows_sys::core::PCWSTR,
+    text: &str,
+    style: WINDOW_STYLE,
+    (x, y, width, height): (i32, i32, i32, i32),
+    id: i32,
+) -> HWND {
+    let text = to_utf16(text);
+    let control = unsafe {
+        CreateWindowExW(
+            if style & WS_BORDER != 0 {
+                WS_EX_CLIENTEDGE
+            } else {
+                0
+            },
+            class,
+            text.as_ptr(),
+            (style | WS_CHILD | WS_VISIBLE) & !WS_BORDER,
+            x,
+            y,
+            width,
+            height,
+            parent,
+            id as isize as _,
+            GetModuleHandleW(ptr::null()),
+            ptr::null(),
+        )
+    };
+    if !control.is_null() {
+        unsafe {
+            SendMessageW(
+                control,
+                WM_SETFONT,
+                GetStockObject(DEFAULT_GUI_FONT) as WPARAM,
+                0,
+            )
+        };
+    }
+    control
+}
+
+fn create_controls(window: HWND) {
+    for (row, &button) in MouseButton::all().iter().enumerate() {
+        let y = 12 + row as i32 * ROW_HEIGHT;
+        let threshold = crate::threshold(button).load(Relaxed);
+        create_control(
+            window,
+            windows_sys::w!("STATIC"),
+            button_name(button),
+            0,
+            (12, y + 3, 60, 20),
+            -1,
+        );
+        create_control(
+            window,
+            windows_sys::w!("EDIT"),
+            &threshold.to_string(),
+            WS_BORDER | WS_TABSTOP | ES_NUMBER as WINDOW_STYLE,
+            (76, y, 70, 22),
+            EDIT_ID + button as i32,
+        );
+        create_control(
+            window,
+            windows_sys::w!("STATIC"),
+            "ms",
+            0,
+            (152, y + 3, 30, 20),
+            -1,
+        );
+    }
+    let y = 12 + MouseButton::COUNT as i32 * ROW_HEIGHT + 6;
+    create_control(
+        window,
+        windows_sys::w!("BUTTON"),
+        "&Apply",
+        WS_TABSTOP | BS_DEFPUSHBUTTON as WINDOW_STYLE,
+        (12, y, 80, 26),
+        APPLY_ID,
+    );
+    create_control(
+        window,
+        windows_sys::w!("BUTTON"),
+        "&Close",
+        WS_TABSTOP | BS_PUSHBUTTON as WINDOW_STYLE,
+        (102, y, 80, 26),
+        CLOSE_ID,
+    );
+}
+
+/// Read the threshold that was typed for a button.
+fn read_threshold(window: HWND, button: MouseButton) -> Result<u32, String> {
+    let mut text = [0_u16; 16];
+    let edit = unsafe { GetDlgItem(window, EDIT_ID + button as i32) };
+    let len = unsafe { GetWindowTextW(edit, text.as_mut_ptr(), text.len() as i32) };
+    let text = String::from_utf16_lossy(&text[..len.max(0) as usize]);
+    let name = button_name(button).trim_end_matches(':');
+    let threshold = crate::duration::parse_millis(&text)
+        .map_err(|e| format!("Invalid threshold \"{text}\" for the {name} button: {e}"))?;
+    if threshold > crate::config::MAX_THRESHOLD {
+        return Err(format!(
+            "The threshold for the {name} button can be at most {} ms",
+            crate::config::MAX_THRESHOLD
+        ));
+    }
+    Ok(threshold)
+}
+
+/// Use the typed thresholds if all of them are valid.
+fn apply(window: HWND) {
+    let mut thresholds = [0; MouseButton::COUNT];
+    for (slot, &button) in thresholds.iter_mut().zip(MouseButton::all()) {
+        match read_threshold(window, button) {
+            Ok(threshold) => *slot = threshold,
+            Err(e) => {
+                let text = to_utf16(&e);
+                unsafe {
+                    MessageBoxW(
+                        window,
+                        text.as_ptr(),
+                        windows_sys::w!("click-once"),
+                        MB_OK | MB_ICONWARNING,
+                    )
+                };
+                return;
+            }
+        }
+    }
+    for (&threshold, &button) in thresholds.iter().zip(MouseButton::all()) {
+        crate::threshold(button).store(threshold, Relaxed);
+    }
+    log![b"\r\nChanged thresholds in the threshold window\r\n"];
+    crate::config::persist_changes();
+    crate::tray::refresh_tooltip();
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    match message {
+        WM_COMMAND if (wparam >> 16) as u32 == BN_CLICKED => {
+            match (wparam & 0xFFFF) as i32 {
+                APPLY_ID => apply(window),
+                CLOSE_ID => {
+                    DestroyWindow(window);
+                }
+                _ => {}
+            }
+            0
+        }
+        WM_CLOSE => {
+            DestroyWindow(window);
+            0
+        }
+        WM_DESTROY => {
+            WINDOW.store(ptr::null_mut(), Relaxed);
+            0
+        }
+        _ => DefWindowProcW(window, message, wparam, lparam),
+    }
+}
+
+/// Open the threshold window, or bring it to the front if it is already open.
+/// Must be called on the thread that runs the message loop.
+pub fn show() {
+    let existing = WINDOW.load(Relaxed);
+    if !existing.is_null() {
+        unsafe {
+            ShowWindow(existing, SW_RESTORE);
+            SetForegroundWindow(existing);
+        }
+        return;
+    }
+
+    let instance = unsafe { GetModuleHandleW(ptr::null()) };
+    let class = WNDCLASSW {
+        style: 0,
+        lpfnWndProc: Some(window_proc),
+        cbClsExtra: 0,
+        cbWndExtra: 0,
+        hInstance: instance,
+        hIcon: ptr::null_mut(),
+        hCursor: ptr::null_mut(),
+        // The system color plus one, as documented for `hbrBackground`:
+        hbrBackground: (COLOR_BTNFACE + 1) as HBRUSH,
+        lpszMenuName: ptr::null(),
+        lpszClassName: CLASS_NAME,
+    };
+    // Fails if the class was registered when the window was opened before,
+    // which is fine:
+    unsafe { RegisterClassW(&class) };
+
+    let window = unsafe {
+        CreateWindowExW(
+            0,
+            CLASS_NAME,
+            windows_sys::w!("click-once: thresholds"),
+            WS_CAPTION | WS_SYSMENU | WS_VISIBLE,
+            CW_USEDEFAULT,
+            CW_USEDEFAULT,
+            210,
+            12 + (MouseButton::COUNT as i32 + 1) * ROW_HEIGHT + 60,
+            ptr::null_mut(),
+            ptr::null_mut(),
+            instance,
+            ptr::null(),
+        )
+    };
+    if window.is_null() {
+        log_error("Failed to create threshold window");
+        return;
+    }
+    WINDOW.store(window, Relaxed);
+    create_controls(window);
+    unsafe { SetForegroundWindow(window) };
+}
@@ -0,0 +1,84 @@
+//! Optionally raises the resolution of the system timer to 1 ms while the
+//! program runs. The timestamps of mouse events and the timers that replay
+//! delayed events only have the resolution of the system timer, which is
+//! usually 15.6 ms and too coarse for very small thresholds.
+//!
+//! A higher resolution makes the whole system wake up more often and uses a
+//! bit more power, so it is only requested when asked for. Windows restores
+//! the resolution when the process exits even if [`end`] isn't called.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/timeapi/nf-timeapi-timebeginperiod>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/timeapi/nf-timeapi-timeendperiod>
+
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use windows_sys::Win32::Media::{timeBeginPeriod, timeEndPeriod, TIMERR_NOERROR};
+use windows_sys::Win32::System::LibraryLoader::{GetModuleHandleW, GetProcAddress};
+
+/// The requested timer period in milliseconds.
+const PERIOD_MS: u32 = 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// `true` while the period from [`begin`] is active and must be ended.
+static ACTIVE: AtomicBool = AtomicBool::new(false);
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Relaxed)
+}
+
+/// The current resolution of the system timer in 100 nanosecond units. Read
+/// using the undocumented `NtQueryTimerResolution` since no documented API
+/// reports it.
+fn current_resolution() -> Option<u32> {
+    let ntdll = unsafe { GetModuleHandleW(windows_sys::w!("ntdll.dll")) };
+    if ntdll.is_null() {
+        return None;
+    }
+    let query = unsafe { GetProcAddress(ntdll, c"NtQueryTimerResolution".as_ptr().cast()) }?;
+    let query: unsafe extern "system" fn(*mut u32, *mut u32, *mut u32) -> i32 =
+        unsafe { core::mem::transmute(query) };
+    let (mut coarsest, mut finest, mut current) = (0, 0, 0);
+    (unsafe { query(&mut coarsest, &mut finest, &mut current) } >= 0).then_some(current)
+}
+
+/// Log the current resolution of the system timer.
+fn log_resolution() {
+    let Some(resolution) = current_resolution() else {
+        return;
+    };
+    log![
+        b"System timer resolution is ",
+        resolution / 10_000,
+        b".",
+        resolution / 1_000 % 10,
+        b" ms\r\n"
+    ];
+}
+
+/// Raise the resolution of the system timer if that was enabled.
+pub fn begin() {
+    if !is_enabled() || ACTIVE.load(Relaxed) {
+        return;
+    }
+    if unsafe { timeBeginPeriod(PERIOD_MS) } != TIMERR_NOERROR {
+        log_error("Failed to raise the resolution of the system timer");
+        return;
+    }
+    ACTIVE.store(true, Relaxed);
+    log_resolution();
+}
+
+/// Stop requesting a higher resolution of the system timer. Does nothing if
+/// [`begin`] didn't raise it.
+pub fn end() {
+    if ACTIVE.swap(false, Relaxed) {
+        unsafe { timeEndPeriod(PERIOD_MS) };
+    }
+}
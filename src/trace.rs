@@ -0,0 +1,185 @@
+//! A compact binary format for recorded mouse events.
+//!
+//! A trace file starts with the 8 byte magic value `CLKTRACE` followed by a
+//! little endian `u32` format version (currently `1`). After that comes one
+//! 10 byte record per mouse event:
+//!
+//! | Offset | Size | Field                                                               |
+//! |--------|------|---------------------------------------------------------------------|
+//! | 0      | 1    | Button: `0` = left, `1` = right, `2` = middle, `3` = X1, `4` = X2   |
+//! | 1      | 1    | Direction: `0` = up, `1` = down                                     |
+//! | 2      | 4    | Timestamp in milliseconds (little endian `u32`)                     |
+//! | 6      | 4    | `MSLLHOOKSTRUCT.flags` (little endian `u32`)                        |
+
+use crate::debounce::{MouseButton, MouseDirection};
+use std::io::Write;
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::sync::OnceLock;
+
+pub const MAGIC: &[u8; 8] = b"CLKTRACE";
+pub const VERSION: u32 = 1;
+pub const HEADER_SIZE: usize = MAGIC.len() + 4;
+pub const RECORD_SIZE: usize = 10;
+
+/// A single recorded mouse event.
+#[derive(Clone, Copy)]
+pub struct TraceEvent {
+    pub button: MouseButton,
+    pub direction: MouseDirection,
+    /// Timestamp in milliseconds.
+    pub time: u32,
+    /// Flags from the low level mouse hook, for example if the event was
+    /// injected.
+    pub flags: u32,
+}
+
+/// Errors that can occur when reading a trace.
+#[derive(Clone, Copy, Debug)]
+pub enum TraceError {
+    NotATrace,
+    UnsupportedVersion(u32),
+    InvalidRecord { index: usize },
+}
+impl core::fmt::Display for TraceError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            TraceError::NotATrace => f.write_str("file is not a click-once event trace"),
+            TraceError::UnsupportedVersion(version) => {
+                write!(f, "unsupported trace format version {version}")
+            }
+            TraceError::InvalidRecord { index } => {
+                write!(f, "trace record {index} is invalid or truncated")
+            }
+        }
+    }
+}
+
+fn encode_record(event: TraceEvent) -> [u8; RECORD_SIZE] {
+    let mut record = [0; RECORD_SIZE];
+    record[0] = match event.button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::X1 => 3,
+        MouseButton::X2 => 4,
+    };
+    record[1] = match event.direction {
+        MouseDirection::Up => 0,
+        MouseDirection::Down => 1,
+    };
+    record[2..6].copy_from_slice(&event.time.to_le_bytes());
+    record[6..10].copy_from_slice(&event.flags.to_le_bytes());
+    record
+}
+
+fn decode_record(record: &[u8]) -> Option<TraceEvent> {
+    let button = match record.first()? {
+        0 => MouseButton::Left,
+        1 => MouseButton::Right,
+        2 => MouseButton::Middle,
+        3 => MouseButton::X1,
+        4 => MouseButton::X2,
+        _ => return None,
+    };
+    let direction = match record.get(1)? {
+        0 => MouseDirection::Up,
+        1 => MouseDirection::Down,
+        _ => return None,
+    };
+    Some(TraceEvent {
+        button,
+        direction,
+        time: u32::from_le_bytes(record.get(2..6)?.try_into().ok()?),
+        flags: u32::from_le_bytes(record.get(6..10)?.try_into().ok()?),
+    })
+}
+
+/// Read the events stored in a trace.
+pub fn decode(
+    data: &[u8],
+) -> Result<impl Iterator<Item = Result<TraceEvent, TraceError>> + '_, TraceError> {
+    if data.len() < HEADER_SIZE || !data.starts_with(MAGIC) {
+        return Err(TraceError::NotATrace);
+    }
+    let version = u32::from_le_bytes([data[8], data[9], data[10], data[11]]);
+    if version != VERSION {
+        return Err(TraceError::UnsupportedVersion(version));
+    }
+    Ok(data[HEADER_SIZE..]
+        .chunks(RECORD_SIZE)
+        .enumerate()
+        .map(|(index, record)| decode_record(record).ok_or(TraceError::InvalidRecord { index })))
+}
+
+/// Sends events to the thread that writes them to the trace file, only set if
+/// recording was requested.
+static RECORDING: OnceLock<SyncSender<TraceEvent>> = OnceLock::new();
+
+/// The file that lists the traces that were recorded, so that they can be
+/// removed when the program is uninstalled. `None` if the `APPDATA`
+/// environment variable isn't set.
+pub fn recordings_path() -> Option<std::path::PathBuf> {
+    Some(crate::config::path()?.with_file_name("recordings.txt"))
+}
+
+/// Add a trace file to the list of recorded traces.
+fn remember_recording(path: &str) {
+    let Some(list) = recordings_path() else {
+        return;
+    };
+    let Ok(path) = std::path::absolute(path) else {
+        return;
+    };
+    let result = list
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|()| {
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&list)
+        })
+        .and_then(|mut file| writeln!(file, "{}", path.display()));
+    if let Err(e) = result {
+        crate::log_error(format_args!(
+            "Failed to remember the trace file in \"{}\": {e}",
+            list.display()
+        ));
+    }
+}
+
+/// Start recording all mouse button events to a new trace file. The file can
+/// later be replayed using the `--simulate` argument or attached to an issue.
+pub fn start_recording(path: &str) -> Result<(), String> {
+    let mut file = std::fs::File::create(path)
+        .map_err(|e| format!("Failed to create trace file \"{path}\": {e}"))?;
+    file.write_all(MAGIC)
+        .and_then(|()| file.write_all(&VERSION.to_le_bytes()))
+        .map_err(|e| format!("Failed to write to trace file \"{path}\": {e}"))?;
+    remember_recording(path);
+    let (sender, receiver) = sync_channel::<TraceEvent>(1024);
+    RECORDING
+        .set(sender)
+        .map_err(|_| "Can only record one trace at a time".to_owned())?;
+    // Each record is written directly (without buffering) since button events
+    // are infrequent and the program is often terminated without a chance to
+    // flush buffers.
+    std::thread::spawn(move || {
+        for event in receiver {
+            if let Err(e) = file.write_all(&encode_record(event)) {
+                crate::log_error(format_args!("Failed to write to trace file: {e}"));
+                return;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Append an event to the trace file if recording is enabled.
+#[inline(always)]
+pub fn record(event: TraceEvent) {
+    if let Some(sender) = RECORDING.get() {
+        // Never block the hook, drop the event if the channel is full:
+        _ = sender.try_send(event);
+    }
+}
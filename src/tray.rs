@@ -1,63 +1,265 @@
 #[cfg(feature = "logging")]
 use {
-    crate::{log, logging},
-    tray_icon::menu::CheckMenuItem,
-    windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_OK},
+    crate::{debounce::MouseButton, logging},
+    windows_sys::Win32::UI::WindowsAndMessaging::{MB_ICONINFORMATION, MB_OK},
 };
 
-use crate::log_error;
-use core::sync::atomic::Ordering::Relaxed;
+use crate::{log, log_error, to_utf16};
+use core::sync::atomic::{AtomicU32, Ordering::Relaxed};
+use std::cell::RefCell;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tray_icon::{
     menu::{
         accelerator::{Accelerator, Code},
-        Menu, MenuEvent, MenuItem,
+        CheckMenuItem, Menu, MenuEvent, MenuItem, Submenu,
     },
     TrayIcon, TrayIconBuilder,
 };
 use windows_sys::Win32::System::LibraryLoader::GetModuleHandleW;
 use windows_sys::Win32::UI::Shell::ExtractIconW;
+#[cfg(feature = "live-feed")]
+use windows_sys::Win32::UI::Shell::ShellExecuteW;
+#[cfg(feature = "live-feed")]
+use windows_sys::Win32::UI::WindowsAndMessaging::SW_SHOWNORMAL;
+use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, IDYES, MB_ICONWARNING, MB_YESNO};
 use winit::{
     application::ApplicationHandler,
-    event::WindowEvent,
-    event_loop::{ActiveEventLoop, EventLoop, EventLoopProxy},
+    event::{StartCause, WindowEvent},
+    event_loop::{ActiveEventLoop, ControlFlow, EventLoop, EventLoopProxy},
     window::WindowId,
 };
 
-fn to_utf16(s: &str) -> Vec<u16> {
-    use std::ffi::OsStr;
-    use std::os::windows::ffi::OsStrExt;
+/// How often the tray icon's tooltip is refreshed, in seconds. `0` means that
+/// the tooltip is never refreshed, which avoids waking up the program
+/// periodically. Defaults to only refreshing when the tooltip includes
+/// statistics.
+static TOOLTIP_REFRESH_SECS: AtomicU32 =
+    AtomicU32::new(if cfg!(feature = "logging") { 10 } else { 0 });
 
-    OsStr::new(s)
-        .encode_wide()
-        .chain(core::iter::once(0u16))
-        .collect()
+pub fn set_tooltip_refresh_interval(seconds: u32) {
+    TOOLTIP_REFRESH_SECS.store(seconds, Relaxed);
+}
+
+fn tooltip_refresh_interval() -> Option<Duration> {
+    match TOOLTIP_REFRESH_SECS.load(Relaxed) {
+        0 => None,
+        seconds => Some(Duration::from_secs(seconds.into())),
+    }
+}
+
+/// Lets other threads send events to the tray's event loop while it runs.
+static PROXY: Mutex<Option<EventLoopProxy<UserEvent>>> = Mutex::new(None);
+
+thread_local! {
+    /// The tray icon, so that it can be removed when Windows ends the session
+    /// without the event loop exiting.
+    static TRAY_ICON: RefCell<Option<TrayIcon>> = const { RefCell::new(None) };
+}
+
+/// Remove the tray icon right away. Must be called on the thread that runs the
+/// event loop.
+pub fn hide_icon() {
+    TRAY_ICON.with_borrow(|tray| {
+        if let Some(Err(e)) = tray.as_ref().map(|tray| tray.set_visible(false)) {
+            log_error(e);
+        }
+    });
+}
+
+/// Quit the program from another thread.
+#[allow(dead_code, reason = "only used by certain features")]
+pub fn quit() {
+    let proxy = PROXY.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(proxy) = &*proxy {
+        _ = proxy.send_event(UserEvent::Quit);
+    }
+}
+
+/// Update the tooltip after settings were changed by another thread.
+pub fn refresh_tooltip() {
+    let proxy = PROXY.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(proxy) = &*proxy {
+        _ = proxy.send_event(UserEvent::RefreshTooltip);
+    }
+}
+
+/// Note: there is a max length for the tooltip, more will be truncated.
+fn tooltip_text() -> String {
+    use std::fmt::Write;
+
+    let mut tooltip = "click-once".to_owned();
+    if crate::pause::is_paused() {
+        tooltip.push_str(" (paused)");
+    }
+    if crate::DRY_RUN.load(Relaxed) {
+        tooltip.push_str(" (dry run)");
+    }
+    for (name, button) in [
+        ("Left", crate::debounce::MouseButton::Left),
+        ("Right", crate::debounce::MouseButton::Right),
+        ("Middle", crate::debounce::MouseButton::Middle),
+        ("X1", crate::debounce::MouseButton::X1),
+        ("X2", crate::debounce::MouseButton::X2),
+    ] {
+        let threshold = crate::threshold(button).load(Relaxed);
+        // Most mice don't have extended buttons, so only list them when they
+        // are debounced to keep the tooltip short:
+        if threshold == 0 && name.starts_with('X') {
+            continue;
+        }
+        write!(tooltip, "\r\n{name}: ").unwrap();
+        if threshold == 0 {
+            tooltip.push_str("Disabled");
+        } else {
+            write!(tooltip, "{threshold} ms").unwrap();
+        }
+        let up_threshold = crate::up_threshold(button);
+        if up_threshold != threshold {
+            write!(tooltip, " (up {up_threshold} ms)").unwrap();
+        }
+    }
+    #[cfg(feature = "logging")]
+    {
+        let (blocked, total) = logging::stats::total_blocked();
+        write!(tooltip, "\r\nBlocked: {blocked} / {total}").unwrap();
+    }
+    tooltip
 }
 
 #[derive(Debug)]
 pub enum UserEvent {
     Quit,
+    ResetToDefaults,
+    ToggleExemptController,
+    ToggleGameMode,
+    ToggleAutostart,
+    CreateElevatedTask,
+    RemoveElevatedTask,
+    ShowAbout,
+    RefreshTooltip,
+    ShowThresholds,
+    /// Use a threshold from [`PRESETS`].
+    ApplyPreset(u32),
+    /// Stop blocking events for a number of minutes.
+    PauseFor(u32),
+    Resume,
+    #[cfg(feature = "live-feed")]
+    OpenDashboard,
     #[cfg(feature = "logging")]
     ToggleLogging,
     #[cfg(feature = "logging")]
     ShowStats,
+    #[cfg(feature = "logging")]
+    ShowHistogram,
+    #[cfg(feature = "logging")]
+    SuggestThresholds,
+    #[cfg(feature = "logging")]
+    ResetStats(MouseButton),
+}
+
+/// Threshold presets in the tray menu as name and threshold in milliseconds.
+const PRESETS: [(&str, u32); 3] = [
+    ("&Gentle (20 ms)", 20),
+    ("&Normal (40 ms)", 40),
+    ("&Aggressive (80 ms)", 80),
+];
+
+/// Use a preset's threshold for the left button and for other buttons that
+/// are debounced.
+fn apply_preset(threshold: u32) {
+    for button in crate::debounce::MouseButton::all() {
+        let current = crate::threshold(*button);
+        if *button == crate::debounce::MouseButton::Left || current.load(Relaxed) != 0 {
+            current.store(threshold, Relaxed);
+        }
+    }
+    log![
+        b"\r\nChanged thresholds to ",
+        threshold,
+        b" ms using a preset\r\n"
+    ];
+}
+
+/// Load an icon from a file in the Windows folder, such as
+/// `System32\main.cpl`.
+fn load_windows_icon(path: &str, index: u32) -> Option<tray_icon::Icon> {
+    // https://learn.microsoft.com/en-us/windows/deployment/usmt/usmt-recognized-environment-variables
+    let win_dir = std::env::var("WINDIR")
+        .map_err(|e| {
+            log_error(format_args!(
+                "Failed to get WINDIR environment variable to locate Windows folder: {e}"
+            ))
+        })
+        .ok()?;
+    let icon_path = to_utf16(&format!("{win_dir}\\{path}"));
+    let h_instance = unsafe { GetModuleHandleW(core::ptr::null()) };
+    let icon_handle = unsafe { ExtractIconW(h_instance, icon_path.as_ptr(), index) };
+    // `ExtractIconW` returns 1 if the file isn't an executable or icon file:
+    if icon_handle as usize <= 1 {
+        log_error("Failed to extract icon");
+        return None;
+    }
+    Some(tray_icon::Icon::from_handle(icon_handle as isize))
 }
 
 pub struct TrayApp {
     tray: TrayIcon,
+    icon: Option<tray_icon::Icon>,
+    /// Shown while blocking is paused.
+    paused_icon: Option<tray_icon::Icon>,
+    showing_paused_icon: bool,
+    next_tooltip_refresh: Option<Instant>,
+    /// When the program should exit, see `--max-runtime`.
+    exit_at: Option<Instant>,
+    /// When blocking should resume after it was paused from the menu.
+    resume_at: Option<Instant>,
+    exempt_controller_item: CheckMenuItem,
+    game_mode_item: CheckMenuItem,
+    autostart_item: CheckMenuItem,
+    preset_items: Vec<(u32, CheckMenuItem)>,
     #[cfg(feature = "logging")]
     logging_item: CheckMenuItem,
 }
 impl TrayApp {
     pub fn new(proxy: EventLoopProxy<UserEvent>) -> Self {
-        let h_instance = unsafe { GetModuleHandleW(core::ptr::null()) };
-
         let tray_menu = Menu::new();
         let quit_item = MenuItem::new("&Quit", true, Some(Accelerator::new(None, Code::KeyQ)));
+        let reset_to_defaults = MenuItem::new("Reset to &Defaults", true, None);
+        let about_item = MenuItem::new("&About", true, None);
+        #[cfg(feature = "live-feed")]
+        let dashboard_item = MenuItem::new("Open Das&hboard", true, None);
+        let exempt_controller_item = CheckMenuItem::new(
+            "Exempt &Controller Input",
+            true,
+            crate::controller::is_enabled(),
+            None,
+        );
+        let game_mode_item = CheckMenuItem::new(
+            "&Game Mode (Pause in Fullscreen)",
+            true,
+            crate::foreground_app::game_mode(),
+            None,
+        );
+        let autostart_item = CheckMenuItem::new(
+            "Start with &Windows",
+            true,
+            crate::autostart::is_enabled(),
+            None,
+        );
+        let create_task_item = MenuItem::new("&Create Task", true, None);
+        let remove_task_item = MenuItem::new("&Remove Task", true, None);
+        let elevated_task_menu = Submenu::with_items(
+            "Start &Elevated at Logon",
+            true,
+            &[&create_task_item, &remove_task_item],
+        )
+        .expect("Failed to add scheduled task menu items");
         #[cfg(feature = "logging")]
         let logging_item = CheckMenuItem::new(
             "Toggle &Logging",
             true,
-            logging::is_logging(),
+            logging::has_console(),
             Some(Accelerator::new(None, Code::KeyL)),
         );
         #[cfg(feature = "logging")]
@@ -66,13 +268,99 @@ impl TrayApp {
             true,
             Some(Accelerator::new(None, Code::KeyS)),
         );
+        #[cfg(feature = "logging")]
+        let show_histogram = MenuItem::new(
+            "Interval &Histogram",
+            true,
+            Some(Accelerator::new(None, Code::KeyH)),
+        );
+        #[cfg(feature = "logging")]
+        let suggest_item = MenuItem::new("S&uggest Thresholds...", true, None);
+        let pause_items = [5, 15, 60].map(|minutes| {
+            let text = match minutes {
+                5 => "For &5 Minutes",
+                15 => "For &15 Minutes",
+                _ => "For &60 Minutes",
+            };
+            (minutes, MenuItem::new(text, true, None))
+        });
+        let resume_item = MenuItem::new("&Resume", true, None);
+        let thresholds_item = MenuItem::new("Set &Thresholds...", true, None);
+        let left_threshold = crate::THRESHOLD_LM.load(Relaxed);
+        let preset_items = PRESETS
+            .iter()
+            .map(|&(text, threshold)| {
+                let checked = threshold == left_threshold;
+                (threshold, CheckMenuItem::new(text, true, checked, None))
+            })
+            .collect::<Vec<_>>();
+        let preset_menu = Submenu::with_items(
+            "&Threshold Preset",
+            true,
+            &preset_items
+                .iter()
+                .map(|(_, item)| item as &dyn tray_icon::menu::IsMenuItem)
+                .collect::<Vec<_>>(),
+        )
+        .expect("Failed to add preset menu items");
+        let pause_menu = Submenu::with_items(
+            "&Pause Blocking",
+            true,
+            &pause_items
+                .iter()
+                .map(|(_, item)| item as &dyn tray_icon::menu::IsMenuItem)
+                .chain([&resume_item as &dyn tray_icon::menu::IsMenuItem])
+                .collect::<Vec<_>>(),
+        )
+        .expect("Failed to add pause menu items");
+        #[cfg(feature = "logging")]
+        let reset_stats_items = MouseButton::all()
+            .iter()
+            .map(|&button| {
+                let text = match button {
+                    MouseButton::Left => "&Left Button",
+                    MouseButton::Right => "&Right Button",
+                    MouseButton::Middle => "&Middle Button",
+                    MouseButton::X1 => "X&1 Button",
+                    MouseButton::X2 => "X&2 Button",
+                };
+                (button, MenuItem::new(text, true, None))
+            })
+            .collect::<Vec<_>>();
+        #[cfg(feature = "logging")]
+        let reset_stats = Submenu::with_items(
+            "&Reset Statistics",
+            true,
+            &reset_stats_items
+                .iter()
+                .map(|(_, item)| item as &dyn tray_icon::menu::IsMenuItem)
+                .collect::<Vec<_>>(),
+        )
+        .expect("Failed to add reset statistics menu items");
 
         tray_menu
             .append_items(&[
+                #[cfg(feature = "live-feed")]
+                &dashboard_item,
                 #[cfg(feature = "logging")]
                 &show_stats,
                 #[cfg(feature = "logging")]
+                &show_histogram,
+                #[cfg(feature = "logging")]
+                &suggest_item,
+                #[cfg(feature = "logging")]
+                &reset_stats,
+                &thresholds_item,
+                &preset_menu,
+                &pause_menu,
+                &reset_to_defaults,
+                &exempt_controller_item,
+                &game_mode_item,
+                &autostart_item,
+                &elevated_task_menu,
+                #[cfg(feature = "logging")]
                 &logging_item,
+                &about_item,
                 &quit_item,
             ])
             .expect("Failed to add context menu items");
@@ -80,64 +368,53 @@ impl TrayApp {
         let mut tray = TrayIconBuilder::new()
             .with_menu(Box::new(tray_menu))
             // Note: there is a max length for the tooltip, more will be truncated
-            .with_tooltip({
-                use std::fmt::Write;
+            .with_tooltip(tooltip_text());
 
-                let mut tooltip = "click-once".to_owned();
-                {
-                    tooltip.push_str("\r\nLeft: ");
-                    let threshold_left = crate::THRESHOLD_LM.load(Relaxed);
-                    if threshold_left == 0 {
-                        tooltip.push_str("Disabled");
-                    } else {
-                        write!(tooltip, "{} ms", threshold_left).unwrap();
-                    }
-                }
-                {
-                    tooltip.push_str("\r\nRight: ");
-                    let threshold_right = crate::THRESHOLD_RM.load(Relaxed);
-                    if threshold_right == 0 {
-                        tooltip.push_str("Disabled");
-                    } else {
-                        write!(tooltip, "{} ms", threshold_right).unwrap();
-                    }
-                }
-                {
-                    tooltip.push_str("\r\nMiddle: ");
-                    let threshold_middle = crate::THRESHOLD_MM.load(Relaxed);
-                    if threshold_middle == 0 {
-                        tooltip.push_str("Disabled");
-                    } else {
-                        write!(tooltip, "{} ms", threshold_middle).unwrap();
-                    }
-                }
-                tooltip
-            });
-
-        // https://learn.microsoft.com/en-us/windows/deployment/usmt/usmt-recognized-environment-variables
-        match std::env::var("WINDIR") {
-            Ok(win_dir) => {
-                let icon_path = win_dir + "\\System32\\main.cpl";
-                let icon_path = to_utf16(&icon_path);
-                let icon_handle = unsafe { ExtractIconW(h_instance, icon_path.as_ptr(), 0) };
-                if icon_handle.is_null() {
-                    log_error("Failed to extract icon");
-                } else {
-                    tray = tray.with_icon(tray_icon::Icon::from_handle(icon_handle as isize));
-                }
-            }
-            Err(e) => log_error(format_args!(
-                "Failed to get WINDIR environment variable to locate Windows folder: {e}"
-            )),
+        let icon = load_windows_icon("System32\\main.cpl", 0);
+        // The "blocked" sign from the shell's icons:
+        let paused_icon = load_windows_icon("System32\\shell32.dll", 109).or_else(|| icon.clone());
+        let showing_paused_icon = crate::pause::is_paused();
+        if let Some(icon) = if showing_paused_icon {
+            &paused_icon
+        } else {
+            &icon
+        } {
+            tray = tray.with_icon(icon.clone());
         }
         let tray = tray.build().unwrap();
+        TRAY_ICON.set(Some(tray.clone()));
 
         MenuEvent::set_event_handler(Some({
             let quit_id = quit_item.id().clone();
+            let reset_to_defaults_id = reset_to_defaults.id().clone();
+            let exempt_controller_id = exempt_controller_item.id().clone();
+            let game_mode_id = game_mode_item.id().clone();
+            let autostart_id = autostart_item.id().clone();
+            let create_task_id = create_task_item.id().clone();
+            let remove_task_id = remove_task_item.id().clone();
+            let about_id = about_item.id().clone();
+            let pause_ids = pause_items.map(|(minutes, item)| (minutes, item.id().clone()));
+            let resume_id = resume_item.id().clone();
+            let thresholds_id = thresholds_item.id().clone();
+            let preset_ids = preset_items
+                .iter()
+                .map(|(threshold, item)| (*threshold, item.id().clone()))
+                .collect::<Vec<_>>();
+            #[cfg(feature = "live-feed")]
+            let dashboard_id = dashboard_item.id().clone();
             #[cfg(feature = "logging")]
             let logging_id = logging_item.id().clone();
             #[cfg(feature = "logging")]
             let show_stats_id = show_stats.id().clone();
+            #[cfg(feature = "logging")]
+            let show_histogram_id = show_histogram.id().clone();
+            #[cfg(feature = "logging")]
+            let suggest_id = suggest_item.id().clone();
+            #[cfg(feature = "logging")]
+            let reset_stats_ids = reset_stats_items
+                .iter()
+                .map(|(button, item)| (*button, item.id().clone()))
+                .collect::<Vec<_>>();
             move |event: MenuEvent| {
                 // Note: this actually runs on the same thread as the main event
                 // loop so don't block.
@@ -147,6 +424,43 @@ impl TrayApp {
                         std::process::exit(1);
                     });
                 }
+                if event.id == reset_to_defaults_id {
+                    _ = proxy.send_event(UserEvent::ResetToDefaults);
+                }
+                if event.id == exempt_controller_id {
+                    _ = proxy.send_event(UserEvent::ToggleExemptController);
+                }
+                if event.id == game_mode_id {
+                    _ = proxy.send_event(UserEvent::ToggleGameMode);
+                }
+                if event.id == autostart_id {
+                    _ = proxy.send_event(UserEvent::ToggleAutostart);
+                }
+                if event.id == create_task_id {
+                    _ = proxy.send_event(UserEvent::CreateElevatedTask);
+                }
+                if event.id == remove_task_id {
+                    _ = proxy.send_event(UserEvent::RemoveElevatedTask);
+                }
+                if event.id == about_id {
+                    _ = proxy.send_event(UserEvent::ShowAbout);
+                }
+                if let Some(&(minutes, _)) = pause_ids.iter().find(|(_, id)| event.id == *id) {
+                    _ = proxy.send_event(UserEvent::PauseFor(minutes));
+                }
+                if event.id == resume_id {
+                    _ = proxy.send_event(UserEvent::Resume);
+                }
+                if event.id == thresholds_id {
+                    _ = proxy.send_event(UserEvent::ShowThresholds);
+                }
+                if let Some(&(threshold, _)) = preset_ids.iter().find(|(_, id)| event.id == *id) {
+                    _ = proxy.send_event(UserEvent::ApplyPreset(threshold));
+                }
+                #[cfg(feature = "live-feed")]
+                if event.id == dashboard_id {
+                    _ = proxy.send_event(UserEvent::OpenDashboard);
+                }
                 #[cfg(feature = "logging")]
                 if event.id == logging_id {
                     _ = proxy.send_event(UserEvent::ToggleLogging);
@@ -155,19 +469,178 @@ impl TrayApp {
                 if event.id == show_stats_id {
                     _ = proxy.send_event(UserEvent::ShowStats);
                 }
+                #[cfg(feature = "logging")]
+                if event.id == show_histogram_id {
+                    _ = proxy.send_event(UserEvent::ShowHistogram);
+                }
+                #[cfg(feature = "logging")]
+                if event.id == suggest_id {
+                    _ = proxy.send_event(UserEvent::SuggestThresholds);
+                }
+                #[cfg(feature = "logging")]
+                if let Some(&(button, _)) = reset_stats_ids.iter().find(|(_, id)| event.id == *id) {
+                    _ = proxy.send_event(UserEvent::ResetStats(button));
+                }
             }
         }));
 
         TrayApp {
             tray,
+            icon,
+            paused_icon,
+            showing_paused_icon,
+            next_tooltip_refresh: tooltip_refresh_interval()
+                .map(|interval| Instant::now() + interval),
+            exit_at: match crate::MAX_RUNTIME_SECS.load(Relaxed) {
+                0 => None,
+                secs => Some(Instant::now() + Duration::from_secs(secs.into())),
+            },
+            resume_at: None,
+            exempt_controller_item,
+            game_mode_item,
+            autostart_item,
+            preset_items,
             #[cfg(feature = "logging")]
             logging_item,
         }
     }
 }
+impl TrayApp {
+    /// Update the tooltip and menu, and show whether blocking is paused.
+    fn refresh(&mut self) {
+        if let Err(e) = self.tray.set_tooltip(Some(tooltip_text())) {
+            log_error(e);
+        }
+        // Check the preset that matches the current thresholds, if any:
+        let left_threshold = crate::THRESHOLD_LM.load(Relaxed);
+        for (threshold, item) in &self.preset_items {
+            item.set_checked(*threshold == left_threshold);
+        }
+        let paused = crate::pause::is_paused();
+        if !paused {
+            // Resumed some other way, for example with the hotkey:
+            self.resume_at = None;
+        }
+        if paused != self.showing_paused_icon {
+            let icon = if paused {
+                &self.paused_icon
+            } else {
+                &self.icon
+            };
+            if let Err(e) = self.tray.set_icon(icon.clone()) {
+                log_error(e);
+            }
+            self.showing_paused_icon = paused;
+        }
+    }
+
+    /// Suggest thresholds from the observed intervals between clicks and use
+    /// them if the user agrees.
+    #[cfg(feature = "logging")]
+    fn suggest_thresholds(&mut self) {
+        use std::fmt::Write;
+
+        let suggestions = MouseButton::all()
+            .iter()
+            .filter_map(|&button| {
+                logging::stats::suggested_threshold(button).map(|threshold| (button, threshold))
+            })
+            .collect::<Vec<_>>();
+        let title = to_utf16("Suggested thresholds for click-once");
+        if suggestions.is_empty() {
+            let text = to_utf16(
+                "No chatter has been observed yet, so there is nothing to suggest. \
+                Use the mouse for a while (start the program with --dry-run to \
+                observe chatter without blocking it) and try again.",
+            );
+            unsafe {
+                MessageBoxW(
+                    core::ptr::null_mut(),
+                    text.as_ptr(),
+                    title.as_ptr(),
+                    MB_OK | MB_ICONINFORMATION,
+                )
+            };
+            return;
+        }
+        let mut text = "Based on the observed time between clicks these thresholds \
+            separate chatter from intentional double-clicks:\n\n"
+            .to_owned();
+        for &(button, threshold) in &suggestions {
+            _ = writeln!(
+                text,
+                "{}: {threshold} ms (currently {} ms)",
+                button.name(),
+                crate::threshold(button).load(Relaxed)
+            );
+            log![
+                b"Suggested threshold for the ",
+                button.name().as_bytes(),
+                b" button: ",
+                threshold,
+                b" ms\r\n"
+            ];
+        }
+        text.push_str("\nUse these thresholds and save them to the config file?");
+        let text = to_utf16(&text);
+        let result = unsafe {
+            MessageBoxW(
+                core::ptr::null_mut(),
+                text.as_ptr(),
+                title.as_ptr(),
+                MB_YESNO | MB_ICONINFORMATION,
+            )
+        };
+        if result != IDYES {
+            return;
+        }
+        for (button, threshold) in suggestions {
+            crate::threshold(button).store(threshold, Relaxed);
+        }
+        crate::config::persist_changes();
+        self.refresh();
+    }
+}
 impl ApplicationHandler<UserEvent> for TrayApp {
     fn resumed(&mut self, _event_loop: &ActiveEventLoop) {}
 
+    fn new_events(&mut self, event_loop: &ActiveEventLoop, cause: StartCause) {
+        if self
+            .exit_at
+            .is_some_and(|exit_at| exit_at <= Instant::now())
+        {
+            self.exit_at = None;
+            crate::on_max_runtime_reached();
+            self.user_event(event_loop, UserEvent::Quit);
+            return;
+        }
+        if self
+            .resume_at
+            .is_some_and(|resume_at| resume_at <= Instant::now())
+        {
+            self.user_event(event_loop, UserEvent::Resume);
+        }
+        if let (StartCause::ResumeTimeReached { .. }, Some(interval)) =
+            (cause, tooltip_refresh_interval())
+        {
+            if let Err(e) = self.tray.set_tooltip(Some(tooltip_text())) {
+                log_error(e);
+            }
+            self.next_tooltip_refresh = Some(Instant::now() + interval);
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        let next_wake_up = [self.next_tooltip_refresh, self.exit_at, self.resume_at]
+            .into_iter()
+            .flatten()
+            .min();
+        event_loop.set_control_flow(match next_wake_up {
+            Some(next) => ControlFlow::WaitUntil(next),
+            None => ControlFlow::Wait,
+        });
+    }
+
     fn window_event(
         &mut self,
         _event_loop: &ActiveEventLoop,
@@ -187,18 +660,161 @@ impl ApplicationHandler<UserEvent> for TrayApp {
                 }
                 event_loop.exit();
             }
+            UserEvent::ResetToDefaults => {
+                let title = to_utf16("Reset click-once to defaults");
+                let text = to_utf16(&format!(
+                    "Reset the thresholds and mode to the built-in defaults \
+                    (left {} ms, right {} ms, middle {} ms, pen disabled, \
+                    suppress both down and up chatter, debounce injected events and \
+                    exempt touch input but not absolute pointing devices or controller emulators) \
+                    and save them to the config file?",
+                    crate::DEFAULT_THRESHOLDS[0],
+                    crate::DEFAULT_THRESHOLDS[1],
+                    crate::DEFAULT_THRESHOLDS[2],
+                ));
+                let result = unsafe {
+                    MessageBoxW(
+                        core::ptr::null_mut(),
+                        text.as_ptr(),
+                        title.as_ptr(),
+                        MB_YESNO | MB_ICONWARNING,
+                    )
+                };
+                if result != IDYES {
+                    return;
+                }
+                for (threshold, default) in [
+                    &crate::THRESHOLD_LM,
+                    &crate::THRESHOLD_RM,
+                    &crate::THRESHOLD_MM,
+                    &crate::THRESHOLD_X1,
+                    &crate::THRESHOLD_X2,
+                ]
+                .into_iter()
+                .zip(crate::DEFAULT_THRESHOLDS)
+                {
+                    threshold.store(default, Relaxed);
+                }
+                crate::SUPPRESSION_MODE.store(crate::SuppressionMode::Both as u8, Relaxed);
+                crate::EXEMPT_ABSOLUTE.store(false, Relaxed);
+                crate::EXEMPT_TOUCH.store(true, Relaxed);
+                crate::controller::set_enabled(false);
+                self.exempt_controller_item.set_checked(false);
+                for threshold in &crate::UP_THRESHOLDS {
+                    threshold.store(crate::SAME_AS_DOWN, Relaxed);
+                }
+                for threshold in &crate::PEN_THRESHOLDS {
+                    threshold.store(0, Relaxed);
+                }
+                for wheel in crate::wheel::ALL {
+                    wheel.threshold().store(0, Relaxed);
+                }
+                for kind in crate::injected::InjectedKind::ALL {
+                    crate::injected::set_policy(kind, crate::injected::InjectedPolicy::Debounce);
+                }
+                crate::config::persist_changes();
+                self.refresh();
+                #[cfg(feature = "logging")]
+                logging::write_all(&logging::log_program_config());
+            }
+            UserEvent::ToggleExemptController => {
+                let enable = !crate::controller::is_enabled();
+                crate::controller::set_enabled(enable);
+                crate::controller::start();
+                self.exempt_controller_item.set_checked(enable);
+                crate::config::persist_changes();
+            }
+            UserEvent::ToggleGameMode => {
+                let enable = !crate::foreground_app::game_mode();
+                crate::foreground_app::set_game_mode(enable);
+                crate::foreground_app::start();
+                self.game_mode_item.set_checked(enable);
+                crate::config::persist_changes();
+            }
+            UserEvent::ToggleAutostart => {
+                if crate::autostart::is_enabled() {
+                    crate::autostart::disable();
+                } else {
+                    // Start with the same arguments that were used this time:
+                    let result = std::env::current_exe()
+                        .map_err(|e| {
+                            format!("Failed to get the path of the current executable: {e}")
+                        })
+                        .and_then(|exe| {
+                            let args = std::env::args().skip(1).collect::<Vec<_>>();
+                            crate::autostart::enable(&exe, &args)
+                        });
+                    if let Err(e) = result {
+                        log_error(e);
+                    }
+                }
+                self.autostart_item
+                    .set_checked(crate::autostart::is_enabled());
+            }
+            UserEvent::CreateElevatedTask => {
+                let args = std::env::args().skip(1).collect::<Vec<_>>();
+                match crate::scheduled_task::create(&args) {
+                    Ok(()) => log![b"\r\nCreated scheduled task\r\n"],
+                    Err(e) => log_error(e),
+                }
+            }
+            UserEvent::RemoveElevatedTask => match crate::scheduled_task::remove() {
+                Ok(()) => log![b"\r\nRemoved scheduled task\r\n"],
+                Err(e) => log_error(e),
+            },
+            UserEvent::ShowAbout => crate::version::show(),
+            UserEvent::RefreshTooltip => self.refresh(),
+            UserEvent::ShowThresholds => crate::threshold_window::show(),
+            UserEvent::ApplyPreset(threshold) => {
+                apply_preset(threshold);
+                crate::config::persist_changes();
+                self.refresh();
+            }
+            UserEvent::PauseFor(minutes) => {
+                crate::pause::set_paused(true);
+                self.resume_at =
+                    Some(Instant::now() + Duration::from_secs(u64::from(minutes) * 60));
+                log![b"Blocking resumes in ", minutes, b" minutes\r\n"];
+            }
+            UserEvent::Resume => {
+                self.resume_at = None;
+                crate::pause::set_paused(false);
+            }
+            #[cfg(feature = "live-feed")]
+            UserEvent::OpenDashboard => {
+                // The dashboard is served by the live feed, start it if it
+                // wasn't enabled with an argument:
+                if crate::live_feed::port() == 0 {
+                    crate::live_feed::set_port(crate::live_feed::DEFAULT_PORT);
+                }
+                crate::live_feed::start();
+                let url = to_utf16(&format!("http://127.0.0.1:{}/", crate::live_feed::port()));
+                // https://learn.microsoft.com/en-us/windows/win32/api/shellapi/nf-shellapi-shellexecutew
+                let result = unsafe {
+                    ShellExecuteW(
+                        core::ptr::null_mut(),
+                        windows_sys::w!("open"),
+                        url.as_ptr(),
+                        core::ptr::null(),
+                        core::ptr::null(),
+                        SW_SHOWNORMAL,
+                    )
+                };
+                if result as isize <= 32 {
+                    log_error("Failed to open the dashboard in a browser");
+                }
+            }
             #[cfg(feature = "logging")]
             UserEvent::ToggleLogging => {
-                let enable = !logging::is_logging();
+                let enable = !logging::has_console();
                 logging::set_should_log(enable);
                 self.logging_item.set_checked(enable);
                 log![
                     b"\r\nLogging for click-once!\r\n\r\n\
-                    Warning: closing this console window will terminate the program!\r\n\r\n"
+                    Warning: closing this console window will terminate the program!\r\n"
                 ];
-                logging::log_program_config()
-                    .iter()
-                    .for_each(|value| value.write());
+                logging::write_all(&logging::log_session_header());
+                logging::write_all(&logging::log_program_config());
                 logging::stats::log_current_stats(&mut |v| v.write());
             }
             #[cfg(feature = "logging")]
@@ -220,6 +836,27 @@ impl ApplicationHandler<UserEvent> for TrayApp {
                     log_error("Failed to open message box");
                 }
             }
+            #[cfg(feature = "logging")]
+            UserEvent::ShowHistogram => crate::histogram_window::show(),
+            #[cfg(feature = "logging")]
+            UserEvent::SuggestThresholds => {
+                self.suggest_thresholds();
+            }
+            #[cfg(feature = "logging")]
+            UserEvent::ResetStats(button) => {
+                logging::stats::reset_button(button);
+                log![
+                    b"\r\nReset statistics for the ",
+                    match button {
+                        MouseButton::Left => b"left".as_slice(),
+                        MouseButton::Right => b"right",
+                        MouseButton::Middle => b"middle",
+                        MouseButton::X1 => b"X1",
+                        MouseButton::X2 => b"X2",
+                    },
+                    b" button\r\n"
+                ];
+            }
         }
     }
 }
@@ -227,5 +864,6 @@ impl ApplicationHandler<UserEvent> for TrayApp {
 pub fn run_event_loop_with_tray() {
     let event_loop = EventLoop::<UserEvent>::with_user_event().build().unwrap();
     let mut app = TrayApp::new(event_loop.create_proxy());
+    *PROXY.lock().unwrap_or_else(|e| e.into_inner()) = Some(event_loop.create_proxy());
     event_loop.run_app(&mut app).unwrap();
 }
@@ -0,0 +1,246 @@
+//! Remove things that the program has left behind on the system so that
+//! removing it is as easy as deleting the executable.
+
+use crate::log_error;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use windows_sys::Win32::Foundation::{ERROR_NO_MORE_ITEMS, ERROR_SUCCESS};
+use windows_sys::Win32::System::Registry::{
+    RegCloseKey, RegDeleteTreeW, RegEnumKeyExW, RegGetValueW, RegOpenKeyExW, HKEY,
+    HKEY_CURRENT_USER, KEY_ALL_ACCESS, RRF_RT_REG_SZ,
+};
+
+/// Windows remembers settings (such as if the icon should always be visible)
+/// for every program that has created a tray icon. Remove the entries that
+/// refer to the current executable.
+///
+/// Returns the number of removed entries.
+fn remove_tray_registration() -> usize {
+    let Ok(exe_path) = std::env::current_exe() else {
+        log_error("Failed to get the path of the current executable");
+        return 0;
+    };
+    let exe_path = exe_path.to_string_lossy().to_lowercase();
+
+    let mut key: HKEY = core::ptr::null_mut();
+    let result = unsafe {
+        RegOpenKeyExW(
+            HKEY_CURRENT_USER,
+            windows_sys::w!("Control Panel\\NotifyIconSettings"),
+            0,
+            KEY_ALL_ACCESS,
+            &mut key,
+        )
+    };
+    if result != ERROR_SUCCESS {
+        // Only exists on Windows 11 and later.
+        return 0;
+    }
+
+    // Find matching entries first since deleting keys while enumerating them
+    // would change their indexes:
+    let mut matching = Vec::new();
+    for index in 0.. {
+        let mut name = [0_u16; 256];
+        let mut name_len = name.len() as u32;
+        let result = unsafe {
+            RegEnumKeyExW(
+                key,
+                index,
+                name.as_mut_ptr(),
+                &mut name_len,
+                core::ptr::null(),
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+                core::ptr::null_mut(),
+            )
+        };
+        if result == ERROR_NO_MORE_ITEMS {
+            break;
+        } else if result != ERROR_SUCCESS {
+            log_error("Failed to enumerate tray icon settings");
+            break;
+        }
+
+        let mut path = [0_u16; 1024];
+        let mut path_size = core::mem::size_of_val(&path) as u32;
+        let result = unsafe {
+            RegGetValueW(
+                key,
+                name.as_ptr(),
+                windows_sys::w!("ExecutablePath"),
+                RRF_RT_REG_SZ,
+                core::ptr::null_mut(),
+                path.as_mut_ptr().cast(),
+                &mut path_size,
+            )
+        };
+        if result != ERROR_SUCCESS {
+            continue;
+        }
+        let path_len = path.iter().position(|&c| c == 0).unwrap_or(path.len());
+        if String::from_utf16_lossy(&path[..path_len]).to_lowercase() == exe_path {
+            matching.push(name);
+        }
+    }
+
+    let mut removed = 0;
+    for name in matching {
+        if unsafe { RegDeleteTreeW(key, name.as_ptr()) } == ERROR_SUCCESS {
+            removed += 1;
+        } else {
+            log_error("Failed to remove tray icon settings");
+        }
+    }
+    unsafe { RegCloseKey(key) };
+    removed
+}
+
+/// Files that the program writes to the config folder.
+const CONFIG_FILES: [&str; 2] = ["config.toml", "recordings.txt"];
+
+/// Files that `--install` and updates of the installed copy write to the
+/// install folder.
+const INSTALL_FILES: [&str; 3] = ["click-once.exe", "click-once.exe.old", "click-once.exe.new"];
+
+/// The traces that were recorded with `--record`. Anyone can edit the list of
+/// recordings, so only files that are still traces are included.
+fn recordings() -> Vec<PathBuf> {
+    let Some(list) = crate::trace::recordings_path() else {
+        return Vec::new();
+    };
+    let Ok(paths) = std::fs::read_to_string(list) else {
+        return Vec::new();
+    };
+    paths
+        .lines()
+        .map(str::trim)
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .filter(|path| is_trace(path))
+        .collect()
+}
+
+/// `true` if the file starts like the traces written by `--record`.
+fn is_trace(path: &Path) -> bool {
+    let mut magic = [0; crate::trace::MAGIC.len()];
+    std::fs::File::open(path)
+        .and_then(|mut file| file.read_exact(&mut magic))
+        .is_ok_and(|()| &magic == crate::trace::MAGIC)
+}
+
+/// The files with the given names in `dir` that exist.
+fn files_in(dir: Option<PathBuf>, names: &[&str]) -> Vec<PathBuf> {
+    dir.map_or_else(Vec::new, |dir| {
+        names
+            .iter()
+            .map(|name| dir.join(name))
+            .filter(|path| path.is_file())
+            .collect()
+    })
+}
+
+/// Remove everything that the program has left behind, after asking unless
+/// `confirmed` is `true`.
+pub fn uninstall(confirmed: bool) {
+    let config_dir = crate::config::path()
+        .as_deref()
+        .and_then(Path::parent)
+        .map(Path::to_path_buf);
+    let install_dir = crate::install::install_dir();
+    let traces = recordings();
+    let config_files = files_in(config_dir.clone(), &CONFIG_FILES);
+    let install_files = files_in(install_dir.clone(), &INSTALL_FILES);
+
+    if !confirmed {
+        let mut message = vec![
+            "click-once will remove its autostart entry, scheduled task, thresholds stored in \
+            the registry, tray icon settings and COM registration if they exist, and these files:"
+                .to_owned(),
+        ];
+        #[cfg(feature = "logging")]
+        message.extend(
+            crate::log_file::paths()
+                .into_iter()
+                .filter(|path| Path::new(path).is_file()),
+        );
+        message.extend(
+            traces
+                .iter()
+                .chain(&config_files)
+                .chain(&install_files)
+                .map(|path| path.display().to_string()),
+        );
+        message.push("Continue?".to_owned());
+        if !crate::console::confirm(&message.join("\r\n")) {
+            return;
+        }
+    }
+
+    // Reported at once so that there is only one message box without a
+    // console:
+    let mut report = Vec::new();
+
+    let tray_entries = remove_tray_registration();
+    report.push(format!("Removed {tray_entries} tray icon registration(s)"));
+
+    if crate::autostart::disable() {
+        report.push("Removed autostart entry".to_owned());
+    }
+    if crate::registry_config::remove() {
+        report.push("Removed thresholds stored in the registry".to_owned());
+    }
+    if crate::scheduled_task::exists() {
+        match crate::scheduled_task::remove() {
+            Ok(()) => report.push("Removed the scheduled task".to_owned()),
+            Err(e) => log_error(e),
+        }
+    }
+
+    #[cfg(feature = "com")]
+    {
+        let com_keys = crate::com_server::unregister();
+        report.push(format!("Removed {com_keys} COM registry key(s)"));
+    }
+
+    #[cfg(feature = "logging")]
+    {
+        let log_files = crate::log_file::delete();
+        if log_files != 0 {
+            report.push(format!("Removed {log_files} log file(s)"));
+        }
+    }
+    let traces = traces
+        .iter()
+        .filter(|path| std::fs::remove_file(path).is_ok())
+        .count();
+    if traces != 0 {
+        report.push(format!("Removed {traces} recorded trace file(s)"));
+    }
+
+    for path in &config_files {
+        match std::fs::remove_file(path) {
+            Ok(()) => report.push(format!("Removed \"{}\"", path.display())),
+            Err(e) => log_error(format_args!("Failed to remove \"{}\": {e}", path.display())),
+        }
+    }
+    for path in &install_files {
+        // Fails while the installed copy is running, including when it is the
+        // program that is uninstalling:
+        match std::fs::remove_file(path) {
+            Ok(()) => report.push(format!("Removed \"{}\"", path.display())),
+            Err(_) => report.push(format!(
+                "\"{}\" can be deleted once click-once has exited",
+                path.display()
+            )),
+        }
+    }
+    // Folders are only removed if nothing else was put in them:
+    for dir in config_dir.iter().chain(&install_dir) {
+        if dir.is_dir() && std::fs::remove_dir(dir).is_ok() {
+            report.push(format!("Removed the folder \"{}\"", dir.display()));
+        }
+    }
+    report.push("Done, the executable itself can now be deleted.".to_owned());
+    crate::console::report(&report.join("\r\n"));
+}
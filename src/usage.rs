@@ -0,0 +1,42 @@
+//! The text shown for `--help`. Like `--version` it is shown in a message box
+//! since the program usually doesn't have a console to print to.
+
+use windows_sys::Win32::UI::WindowsAndMessaging::{MessageBoxW, MB_ICONINFORMATION, MB_OK};
+
+/// Show a summary of the most common arguments. The README describes all of
+/// them.
+pub fn show() {
+    let result = unsafe {
+        MessageBoxW(
+            core::ptr::null_mut(),
+            windows_sys::w!(
+                "Usage: click-once.exe [options] [<left> [<right> [<middle>]]]\r\n\
+                \r\n\
+                Thresholds are in milliseconds, 0 disables debouncing a button.\r\n\
+                \r\n\
+                --left <ms>\tThreshold for the left button (default 30)\r\n\
+                --right <ms>\tThreshold for the right button (default 0)\r\n\
+                --middle <ms>\tThreshold for the middle button (default 0)\r\n\
+                --left-up <ms>\tSeparate threshold for releases (also --left-down)\r\n\
+                --x1 <ms>, --x2 <ms>\tThresholds for the extended buttons\r\n\
+                --wheel=<ms>, --hwheel=<ms>\tThresholds for the scroll wheels\r\n\
+                --up-only\tOnly suppress chatter when a button is released\r\n\
+                --delay\tDelay suspicious presses instead of dropping them\r\n\
+                --logging\tOpen a console window that logs clicks\r\n\
+                --profile <name>\tUse a profile from the config file\r\n\
+                --install, --uninstall\tSet up or remove the program\r\n\
+                --yes\tUninstall without asking first\r\n\
+                --version\tShow build information\r\n\
+                --help\tShow this text\r\n\
+                \r\n\
+                Some options depend on the Cargo features the program was built with.\r\n\
+                See the README for all options."
+            ),
+            windows_sys::w!("click-once help"),
+            MB_ICONINFORMATION | MB_OK,
+        )
+    };
+    if result == 0 {
+        crate::log_error("Failed to open message box");
+    }
+}
@@ -0,0 +1,189 @@
+//! Identifies the running build using metadata collected by the build script,
+//! so that bug reports and log files can be traced back to an exact artifact.
+//!
+//! The build script also embeds a version resource and an application manifest
+//! in MSVC builds. They are read back from the executable so that the shown
+//! information matches what Windows uses.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-messageboxw>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/libloaderapi/nf-libloaderapi-findresourcew>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/verrsrc/ns-verrsrc-vs_fixedfileinfo>
+
+use windows_sys::Win32::System::LibraryLoader::{
+    FindResourceW, GetModuleHandleW, LoadResource, LockResource, SizeofResource,
+};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    MessageBoxW, MB_ICONINFORMATION, MB_OK, RT_MANIFEST, RT_VERSION,
+};
+
+#[cfg(feature = "logging")]
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short hash of the git commit that was built, `unknown` if built outside of
+/// a git checkout.
+#[cfg(feature = "logging")]
+pub const GIT_HASH: &str = env!("CLICK_ONCE_GIT_HASH");
+#[cfg(feature = "logging")]
+pub const TARGET: &str = env!("CLICK_ONCE_TARGET");
+/// Comma separated list of the enabled Cargo features, `none` if there are no
+/// enabled features.
+#[cfg(feature = "logging")]
+pub const FEATURES: &str = env!("CLICK_ONCE_FEATURES");
+
+/// All build metadata as one text, with Windows line endings.
+pub const INFO: &str = concat!(
+    "click-once v",
+    env!("CARGO_PKG_VERSION"),
+    "\r\nCommit: ",
+    env!("CLICK_ONCE_GIT_HASH"),
+    "\r\nTarget: ",
+    env!("CLICK_ONCE_TARGET"),
+    "\r\nFeatures: ",
+    env!("CLICK_ONCE_FEATURES"),
+);
+
+/// Get the data of a resource with the id `1` in the executable, `None` if
+/// there is no such resource.
+fn resource(kind: windows_sys::core::PCWSTR) -> Option<&'static [u8]> {
+    unsafe {
+        let module = GetModuleHandleW(core::ptr::null());
+        let info = FindResourceW(module, 1 as windows_sys::core::PCWSTR, kind);
+        if info.is_null() {
+            return None;
+        }
+        let data = LockResource(LoadResource(module, info));
+        if data.is_null() {
+            return None;
+        }
+        // Resources stay loaded for the lifetime of the module:
+        Some(core::slice::from_raw_parts(
+            data.cast::<u8>(),
+            SizeofResource(module, info) as usize,
+        ))
+    }
+}
+
+/// The file version from the embedded version resource.
+fn file_version() -> Option<[u32; 4]> {
+    const SIGNATURE: [u8; 4] = 0xFEEF_04BD_u32.to_le_bytes();
+
+    let data = resource(RT_VERSION)?;
+    // `VS_FIXEDFILEINFO` starts with a signature and is aligned to 4 bytes:
+    let start = (0..data.len().saturating_sub(16))
+        .step_by(4)
+        .find(|&ix| data[ix..ix + 4] == SIGNATURE)?;
+    let field = |index: usize| {
+        let offset = start + index * 4;
+        u32::from_le_bytes([
+            data[offset],
+            data[offset + 1],
+            data[offset + 2],
+            data[offset + 3],
+        ])
+    };
+    let (ms, ls) = (field(2), field(3));
+    Some([ms >> 16, ms & 0xFFFF, ls >> 16, ls & 0xFFFF])
+}
+
+/// Find the text between `start` and the next `end` in a manifest.
+fn manifest_value<'a>(manifest: &'a [u8], start: &[u8], end: u8) -> &'a [u8] {
+    let Some(value_start) = manifest
+        .windows(start.len())
+        .position(|part| part == start)
+        .map(|ix| ix + start.len())
+    else {
+        return b"unknown";
+    };
+    let value = &manifest[value_start..];
+    &value[..value
+        .iter()
+        .position(|&byte| byte == end)
+        .unwrap_or(value.len())]
+}
+
+/// A nul terminated UTF-16 text that is built without `core::fmt` to keep the
+/// binary small.
+struct WideText {
+    buffer: [u16; 1024],
+    len: usize,
+}
+impl WideText {
+    fn push(&mut self, text: &[u8]) {
+        for &byte in text {
+            // The last element is left as the nul terminator:
+            if self.len + 1 >= self.buffer.len() {
+                return;
+            }
+            self.buffer[self.len] = u16::from(byte);
+            self.len += 1;
+        }
+    }
+    fn push_number(&mut self, mut number: u32) {
+        let mut digits = [0_u8; 10];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (number % 10) as u8;
+            number /= 10;
+            if number == 0 {
+                break;
+            }
+        }
+        self.push(&digits[start..]);
+    }
+}
+
+/// Show the build metadata in a message box. Used for `--version` since the
+/// program usually doesn't have a console to print to.
+pub fn show() {
+    let mut text = WideText {
+        buffer: [0; 1024],
+        len: 0,
+    };
+    text.push(INFO.as_bytes());
+
+    text.push(b"\r\n\r\nFile version: ");
+    match file_version() {
+        Some(version) => {
+            for (ix, part) in version.into_iter().enumerate() {
+                if ix != 0 {
+                    text.push(b".");
+                }
+                text.push_number(part);
+            }
+        }
+        None => text.push(b"no version resource"),
+    }
+    match resource(RT_MANIFEST) {
+        Some(manifest) => {
+            text.push(b"\r\nExecution level: ");
+            text.push(manifest_value(manifest, b"level=\"", b'"'));
+            text.push(b"\r\nDPI awareness: ");
+            text.push(manifest_value(
+                manifest,
+                b"<dpiAwareness xmlns=\"http://schemas.microsoft.com/SMI/2016/WindowsSettings\">",
+                b'<',
+            ));
+            text.push(b"\r\nLong path aware: ");
+            text.push(manifest_value(
+                manifest,
+                b"<longPathAware xmlns=\"http://schemas.microsoft.com/SMI/2016/WindowsSettings\">",
+                b'<',
+            ));
+        }
+        None => text.push(b"\r\nNo application manifest"),
+    }
+
+    let result = unsafe {
+        MessageBoxW(
+            core::ptr::null_mut(),
+            text.buffer.as_ptr(),
+            windows_sys::w!("About click-once"),
+            MB_ICONINFORMATION | MB_OK,
+        )
+    };
+    if result == 0 {
+        crate::log_error("Failed to open message box");
+    }
+}
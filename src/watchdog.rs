@@ -0,0 +1,138 @@
+//! Detects that Windows silently removed the mouse hook and installs it again.
+//! Windows removes low level hooks without any notification when they take too
+//! long to respond, after which no clicks are debounced.
+//!
+//! A timer compares when the hook was last called with when the system last
+//! received input. If there was input that the hook didn't see, a mouse move
+//! that doesn't move the cursor is injected as a heartbeat and the hook is
+//! re-installed if it doesn't see that either. Keyboard input also counts as
+//! input, which is why a missing mouse event isn't enough on its own.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/winmsg/lowlevelmouseproc#remarks>
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-getlastinputinfo>
+
+use crate::{log, log_error};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::{HWND, LPARAM, LRESULT, WPARAM};
+use windows_sys::Win32::System::SystemInformation::GetTickCount;
+use windows_sys::Win32::UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO};
+use windows_sys::Win32::UI::WindowsAndMessaging::{DefWindowProcW, SetTimer, WM_TIMER};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Tick count when the mouse hook was last called.
+static LAST_HOOK_CALL: AtomicU32 = AtomicU32::new(0);
+
+/// Tick count when a heartbeat was injected, `0` if no heartbeat is waiting to
+/// be seen by the hook.
+static HEARTBEAT_SENT: AtomicU32 = AtomicU32::new(0);
+
+/// Tick count when the last heartbeat was injected, so that they aren't sent
+/// every time the timer fires while only the keyboard is used.
+static LAST_HEARTBEAT: AtomicU32 = AtomicU32::new(0);
+
+/// How often the timer checks the hook. The hook must see a heartbeat before
+/// the next check.
+const CHECK_INTERVAL_MS: u32 = 5_000;
+const CHECK_TIMER_ID: usize = 1;
+
+/// Input that the hook didn't see must be at least this old before it is
+/// suspicious, since the hook is called after the input was received.
+const MIN_UNSEEN_MS: u32 = 1_000;
+
+/// At most one heartbeat is sent this often.
+const MIN_HEARTBEAT_INTERVAL_MS: u32 = 60_000;
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Relaxed);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Relaxed)
+}
+
+/// Remember that the mouse hook is alive, called for every mouse event.
+#[inline(always)]
+pub fn on_hook_called() {
+    LAST_HOOK_CALL.store(unsafe { GetTickCount() }, Relaxed);
+}
+
+/// Tick count of the last mouse or keyboard input that the system received.
+fn last_input_tick() -> Option<u32> {
+    let mut info = LASTINPUTINFO {
+        cbSize: size_of::<LASTINPUTINFO>() as u32,
+        dwTime: 0,
+    };
+    (unsafe { GetLastInputInfo(&mut info) } != 0).then_some(info.dwTime)
+}
+
+fn on_timer() {
+    let now = unsafe { GetTickCount() };
+    let last_hook_call = LAST_HOOK_CALL.load(Relaxed);
+
+    let sent = HEARTBEAT_SENT.swap(0, Relaxed);
+    if sent != 0 {
+        // Tick counts wrap around, so compare how long ago they were:
+        if now.wrapping_sub(last_hook_call) <= now.wrapping_sub(sent) {
+            return;
+        }
+        if crate::reinstall_mouse_hook() {
+            on_hook_called();
+            log![b"\r\nThe mouse hook was removed by Windows, re-installed it\r\n"];
+        } else {
+            log_error("The mouse hook was removed by Windows and re-installing it failed");
+        }
+        return;
+    }
+
+    let Some(last_input) = last_input_tick() else {
+        return;
+    };
+    let unseen_input = now.wrapping_sub(last_hook_call) > now.wrapping_sub(last_input)
+        && last_input.wrapping_sub(last_hook_call) >= MIN_UNSEEN_MS;
+    if !unseen_input || now.wrapping_sub(LAST_HEARTBEAT.load(Relaxed)) < MIN_HEARTBEAT_INTERVAL_MS {
+        return;
+    }
+    // `0` means that no heartbeat is waiting:
+    let now = now.max(1);
+    HEARTBEAT_SENT.store(now, Relaxed);
+    LAST_HEARTBEAT.store(now, Relaxed);
+    crate::deferred::inject_heartbeat();
+}
+
+unsafe extern "system" fn window_proc(
+    window: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    if message == WM_TIMER && wparam == CHECK_TIMER_ID {
+        on_timer();
+        return 0;
+    }
+    DefWindowProcW(window, message, wparam, lparam)
+}
+
+/// Start checking the mouse hook if the watchdog is enabled. Must be called on
+/// the thread that installed the hook and runs the message loop.
+pub fn start() {
+    if !is_enabled() {
+        return;
+    }
+    on_hook_called();
+    // Allow a heartbeat right away:
+    LAST_HEARTBEAT.store(
+        unsafe { GetTickCount() }.wrapping_sub(MIN_HEARTBEAT_INTERVAL_MS),
+        Relaxed,
+    );
+    let Some(window) =
+        crate::message_window::create(windows_sys::w!("click-once-watchdog"), Some(window_proc))
+    else {
+        return;
+    };
+    if unsafe { SetTimer(window, CHECK_TIMER_ID, CHECK_INTERVAL_MS, None) } == 0 {
+        log_error("Failed to create timer for checking the mouse hook");
+    }
+}
@@ -0,0 +1,83 @@
+//! Debounces the scroll wheel and the horizontal (tilt) wheel. A failing wheel
+//! encoder or a bouncing tilt switch sends spurious events that scroll back
+//! and forth, so an event is blocked if it scrolls in the opposite direction of
+//! the previous accepted event within a threshold. Scrolling quickly in the
+//! same direction is never blocked.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/inputdev/wm-mousewheel>
+//! - <https://learn.microsoft.com/en-us/windows/win32/inputdev/wm-mousehwheel>
+
+use crate::log;
+use core::sync::atomic::{AtomicI8, AtomicU32, Ordering::Relaxed};
+
+/// Debounce state for a wheel.
+pub struct Wheel {
+    /// Used in log messages.
+    name: &'static [u8],
+    /// Reversals within this many milliseconds are blocked, `0` disables
+    /// debouncing.
+    threshold: AtomicU32,
+    /// Tick count of the last accepted event.
+    last_time: AtomicU32,
+    /// Direction of the last accepted event: `1`, `-1` or `0` if there was no
+    /// event yet.
+    last_direction: AtomicI8,
+    blocked: AtomicU32,
+}
+impl Wheel {
+    const fn new(name: &'static [u8]) -> Self {
+        Self {
+            name,
+            threshold: AtomicU32::new(0),
+            last_time: AtomicU32::new(0),
+            last_direction: AtomicI8::new(0),
+            blocked: AtomicU32::new(0),
+        }
+    }
+
+    pub fn threshold(&self) -> &AtomicU32 {
+        &self.threshold
+    }
+
+    /// The number of events that were blocked.
+    #[allow(dead_code, reason = "only used by certain features")]
+    pub fn blocked(&self) -> u32 {
+        self.blocked.load(Relaxed)
+    }
+
+    /// Decide if a wheel event with the given rotation (`WHEEL_DELTA` units)
+    /// should be blocked.
+    pub fn should_block(&self, delta: i16, tick: u32) -> bool {
+        let threshold = self.threshold.load(Relaxed);
+        let direction = delta.signum() as i8;
+        if threshold == 0 || direction == 0 {
+            return false;
+        }
+        let elapsed = tick.wrapping_sub(self.last_time.load(Relaxed));
+        let last_direction = self.last_direction.load(Relaxed);
+        if last_direction == -direction && elapsed < threshold {
+            _ = self.blocked.fetch_add(1, Relaxed);
+            log![
+                self.name,
+                b" reversal ignored (within ",
+                elapsed,
+                b" ms)\r\n"
+            ];
+            return true;
+        }
+        self.last_time.store(tick, Relaxed);
+        self.last_direction.store(direction, Relaxed);
+        false
+    }
+}
+
+/// The vertical scroll wheel.
+pub static VERTICAL: Wheel = Wheel::new(b"Wheel");
+
+/// The horizontal wheel, usually a tilt wheel.
+pub static HORIZONTAL: Wheel = Wheel::new(b"Horizontal wheel");
+
+#[allow(dead_code, reason = "only used by certain features")]
+pub const ALL: [&Wheel; 2] = [&VERTICAL, &HORIZONTAL];
@@ -0,0 +1,89 @@
+//! A thin layer over the Windows API calls that the mouse hook's logic makes,
+//! so that the logic can be tested with a fake implementation instead of a
+//! real hook.
+
+use crate::debounce::{MouseButton, MouseDirection};
+use core::sync::atomic::{AtomicU64, Ordering::Relaxed};
+
+pub trait WinApi {
+    /// Milliseconds since the system was started, see `GetTickCount64`.
+    fn tick_count(&self) -> u64;
+    /// Microseconds since an arbitrary point in time, see
+    /// `QueryPerformanceCounter`. Only has the tick count's resolution unless
+    /// implemented.
+    fn precise_micros(&self) -> u64 {
+        self.tick_count().saturating_mul(1000)
+    }
+    /// `true` if the primary and secondary mouse buttons are swapped.
+    fn buttons_swapped(&self) -> bool;
+    /// Inject mouse button events using `SendInput`.
+    fn inject(&self, events: &[(MouseButton, MouseDirection)]);
+    /// Replay a button's deferred event after `delay` milliseconds using a
+    /// timer.
+    fn schedule_replay(&self, button: MouseButton, delay: u32);
+
+    /// The button that acts as the primary (usually left) button.
+    fn primary_button(&self) -> MouseButton {
+        if self.buttons_swapped() {
+            MouseButton::Right
+        } else {
+            MouseButton::Left
+        }
+    }
+}
+
+/// A backend that doesn't need Windows, for running the platform independent
+/// logic on other targets. Time only moves when [`NoopApi::advance`] is
+/// called and injected events and timers are ignored.
+pub struct NoopApi {
+    tick: AtomicU64,
+}
+impl NoopApi {
+    pub const fn new() -> Self {
+        Self {
+            tick: AtomicU64::new(0),
+        }
+    }
+    /// Move time forward by `millis` milliseconds.
+    pub fn advance(&self, millis: u32) {
+        _ = self.tick.fetch_add(u64::from(millis), Relaxed);
+    }
+}
+impl Default for NoopApi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl WinApi for NoopApi {
+    fn tick_count(&self) -> u64 {
+        self.tick.load(Relaxed)
+    }
+    fn buttons_swapped(&self) -> bool {
+        false
+    }
+    fn inject(&self, _events: &[(MouseButton, MouseDirection)]) {}
+    fn schedule_replay(&self, _button: MouseButton, _delay: u32) {}
+}
+
+/// Extend a 32 bit timestamp from `GetTickCount`'s time base, such as
+/// `MSLLHOOKSTRUCT.time`, to 64 bits using the current 64 bit tick count
+/// `now`. The timestamp is assumed to be within 24 days of `now`, so this
+/// works across the wrap around that happens every 49.7 days.
+pub fn extend_tick(now: u64, time: u32) -> u64 {
+    let offset = time.wrapping_sub(now as u32) as i32;
+    now.wrapping_add_signed(i64::from(offset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extend_tick_handles_wrap() {
+        let wrap = 1_u64 << 32;
+        assert_eq!(extend_tick(wrap + 10, 5), wrap + 5);
+        assert_eq!(extend_tick(wrap + 10, u32::MAX - 9), wrap - 10);
+        assert_eq!(extend_tick(wrap - 10, 5), wrap + 5);
+        assert_eq!(extend_tick(1000, 900), 900);
+    }
+}
@@ -0,0 +1,105 @@
+//! Keeps track of the title of the foreground window so that it can be included
+//! in event logs. The title is resolved when the foreground window changes
+//! instead of when a mouse event happens so that the mouse hook never has to
+//! wait for it.
+//!
+//! # References
+//!
+//! - <https://learn.microsoft.com/en-us/windows/win32/api/winuser/nf-winuser-setwineventhook>
+//! - <https://learn.microsoft.com/en-us/windows/win32/winauto/event-constants>
+
+use crate::log_error;
+use crate::logging::utf16_to_ascii;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering::Relaxed};
+use windows_sys::Win32::Foundation::HWND;
+use windows_sys::Win32::UI::Accessibility::{SetWinEventHook, HWINEVENTHOOK};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    GetForegroundWindow, GetWindowTextW, EVENT_OBJECT_NAMECHANGE, EVENT_SYSTEM_FOREGROUND,
+    OBJID_WINDOW, WINEVENT_OUTOFCONTEXT,
+};
+
+/// Only track the window title if the user asked for it.
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// ASCII version of the foreground window's title. Only written on the thread
+/// that runs the message loop, a title that is logged while it changes can be
+/// a mix of the old and new titles which is good enough for logging.
+static TITLE: [AtomicU8; 128] = [const { AtomicU8::new(0) }; 128];
+static TITLE_LEN: AtomicUsize = AtomicUsize::new(0);
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Relaxed)
+}
+
+pub fn set_enabled(enabled: bool) {
+    ENABLED.store(enabled, Relaxed);
+}
+
+fn update_title(window: HWND) {
+    let mut wide = [0_u16; 256];
+    let len = unsafe { GetWindowTextW(window, wide.as_mut_ptr(), wide.len() as i32) };
+    let mut ascii = [0; 128];
+    let ascii = utf16_to_ascii(&wide[..len.max(0) as usize], &mut ascii);
+    for (stored, &byte) in TITLE.iter().zip(ascii) {
+        stored.store(byte, Relaxed);
+    }
+    TITLE_LEN.store(ascii.len(), Relaxed);
+}
+
+unsafe extern "system" fn on_win_event(
+    _hook: HWINEVENTHOOK,
+    event: u32,
+    window: HWND,
+    object: i32,
+    _child: i32,
+    _thread: u32,
+    _time: u32,
+) {
+    // Name changes are reported for all objects in all windows, we only care
+    // about the title of the foreground window:
+    if event == EVENT_OBJECT_NAMECHANGE
+        && (object != OBJID_WINDOW || window != GetForegroundWindow())
+    {
+        return;
+    }
+    update_title(window);
+}
+
+/// Start tracking the foreground window's title if that is enabled. Must be
+/// called on the thread that runs the message loop since that is where the
+/// window events are delivered.
+pub fn start() {
+    if !is_enabled() {
+        return;
+    }
+    for event in [EVENT_SYSTEM_FOREGROUND, EVENT_OBJECT_NAMECHANGE] {
+        // The hooks are removed automatically when the program exits:
+        let hook = unsafe {
+            SetWinEventHook(
+                event,
+                event,
+                ptr::null_mut(),
+                Some(on_win_event),
+                0,
+                0,
+                WINEVENT_OUTOFCONTEXT,
+            )
+        };
+        if hook.is_null() {
+            log_error("Failed to track the foreground window's title");
+            return;
+        }
+    }
+    update_title(unsafe { GetForegroundWindow() });
+}
+
+/// Call `f` with the last known title of the foreground window.
+pub fn with_title<R>(f: impl FnOnce(&[u8]) -> R) -> R {
+    let mut title = [0; 128];
+    let len = TITLE_LEN.load(Relaxed).min(title.len());
+    for (byte, stored) in title.iter_mut().zip(&TITLE[..len]) {
+        *byte = stored.load(Relaxed);
+    }
+    f(&title[..len])
+}
@@ -0,0 +1,73 @@
+//! Detects if the program is running under Wine (which includes Proton), where
+//! some Windows APIs behave differently. For example the console APIs fail
+//! when output goes to a Unix terminal and launchers don't always quote the
+//! executable's path on the command line.
+//!
+//! # References
+//!
+//! - <https://wiki.winehq.org/Developer_FAQ#How_can_I_detect_Wine.3F>
+
+use core::ffi::c_char;
+use core::sync::atomic::{AtomicPtr, AtomicU8, Ordering::Relaxed};
+use windows_sys::Win32::System::LibraryLoader::{GetModuleHandleW, GetProcAddress};
+
+const UNKNOWN: u8 = 0;
+const NATIVE: u8 = 1;
+const WINE: u8 = 2;
+
+static STATE: AtomicU8 = AtomicU8::new(UNKNOWN);
+static VERSION: AtomicPtr<c_char> = AtomicPtr::new(core::ptr::null_mut());
+
+fn detect() -> u8 {
+    let state = STATE.load(Relaxed);
+    if state != UNKNOWN {
+        return state;
+    }
+    let ntdll = unsafe { GetModuleHandleW(windows_sys::w!("ntdll.dll")) };
+    let get_version = if ntdll.is_null() {
+        None
+    } else {
+        unsafe { GetProcAddress(ntdll, c"wine_get_version".as_ptr().cast()) }
+    };
+    let state = match get_version {
+        Some(get_version) => {
+            let get_version: unsafe extern "C" fn() -> *const c_char =
+                unsafe { core::mem::transmute(get_version) };
+            VERSION.store(unsafe { get_version() }.cast_mut(), Relaxed);
+            WINE
+        }
+        None => NATIVE,
+    };
+    STATE.store(state, Relaxed);
+    state
+}
+
+/// `true` if the program is running under Wine.
+pub fn is_wine() -> bool {
+    detect() == WINE
+}
+
+/// Wine's version, `None` if the program isn't running under Wine.
+#[cfg(feature = "logging")]
+pub fn version() -> Option<&'static [u8]> {
+    if !is_wine() {
+        return None;
+    }
+    let version = VERSION.load(Relaxed);
+    if version.is_null() {
+        return Some(b"unknown");
+    }
+    Some(unsafe { core::ffi::CStr::from_ptr(version) }.to_bytes())
+}
+
+/// Get the length of the executable's path at the start of a command line
+/// where the path isn't quoted. Windows always quotes paths with spaces but
+/// Wine launchers don't, so the path might continue past the first space
+/// until it ends with `.exe`. Returns `None` if no such path was found.
+pub fn unquoted_program_len(command_line: &[u8]) -> Option<usize> {
+    const EXE: &[u8] = b".exe";
+    (EXE.len()..=command_line.len()).find(|&end| {
+        command_line[end - EXE.len()..end].eq_ignore_ascii_case(EXE)
+            && matches!(command_line.get(end), None | Some(b' ' | b'\t'))
+    })
+}